@@ -0,0 +1,79 @@
+//! Compares checksumming uploaded files inline against checksumming them on a
+//! [HashingPool], for a directory of many small files.
+//!
+//! Uploads are simulated with a sleep, so the benchmark doesn't need cloud
+//! storage. Run with `cargo bench --bench checksum`.
+
+use std::time::{Duration, Instant};
+
+use bolster::{
+    app_config::ChecksumAlgorithm,
+    core::{
+        api::storage::{ChecksumContext, HashingPool},
+        commands::{MAX_FILES_HASHING_CONCURRENTLY, MAX_FILES_UPLOADING_CONCURRENTLY},
+    },
+};
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+
+/// Number of files uploaded per run.
+const NUM_FILES: usize = 200;
+/// Size of each file.
+const FILE_SIZE: usize = 4 * 1024 * 1024;
+/// Simulated time to upload each file.
+const UPLOAD_TIME: Duration = Duration::from_millis(20);
+
+/// Uploads every file, checksumming each one inline before its upload (as
+/// uploads were done before [HashingPool]).
+async fn upload_hashing_inline(files: &[Bytes]) {
+    stream::iter(files)
+        .map(|data| async move {
+            let mut ctx = ChecksumContext::new(ChecksumAlgorithm::Md5);
+            ctx.update(data);
+            let checksum = ctx.finish();
+            tokio::time::sleep(UPLOAD_TIME).await;
+            checksum
+        })
+        .buffer_unordered(MAX_FILES_UPLOADING_CONCURRENTLY)
+        .collect::<Vec<_>>()
+        .await;
+}
+
+/// Uploads every file, checksumming each one on `hashing` before its upload.
+async fn upload_hashing_pool(files: &[Bytes], hashing: &HashingPool) {
+    stream::iter(files)
+        .map(|data| async move {
+            let checksum = hashing
+                .checksum(data.clone(), ChecksumAlgorithm::Md5)
+                .await
+                .unwrap();
+            tokio::time::sleep(UPLOAD_TIME).await;
+            checksum
+        })
+        .buffer_unordered(MAX_FILES_UPLOADING_CONCURRENTLY)
+        .collect::<Vec<_>>()
+        .await;
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let files: Vec<Bytes> = (0..NUM_FILES)
+        .map(|i| Bytes::from(vec![i as u8; FILE_SIZE]))
+        .collect();
+
+    let start = Instant::now();
+    runtime.block_on(upload_hashing_inline(&files));
+    println!("inline:           {:?}", start.elapsed());
+
+    let hashing = HashingPool::new(MAX_FILES_HASHING_CONCURRENTLY);
+    let start = Instant::now();
+    runtime.block_on(upload_hashing_pool(&files, &hashing));
+    println!(
+        "hashing pool ({}): {:?}",
+        MAX_FILES_HASHING_CONCURRENTLY,
+        start.elapsed()
+    );
+}
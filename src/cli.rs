@@ -5,35 +5,67 @@
 use std::{
     ffi::OsStr,
     fmt::Display,
-    io::{self, Write},
+    io::{self, BufRead, Write},
+    iter,
+    num::NonZeroU64,
     path::{Component, Path, PathBuf},
     str::FromStr,
 };
 
 use anyhow::{anyhow, bail, Context, Result};
-use byte_unit::Byte;
-use chrono::NaiveDate;
+use byte_unit::{Byte, MEBIBYTE};
+use chrono::{DateTime, SecondsFormat, Utc};
 use clap::{crate_authors, crate_description, crate_version, App, AppSettings, Arg};
-use strum::VariantNames;
+use futures::{
+    future,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use jsonschema::JSONSchema;
+use serde_json::json;
+use strum::{IntoEnumIterator, VariantNames};
 use uuid::Uuid;
 use walkdir::WalkDir;
 
 use crate::{
-    app_config::{DatabaseConfig, StorageProviderChoices},
+    app_config::{
+        self, DatabaseConfig, OutputConfig, OutputFormat, PerformanceConfig,
+        StorageProviderChoices, UploadConfig,
+    },
+    completions::{self, Shell},
     core::{
         api::{
-            datasets::{DatabaseApiConfig, DatasetGetRequest, DatasetOrdering},
+            datasets::{
+                self, CreatedDateBound, DatabaseApiConfig, DatasetGetRequest, DatasetOrdering,
+                FileOrdering,
+            },
+            proxy::ProxyConfig,
             storage,
             storage::StorageConfig,
         },
-        commands,
+        commands, compression,
+        models::{DatasetSummary, UploadedFile},
+        progress::ProgressMode,
     },
     object_space,
 };
 
-/// If trying to upload more files, exit and prompt to tar/zip files.
+/// If trying to upload more files, exit and prompt to tar/zip files (unless
+/// overridden by `--max-files` or `max_files` in the `[upload]` config).
 const UPLOAD_MAX_FILES_ALLOWED: usize = 200;
 
+/// Extensions of data files accepted by upload (unless overridden by
+/// `--data-ext` or `data_extensions` in the `[upload]` config).
+const DEFAULT_DATA_EXTENSIONS: [&str; 1] = ["bag"];
+
+/// Highest allowed override of the upload file limit, so a single upload
+/// can't flood cloud storage and the database with requests.
+const UPLOAD_MAX_FILES_CEILING: usize = 10_000;
+
+/// Number of recent datasets offered by the interactive dataset picker.
+const PICKER_DATASET_COUNT: usize = 20;
+
 /// Extract optional arg with a specific type, exiting on parse error.
 pub fn handle_optional_arg<T>(matches: &clap::ArgMatches, arg_name: &str) -> Option<T>
 where
@@ -50,38 +82,53 @@ where
     }
 }
 
+/// Splits a `KEY=VALUE` argument into its key and value.
+///
+/// Only the first `=` is treated as a separator, so values may contain `=`.
+///
+/// # Errors
+///
+/// Returns an error if the argument contains no `=` or if the key is empty.
+pub fn parse_key_value(arg: &str) -> Result<(String, String)> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        Some(_) => bail!("Expected KEY=VALUE but key is empty: {:?}", arg),
+        None => bail!("Expected KEY=VALUE but found no '=': {:?}", arg),
+    }
+}
+
 /// Different kinds of paths that bolster expects as arguments
 #[derive(Debug)]
-pub enum PathKind {
+pub enum PathKind<'a> {
     /// Plex (associated path should point to a .plex file)
     Plex,
     /// Object-space TOML (associated path should point to a .toml file)
     ObjectSpaceToml,
-    /// Data (associated path(s) should point to a .bag file or folders)
-    Data,
+    /// Data (associated path(s) should point to a file with one of the given
+    /// extensions, e.g. `bag`, or folders)
+    Data(&'a [String]),
 }
 
-impl PathKind {
+impl PathKind<'_> {
     /// Validates that the given path matches expectations for the PathKind
     ///
     /// # Errors
     ///
     /// - For [PathKind::Plex], an error is raised if the path doesn't end in
-    /// `.plex` or if the path points to a non-existent or unreadable file.
+    ///   `.plex` or if the path points to a non-existent or unreadable file.
     /// - For [PathKind::ObjectSpaceToml], an error is raised if the path doesn't
-    /// end in `.toml` or if the path points to a non-existent or unreadable file.
+    ///   end in `.toml` or if the path points to a non-existent or unreadable file.
     /// - For [PathKind::Data], an error is raised if the path points to a file
-    /// but the file doesn't end in `.bag`, or if the path points to an
-    /// unreadable file or directory, or if the path points to a non-existent
-    /// file/folder.
+    ///   but the file doesn't end in one of the data extensions, or if the path points to an
+    ///   unreadable file or directory, or if the path points to a non-existent
+    ///   file/folder.
     pub fn validate(self, path: &Path) -> Result<()> {
         match self {
             PathKind::Plex => {
-                if path
+                if !path
                     .extension()
                     .unwrap_or_else(|| OsStr::new(""))
-                    .to_ascii_lowercase()
-                    != "plex"
+                    .eq_ignore_ascii_case("plex")
                 {
                     bail!("Plex file ({:?}) doesn't end in .plex", path);
                 }
@@ -91,11 +138,10 @@ impl PathKind {
                 Ok(())
             }
             PathKind::ObjectSpaceToml => {
-                if path
+                if !path
                     .extension()
                     .unwrap_or_else(|| OsStr::new(""))
-                    .to_ascii_lowercase()
-                    != "toml"
+                    .eq_ignore_ascii_case("toml")
                 {
                     bail!("Object-space TOML file ({:?}) doesn't end in .toml", path);
                 }
@@ -107,18 +153,24 @@ impl PathKind {
                 }
                 Ok(())
             }
-            PathKind::Data => {
+            PathKind::Data(extensions) => {
                 if path.is_file() {
-                    if path
-                        .extension()
-                        .unwrap_or_else(|| OsStr::new(""))
-                        .to_ascii_lowercase()
-                        != "bag"
+                    let extension = path.extension().unwrap_or_else(|| OsStr::new(""));
+                    if !extensions
+                        .iter()
+                        .any(|ext| extension.eq_ignore_ascii_case(ext))
                     {
+                        let allowed = extensions
+                            .iter()
+                            .map(|ext| format!(".{}", ext))
+                            .collect::<Vec<_>>()
+                            .join(" or ");
                         bail!(
-                            "Data file ({:?}) doesn't end in .bag. Data input \
-                            must be .bag files or folders.",
-                            path
+                            "Data file ({:?}) doesn't end in {}. Data input \
+                            must be {} files or folders.",
+                            path,
+                            allowed,
+                            allowed
                         );
                     }
                 } else if path.is_dir() {
@@ -142,9 +194,9 @@ impl PathKind {
 /// - Contain `.` (current directory) or `..` (parent directory)
 /// - Are not valid UTF-8
 /// - Do not exist (plex and toml arguments must point to a file, data arguments
-/// must point to a file or folder)
+///   must point to a file or folder)
 /// - Have the wrong extension (the plex argument must be a file ending with
-/// .plex, the object space toml argument must be a file ending with .toml)
+///   .plex, the object space toml argument must be a file ending with .toml)
 pub fn clean_and_validate_path(path_os_str: &OsStr, path_kind: PathKind) -> Result<String> {
     let path = Path::new(path_os_str);
     path_kind.validate(path)?;
@@ -177,6 +229,851 @@ pub fn clean_and_validate_path(path_os_str: &OsStr, path_kind: PathKind) -> Resu
     Ok(utf8_path)
 }
 
+/// Determines how many files an upload may contain: the `--max-files` CLI
+/// override, else `max_files` from the `[upload]` config, else the default.
+///
+/// # Errors
+///
+/// Returns an error if the limit is 0 or above [`UPLOAD_MAX_FILES_CEILING`].
+fn upload_max_files(cli_override: Option<usize>, config_value: Option<usize>) -> Result<usize> {
+    let max_files = cli_override
+        .or(config_value)
+        .unwrap_or(UPLOAD_MAX_FILES_ALLOWED);
+    if max_files == 0 || max_files > UPLOAD_MAX_FILES_CEILING {
+        bail!(
+            "Upload file limit must be between 1 and {} (got {}). Please tar/zip the files before uploading!",
+            UPLOAD_MAX_FILES_CEILING,
+            max_files
+        );
+    }
+    Ok(max_files)
+}
+
+/// Determines the size (in bytes) at or above which files are uploaded with a
+/// multipart upload: `multipart_threshold_mib` from the `[performance]`
+/// config, else [`commands::MULTIPART_FILESIZE_THRESHOLD`].
+///
+/// # Errors
+///
+/// Returns an error if the threshold is below the minimum part size of a
+/// multipart upload ([`storage::MIN_PART_SIZE`]), or above the largest object
+/// a single request can upload ([`storage::MAX_PART_SIZE`]), since smaller
+/// files are read into memory and uploaded in one request.
+fn upload_multipart_threshold(config_value: Option<usize>) -> Result<usize> {
+    let threshold = match config_value {
+        Some(mib) => mib.checked_mul(MEBIBYTE as usize).ok_or_else(|| {
+            anyhow!(
+                "Config error: [performance] multipart_threshold_mib is too large (got {})",
+                mib
+            )
+        })?,
+        None => commands::MULTIPART_FILESIZE_THRESHOLD,
+    };
+    if threshold < storage::MIN_PART_SIZE {
+        bail!(
+            "Config error: [performance] multipart_threshold_mib must be at least {} (the minimum multipart upload part size in MiB), got {}",
+            storage::MIN_PART_SIZE / MEBIBYTE as usize,
+            threshold / MEBIBYTE as usize
+        );
+    }
+    if threshold > storage::MAX_PART_SIZE {
+        bail!(
+            "Config error: [performance] multipart_threshold_mib must be at most {} (the largest single-request upload in MiB), got {}",
+            storage::MAX_PART_SIZE / MEBIBYTE as usize,
+            threshold / MEBIBYTE as usize
+        );
+    }
+    Ok(threshold)
+}
+
+/// Determines how many files are checksummed at the same time while
+/// uploading: `--hash-jobs` if given, else `hash_jobs` from the
+/// `[performance]` config, else [`commands::MAX_FILES_HASHING_CONCURRENTLY`].
+///
+/// # Errors
+///
+/// Returns an error if the configured number is 0.
+fn upload_hash_jobs(cli_value: Option<usize>, config_value: Option<usize>) -> Result<usize> {
+    if config_value == Some(0) {
+        bail!("Config error: [performance] hash_jobs must be at least 1");
+    }
+    Ok(cli_value
+        .or(config_value)
+        .unwrap_or(commands::MAX_FILES_HASHING_CONCURRENTLY))
+}
+
+/// Checks that every section of the config is usable before running a
+/// command (for `--config-check`), so problems are reported up front instead
+/// of partway through an operation.
+///
+/// Checks that the database JWT is well-formed and unexpired, that
+/// `request_attempts` is at least 1, that the `[upload]`, `[performance]`,
+/// `[output]`, and `[network]` sections (and proxy env vars) are valid, and
+/// that each configured storage provider's section has usable settings. No
+/// network requests are made.
+///
+/// # Errors
+///
+/// Returns an error listing every problem found.
+pub fn check_config(config: &config::Config) -> Result<()> {
+    let mut problems: Vec<String> = Vec::new();
+    let mut check = |result: Result<()>| {
+        if let Err(e) = result {
+            // Storage providers also read the [network] section, so its
+            // problems would otherwise be repeated
+            let problem = format!("{:#}", e);
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+    };
+
+    check((|| {
+        let db = config.clone().try_into::<DatabaseConfig>()?.database;
+        db.check_jwt_expiry(Utc::now())?;
+        if db.request_attempts == Some(0) {
+            bail!("Config error: [database] request_attempts must be at least 1");
+        }
+        db.user_id_from_jwt()?;
+        Ok(())
+    })());
+    check(
+        config
+            .clone()
+            .try_into::<UploadConfig>()
+            .map(|_| ())
+            .context("Config error: invalid [upload] section"),
+    );
+    check((|| {
+        let performance = config
+            .clone()
+            .try_into::<PerformanceConfig>()
+            .context("Config error: invalid [performance] section")?
+            .performance;
+        upload_multipart_threshold(performance.multipart_threshold_mib)?;
+        upload_hash_jobs(None, performance.hash_jobs)?;
+        Ok(())
+    })());
+    check(
+        config
+            .clone()
+            .try_into::<OutputConfig>()
+            .map(|_| ())
+            .context("Config error: invalid [output] section"),
+    );
+    check(ProxyConfig::from_config(config).map(|_| ()));
+    for provider in StorageProviderChoices::iter() {
+        // Unconfigured providers are fine, as long as they aren't used
+        if config
+            .get::<config::Value>(provider.config_section())
+            .is_ok()
+        {
+            check(StorageConfig::new(config.clone(), provider).map(|_| ()));
+        }
+    }
+
+    if !problems.is_empty() {
+        bail!(
+            "Config check failed:\n{}",
+            problems
+                .iter()
+                .map(|problem| format!("  - {}", problem))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+    Ok(())
+}
+
+/// Determines which extensions data files passed to upload may have: the
+/// `--data-ext` CLI overrides, else `data_extensions` from the `[upload]`
+/// config, else [`DEFAULT_DATA_EXTENSIONS`].
+///
+/// Leading periods are dropped, so `.mcap` and `mcap` are equivalent.
+fn upload_data_extensions(
+    cli_overrides: Vec<&str>,
+    config_value: Option<Vec<String>>,
+) -> Vec<String> {
+    let extensions = if !cli_overrides.is_empty() {
+        cli_overrides.into_iter().map(str::to_owned).collect()
+    } else {
+        config_value.unwrap_or_else(|| {
+            DEFAULT_DATA_EXTENSIONS
+                .iter()
+                .map(|ext| (*ext).to_owned())
+                .collect()
+        })
+    };
+    extensions
+        .into_iter()
+        .map(|ext| ext.trim_start_matches('.').to_owned())
+        .collect()
+}
+
+/// How data folders are walked by [collect_data_files].
+#[derive(Clone, Copy, Debug, Default)]
+struct DataWalkOptions {
+    /// Also collect hidden files and folders (whose names start with a
+    /// period, e.g. `.git`), which are skipped by default.
+    include_hidden: bool,
+    /// Follow symlinks to files and folders, which are skipped by default.
+    follow_symlinks: bool,
+}
+
+/// Collects UTF-8 paths to all files in any provided data folders (including
+/// subfolders) that pass `path_filter`. Paths to files are kept as-is.
+///
+/// Unless [DataWalkOptions::include_hidden] is set, hidden files and folders
+/// inside data folders are skipped. Hidden paths given directly are still
+/// collected.
+///
+/// With [DataWalkOptions::follow_symlinks], files reached through a symlink
+/// are collected at their path through the symlink (not the target's path),
+/// so the uploaded folder structure matches the data folder's.
+///
+/// # Errors
+///
+/// Returns an error if a path is neither a directory nor a file, or if any
+/// file path found in a folder isn't valid UTF-8.
+///
+/// Returns an error if following symlinks leads to a symlink loop.
+fn collect_data_files(
+    utf8_paths: &[String],
+    path_filter: &PathFilter,
+    walk_options: DataWalkOptions,
+) -> Result<Vec<String>> {
+    utf8_paths
+        .iter()
+        .try_fold(Vec::new(), |mut acc, utf8_path| -> Result<Vec<PathBuf>> {
+            let path = Path::new(utf8_path);
+            let file_list: Result<Vec<PathBuf>> = match path {
+                path if path.is_dir() => WalkDir::new(path)
+                    .follow_links(walk_options.follow_symlinks)
+                    .into_iter()
+                    // Skipping a hidden folder skips everything in it, but the
+                    // folder being walked is kept even if it's hidden
+                    .filter_entry(|entry| {
+                        walk_options.include_hidden
+                            || entry.depth() == 0
+                            || !entry.file_name().to_string_lossy().starts_with('.')
+                    })
+                    .filter_map(|entry| match entry {
+                        Ok(entry)
+                            if entry.file_type().is_file()
+                                && path_filter.is_match(
+                                    entry.path().strip_prefix(path).unwrap_or(entry.path()),
+                                ) =>
+                        {
+                            Some(Ok(entry.into_path()))
+                        }
+                        Ok(_) => None,
+                        // Other errors (e.g. unreadable folders) skip the
+                        // entry, as when not following symlinks
+                        Err(error) => error.loop_ancestor().map(|ancestor| {
+                            Err(anyhow!(
+                                "Symlink loop: {} links back to {}, remove the link or \
+                                 don't pass --follow-symlinks",
+                                // Loop errors always have a path
+                                error.path().unwrap_or(path).display(),
+                                ancestor.display()
+                            ))
+                        }),
+                    })
+                    .collect::<Result<Vec<PathBuf>>>(),
+                path if path.is_file() => Ok(vec![path.to_path_buf()]),
+                _ => Err(anyhow!("File path {:?} is not a directory or a file", path)),
+            };
+            let mut file_list = file_list?;
+            acc.append(&mut file_list);
+            Ok(acc)
+        })?
+        .iter()
+        .map(|pathbuf| Ok(pathbuf.as_path().to_str().ok_or_else(||
+            anyhow!("All file/folder names must be valid UTF-8 (AWS S3 requirement). Invalid UTF-8: {:?}", pathbuf)
+        )?.to_owned()))
+        .collect()
+}
+
+/// Splits the positional arguments of `bolster upload` into the plex path,
+/// the object-space path, and the data paths.
+///
+/// Clap fills positional arguments in order, so with `--no-plex` or
+/// `--no-object-space` the first data paths land in the plex and
+/// object-space arguments and are moved back to the data paths here.
+///
+/// # Errors
+///
+/// Returns an error if the plex, object-space, or data paths are missing
+/// (data paths may be left out when uploading stdin).
+fn upload_positional_paths(
+    upload_matches: &clap::ArgMatches,
+) -> Result<(Option<&OsStr>, Option<&OsStr>, Vec<&OsStr>)> {
+    let mut paths = ["plex_path", "object_space_toml_path", "path"]
+        .iter()
+        .flat_map(|name| upload_matches.values_of_os(name).into_iter().flatten());
+    let plex_path = if upload_matches.is_present("no_plex") {
+        None
+    } else {
+        Some(paths.next().ok_or_else(|| anyhow!("Missing PLEX_PATH"))?)
+    };
+    let toml_path = if upload_matches.is_present("no_object_space") {
+        None
+    } else {
+        Some(
+            paths
+                .next()
+                .ok_or_else(|| anyhow!("Missing OBJECT_SPACE_TOML_PATH"))?,
+        )
+    };
+    let file_paths: Vec<&OsStr> = paths.collect();
+    if file_paths.is_empty() && !upload_matches.is_present("stdin") {
+        bail!("Missing PATH: give at least one data file or folder to upload");
+    }
+    Ok((plex_path, toml_path, file_paths))
+}
+
+/// Settings from the `[upload]` config that every dataset in a batch upload
+/// is checked against.
+struct BatchChecks {
+    /// Extensions accepted for data files (see [upload_data_extensions]).
+    data_extensions: Vec<String>,
+    /// Maximum number of data files per dataset (see [upload_max_files]).
+    max_files: usize,
+    /// Custom AprilTag families accepted in object-space files.
+    custom_families: Vec<String>,
+    /// Schema that dataset metadata must conform to, if configured.
+    metadata_schema: Option<JSONSchema>,
+}
+
+/// Validates a dataset from a batch upload spec the same way `bolster upload`
+/// validates its arguments, expanding data folders into files.
+///
+/// # Errors
+///
+/// Returns an error if any path is invalid (see [clean_and_validate_path]),
+/// if there are too many data files, if the object-space file is invalid, or
+/// if the metadata doesn't conform to the configured schema.
+fn prepare_batch_item(
+    dataset: commands::BatchDatasetSpec,
+    checks: &BatchChecks,
+) -> Result<commands::BatchUploadItem> {
+    let plex_path = clean_and_validate_path(dataset.plex.as_os_str(), PathKind::Plex)?;
+    let object_space_path =
+        clean_and_validate_path(dataset.object_space.as_os_str(), PathKind::ObjectSpaceToml)?;
+    let utf8_file_paths = dataset
+        .paths
+        .iter()
+        .map(|path| {
+            clean_and_validate_path(path.as_os_str(), PathKind::Data(&checks.data_extensions))
+        })
+        .collect::<Result<Vec<String>>>()?;
+    let file_paths = collect_data_files(
+        &utf8_file_paths,
+        &PathFilter::new(Vec::new(), Vec::new())?,
+        DataWalkOptions::default(),
+    )?;
+    if file_paths.len() > checks.max_files {
+        bail!(
+            "Dataset has {} files (max = {}). Please tar/zip the files before uploading!",
+            file_paths.len(),
+            checks.max_files
+        );
+    }
+    object_space::read_object_space_config(&object_space_path, &checks.custom_families)
+        .context("Unable to read TOML object-space file!")?;
+    if let Some(schema) = &checks.metadata_schema {
+        commands::validate_metadata(schema, &dataset.meta)?;
+    }
+    Ok(commands::BatchUploadItem {
+        system_id: dataset.system_id,
+        plex_path,
+        object_space_path,
+        file_paths,
+        metadata: dataset.meta,
+    })
+}
+
+/// Ensures the user can be prompted to pick a dataset with `--interactive`.
+///
+/// # Errors
+///
+/// Returns an error if stdin or stdout isn't a terminal (e.g. in scripts or
+/// CI) or if `--quiet` was passed.
+pub fn ensure_interactive(quiet: bool) -> Result<()> {
+    if quiet || !atty::is(atty::Stream::Stdin) || !atty::is(atty::Stream::Stdout) {
+        bail!("--interactive requires a terminal to prompt in, pass a dataset UUID instead");
+    }
+    Ok(())
+}
+
+/// Lists the most recent datasets and prompts the user to pick one by number,
+/// returning the picked dataset's id.
+///
+/// Prompts again if the input isn't one of the listed numbers.
+///
+/// # Errors
+///
+/// Returns an error if no datasets are found or if the input ends (or is
+/// empty) before a dataset is picked.
+///
+/// Wraps [commands::list_dataset_summaries] -- see its documentation for
+/// possible errors.
+pub async fn pick_dataset<R, W>(
+    db_config: &DatabaseApiConfig,
+    mut input: R,
+    mut output: W,
+) -> Result<Uuid>
+where
+    R: BufRead,
+    W: Write,
+{
+    let params = DatasetGetRequest {
+        order: Some(DatasetOrdering::CreatedDateDesc),
+        limit: Some(PICKER_DATASET_COUNT),
+        ..Default::default()
+    };
+    let summaries = commands::list_dataset_summaries(db_config, &params).await?;
+    if summaries.is_empty() {
+        bail!("No datasets found!");
+    }
+
+    writeln!(output, "Recent datasets:\n")?;
+    for (idx, d) in summaries.iter().enumerate() {
+        writeln!(
+            output,
+            "{:>3}) {:<40} {:<40.38} {:<26} {} file(s)",
+            idx + 1,
+            d.dataset_id.to_string(),
+            d.system_id,
+            d.created_date.format("%Y-%m-%d %H:%M:%S UTC"),
+            d.files.file_count,
+        )?;
+    }
+    loop {
+        write!(output, "\nSelect a dataset [1-{}]: ", summaries.len())?;
+        output.flush()?;
+
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        let choice = line.trim();
+        if choice.is_empty() {
+            bail!("No dataset selected");
+        }
+        match choice.parse::<usize>() {
+            Ok(n) if (1..=summaries.len()).contains(&n) => return Ok(summaries[n - 1].dataset_id),
+            _ => writeln!(output, "Invalid selection: {}", choice)?,
+        }
+    }
+}
+
+/// Asks before overwriting each existing file that downloading
+/// `uploaded_files` would replace, returning the files to download and the
+/// local paths of the files that were skipped.
+///
+/// Answering `y` overwrites the file and `n` (or anything else) skips it,
+/// while `a` overwrites and `s` skips it and every remaining existing file
+/// without asking again.
+///
+/// # Errors
+///
+/// Returns an error if any file already exists in quiet mode, since there's
+/// no one to ask and silently overwriting is dangerous.
+///
+/// Returns an error if a file's destination can't be determined -- see
+/// [commands::DownloadOptions::destination].
+pub fn confirm_overwrites<R, W>(
+    uploaded_files: Vec<UploadedFile>,
+    options: &commands::DownloadOptions,
+    mut input: R,
+    mut output: W,
+) -> Result<(Vec<UploadedFile>, Vec<PathBuf>)>
+where
+    R: BufRead,
+    W: Write,
+{
+    // Answer given with `a` or `s` that applies to all remaining files
+    let mut answer_for_all = None;
+    let mut to_download = Vec::with_capacity(uploaded_files.len());
+    let mut skipped = Vec::new();
+    for file in uploaded_files {
+        let filepath = options.destination(&file)?;
+        if !filepath.exists() {
+            to_download.push(file);
+            continue;
+        }
+        // TODO: add --force flag to skip prompt
+        if options.quiet {
+            bail!(
+                "File {} already exists, refusing to overwrite it in quiet mode",
+                filepath.display()
+            );
+        }
+        let overwrite = match answer_for_all {
+            Some(overwrite) => overwrite,
+            None => {
+                write!(
+                    output,
+                    "Overwrite file: {} ? [y/n/a/s] ",
+                    filepath.display()
+                )?;
+                output.flush()?;
+
+                let mut line = String::new();
+                input.read_line(&mut line)?;
+                match line.trim().to_lowercase().as_str() {
+                    "y" | "yes" => true,
+                    "a" | "all" => {
+                        answer_for_all = Some(true);
+                        true
+                    }
+                    "s" | "skip" => {
+                        answer_for_all = Some(false);
+                        false
+                    }
+                    _ => false,
+                }
+            }
+        };
+        if overwrite {
+            to_download.push(file);
+        } else {
+            skipped.push(filepath);
+        }
+    }
+    Ok((to_download, skipped))
+}
+
+/// Whether to colorize output: stdout is a terminal and the `NO_COLOR`
+/// environment variable isn't set (see <https://no-color.org>).
+pub fn use_color() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && atty::is(atty::Stream::Stdout)
+}
+
+/// Makes `text` bold with ANSI escape codes, if `color` is true.
+fn bold(text: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[1m{}\x1b[0m", text)
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Alignment of a column in a [Table].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    /// Pad cells on the right (for text)
+    Left,
+    /// Pad cells on the left (for numbers and sizes)
+    Right,
+}
+
+/// Rows of text rendered with each column as wide as its widest cell.
+#[derive(Debug)]
+pub struct Table {
+    /// Header and alignment of each column
+    columns: Vec<(&'static str, Align)>,
+    /// Cells of each row, one per column
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Creates an empty table with the given column headers and alignments.
+    pub fn new(columns: Vec<(&'static str, Align)>) -> Self {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row, which should have one cell per column.
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Renders the header and rows, one per line. The header is bold if
+    /// `color` is true.
+    pub fn render(&self, color: bool) -> String {
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(idx, (header, _))| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(idx))
+                    .map(|cell| cell.chars().count())
+                    .chain(iter::once(header.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let render_line = |cells: Vec<&str>| {
+            let line = cells
+                .iter()
+                .zip(&self.columns)
+                .zip(&widths)
+                .map(|((cell, (_, align)), width)| match align {
+                    Align::Left => format!("{:<width$}", cell, width = width),
+                    Align::Right => format!("{:>width$}", cell, width = width),
+                })
+                .collect::<Vec<_>>()
+                .join("  ");
+            line.trim_end().to_owned()
+        };
+
+        let header = render_line(self.columns.iter().map(|(header, _)| *header).collect());
+        iter::once(bold(&header, color))
+            .chain(
+                self.rows
+                    .iter()
+                    .map(|row| render_line(row.iter().map(String::as_str).collect())),
+            )
+            .map(|line| line + "\n")
+            .collect()
+    }
+}
+
+/// Keys of each dataset listed by `bolster ls` in a machine-readable format.
+const DATASET_RECORD_KEYS: &[&str] = &[
+    "dataset_id",
+    "system_id",
+    "name",
+    "created_date",
+    "file_count",
+    "total_filesize",
+];
+
+/// Keys of each file listed by `bolster ls --files-only` in a machine-readable
+/// format.
+const FILE_SEARCH_RECORD_KEYS: &[&str] = &[
+    "dataset_id",
+    "file_id",
+    "path",
+    "created_date",
+    "filesize",
+    "url",
+];
+
+/// Keys of each file listed by `bolster ls --uuid` in a machine-readable
+/// format.
+const FILE_RECORD_KEYS: &[&str] = &[
+    "file_id",
+    "created_date",
+    "modified_time",
+    "filesize",
+    "url",
+];
+
+/// Writes records (JSON objects) in a machine-readable [OutputFormat] one at
+/// a time, so long listings can be printed as they're fetched.
+///
+/// [OutputFormat::Table] isn't handled here, since aligning columns requires
+/// every row (see [Table]).
+#[derive(Debug)]
+pub struct RecordWriter {
+    /// Format records are written in
+    format: OutputFormat,
+    /// Keys of each record, in the order of CSV columns
+    keys: &'static [&'static str],
+    /// Number of records written so far
+    records_written: usize,
+}
+
+impl RecordWriter {
+    /// Creates a writer for records with the given keys.
+    pub fn new(format: OutputFormat, keys: &'static [&'static str]) -> Self {
+        RecordWriter {
+            format,
+            keys,
+            records_written: 0,
+        }
+    }
+
+    /// Writes a record, preceded by the CSV header or the opening bracket of
+    /// the JSON array if it's the first record.
+    pub fn write(&mut self, out: &mut impl Write, record: &serde_json::Value) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                let separator = if self.records_written == 0 { "[" } else { "," };
+                writeln!(out, "{}", separator)?;
+                write!(out, "  {}", record)?;
+            }
+            OutputFormat::Csv => {
+                if self.records_written == 0 {
+                    writeln!(out, "{}", self.keys.join(","))?;
+                }
+                let fields: Vec<String> = self
+                    .keys
+                    .iter()
+                    .map(|key| csv_field(&record[key]))
+                    .collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            OutputFormat::Ndjson | OutputFormat::Table => writeln!(out, "{}", record)?,
+        }
+        self.records_written += 1;
+        Ok(())
+    }
+
+    /// Closes the JSON array, or writes the CSV header or an empty JSON array
+    /// if no records were written.
+    pub fn finish(self, out: &mut impl Write) -> io::Result<()> {
+        match (self.format, self.records_written) {
+            (OutputFormat::Json, 0) => writeln!(out, "[]"),
+            (OutputFormat::Json, _) => writeln!(out, "\n]"),
+            (OutputFormat::Csv, 0) => writeln!(out, "{}", self.keys.join(",")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Formats a JSON value as a CSV field, quoting it if it contains a comma,
+/// quote, or newline. Null values are empty.
+fn csv_field(value: &serde_json::Value) -> String {
+    let text = match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    if text.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// Formats a date in a machine-readable record as RFC 3339 in UTC with
+/// microseconds (e.g. "2021-02-03T21:21:57.713584Z"), so every record's dates
+/// have the same shape regardless of their precision.
+fn record_datetime(datetime: &DateTime<Utc>) -> String {
+    datetime.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Chooses the format listings are printed in: the `--format` argument if
+/// given (or [OutputFormat::Ndjson] for `--json-lines`), otherwise the
+/// `[output] format` config setting, otherwise [OutputFormat::Table].
+///
+/// # Errors
+///
+/// Returns an error if the `[output]` config section is invalid.
+fn output_format(matches: &clap::ArgMatches, config: &config::Config) -> Result<OutputFormat> {
+    if matches.is_present("json_lines") {
+        return Ok(OutputFormat::Ndjson);
+    }
+    match handle_optional_arg(matches, "format") {
+        Some(format) => Ok(format),
+        None => Ok(config
+            .clone()
+            .try_into::<OutputConfig>()
+            .context("Config error: invalid [output] section")?
+            .output
+            .format
+            .unwrap_or_default()),
+    }
+}
+
+/// Include/exclude glob filters for files found in data folders.
+///
+/// Globs are matched against each file's path relative to the data folder it
+/// was found in. `*` matches across directories, so `*.log` skips log files
+/// at any depth.
+#[derive(Debug)]
+pub struct PathFilter {
+    /// If present, only files matching one of these globs are kept
+    include: Option<GlobSet>,
+    /// Files matching any of these globs are skipped, even if included
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    /// Build a filter from include and exclude glob patterns. With no include
+    /// patterns, every file not excluded is kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any pattern isn't a valid glob.
+    pub fn new<'a, I, E>(include: I, exclude: E) -> Result<PathFilter>
+    where
+        I: IntoIterator<Item = &'a str>,
+        E: IntoIterator<Item = &'a str>,
+    {
+        /// Combine patterns into one set.
+        fn build_globset<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<GlobSet> {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(
+                    Glob::new(pattern)
+                        .with_context(|| format!("Invalid glob pattern: {}", pattern))?,
+                );
+            }
+            Ok(builder.build()?)
+        }
+
+        let include = build_globset(include)?;
+        Ok(PathFilter {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(include)
+            },
+            exclude: build_globset(exclude)?,
+        })
+    }
+
+    /// Whether a file (given by its path relative to its data folder) should
+    /// be uploaded. Excludes take precedence over includes.
+    pub fn is_match(&self, relative_path: &Path) -> bool {
+        if self.exclude.is_match(relative_path) {
+            return false;
+        }
+        match &self.include {
+            Some(include) => include.is_match(relative_path),
+            None => true,
+        }
+    }
+}
+
+/// Process the `object-space` subcommand.
+///
+/// Object-space subcommands work offline, so unlike [cli_match] they don't
+/// need a config file. `config` may be empty, but if it has `[upload]
+/// apriltag_families`, they're accepted along with `--apriltag-family`
+/// values, like when uploading.
+///
+/// # Errors
+///
+/// Returns an error if the `[upload]` config section is invalid, or if the
+/// object-space TOML file is unreadable or invalid (see
+/// [object_space::read_object_space_config]).
+pub fn object_space_match(
+    config: config::Config,
+    object_space_matches: &clap::ArgMatches,
+) -> Result<()> {
+    if let Some(("validate", validate_matches)) = object_space_matches.subcommand() {
+        let toml_path = Path::new(
+            validate_matches
+                .value_of_os("object_space_toml_path")
+                .unwrap(),
+        );
+        let upload_settings = config
+            .try_into::<UploadConfig>()
+            .context("Config error: invalid [upload] section")?
+            .upload;
+        let custom_families: Vec<String> = validate_matches
+            .values_of("apriltag_family")
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .chain(upload_settings.apriltag_families.unwrap_or_default())
+            .collect();
+        object_space::read_object_space_config(toml_path, &custom_families)
+            .with_context(|| format!("Object-space TOML file {:?} is invalid", toml_path))?;
+        println!("OK: {:?} is a valid object-space TOML file", toml_path);
+    }
+    Ok(())
+}
+
 /// Process provided CLI subcommands and options.
 ///
 /// # Errors
@@ -193,90 +1090,243 @@ pub fn clean_and_validate_path(path_os_str: &OsStr, path_kind: PathKind) -> Resu
 /// downloading)
 #[tokio::main]
 pub async fn cli_match(config: config::Config, cli_matches: clap::ArgMatches) -> Result<()> {
+    if cli_matches.is_present("config_check") {
+        check_config(&config)?;
+    }
+
     // Handle config subcommand first, because it doesn't need any valid configuration, and is helpful for debugging bad config!
-    if let Some(("config", _config_matches)) = cli_matches.subcommand() {
-        commands::print_config(config)?;
+    if let Some(("config", config_matches)) = cli_matches.subcommand() {
+        let show_secrets = config_matches.is_present("show_secrets")
+            || matches!(config_matches.subcommand(), Some(("get", get_matches)) if get_matches.is_present("show_secrets"));
+        commands::print_config(config, show_secrets)?;
         return Ok(());
     }
 
+    let quiet = cli_matches.is_present("quiet");
+    // Clap only accepts valid modes, and has a default
+    let progress: ProgressMode = cli_matches.value_of_t("progress").unwrap_or_default();
+
     // Derive config needed for all commands (they all interact with the database)
     let db = config.clone().try_into::<DatabaseConfig>()?.database;
-    let db_config = DatabaseApiConfig::new(db.url.clone(), db.jwt.clone())?;
+    // Catch an expired token before it causes a confusing 401 partway through
+    db.check_jwt_expiry(Utc::now())?;
+    let timeout = match cli_matches.value_of("timeout") {
+        Some(val) => val.parse::<NonZeroU64>()?,
+        None => db
+            .database_timeout_secs
+            .unwrap_or_else(|| NonZeroU64::new(datasets::DEFAULT_TIMEOUT_SECS).unwrap()),
+    };
+    let proxy = ProxyConfig::from_config(&config)?;
+    let mut db_config =
+        DatabaseApiConfig::new_with_params(db.url.clone(), db.jwt.clone(), timeout.get(), &proxy)?;
+    if let Some(attempts) = db.request_attempts {
+        if attempts == 0 {
+            bail!("Config error: [database] request_attempts must be at least 1");
+        }
+        db_config.request_attempts = attempts;
+    }
 
     // Handle all subcommands that interact with database or storage
     match cli_matches.subcommand() {
         Some(("upload", upload_matches)) => {
             let provider =
                 StorageProviderChoices::from_str(upload_matches.value_of("provider").unwrap())?;
-            let storage_config = storage::StorageConfig::new(config, provider)?;
+            let upload_settings = config
+                .clone()
+                .try_into::<UploadConfig>()
+                .context("Config error: invalid [upload] section")?
+                .upload;
+            let performance = config
+                .clone()
+                .try_into::<PerformanceConfig>()
+                .context("Config error: invalid [performance] section")?
+                .performance;
+            let multipart_threshold =
+                upload_multipart_threshold(performance.multipart_threshold_mib)?;
+            let hash_jobs = upload_hash_jobs(
+                upload_matches
+                    .value_of("hash_jobs")
+                    .map(|val| val.parse::<usize>())
+                    .transpose()?,
+                performance.hash_jobs,
+            )?;
+            let storage_config = storage::StorageConfig::new(config, provider)?.with_overrides(
+                upload_matches.value_of("bucket").map(str::to_owned),
+                upload_matches
+                    .value_of("region")
+                    .map(storage::parse_region_override)
+                    .transpose()?,
+            );
             let prefix = db.user_id_from_jwt()?.to_string();
 
             let system_id: String = upload_matches.value_of_t_or_exit::<String>("system_id");
-            let plex_path = upload_matches.value_of_os("plex_path").unwrap();
-            let utf8_plex_path = clean_and_validate_path(plex_path, PathKind::Plex)?;
-
-            let toml_path = upload_matches
-                .value_of_os("object_space_toml_path")
-                .unwrap();
-            let utf8_toml_path = clean_and_validate_path(toml_path, PathKind::ObjectSpaceToml)?;
+            let (plex_path, toml_path, file_paths) = upload_positional_paths(upload_matches)?;
+            let utf8_plex_path = plex_path
+                .map(|path| clean_and_validate_path(path, PathKind::Plex))
+                .transpose()?;
+            let utf8_toml_path = toml_path
+                .map(|path| clean_and_validate_path(path, PathKind::ObjectSpaceToml))
+                .transpose()?;
 
-            let file_paths: Vec<&OsStr> = upload_matches.values_of_os("path").unwrap().collect();
-            let mut utf8_file_paths: Vec<String> = file_paths
+            let data_extensions = upload_data_extensions(
+                upload_matches
+                    .values_of("data_ext")
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                upload_settings.data_extensions.clone(),
+            );
+            let utf8_file_paths: Vec<String> = file_paths
                 .iter()
-                .map(|os_str| clean_and_validate_path(os_str, PathKind::Data))
+                .map(|os_str| clean_and_validate_path(os_str, PathKind::Data(&data_extensions)))
                 .collect::<Result<Vec<String>>>()?;
 
-            // Collect utf8 paths to all files in any provided data folders (including subfolders)
-            let all_utf8_file_paths: Vec<String> = utf8_file_paths
-                .iter_mut()
-                .try_fold(Vec::new(), |mut acc, utf8_path| -> Result<Vec<PathBuf>> {
-                    let path = Path::new(utf8_path);
-                    let file_list: Result<Vec<PathBuf>> = match path {
-                        // WalkDir does not follow symlinks by default
-                        path if path.is_dir() => Ok(WalkDir::new(path)
-                            .into_iter()
-                            .filter_map(Result::ok)
-                            .filter(|entry| entry.file_type().is_file())
-                            .map(|entry| entry.into_path())
-                            .collect::<Vec<PathBuf>>()),
-                        path if path.is_file() => Ok(vec![path.to_path_buf()]),
-                        _ => Err(anyhow!("File path {:?} is not a directory or a file", path)),
-                    };
-                    let mut file_list = file_list?;
-                    acc.append(&mut file_list);
-                    Ok(acc)
-                })?
-                .iter()
-                .map(|pathbuf| Ok(pathbuf.as_path().to_str().ok_or_else(||
-                    anyhow!("All file/folder names must be valid UTF-8 (AWS S3 requirement). Invalid UTF-8: {:?}", pathbuf)
-                )?.to_owned()))
-                .collect::<Result<Vec<String>>>()?;
+            let path_filter = PathFilter::new(
+                upload_matches.values_of("include").into_iter().flatten(),
+                upload_matches.values_of("exclude").into_iter().flatten(),
+            )?;
+
+            let all_utf8_file_paths = collect_data_files(
+                &utf8_file_paths,
+                &path_filter,
+                DataWalkOptions {
+                    include_hidden: upload_matches.is_present("include_hidden"),
+                    follow_symlinks: upload_matches.is_present("follow_symlinks"),
+                },
+            )?;
 
-            if all_utf8_file_paths.len() > UPLOAD_MAX_FILES_ALLOWED {
-                bail!("You're trying to upload {} files (max = {}). Please tar/zip the files before uploading!", all_utf8_file_paths.len(), UPLOAD_MAX_FILES_ALLOWED);
+            let max_files = upload_max_files(
+                upload_matches
+                    .value_of("max_files")
+                    .map(|val| val.parse::<usize>())
+                    .transpose()?,
+                upload_settings.max_files,
+            )?;
+            if all_utf8_file_paths.len() > max_files {
+                bail!("You're trying to upload {} files (max = {}). Please tar/zip the files before uploading!", all_utf8_file_paths.len(), max_files);
             }
 
             // Validate that toml are readable and parseable
-            object_space::read_object_space_config(utf8_toml_path.clone())
-                .context("Unable to read TOML object-space file!")?;
+            let custom_families: Vec<String> = upload_matches
+                .values_of("apriltag_family")
+                .into_iter()
+                .flatten()
+                .map(String::from)
+                .chain(
+                    upload_settings
+                        .apriltag_families
+                        .clone()
+                        .unwrap_or_default(),
+                )
+                .collect();
+            if let Some(toml_path) = &utf8_toml_path {
+                object_space::read_object_space_config(toml_path, &custom_families)
+                    .context("Unable to read TOML object-space file!")?;
+            }
 
-            let skip_prompt = upload_matches.is_present("yes");
-            if skip_prompt {
-                println!(
-                    "Creating a dataset of {} file(s)",
-                    all_utf8_file_paths.len()
-                );
+            let processing_options = upload_matches
+                .value_of("processing_options")
+                .map(commands::parse_processing_options)
+                .transpose()?;
+            let metadata_pairs = upload_matches.values_of("meta").map_or_else(
+                || Ok(Vec::new()),
+                |values| values.map(parse_key_value).collect(),
+            )?;
+            let mut metadata = commands::metadata_from_pairs(metadata_pairs);
+            if let Some(name) = upload_matches.value_of("name") {
+                metadata.insert(commands::NAME_METADATA_KEY.to_owned(), json!(name));
+            }
+            // Reject metadata that breaks the org's conventions before
+            // anything is created
+            if let Some(schema_path) = upload_matches
+                .value_of_os("metadata_schema")
+                .map(PathBuf::from)
+                .or_else(|| upload_settings.metadata_schema.clone())
+            {
+                let schema = commands::read_metadata_schema(&schema_path)?;
+                commands::validate_metadata(&schema, &metadata)?;
+            }
+            let options = commands::UploadOptions {
+                dry_run: upload_matches.is_present("dry_run"),
+                processing_options,
+                metadata,
+                share: upload_matches.is_present("share"),
+                presign: upload_matches.is_present("presign"),
+                quiet,
+                progress,
+                created_message: upload_matches.value_of("created_message").map(String::from),
+                on_create: upload_matches.value_of("on_create").map(String::from),
+                checksum_algorithm: upload_settings.checksum_algorithm,
+                chunk_read_ahead: upload_matches.value_of_t_or_exit("chunk_read_ahead"),
+                num_parts: upload_matches
+                    .value_of("num_parts")
+                    .map(|val| val.parse::<usize>())
+                    .transpose()?,
+                error_report: upload_matches
+                    .value_of_os("error_report")
+                    .map(PathBuf::from),
+                transfer_stats: Default::default(),
+                timings: Default::default(),
+                resume: upload_matches
+                    .value_of("resume")
+                    .map(Uuid::parse_str)
+                    .transpose()?,
+                force: upload_matches.is_present("force"),
+                keep_going: upload_matches.is_present("keep_going"),
+                hash_jobs: Some(hash_jobs),
+                stdin_name: upload_matches.value_of("stdin").map(String::from),
+                multipart_threshold: Some(multipart_threshold),
+                part_size_strategy: upload_matches.value_of_t_or_exit("part_size_strategy"),
+                skip_part_check: upload_settings.verify_parts == Some(false),
+                compression: handle_optional_arg(upload_matches, "compress"),
+                compress_all: upload_matches.is_present("compress_all"),
+            };
+            // Stdin holds the data to upload, so the user can't answer a
+            // prompt there
+            let skip_prompt = upload_matches.is_present("yes") || options.stdin_name.is_some();
+            if options.dry_run || quiet {
+                // Nothing will be created or uploaded (or the user asked not
+                // to be prompted), so no need to prompt
+            } else if skip_prompt {
+                match options.resume {
+                    Some(dataset_id) => println!(
+                        "Resuming upload of {} file(s) to dataset {}",
+                        all_utf8_file_paths.len(),
+                        dataset_id
+                    ),
+                    None => println!(
+                        "Creating a dataset of {} file(s)",
+                        all_utf8_file_paths.len() + options.stdin_name.iter().count()
+                    ),
+                }
             } else {
-                println!(
-                    "This command will create a dataset with a plex, a toml, and {} data file(s):",
-                    all_utf8_file_paths.len()
-                );
-                println!(
-                    "\t{}\n\t{}\n\t{}",
-                    utf8_plex_path,
-                    utf8_toml_path,
-                    all_utf8_file_paths.join("\n\t")
-                );
+                let calibration_files = match (&utf8_plex_path, &utf8_toml_path) {
+                    (Some(_), Some(_)) => "a plex, a toml, and ",
+                    (Some(_), None) => "a plex and ",
+                    (None, Some(_)) => "a toml and ",
+                    (None, None) => "",
+                };
+                match options.resume {
+                    Some(dataset_id) => println!(
+                        "This command will upload {}{} data file(s) \
+                         to dataset {}, skipping files it already has:",
+                        calibration_files,
+                        all_utf8_file_paths.len(),
+                        dataset_id
+                    ),
+                    None => println!(
+                        "This command will create a dataset with {}{} data file(s):",
+                        calibration_files,
+                        all_utf8_file_paths.len()
+                    ),
+                }
+                for path in utf8_plex_path
+                    .iter()
+                    .chain(&utf8_toml_path)
+                    .chain(&all_utf8_file_paths)
+                {
+                    println!("\t{}", path);
+                }
                 print!("Continue? [y/n] ");
                 io::stdout().flush()?;
 
@@ -286,8 +1336,13 @@ pub async fn cli_match(config: config::Config, cli_matches: clap::ArgMatches) ->
                     return Ok(());
                 }
             }
+            if !options.dry_run {
+                if let Some(warning) = commands::versioning_warning(&storage_config).await {
+                    eprintln!("{}", warning);
+                }
+            }
 
-            commands::create_and_upload_dataset(
+            let result = commands::create_and_upload_dataset(
                 storage_config,
                 &db_config,
                 system_id,
@@ -295,15 +1350,196 @@ pub async fn cli_match(config: config::Config, cli_matches: clap::ArgMatches) ->
                 utf8_plex_path,
                 utf8_toml_path,
                 all_utf8_file_paths,
+                &options,
             )
-            .await?;
+            .await;
+            if !quiet && !options.dry_run {
+                // Shown even if uploading failed, since failed transfers
+                // still used data
+                println!(
+                    "{}",
+                    commands::transfer_summary("Uploaded", "sent", &options.transfer_stats)
+                );
+            }
+            if let (Some(path), false) =
+                (upload_matches.value_of_os("timings_json"), options.dry_run)
+            {
+                options.timings.write(Path::new(path), "upload")?;
+            }
+            result?;
+        }
+        Some(("upload-batch", batch_matches)) => {
+            let provider =
+                StorageProviderChoices::from_str(batch_matches.value_of("provider").unwrap())?;
+            let upload_settings = config
+                .clone()
+                .try_into::<UploadConfig>()
+                .context("Config error: invalid [upload] section")?
+                .upload;
+            let performance = config
+                .clone()
+                .try_into::<PerformanceConfig>()
+                .context("Config error: invalid [performance] section")?
+                .performance;
+            let multipart_threshold =
+                upload_multipart_threshold(performance.multipart_threshold_mib)?;
+            let hash_jobs = upload_hash_jobs(None, performance.hash_jobs)?;
+            let storage_config = storage::StorageConfig::new(config, provider)?;
+            let prefix = db.user_id_from_jwt()?.to_string();
+
+            let spec_path = Path::new(batch_matches.value_of_os("spec_path").unwrap());
+            let spec = commands::read_batch_upload_spec(spec_path)?;
+            let checks = BatchChecks {
+                data_extensions: upload_data_extensions(
+                    Vec::new(),
+                    upload_settings.data_extensions.clone(),
+                ),
+                max_files: upload_max_files(None, upload_settings.max_files)?,
+                custom_families: upload_settings
+                    .apriltag_families
+                    .clone()
+                    .unwrap_or_default(),
+                metadata_schema: upload_settings
+                    .metadata_schema
+                    .as_deref()
+                    .map(commands::read_metadata_schema)
+                    .transpose()?,
+            };
+            // Validate every dataset before creating any of them
+            let items = spec
+                .datasets
+                .into_iter()
+                .enumerate()
+                .map(|(index, dataset)| {
+                    let system_id = dataset.system_id.clone();
+                    prepare_batch_item(dataset, &checks).with_context(|| {
+                        format!(
+                            "Dataset {} ({}) in the batch upload spec is invalid",
+                            index + 1,
+                            system_id
+                        )
+                    })
+                })
+                .collect::<Result<Vec<commands::BatchUploadItem>>>()?;
+
+            if batch_matches.is_present("dry_run") {
+                for (index, item) in items.into_iter().enumerate() {
+                    println!("Dataset {} ({}):", index + 1, item.system_id);
+                    let options = commands::UploadOptions {
+                        dry_run: true,
+                        metadata: item.metadata,
+                        multipart_threshold: Some(multipart_threshold),
+                        ..Default::default()
+                    };
+                    commands::create_and_upload_dataset(
+                        storage_config.clone(),
+                        &db_config,
+                        item.system_id,
+                        &prefix,
+                        Some(item.plex_path),
+                        Some(item.object_space_path),
+                        item.file_paths,
+                        &options,
+                    )
+                    .await?;
+                    println!();
+                }
+                return Ok(());
+            }
+
+            if quiet {
+                // The user asked not to be prompted
+            } else if batch_matches.is_present("yes") {
+                println!("Creating {} dataset(s)", items.len());
+            } else {
+                println!("This command will create {} dataset(s):", items.len());
+                for item in &items {
+                    println!(
+                        "\t{}: {}, {}, and {} data file(s)",
+                        item.system_id,
+                        item.plex_path,
+                        item.object_space_path,
+                        item.file_paths.len()
+                    );
+                }
+                print!("Continue? [y/n] ");
+                io::stdout().flush()?;
+
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                if !input.to_lowercase().starts_with('y') {
+                    return Ok(());
+                }
+            }
+            if let Some(warning) = commands::versioning_warning(&storage_config).await {
+                eprintln!("{}", warning);
+            }
+
+            let labels: Vec<String> = items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| format!("Dataset {} ({})", index + 1, item.system_id))
+                .collect();
+            let options = commands::UploadOptions {
+                quiet,
+                progress,
+                // Outcomes (including dataset ids) are reported once all
+                // datasets finish
+                created_message: Some(String::new()),
+                checksum_algorithm: upload_settings.checksum_algorithm,
+                hash_jobs: Some(hash_jobs),
+                multipart_threshold: Some(multipart_threshold),
+                skip_part_check: upload_settings.verify_parts == Some(false),
+                ..Default::default()
+            };
+            let outcomes = commands::upload_datasets(
+                storage_config,
+                &db_config,
+                &prefix,
+                items,
+                &options,
+                batch_matches.value_of_t_or_exit("max_concurrent_datasets"),
+            )
+            .await;
+
+            let total = outcomes.len();
+            let mut failed = 0;
+            for (label, outcome) in labels.iter().zip(outcomes) {
+                match outcome {
+                    commands::BatchUploadOutcome::Uploaded(dataset_id) => {
+                        println!("{}: uploaded {}", label, dataset_id)
+                    }
+                    commands::BatchUploadOutcome::UploadFailed(dataset_id, error) => {
+                        failed += 1;
+                        println!(
+                            "{}: failed after creating {}: {:#}",
+                            label, dataset_id, error
+                        );
+                    }
+                    commands::BatchUploadOutcome::CreateFailed(error) => {
+                        failed += 1;
+                        println!("{}: failed: {:#}", label, error);
+                    }
+                }
+            }
+            if !quiet {
+                println!(
+                    "{}",
+                    commands::transfer_summary("Uploaded", "sent", &options.transfer_stats)
+                );
+            }
+            if failed > 0 {
+                bail!("{} of {} dataset(s) failed to upload", failed, total);
+            }
         }
         Some(("ls", ls_matches)) => {
             // For optional arguments, if they're missing (ArgumentNotFound)
             // treat it as Option::None. Any other error should cause an exit
             // and error message.
-            let after_date: Option<NaiveDate> = handle_optional_arg(ls_matches, "after_date");
-            let before_date: Option<NaiveDate> = handle_optional_arg(ls_matches, "before_date");
+            let after_date: Option<CreatedDateBound> =
+                handle_optional_arg(ls_matches, "after_date");
+            let before_date: Option<CreatedDateBound> =
+                handle_optional_arg(ls_matches, "before_date");
 
             // Validation to ensure before and after date bounds are sane
             if let (Some(before), Some(after)) = (before_date, after_date) {
@@ -319,18 +1555,185 @@ pub async fn cli_match(config: config::Config, cli_matches: clap::ArgMatches) ->
                 }
             }
 
-            // TODO: Implement metadata CLI input
-            // Related to
-            // - https://gitlab.com/tangram-vision/oss/bolster/-/issues/1
-            // - https://gitlab.com/tangram-vision/oss/bolster/-/issues/4
+            let metadata = ls_matches.values_of("metadata").map_or_else(
+                || Ok(Vec::new()),
+                |values| values.map(parse_key_value).collect(),
+            )?;
 
-            let dataset_id: Option<Uuid> = handle_optional_arg(ls_matches, "dataset_uuid");
+            let dataset_id: Option<Uuid> = if ls_matches.is_present("interactive") {
+                ensure_interactive(quiet)?;
+                Some(pick_dataset(&db_config, io::stdin().lock(), io::stdout()).await?)
+            } else {
+                handle_optional_arg(ls_matches, "dataset_uuid")
+            };
             let system_id: Option<String> = handle_optional_arg(ls_matches, "system_id");
             let limit: Option<usize> = handle_optional_arg(ls_matches, "limit");
             let offset: Option<usize> = handle_optional_arg(ls_matches, "offset");
 
-            let order: Option<DatasetOrdering> = handle_optional_arg(ls_matches, "order");
+            let order: Option<&str> = ls_matches.value_of("order");
+            let tag: Option<String> = handle_optional_arg(ls_matches, "tag");
+            let name_contains: Option<String> = handle_optional_arg(ls_matches, "name_contains");
+            let format = output_format(ls_matches, &config)?;
+
+            // If user is listing a single dataset, stream its files a page at
+            // a time, so datasets with many files aren't held in memory...
+            if let Some(dataset_id) = dataset_id {
+                let get_params = DatasetGetRequest {
+                    dataset_id: Some(dataset_id),
+                    system_id,
+                    before_date,
+                    after_date,
+                    tag,
+                    metadata,
+                    name_contains,
+                    ..Default::default()
+                };
+                if commands::list_dataset_summaries(&db_config, &get_params)
+                    .await?
+                    .is_empty()
+                {
+                    if format == OutputFormat::Table {
+                        println!("No datasets found!");
+                    } else {
+                        // Keep stdout parseable
+                        eprintln!("No datasets found!");
+                        RecordWriter::new(format, FILE_RECORD_KEYS).finish(&mut io::stdout())?;
+                    }
+                    return Ok(());
+                }
+                // --limit has a default for datasets, but files are all shown
+                // unless it's given explicitly
+                let file_limit = if ls_matches.occurrences_of("limit") > 0 {
+                    limit
+                } else {
+                    None
+                };
+                let file_order = order.map(FileOrdering::from_str).transpose()?;
+                let mut files = Box::pin(commands::stream_files(
+                    &db_config,
+                    dataset_id,
+                    file_order,
+                    file_limit,
+                    offset.unwrap_or(0),
+                ));
+                if format != OutputFormat::Table {
+                    let stdout = io::stdout();
+                    let mut writer = RecordWriter::new(format, FILE_RECORD_KEYS);
+                    while let Some(f) = files.try_next().await? {
+                        writer.write(
+                            &mut stdout.lock(),
+                            &json!({
+                                "file_id": f.file_id,
+                                "created_date": record_datetime(&f.created_date),
+                                "modified_time": f.modified_time().as_ref().map(record_datetime),
+                                "filesize": f.filesize,
+                                "url": f.url,
+                            }),
+                        )?;
+                        stdout.lock().flush()?;
+                    }
+                    writer.finish(&mut stdout.lock())?;
+                    return Ok(());
+                }
+                let mut any_files = false;
+                while let Some(f) = files.try_next().await? {
+                    if !any_files {
+                        any_files = true;
+                        println!("Files in dataset {}:\n", dataset_id);
+                        // Rows are printed as they arrive, so column widths
+                        // can't be measured from the data
+                        println!(
+                            "{}",
+                            bold(
+                                &format!(
+                                    "{:<32} {:<24} {:<12} URL",
+                                    "Created Datetime", "Modified Datetime", "Filesize",
+                                ),
+                                use_color()
+                            )
+                        );
+                    }
+                    println!(
+                        "{:<32} {:<24} {:<12} {}",
+                        f.created_date.to_string(),
+                        f.modified_time().map_or_else(
+                            || "-".to_owned(),
+                            |mtime| mtime.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+                        ),
+                        Byte::from_bytes(f.filesize as u128)
+                            .get_appropriate_unit(false)
+                            .to_string(),
+                        f.url,
+                    );
+                }
+                if !any_files {
+                    println!("No files found in dataset {}", dataset_id);
+                }
+                return Ok(());
+            }
+
+            // ... or search files across all datasets ...
+            if ls_matches.is_present("files_only") {
+                let file_order = order.map(FileOrdering::from_str).transpose()?;
+                let files = datasets::files_search(
+                    &db_config,
+                    name_contains.as_deref(),
+                    file_order.as_ref(),
+                    ls_matches.value_of_t_or_exit("limit"),
+                    offset.unwrap_or(0),
+                )
+                .await?;
+                if format != OutputFormat::Table {
+                    let stdout = io::stdout();
+                    let mut writer = RecordWriter::new(format, FILE_SEARCH_RECORD_KEYS);
+                    for f in files {
+                        writer.write(
+                            &mut stdout.lock(),
+                            &json!({
+                                "dataset_id": f.dataset_id,
+                                "file_id": f.file_id,
+                                "path": commands::display_path(&f),
+                                "created_date": record_datetime(&f.created_date),
+                                "filesize": f.filesize,
+                                "url": f.url,
+                            }),
+                        )?;
+                    }
+                    writer.finish(&mut stdout.lock())?;
+                } else if files.is_empty() {
+                    println!("No files found!");
+                } else {
+                    let mut table = Table::new(vec![
+                        ("Dataset UUID", Align::Left),
+                        ("Path", Align::Left),
+                        ("Created Datetime", Align::Left),
+                        ("Filesize", Align::Right),
+                    ]);
+                    for f in files {
+                        table.add_row(vec![
+                            f.dataset_id.to_string(),
+                            commands::display_path(&f),
+                            f.created_date.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                            Byte::from_bytes(f.filesize as u128)
+                                .get_appropriate_unit(false)
+                                .to_string(),
+                        ]);
+                    }
+                    print!("{}", table.render(use_color()));
+                }
+                return Ok(());
+            }
 
+            let order = order
+                .map(|order| {
+                    DatasetOrdering::from_str(order).map_err(|_| {
+                        anyhow!(
+                            "--order-by {} only applies to the files of a dataset (with --uuid)",
+                            order
+                        )
+                    })
+                })
+                .transpose()?;
             let get_params = DatasetGetRequest {
                 dataset_id,
                 system_id,
@@ -339,94 +1742,415 @@ pub async fn cli_match(config: config::Config, cli_matches: clap::ArgMatches) ->
                 order,
                 limit,
                 offset,
+                tag,
+                metadata,
+                name_contains,
             };
 
-            let datasets = commands::list_datasets(&db_config, &get_params).await?;
+            // Summaries are aggregated by the datasets API, so they're much
+            // cheaper to fetch than datasets with all of their files. With
+            // --all, pages are streamed so records can be printed as each
+            // page arrives.
+            let all = ls_matches.is_present("all");
+            let summaries: BoxStream<'_, Result<DatasetSummary>> =
+                match (ls_matches.is_present("summary"), all) {
+                    (true, true) => {
+                        commands::stream_all_dataset_summaries(&db_config, get_params).boxed()
+                    }
+                    (true, false) => {
+                        stream::once(commands::list_dataset_summaries(&db_config, &get_params))
+                            .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+                            .try_flatten()
+                            .boxed()
+                    }
+                    (false, true) => commands::stream_all_datasets(&db_config, get_params)
+                        .map_ok(DatasetSummary::from)
+                        .boxed(),
+                    (false, false) => {
+                        stream::once(commands::list_datasets(&db_config, &get_params))
+                            .map_ok(|page| {
+                                stream::iter(page.into_iter().map(DatasetSummary::from).map(Ok))
+                            })
+                            .try_flatten()
+                            .boxed()
+                    }
+                };
 
-            if datasets.is_empty() {
+            // ... otherwise show a row per dataset
+            if format != OutputFormat::Table {
+                let stdout = io::stdout();
+                let mut writer = RecordWriter::new(format, DATASET_RECORD_KEYS);
+                let mut summaries = summaries;
+                while let Some(d) = summaries.try_next().await? {
+                    writer.write(
+                        &mut stdout.lock(),
+                        &json!({
+                            "dataset_id": d.dataset_id,
+                            "system_id": d.system_id,
+                            "name": commands::name_from_metadata(&d.metadata),
+                            "created_date": record_datetime(&d.created_date),
+                            "file_count": d.files.file_count,
+                            "total_filesize": d.files.total_filesize,
+                        }),
+                    )?;
+                    // Flush each record, so output piped into other tools
+                    // arrives as it's fetched
+                    stdout.lock().flush()?;
+                }
+                writer.finish(&mut stdout.lock())?;
+                return Ok(());
+            }
+            let summaries: Vec<DatasetSummary> = summaries.try_collect().await?;
+            if summaries.is_empty() {
                 println!("No datasets found!");
             } else {
-                // If user is listing a single dataset, show its files...
-                if let Some(dataset_id) = dataset_id {
-                    if datasets[0].files.is_empty() {
-                        println!("No files found in dataset {}", dataset_id);
-                    } else {
-                        println!("Files in dataset {}:\n", dataset_id);
-                        println!("{:<32} {:<12} URL", "Created Datetime", "Filesize",);
-                        for f in &datasets[0].files {
-                            println!(
-                                "{:<32} {:<12} {}",
-                                f.created_date.to_string(),
-                                Byte::from_bytes(f.filesize as u128)
-                                    .get_appropriate_unit(false)
-                                    .to_string(),
-                                f.url,
-                            );
-                        }
-                    }
-                }
-                // ... otherwise show just datasets
-                else {
-                    println!(
-                        "{:<40} {:<40.38} {:<26} {:<8} {:<12}",
-                        "UUID", "System ID", "Created Datetime", "# Files", "Filesize",
-                    );
-                    for d in datasets {
-                        println!(
-                            "{:<40} {:<40.38} {:<26} {:<8} {:<12}",
-                            d.dataset_id.to_string(),
-                            d.system_id,
-                            d.created_date.format("%Y-%m-%d %H:%M:%S UTC"),
-                            d.files.len(),
-                            Byte::from_bytes(
-                                d.files.iter().fold(0, |acc, x| acc + x.filesize as u128)
-                            )
+                let mut table = Table::new(vec![
+                    ("UUID", Align::Left),
+                    ("System ID", Align::Left),
+                    ("Name", Align::Left),
+                    ("Created Datetime", Align::Left),
+                    ("# Files", Align::Right),
+                    ("Filesize", Align::Right),
+                ]);
+                for d in summaries {
+                    let name = commands::name_from_metadata(&d.metadata)
+                        .unwrap_or("")
+                        .to_owned();
+                    table.add_row(vec![
+                        d.dataset_id.to_string(),
+                        d.system_id,
+                        name,
+                        d.created_date.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                        d.files.file_count.to_string(),
+                        Byte::from_bytes(d.files.total_filesize as u128)
                             .get_appropriate_unit(false)
-                            .to_string()
-                        );
-                    }
+                            .to_string(),
+                    ]);
                 }
+                print!("{}", table.render(use_color()));
             }
         }
         Some(("download", download_matches)) => {
-            // Safe to unwrap because argument is required
-            let dataset_id: Uuid = download_matches.value_of_t_or_exit("dataset_uuid");
-            let prefixes = download_matches
+            let mut prefixes = download_matches
                 .values_of("prefix")
                 .map_or_else(Vec::new, |values| {
                     values.map(|s| s.to_owned()).collect::<Vec<String>>()
                 });
-            let uploaded_files = commands::list_files(&db_config, dataset_id, prefixes).await?;
+            let dataset_id: Uuid = if download_matches.is_present("interactive") {
+                // Without a dataset UUID, the first positional argument is a
+                // prefix
+                if let Some(prefix) = download_matches.value_of("dataset_uuid") {
+                    prefixes.insert(0, prefix.to_owned());
+                }
+                ensure_interactive(quiet)?;
+                pick_dataset(&db_config, io::stdin().lock(), io::stdout()).await?
+            } else if let Some(name) = download_matches.value_of("name") {
+                // Without a dataset UUID, the first positional argument is a
+                // prefix
+                if let Some(prefix) = download_matches.value_of("dataset_uuid") {
+                    prefixes.insert(0, prefix.to_owned());
+                }
+                commands::resolve_dataset_name(&db_config, name).await?
+            } else {
+                // Safe to unwrap because argument is required without
+                // --interactive or --name
+                download_matches.value_of_t_or_exit("dataset_uuid")
+            };
+            let options = commands::DownloadOptions {
+                no_verify: download_matches.is_present("no_verify"),
+                output_dir: download_matches.value_of_os("output").map(PathBuf::from),
+                sequential_writes: download_matches.is_present("sequential_writes"),
+                quiet,
+                progress,
+                keep_going: download_matches.is_present("keep_going"),
+                error_report: download_matches
+                    .value_of_os("error_report")
+                    .map(PathBuf::from),
+                transfer_stats: Default::default(),
+                timings: Default::default(),
+                concurrency: Some(download_matches.value_of_t_or_exit("concurrency")),
+                output_template: download_matches
+                    .value_of("output_template")
+                    .map(String::from),
+                no_decompress: download_matches.is_present("no_decompress"),
+            };
+            let dry_run = download_matches.is_present("dry_run");
+            if let (Some(dir), false) = (&options.output_dir, dry_run) {
+                commands::prepare_output_dir(dir)?;
+            }
+            let uploaded_files = options
+                .timings
+                .time(
+                    "list_files",
+                    None,
+                    commands::list_files(&db_config, dataset_id, prefixes),
+                )
+                .await?;
+
+            if options.output_template.is_some() {
+                commands::check_unique_destinations(&uploaded_files, &options)?;
+            }
 
             // Based on url from database, find which StorageProvider's config to use
-            let provider = StorageProviderChoices::from_url(&uploaded_files[0].url)?;
+            let provider = StorageProviderChoices::from_url(&uploaded_files[0].url, &config)?;
             let storage_config = StorageConfig::new(config, provider)?;
 
             let total_filesize = uploaded_files.iter().fold(0, |acc, f| acc + f.filesize);
             let number_of_files = uploaded_files.len();
+            let concurrency = options
+                .concurrency
+                .unwrap_or(commands::MAX_FILES_DOWNLOADING_CONCURRENTLY);
+            if concurrency > number_of_files
+                && concurrency > commands::MAX_FILES_DOWNLOADING_CONCURRENTLY
+            {
+                eprintln!(
+                    "Warning: --concurrency {} is more than the {} file(s) to download, \
+                     so at most {} file(s) will download at a time",
+                    concurrency, number_of_files, number_of_files
+                );
+            }
 
-            println!(
-                "Downloading {} files, total {}",
-                number_of_files,
-                Byte::from_bytes(total_filesize as u128).get_appropriate_unit(false)
-            );
+            if dry_run {
+                let plan = commands::plan_download(&uploaded_files, &options)?;
+                let available_space = commands::available_download_space(
+                    options
+                        .output_dir
+                        .as_deref()
+                        .unwrap_or_else(|| Path::new(".")),
+                )?;
+                commands::print_download_plan(&plan, available_space);
+                return Ok(());
+            }
+            if !quiet {
+                println!(
+                    "Downloading {} files, total {}",
+                    number_of_files,
+                    Byte::from_bytes(total_filesize as u128).get_appropriate_unit(false)
+                );
+            }
 
-            for file in uploaded_files.iter() {
-                let filepath = file.filepath_from_url()?;
+            let (uploaded_files, skipped) =
+                confirm_overwrites(uploaded_files, &options, io::stdin().lock(), io::stdout())?;
+            // On Ctrl-C, stop downloading and clean up partially downloaded files
+            let interrupted = async {
+                if tokio::signal::ctrl_c().await.is_err() {
+                    // Without a signal handler, downloads can't be interrupted
+                    future::pending::<()>().await;
+                }
+            };
+            let result =
+                commands::download_files(storage_config, uploaded_files, &options, interrupted)
+                    .await;
+            if !quiet {
+                // Shown even if downloading failed, since failed transfers
+                // still used data
+                println!(
+                    "{}",
+                    commands::transfer_summary("Downloaded", "received", &options.transfer_stats)
+                );
+            }
+            if let Some(path) = download_matches.value_of_os("timings_json") {
+                options.timings.write(Path::new(path), "download")?;
+            }
+            if !skipped.is_empty() {
+                println!(
+                    "Skipped {} existing file(s) instead of overwriting them:",
+                    skipped.len()
+                );
+                for path in &skipped {
+                    println!("\t{}", path.display());
+                }
+            }
+            result?;
+        }
+        Some(("export", export_matches)) => {
+            let dataset_id: Uuid = export_matches.value_of_t_or_exit("dataset_uuid");
+            // Safe to unwrap because argument is required
+            let output = PathBuf::from(export_matches.value_of_os("output").unwrap());
+            let format: commands::ExportFormat = export_matches.value_of_t_or_exit("format");
+            let options = commands::DownloadOptions {
+                no_verify: export_matches.is_present("no_verify"),
+                quiet,
+                ..Default::default()
+            };
+            let get_params = DatasetGetRequest {
+                dataset_id: Some(dataset_id),
+                ..Default::default()
+            };
+            let dataset = match commands::list_datasets(&db_config, &get_params)
+                .await?
+                .into_iter()
+                .next()
+            {
+                Some(dataset) => dataset,
+                None => bail!("Dataset {} not found", dataset_id),
+            };
+            let uploaded_files = commands::list_files(&db_config, dataset_id, Vec::new()).await?;
 
-                // TODO: add --force flag to skip prompt
-                if filepath.exists() {
-                    print!("Overwrite file: {} ? [y/n]", filepath.as_path().display());
-                    io::stdout().flush()?;
+            // Based on url from database, find which StorageProvider's config to use
+            let provider = StorageProviderChoices::from_url(&uploaded_files[0].url, &config)?;
+            let storage_config = StorageConfig::new(config, provider)?;
+            if !quiet {
+                println!(
+                    "Exporting {} files, total {}",
+                    uploaded_files.len(),
+                    Byte::from_bytes(uploaded_files.iter().map(|f| f.filesize as u128).sum())
+                        .get_appropriate_unit(false)
+                );
+            }
+            // On Ctrl-C, stop downloading and clean up the staged files
+            let interrupted = async {
+                if tokio::signal::ctrl_c().await.is_err() {
+                    future::pending::<()>().await;
+                }
+            };
+            commands::export_dataset(
+                storage_config,
+                &dataset,
+                uploaded_files,
+                &output,
+                format,
+                &options,
+                interrupted,
+            )
+            .await?;
+            if !quiet {
+                println!("Exported dataset {} to {}", dataset_id, output.display());
+            }
+        }
+        Some(("verify", verify_matches)) => {
+            let dataset_id: Uuid = verify_matches.value_of_t_or_exit("dataset_uuid");
+            let uploaded_files = commands::list_files(&db_config, dataset_id, Vec::new()).await?;
 
-                    let mut input = String::new();
-                    io::stdin().read_line(&mut input)?;
-                    if !input.to_lowercase().starts_with('y') {
-                        return Ok(());
-                    }
+            // Based on url from database, find which StorageProvider's config to use
+            let provider = StorageProviderChoices::from_url(&uploaded_files[0].url, &config)?;
+            let storage_config = StorageConfig::new(config, provider)?;
+
+            let results = commands::verify_files(&storage_config, &uploaded_files).await?;
+            let count = |is_match: fn(&commands::VerifyStatus) -> bool| {
+                results
+                    .iter()
+                    .filter(|(_, status)| is_match(status))
+                    .count()
+            };
+            let ok = count(|status| *status == commands::VerifyStatus::Ok);
+            let missing = count(|status| *status == commands::VerifyStatus::Missing);
+            let mismatched = results.len() - ok - missing;
+            for (path, status) in results.iter() {
+                if *status != commands::VerifyStatus::Ok {
+                    println!("{}: {}", path, status);
+                }
+            }
+            println!("{} OK, {} missing, {} mismatched", ok, missing, mismatched);
+            if ok < results.len() {
+                bail!(
+                    "{} of {} file(s) in dataset {} failed verification",
+                    results.len() - ok,
+                    results.len(),
+                    dataset_id
+                );
+            }
+        }
+        Some(("notify-complete", notify_matches)) => {
+            let dataset_id: Uuid = notify_matches.value_of_t_or_exit("dataset_uuid");
+            let processing_options = notify_matches
+                .value_of("processing_options")
+                .map(commands::parse_processing_options)
+                .transpose()?;
+            let outcome = commands::notify_upload_complete(
+                &db_config,
+                dataset_id,
+                notify_matches.value_of("plex"),
+                notify_matches.value_of("object_space"),
+                processing_options,
+            )
+            .await?;
+            match outcome {
+                datasets::NotifyOutcome::Notified => {
+                    println!("Notified backend that dataset {} is uploaded", dataset_id)
+                }
+                datasets::NotifyOutcome::AlreadyNotified => println!(
+                    "Backend was already notified that dataset {} is uploaded",
+                    dataset_id
+                ),
+            }
+        }
+        Some(("head", head_matches)) => {
+            let dataset_id: Uuid = head_matches.value_of_t_or_exit("dataset_uuid");
+            let path = head_matches.value_of("path").unwrap();
+            let uploaded_files =
+                commands::list_files(&db_config, dataset_id, vec![path.to_owned()]).await?;
+            let uploaded_file = commands::find_file(&uploaded_files, path)
+                .ok_or_else(|| anyhow!("Dataset {} has no file {}", dataset_id, path))?;
+
+            // Based on url from database, find which StorageProvider's config to use
+            let provider = StorageProviderChoices::from_url(&uploaded_file.url, &config)?;
+            let storage_config = StorageConfig::new(config, provider)?;
+
+            let stored = storage::head_object(
+                &storage_config,
+                &uploaded_file.url,
+                Some(&uploaded_file.version),
+            )
+            .await?
+            .ok_or_else(|| {
+                anyhow!(
+                    "File {} (version {}) is in dataset {} but missing from cloud storage",
+                    path,
+                    uploaded_file.version,
+                    dataset_id
+                )
+            })?;
+            for line in commands::stored_object_lines(&stored) {
+                println!("{}", line);
+            }
+        }
+        Some(("diff", diff_matches)) => {
+            let dataset_id: Uuid = diff_matches.value_of_t_or_exit("dataset_uuid");
+            let local_dir = Path::new(diff_matches.value_of_os("local_dir").unwrap());
+            let uploaded_files = commands::list_files(&db_config, dataset_id, Vec::new()).await?;
+            let diff = commands::diff_dataset(&uploaded_files, local_dir).await?;
+            for path in diff.only_local.iter() {
+                println!("only local: {}", path);
+            }
+            for path in diff.only_remote.iter() {
+                println!("only remote: {}", path);
+            }
+            for path in diff.differing.iter() {
+                println!("differs: {}", path);
+            }
+            println!(
+                "{} only local, {} only remote, {} differing",
+                diff.only_local.len(),
+                diff.only_remote.len(),
+                diff.differing.len()
+            );
+        }
+        Some(("tag", tag_matches)) => {
+            let (tags, dataset_id) = match tag_matches.subcommand() {
+                Some(("add", add_matches)) => {
+                    let dataset_id: Uuid = add_matches.value_of_t_or_exit("dataset_uuid");
+                    let tag: String = add_matches.value_of_t_or_exit("tag");
+                    (
+                        commands::add_tag(&db_config, dataset_id, tag).await?,
+                        dataset_id,
+                    )
                 }
+                Some(("rm", rm_matches)) => {
+                    let dataset_id: Uuid = rm_matches.value_of_t_or_exit("dataset_uuid");
+                    let tag: String = rm_matches.value_of_t_or_exit("tag");
+                    (
+                        commands::remove_tag(&db_config, dataset_id, tag).await?,
+                        dataset_id,
+                    )
+                }
+                _ => unreachable!("No matching tag subcommand!"),
+            };
+            if tags.is_empty() {
+                println!("Dataset {} has no tags", dataset_id);
+            } else {
+                println!("Tags for dataset {}: {}", dataset_id, tags.join(", "));
             }
-            commands::download_files(storage_config, uploaded_files).await?;
         }
         _ => {
             // Arguments are required by default (in Clap).
@@ -438,11 +2162,13 @@ pub async fn cli_match(config: config::Config, cli_matches: clap::ArgMatches) ->
 }
 
 /// Configures CLI arguments and help messages.
-pub fn cli_config() -> Result<clap::ArgMatches> {
-    // Can't get default enum variant's &'static str, so own it here
-    let default_storage_provider = StorageProviderChoices::default();
+pub fn cli_app() -> App<'static> {
+    let default_storage_provider: &'static str = StorageProviderChoices::default().into();
+    let show_secrets_arg = Arg::new("show_secrets")
+        .about("Show the database JWT and storage keys instead of masking them")
+        .long("show-secrets");
 
-    let cli_app = App::new("bolster")
+    App::new("bolster")
         .setting(AppSettings::ArgRequiredElseHelp)
         .version(crate_version!())
         .about(crate_description!())
@@ -455,6 +2181,43 @@ pub fn cli_config() -> Result<clap::ArgMatches> {
                 .about("Set a custom config file")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("config_check")
+                .long("config-check")
+                .about("Check that the config is usable (without contacting any server) \
+                        before running the command, and abort if it isn't"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .about("Hide progress bars and skip prompts (for scripts and CI)"),
+        )
+        .arg(
+            Arg::new("progress")
+                .long("progress")
+                .alias("progress-style")
+                .value_name("STYLE")
+                .about("How to show upload and download progress: bars, plain \
+                        lines of text printed every few seconds (for CI logs), \
+                        or none (hidden by --quiet)")
+                .possible_values(ProgressMode::VARIANTS)
+                .default_value(ProgressMode::Bar.as_ref()),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("SECS")
+                .about("Seconds to wait for each database request before it times out, \
+                        overriding database_timeout_secs in the [database] config \
+                        (default 30, must be at least 1)")
+                .takes_value(true)
+                .validator(|val| match val.parse::<u64>() {
+                    Ok(0) => Err("must be at least 1".to_owned()),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                }),
+        )
         .subcommand(
             App::new("upload")
                 .about("Upload files, creating a new remote dataset")
@@ -469,44 +2232,397 @@ pub fn cli_config() -> Result<clap::ArgMatches> {
                         .takes_value(true)
                 )
                 .arg(
-                    Arg::new("plex_path")
-                        .about("Path to .plex file describing system's sensor \
-                                configuration.")
-                        .value_name("PLEX_PATH")
-                        .required(true)
+                    Arg::new("plex_path")
+                        .about("Path to .plex file describing system's sensor \
+                                configuration (left out with --no-plex).")
+                        .value_name("PLEX_PATH")
+                        .required_unless_present_any(["no_plex", "no_object_space"])
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("object_space_toml_path")
+                        .about("Path to .toml file describing object space (left \
+                                out with --no-object-space).")
+                        .value_name("OBJECT_SPACE_TOML_PATH")
+                        .required_unless_present_any(["no_plex", "no_object_space"])
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("no_plex")
+                        .about("Upload without a plex (e.g. raw data not meant for \
+                                calibration). The dataset's metadata is tagged \
+                                \"uncalibrated\".")
+                        .long("no-plex")
+                )
+                .arg(
+                    Arg::new("no_object_space")
+                        .about("Upload without an object-space TOML file. The \
+                                dataset's metadata is tagged \"uncalibrated\".")
+                        .long("no-object-space")
+                )
+                .arg(
+                    Arg::new("apriltag_family")
+                        .about("Accept this custom AprilTag family in the \
+                                object-space TOML file (may be repeated), in \
+                                addition to apriltag_families in the [upload] \
+                                config")
+                        .long("apriltag-family")
+                        .value_name("FAMILY")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                )
+                .arg(
+                    Arg::new("path")
+                        .about("Path to .bag file (where topic names of data \
+                                streams must match component names in the plex) \
+                                or path(s) to folder(s) containing data (folder \
+                                names must match component names in the plex).")
+                        .value_name("PATH")
+                        .required_unless_present_any(["no_plex", "no_object_space", "stdin"])
+                        .takes_value(true)
+                        .multiple(true)
+                )
+                .arg(
+                    Arg::new("data_ext")
+                        .about("Accept data files ending in this extension (may be \
+                                repeated), overriding data_extensions in the \
+                                [upload] config (default bag)")
+                        .long("data-ext")
+                        .value_name("EXT")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                )
+                .arg(
+                    Arg::new("include")
+                        .about("Only upload files in data folders whose path \
+                                (relative to the folder) matches this glob (may \
+                                be repeated)")
+                        .long("include")
+                        .value_name("GLOB")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .validator(|val| Glob::new(val).map(|_| ()))
+                )
+                .arg(
+                    Arg::new("exclude")
+                        .about("Skip files in data folders whose path (relative \
+                                to the folder) matches this glob, even if \
+                                included (may be repeated)")
+                        .long("exclude")
+                        .value_name("GLOB")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .validator(|val| Glob::new(val).map(|_| ()))
+                )
+                .arg(
+                    Arg::new("include_hidden")
+                        .about("Also upload hidden files and folders (whose names \
+                                start with a period, e.g. .git) in data folders, \
+                                which are skipped by default")
+                        .long("include-hidden")
+                )
+                .arg(
+                    Arg::new("follow_symlinks")
+                        .about("Follow symlinks in data folders, uploading the files \
+                                they point to at the symlink's path (symlinks are \
+                                skipped by default)")
+                        .long("follow-symlinks")
+                )
+                .arg(
+                    Arg::new("yes")
+                        .about("Automatic yes to prompt that lists files to upload")
+                        .short('y')
+                        .long("yes")
+                )
+                .arg(
+                    Arg::new("dry_run")
+                        .about("List the files, cloud storage keys, and upload \
+                                strategies that would be used, without creating \
+                                a dataset or uploading anything")
+                        .long("dry-run")
+                )
+                .arg(
+                    Arg::new("bucket")
+                        .about("Upload to this bucket instead of the configured \
+                                storage provider's bucket (e.g. a staging bucket)")
+                        .long("bucket")
+                        .value_name("BUCKET")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("region")
+                        .about("Upload to this AWS region (e.g. us-east-2) instead \
+                                of the configured storage provider's region, or to \
+                                an S3-compatible endpoint with custom:ENDPOINT")
+                        .long("region")
+                        .value_name("REGION")
+                        .takes_value(true)
+                        .validator(|val| storage::parse_region_override(val).map(|_| ()))
+                )
+                .arg(
+                    Arg::new("meta")
+                        .about("Attach metadata to the dataset (may be repeated). \
+                                Numbers and booleans are stored as such, other \
+                                values as strings. Query with `ls --metadata`.")
+                        .long("meta")
+                        .value_name("KEY=VALUE")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .validator(|val| parse_key_value(val).map(|_| ()))
+                )
+                .arg(
+                    Arg::new("name")
+                        .about("Give the dataset a human-readable name, which can be \
+                                used instead of its UUID with `download --name` and \
+                                searched with `ls --name-contains`")
+                        .long("name")
+                        .value_name("NAME")
+                        .takes_value(true)
+                        .conflicts_with("resume")
+                )
+                .arg(
+                    Arg::new("metadata_schema")
+                        .about("Check --meta values against this JSON Schema \
+                                before uploading, overriding metadata_schema in \
+                                the [upload] config")
+                        .long("metadata-schema")
+                        .value_name("SCHEMA_PATH")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("share")
+                        .about("After uploading, print the command to download \
+                                each file (for handing the dataset off to others)")
+                        .long("share")
+                )
+                .arg(
+                    Arg::new("presign")
+                        .about("After uploading, print a presigned url for each \
+                                file that can be downloaded without credentials \
+                                (valid for 7 days)")
+                        .long("presign")
+                )
+                .arg(
+                    Arg::new("created_message")
+                        .about("Message to print when the dataset is created, \
+                                where {uuid} is replaced by the dataset's UUID \
+                                (an empty message prints nothing)")
+                        .long("created-message")
+                        .value_name("TEMPLATE")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("on_create")
+                        .about("Shell command to run when the dataset is created, \
+                                with the dataset's UUID as its last argument \
+                                (e.g. to record it in a lab database)")
+                        .long("on-create")
+                        .value_name("CMD")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("resume")
+                        .about("Resume an upload that failed partway by uploading to \
+                                this existing dataset instead of creating one, \
+                                skipping files it already has (same path, size, and \
+                                checksum)")
+                        .long("resume")
+                        .value_name("DATASET_UUID")
+                        .takes_value(true)
+                        .validator(|val| Uuid::parse_str(val).map(|_| ()))
+                        .conflicts_with_all(&["dry_run", "meta", "created_message", "on_create"])
+                )
+                .arg(
+                    Arg::new("force")
+                        .about("When resuming, also upload files that differ from the \
+                                dataset's file at the same path (as new versions), \
+                                instead of warning and skipping them")
+                        .long("force")
+                        .requires("resume")
+                )
+                .arg(
+                    Arg::new("keep_going")
+                        .about("Keep uploading the remaining files after a file fails \
+                                (the dataset still isn't processed until the failed \
+                                files are uploaded with --resume)")
+                        .long("keep-going")
+                )
+                .arg(
+                    Arg::new("stdin")
+                        .about("Also upload everything read from stdin as a data file \
+                                at this path in the dataset, e.g. for \
+                                `rosbag record -O /dev/stdout ... | bolster upload`. \
+                                Stdin is checksummed while it uploads, and no \
+                                confirmation prompt is shown.")
+                        .long("stdin")
+                        .value_name("NAME")
+                        .takes_value(true)
+                        .validator(|val| commands::validate_stdin_name(val).map_err(|e| e.to_string()))
+                        .conflicts_with_all(&["dry_run", "resume"])
+                )
+                .arg(
+                    Arg::new("hash_jobs")
+                        .about("Number of files to checksum at the same time while \
+                                uploading and when checking which files a resumed \
+                                dataset already has, overriding hash_jobs in the \
+                                [performance] config (default: 4)")
+                        .long("hash-jobs")
+                        .value_name("N")
+                        .takes_value(true)
+                        .validator(|val| match val.parse::<usize>() {
+                            Ok(0) => Err("must be at least 1".to_owned()),
+                            Ok(_) => Ok(()),
+                            Err(e) => Err(e.to_string()),
+                        }),
+                )
+                .arg(
+                    Arg::new("chunk_read_ahead")
+                        .about("Number of chunks of each large file to read ahead \
+                                while earlier chunks upload (may improve throughput \
+                                on fast connections, but holds more of each file \
+                                in RAM)")
+                        .long("chunk-read-ahead")
+                        .value_name("N")
+                        .default_value("0")
+                        .takes_value(true)
+                        .validator(|val| val.parse::<usize>().map(|_| ()))
+                )
+                .arg(
+                    Arg::new("num_parts")
+                        .about("Split each file uploaded in chunks (files at or above \
+                                the multipart threshold, 64 MB by default) into exactly \
+                                N parts, e.g. for benchmarking (each part must be \
+                                between 5 MiB and 5 GiB)")
+                        .long("num-parts")
+                        .value_name("N")
+                        .takes_value(true)
+                        .validator(|val| match val.parse::<usize>() {
+                            Ok(0) => Err("must be at least 1".to_owned()),
+                            Ok(_) => Ok(()),
+                            Err(e) => Err(e.to_string()),
+                        })
+                )
+                .arg(
+                    Arg::new("part_size_strategy")
+                        .about("How to size the parts of files uploaded in chunks: \
+                                fixed uses the same size for every part, adaptive \
+                                starts with small parts (so early failures lose \
+                                little) that double up to 4 times that size")
+                        .long("part-size-strategy")
+                        .value_name("STRATEGY")
+                        .possible_values(storage::PartSizeStrategy::VARIANTS)
+                        .default_value(storage::PartSizeStrategy::Fixed.as_ref())
+                        .conflicts_with("num_parts")
                         .takes_value(true)
                 )
                 .arg(
-                    Arg::new("object_space_toml_path")
-                        .about("Path to .toml file describing object space.")
-                        .value_name("OBJECT_SPACE_TOML_PATH")
-                        .required(true)
+                    Arg::new("compress")
+                        .about("Compress data files with this format before uploading \
+                                them (files like .bag, .zip, and .jpg that are usually \
+                                already compressed are skipped, see --compress-all). \
+                                Downloads decompress them automatically")
+                        .long("compress")
+                        .value_name("FORMAT")
+                        .possible_values(compression::CompressionFormat::VARIANTS)
                         .takes_value(true)
                 )
                 .arg(
-                    Arg::new("path")
-                        .about("Path to .bag file (where topic names of data \
-                                streams must match component names in the plex) \
-                                or path(s) to folder(s) containing data (folder \
-                                names must match component names in the plex).")
+                    Arg::new("compress_all")
+                        .about("With --compress, also compress files that are usually \
+                                already compressed")
+                        .long("compress-all")
+                        .requires("compress")
+                )
+                .arg(
+                    Arg::new("error_report")
+                        .about("Write a JSON report listing each file that failed to \
+                                upload (with its error message and category) to this file")
+                        .long("error-report")
+                        .value_name("PATH")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("timings_json")
+                        .about("Write how long each phase of the upload took (creating \
+                                the dataset, each file's checksum, transfer, and \
+                                registration, and notifying completion) as JSON to this file")
+                        .long("timings-json")
                         .value_name("PATH")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("max_files")
+                        .about("Maximum number of files to upload, overriding \
+                                max_files in the [upload] config (default 200, \
+                                at most 10000)")
+                        .long("max-files")
+                        .value_name("N")
+                        .takes_value(true)
+                        .validator(|val| val.parse::<usize>().map(|_| ()))
+                )
+                .arg(
+                    Arg::new("processing_options")
+                        .about("JSON object of options that control how the \
+                                dataset is processed (e.g. which pipeline to run)")
+                        .long("processing-options")
+                        .value_name("JSON")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("provider")
+                        .short('p')
+                        .long("provider")
+                        .value_name("PROVIDER")
+                        .about("Upload to specified cloud storage provider")
+                        .default_value(default_storage_provider)
+                        .possible_values(StorageProviderChoices::VARIANTS)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("upload-batch")
+                .about("Upload several datasets described in a spec file")
+                .arg(
+                    Arg::new("spec_path")
+                        .about("Path to a TOML (or .json) file with a [[dataset]] \
+                                entry (system_id, plex, object_space, paths, and \
+                                optional meta) for each dataset to upload")
+                        .value_name("SPEC_PATH")
                         .required(true)
                         .takes_value(true)
-                        .multiple(true)
+                )
+                .arg(
+                    Arg::new("max_concurrent_datasets")
+                        .about("Number of datasets to upload at the same time")
+                        .long("max-concurrent-datasets")
+                        .value_name("N")
+                        .default_value("2")
+                        .takes_value(true)
+                        .validator(|val| match val.parse::<usize>() {
+                            Ok(0) => Err("must be at least 1".to_owned()),
+                            Ok(_) => Ok(()),
+                            Err(e) => Err(e.to_string()),
+                        })
                 )
                 .arg(
                     Arg::new("yes")
-                        .about("Automatic yes to prompt that lists files to upload")
+                        .about("Automatic yes to prompt that lists datasets to upload")
                         .short('y')
                         .long("yes")
                 )
+                .arg(
+                    Arg::new("dry_run")
+                        .about("List each dataset's files and cloud storage keys \
+                                without creating datasets or uploading anything")
+                        .long("dry-run")
+                )
                 .arg(
                     Arg::new("provider")
                         .short('p')
                         .long("provider")
                         .value_name("PROVIDER")
                         .about("Upload to specified cloud storage provider")
-                        .default_value(default_storage_provider.as_ref())
+                        .default_value(default_storage_provider)
                         .possible_values(StorageProviderChoices::VARIANTS)
                         .takes_value(true),
                 ),
@@ -517,33 +2633,41 @@ pub fn cli_config() -> Result<clap::ArgMatches> {
                 // Using `.args` instead of repeated `.arg` so we can apply a feature flag
                 .args(&[
                     Arg::new("after_date")
-                        .about("Show datasets created on or after 00:00 UTC of this date (format: YYYY-mm-dd)")
+                        .about("Show datasets created on or after this date (YYYY-mm-dd, \
+                                meaning 00:00 UTC) or datetime (RFC 3339, e.g. \
+                                YYYY-mm-ddTHH:MM:SSZ)")
                         .short('a')
                         .long("after-date")
                         .value_name("DATE")
                         .takes_value(true),
                     Arg::new("before_date")
-                        .about("Show datasets created before 00:00 UTC of this date (format: YYYY-mm-dd)")
+                        .about("Show datasets created before this date (YYYY-mm-dd, \
+                                meaning 00:00 UTC) or datetime (RFC 3339, e.g. \
+                                YYYY-mm-ddTHH:MM:SSZ)")
                         .short('b')
                         .long("before-date")
                         .value_name("DATE")
                         .takes_value(true),
-                    // TODO: Implement metadata CLI input
-                    // Related to
-                    // - https://gitlab.com/tangram-vision/oss/bolster/-/issues/1
-                    // - https://gitlab.com/tangram-vision/oss/bolster/-/issues/4
-                    // Arg::new("metadata")
-                    //     .about("NOT IMPLEMENTED: Show dataset matching metadata")
-                    //     .short('m')
-                    //     .long("metadata")
-                    //     .value_name("???")
-                    //     .takes_value(true),
+                    Arg::new("metadata")
+                        .about("Show datasets with matching metadata (may be repeated, all must match)")
+                        .short('m')
+                        .long("metadata")
+                        .value_name("KEY=VALUE")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .validator(|val| parse_key_value(val).map(|_| ())),
                     Arg::new("dataset_uuid")
                         .about("Show files in dataset matching uuid")
                         .short('u')
                         .long("uuid")
                         .value_name("UUID")
                         .takes_value(true),
+                    Arg::new("interactive")
+                        .about("Pick a dataset to show files in from a list of recent \
+                                datasets")
+                        .short('i')
+                        .long("interactive")
+                        .conflicts_with_all(&["dataset_uuid", "summary"]),
                     Arg::new("system_id")
                         .about("Show datasets from specified system")
                         .short('d')
@@ -551,14 +2675,17 @@ pub fn cli_config() -> Result<clap::ArgMatches> {
                         .value_name("SYSTEM_ID")
                         .takes_value(true),
                     Arg::new("order")
-                        .about("Sort results by field")
+                        .about("Sort results by field (filesize and key only sort \
+                                files, with --uuid or --files-only)")
                         .short('o')
                         .long("order-by")
                         .value_name("FIELD.DIRECTION")
-                        .possible_values(DatasetOrdering::VARIANTS)
+                        // Datasets can be sorted by a subset of the file fields
+                        .possible_values(FileOrdering::VARIANTS)
                         .takes_value(true),
                     Arg::new("limit")
-                        .about("Show N results (max 100)")
+                        .about("Show N results (max 100). With --uuid, show N of the \
+                                dataset's files (all by default)")
                         .short('l')
                         .long("limit")
                         .default_value("20")
@@ -578,14 +2705,177 @@ pub fn cli_config() -> Result<clap::ArgMatches> {
                                 )),
                             }
                         }),
+                    Arg::new("all")
+                        .about("Show all results, fetching them in pages of 100 \
+                                (oldest first)")
+                        .long("all")
+                        .conflicts_with_all(&["limit", "offset", "order"]),
+                    Arg::new("summary")
+                        .about("Only show each dataset's file count and total size, which \
+                                the server computes without sending every file (faster \
+                                for datasets with many files)")
+                        .long("summary")
+                        .conflicts_with("dataset_uuid"),
                     Arg::new("offset")
                         .about(
-                            "Skip N results (WARNING: Results may shift between subsequent calls)",
+                            "Skip N results, or N files with --uuid (WARNING: Results may \
+                             shift between subsequent calls)",
                         )
                         .short('s')
                         .long("offset")
                         .value_name("N")
                         .takes_value(true),
+                    Arg::new("tag")
+                        .about("Show datasets with the specified tag")
+                        .short('t')
+                        .long("tag")
+                        .value_name("TAG")
+                        .takes_value(true),
+                    Arg::new("name_contains")
+                        .about("Show datasets whose name contains this text, or files \
+                                whose path does with --files-only (case-insensitive)")
+                        .long("name-contains")
+                        .value_name("TEXT")
+                        .takes_value(true),
+                    Arg::new("format")
+                        .about("Print results in this format (defaults to the [output] \
+                                format config setting, or table)")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(OutputFormat::VARIANTS)
+                        .takes_value(true),
+                    Arg::new("json_lines")
+                        .about("Print one JSON object per line as results are fetched \
+                                (same as --format ndjson)")
+                        .long("json-lines")
+                        .conflicts_with("format"),
+                    Arg::new("files_only")
+                        .about("List files across all datasets instead of datasets, with \
+                                the UUID of each file's dataset (with --name-contains, only \
+                                files whose path contains the text)")
+                        .long("files-only")
+                        .conflicts_with_all(&[
+                            "dataset_uuid",
+                            "interactive",
+                            "summary",
+                            "all",
+                            "system_id",
+                            "tag",
+                            "metadata",
+                            "after_date",
+                            "before_date",
+                        ]),
+                ]),
+        )
+        .subcommand(
+            App::new("tag")
+                .about("Add or remove dataset tags")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    App::new("add")
+                        .about("Add a tag to a remote dataset")
+                        .args(&[
+                            Arg::new("dataset_uuid")
+                                .value_name("DATASET_UUID")
+                                .required(true)
+                                .takes_value(true),
+                            Arg::new("tag")
+                                .value_name("TAG")
+                                .required(true)
+                                .takes_value(true),
+                        ]),
+                )
+                .subcommand(
+                    App::new("rm")
+                        .about("Remove a tag from a remote dataset")
+                        .args(&[
+                            Arg::new("dataset_uuid")
+                                .value_name("DATASET_UUID")
+                                .required(true)
+                                .takes_value(true),
+                            Arg::new("tag")
+                                .value_name("TAG")
+                                .required(true)
+                                .takes_value(true),
+                        ]),
+                ),
+        )
+        .subcommand(
+            App::new("verify")
+                .about("Check that files in a remote dataset are present and intact in \
+                        cloud storage, without downloading them")
+                .arg(
+                    Arg::new("dataset_uuid")
+                        .value_name("DATASET_UUID")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("notify-complete")
+                .about("Notify the backend that a dataset's upload is complete, so it \
+                        gets processed (if the notification at the end of upload failed)")
+                .arg(
+                    Arg::new("dataset_uuid")
+                        .value_name("DATASET_UUID")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("plex")
+                        .about("Path of the dataset's plex file (default: its only \
+                                .plex file)")
+                        .long("plex")
+                        .value_name("PATH")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("object_space")
+                        .about("Path of the dataset's object space file (default: its \
+                                only .toml file)")
+                        .long("object-space")
+                        .value_name("PATH")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::new("processing_options")
+                        .about("JSON object of options that control how the \
+                                dataset is processed (e.g. which pipeline to run)")
+                        .long("processing-options")
+                        .value_name("JSON")
+                        .takes_value(true)
+                ),
+        )
+        .subcommand(
+            App::new("head")
+                .about("Show a file's metadata in cloud storage (size, last-modified \
+                        time, ETag, content type, storage class, and version id), \
+                        without downloading it")
+                .args(&[
+                    Arg::new("dataset_uuid")
+                        .value_name("DATASET_UUID")
+                        .required(true)
+                        .takes_value(true),
+                    Arg::new("path")
+                        .about("Path of the file in the dataset, e.g. data/a.bag")
+                        .value_name("FILE")
+                        .required(true)
+                        .takes_value(true),
+                ]),
+        )
+        .subcommand(
+            App::new("diff")
+                .about("Compare a local directory against a remote dataset (by path, size, \
+                        and checksum), without transferring any files")
+                .args(&[
+                    Arg::new("dataset_uuid")
+                        .value_name("DATASET_UUID")
+                        .required(true)
+                        .takes_value(true),
+                    Arg::new("local_dir")
+                        .value_name("LOCAL_DIR")
+                        .required(true)
+                        .takes_value(true),
                 ]),
         )
         .subcommand(
@@ -594,28 +2884,214 @@ pub fn cli_config() -> Result<clap::ArgMatches> {
                 .args(&[
                     Arg::new("dataset_uuid")
                         .value_name("DATASET_UUID")
-                        .required(true)
+                        .required_unless_present_any(["interactive", "name"])
                         .takes_value(true),
+                    Arg::new("interactive")
+                        .about("Pick the dataset from a list of recent datasets instead \
+                                of passing its UUID (all positional arguments are then \
+                                prefixes)")
+                        .short('i')
+                        .long("interactive"),
+                    Arg::new("name")
+                        .about("Download the dataset with this name (see `upload --name`) \
+                                instead of passing its UUID (all positional arguments \
+                                are then prefixes)")
+                        .long("name")
+                        .value_name("NAME")
+                        .takes_value(true)
+                        .conflicts_with("interactive"),
                     Arg::new("prefix")
                         .value_name("PREFIX")
                         .about("All files with names starting with a prefix will be downloaded")
                         .takes_value(true)
-                        .multiple(true)
+                        .multiple(true),
+                    Arg::new("no_verify")
+                        .about("Skip verifying downloaded files against their checksums")
+                        .long("no-verify"),
+                    Arg::new("no_decompress")
+                        .about("Keep files that were compressed by upload --compress \
+                                compressed (e.g. as .gz files) instead of decompressing them")
+                        .long("no-decompress"),
+                    Arg::new("output")
+                        .about("Download files into this directory (created if it doesn't exist)")
+                        .short('o')
+                        .long("output")
+                        .value_name("DIR")
+                        .takes_value(true),
+                    Arg::new("output_template")
+                        .about("Download each file to a path built from this template instead \
+                                of mirroring its path in the dataset. Placeholders: \
+                                {dataset_id}, {file_id}, {created_date}, {path}, {filename} \
+                                (e.g. '{dataset_id}/{filename}')")
+                        .long("output-template")
+                        .value_name("TEMPLATE")
+                        .takes_value(true)
+                        .validator(|val| {
+                            commands::validate_output_template(val).map_err(|e| e.to_string())
+                        }),
+                    Arg::new("sequential_writes")
+                        .about("Fetch files concurrently but write them to disk one at a time \
+                                (reduces seek thrashing on spinning disks)")
+                        .long("sequential-writes"),
+                    Arg::new("keep_going")
+                        .about("Keep downloading the remaining files after a file fails")
+                        .long("keep-going"),
+                    Arg::new("error_report")
+                        .about("Write a JSON report listing each file that failed to \
+                                download (with its error message and category) to this file")
+                        .long("error-report")
+                        .value_name("PATH")
+                        .takes_value(true),
+                    Arg::new("timings_json")
+                        .about("Write how long listing files and downloading each file \
+                                took as JSON to this file")
+                        .long("timings-json")
+                        .value_name("PATH")
+                        .takes_value(true),
+                    Arg::new("dry_run")
+                        .about("List the files that would be downloaded, which existing \
+                                files they would overwrite, and whether there's enough \
+                                free disk space, without downloading anything")
+                        .long("dry-run"),
+                    Arg::new("concurrency")
+                        .about("Number of files to download at the same time")
+                        .short('j')
+                        .long("concurrency")
+                        .value_name("N")
+                        .default_value("4")
+                        .takes_value(true)
+                        .validator(|val| match val.parse::<usize>() {
+                            Ok(0) => Err("must be at least 1".to_owned()),
+                            Ok(_) => Ok(()),
+                            Err(e) => Err(e.to_string()),
+                        }),
+                ])
+        )
+        .subcommand(
+            App::new("export")
+                .about("Download a dataset's files into a single archive, with a \
+                        manifest.json describing the dataset and its files")
+                .args(&[
+                    Arg::new("dataset_uuid")
+                        .value_name("DATASET_UUID")
+                        .required(true)
+                        .takes_value(true),
+                    Arg::new("output")
+                        .about("Path of the archive to write (must not already exist)")
+                        .value_name("OUTPUT")
+                        .required(true)
+                        .takes_value(true),
+                    Arg::new("format")
+                        .about("Archive format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .possible_values(commands::ExportFormat::VARIANTS)
+                        .default_value(commands::ExportFormat::Tar.as_ref())
+                        .takes_value(true),
+                    Arg::new("no_verify")
+                        .about("Skip verifying downloaded files against their checksums")
+                        .long("no-verify"),
                 ])
-            // TODO: Add path to download files to?
         )
-        .subcommand(App::new("config").about("Show Configuration"));
+        .subcommand(
+            App::new("object-space")
+                .about("Work with object-space TOML files (offline)")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    App::new("validate")
+                        .about("Check that an object-space TOML file is valid before uploading it")
+                        .arg(
+                            Arg::new("object_space_toml_path")
+                                .about("Path to .toml file describing object space.")
+                                .value_name("OBJECT_SPACE_TOML_PATH")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("apriltag_family")
+                                .about("Accept this custom AprilTag family (may be repeated)")
+                                .long("apriltag-family")
+                                .value_name("FAMILY")
+                                .takes_value(true)
+                                .multiple_occurrences(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("config")
+                .about("Show Configuration (the merged config file and environment overrides)")
+                .arg(show_secrets_arg.clone())
+                .subcommand(
+                    App::new("get")
+                        .about("Show Configuration (the default)")
+                        .arg(show_secrets_arg),
+                )
+                .subcommand(
+                    App::new("set")
+                        .about("Set a value in the config file, creating the file if needed")
+                        .arg(
+                            Arg::new("key")
+                                .about("Key to set, as section.key (e.g. database.jwt)")
+                                .value_name("KEY")
+                                .required(true)
+                                .takes_value(true)
+                                .possible_values(&app_config::CONFIG_KEYS)
+                                .hide_possible_values(true),
+                        )
+                        .arg(
+                            Arg::new("value")
+                                .about("Value to set, read as TOML if it's a TOML value \
+                                        (e.g. 500 or [\"bag\", \"mcap\"]) and as a \
+                                        string otherwise")
+                                .value_name("VALUE")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("completions")
+                .about("Print a shell completion script (works offline)")
+                .arg(
+                    Arg::new("shell")
+                        .value_name("SHELL")
+                        .required(true)
+                        .takes_value(true)
+                        .possible_values(Shell::VARIANTS),
+                ),
+        )
+}
+
+/// Parses CLI arguments, exiting with help or usage errors as needed.
+pub fn cli_config() -> Result<clap::ArgMatches> {
+    Ok(cli_app().get_matches())
+}
 
-    // Get matches
-    let cli_matches = cli_app.get_matches();
+/// Sets a value in the config file at `path` for `config set`, which works
+/// before any config file exists.
+pub fn config_set_match(set_matches: &clap::ArgMatches, path: &Path) -> Result<()> {
+    // Safe to unwrap because arguments are required
+    let key = set_matches.value_of("key").unwrap();
+    let value = set_matches.value_of("value").unwrap();
+    app_config::set_config_value(path, key, value)?;
+    println!("Set {} in {}", key, path.display());
+    Ok(())
+}
 
-    Ok(cli_matches)
+/// Prints the completion script for the shell chosen in `completions`.
+pub fn completions_match(completions_matches: &clap::ArgMatches) -> Result<()> {
+    let shell = completions_matches.value_of_t_or_exit::<Shell>("shell");
+    completions::generate(shell, &mut cli_app(), &mut io::stdout().lock());
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use std::{ffi::OsString, os::unix::ffi::OsStringExt};
 
+    use httpmock::{Method::GET, MockServer};
+    use serde_json::json;
+
     use super::*;
 
     #[test]
@@ -633,6 +3109,223 @@ mod tests {
         assert_eq!(error.to_string(), "missing field `jwt`");
     }
 
+    #[test]
+    fn test_check_config() {
+        let config_from = |extra: &str| {
+            let mut config = config::Config::default();
+            config
+                .merge(config::File::from_str(
+                    &format!(
+                        "{}\n{}",
+                        include_str!("../fixtures/test_full_config.toml"),
+                        extra
+                    ),
+                    config::FileFormat::Toml,
+                ))
+                .unwrap();
+            config
+        };
+        check_config(&config_from("")).unwrap();
+
+        // Every problem is reported, not just the first
+        let error = check_config(&config_from(
+            "[performance]\nmultipart_threshold_mib = 1\n\
+             [output]\nformat = \"yaml\"\n\
+             [s3_compatible]\nendpoint = \"not a url\"\nbucket = \"datasets\"\n\
+             access_key = \"abc\"\nsecret_key = \"def\"\n\
+             [network]\nproxy = \"socks5://proxy:1080\"",
+        ))
+        .unwrap_err()
+        .to_string();
+        assert!(error.starts_with("Config check failed:\n"), "{}", error);
+        assert!(
+            error.contains(
+                "  - Config error: [performance] multipart_threshold_mib must be at least 5"
+            ),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("  - Config error: invalid [output] section"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("  - Config error: invalid [s3_compatible] endpoint not a url"),
+            "{}",
+            error
+        );
+        // Reported once, even though each storage provider reads the proxy
+        assert!(
+            error.contains(
+                "  - Invalid [network] proxy socks5://proxy:1080 -- only http:// proxies are supported"
+            ),
+            "{}",
+            error
+        );
+        assert_eq!(error.lines().count(), 5, "{}", error);
+    }
+
+    #[test]
+    fn test_confirm_overwrites() {
+        let tmp_dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        for name in &["a.bag", "b.bag", "d.bag", "e.bag"] {
+            std::fs::write(tmp_dir.join(name), b"old").unwrap();
+        }
+        let uploaded_file = |name: &str| {
+            UploadedFile {
+            file_id: Uuid::new_v4(),
+            dataset_id: Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            created_date: Utc::now(),
+            url: url::Url::parse(&format!(
+                "https://tangram-vision-datasets.s3.us-west-1.amazonaws.com/d11cc371-f33b-4dad-ac2e-3c4cca30a256/{}",
+                name
+            ))
+            .unwrap(),
+            filesize: 3,
+            version: "blah".to_owned(),
+            metadata: json!({}),
+        }
+        };
+        let files: Vec<UploadedFile> = ["a.bag", "b.bag", "c.bag", "d.bag", "e.bag"]
+            .iter()
+            .map(|name| uploaded_file(name))
+            .collect();
+        let names = |files: &[UploadedFile]| -> Vec<String> {
+            files
+                .iter()
+                .map(|f| f.filepath_from_url().unwrap().display().to_string())
+                .collect()
+        };
+        let mut options = commands::DownloadOptions {
+            output_dir: Some(tmp_dir.clone()),
+            ..Default::default()
+        };
+
+        // Declining one file only skips that file, and "a" stops asking
+        let mut output = Vec::new();
+        let (to_download, skipped) =
+            confirm_overwrites(files.clone(), &options, &b"n\ny\na\n"[..], &mut output).unwrap();
+        assert_eq!(names(&to_download), ["b.bag", "c.bag", "d.bag", "e.bag"]);
+        assert_eq!(skipped, [tmp_dir.join("a.bag")]);
+        // c.bag doesn't exist, and e.bag is covered by "a"
+        assert_eq!(
+            String::from_utf8(output)
+                .unwrap()
+                .matches("[y/n/a/s]")
+                .count(),
+            3
+        );
+
+        // "s" skips every remaining existing file
+        let (to_download, skipped) =
+            confirm_overwrites(files.clone(), &options, &b"y\ns\n"[..], io::sink()).unwrap();
+        assert_eq!(names(&to_download), ["a.bag", "c.bag"]);
+        assert_eq!(
+            skipped,
+            [
+                tmp_dir.join("b.bag"),
+                tmp_dir.join("d.bag"),
+                tmp_dir.join("e.bag")
+            ]
+        );
+
+        // Quiet mode can't ask, so it refuses
+        options.quiet = true;
+        let error = confirm_overwrites(files, &options, &b""[..], io::sink()).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("a.bag already exists, refusing to overwrite it in quiet mode"));
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_table_render() {
+        let mut table = Table::new(vec![
+            ("System ID", Align::Left),
+            ("# Files", Align::Right),
+            ("Filesize", Align::Right),
+        ]);
+        table.add_row(vec![
+            "a-robot-with-a-long-name".to_owned(),
+            "3".to_owned(),
+            "12 B".to_owned(),
+        ]);
+        table.add_row(vec![
+            "r2".to_owned(),
+            "1024".to_owned(),
+            "1.05 MB".to_owned(),
+        ]);
+
+        assert_eq!(
+            table.render(false),
+            "System ID                 # Files  Filesize\n\
+             a-robot-with-a-long-name        3      12 B\n\
+             r2                           1024   1.05 MB\n"
+        );
+        assert!(table
+            .render(true)
+            .starts_with("\x1b[1mSystem ID                 # Files  Filesize\x1b[0m\n"));
+    }
+
+    #[test]
+    fn test_record_writer() {
+        let render = |format: OutputFormat, records: &[serde_json::Value]| {
+            let mut out = Vec::new();
+            let mut writer = RecordWriter::new(format, &["name", "size"]);
+            for record in records {
+                writer.write(&mut out, record).unwrap();
+            }
+            writer.finish(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let records = [
+            json!({"name": "a.bag", "size": 12}),
+            json!({"name": "b, \"c\".bag", "size": null}),
+        ];
+
+        assert_eq!(
+            render(OutputFormat::Json, &records),
+            "[\n  {\"name\":\"a.bag\",\"size\":12},\n  {\"name\":\"b, \\\"c\\\".bag\",\"size\":null}\n]\n"
+        );
+        assert_eq!(render(OutputFormat::Json, &[]), "[]\n");
+        assert_eq!(
+            render(OutputFormat::Ndjson, &records),
+            "{\"name\":\"a.bag\",\"size\":12}\n{\"name\":\"b, \\\"c\\\".bag\",\"size\":null}\n"
+        );
+        assert_eq!(render(OutputFormat::Ndjson, &[]), "");
+        assert_eq!(
+            render(OutputFormat::Csv, &records),
+            "name,size\na.bag,12\n\"b, \"\"c\"\".bag\",\n"
+        );
+        assert_eq!(render(OutputFormat::Csv, &[]), "name,size\n");
+    }
+
+    #[test]
+    fn test_parse_key_value() {
+        assert_eq!(
+            parse_key_value("location=lab").unwrap(),
+            ("location".to_owned(), "lab".to_owned())
+        );
+        assert_eq!(
+            parse_key_value("query=a=b").unwrap(),
+            ("query".to_owned(), "a=b".to_owned())
+        );
+        assert_eq!(
+            parse_key_value("empty=").unwrap(),
+            ("empty".to_owned(), "".to_owned())
+        );
+        assert!(parse_key_value("no-separator")
+            .unwrap_err()
+            .to_string()
+            .contains("found no '='"));
+        assert!(parse_key_value("=value")
+            .unwrap_err()
+            .to_string()
+            .contains("key is empty"));
+    }
+
     #[test]
     fn test_plex_pathkind_validation_good() {
         let path = Path::new("fixtures/empty.plex");
@@ -672,25 +3365,106 @@ mod tests {
     #[test]
     fn test_data_pathkind_validation_good_bag() {
         let path = Path::new("fixtures/empty.bag");
-        PathKind::Data.validate(path).unwrap();
+        PathKind::Data(&["bag".to_owned()]).validate(path).unwrap();
     }
 
     #[test]
     fn test_data_pathkind_validation_good_folder() {
         let path = Path::new("fixtures");
-        PathKind::Data.validate(path).unwrap();
+        PathKind::Data(&["bag".to_owned()]).validate(path).unwrap();
     }
 
     #[test]
     fn test_data_pathkind_validation_bad_extension() {
         let path = Path::new("fixtures/test_full_config.toml");
-        PathKind::Data.validate(path).unwrap_err();
+        PathKind::Data(&["bag".to_owned()])
+            .validate(path)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_data_pathkind_validation_configured_extensions() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mcap_path = dir.join("test.mcap");
+        let h5_path = dir.join("test.h5");
+        std::fs::write(&mcap_path, "").unwrap();
+        std::fs::write(&h5_path, "").unwrap();
+        let extensions = upload_data_extensions(Vec::new(), Some(vec![".mcap".to_owned()]));
+
+        let mcap_result = PathKind::Data(&extensions).validate(&mcap_path);
+        let h5_result = PathKind::Data(&extensions).validate(&h5_path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        mcap_result.unwrap();
+        assert_eq!(
+            h5_result.unwrap_err().to_string(),
+            format!(
+                "Data file ({:?}) doesn't end in .mcap. Data input must be .mcap files or folders.",
+                h5_path
+            )
+        );
+    }
+
+    #[test]
+    fn test_upload_data_extensions() {
+        assert_eq!(upload_data_extensions(Vec::new(), None), vec!["bag"]);
+        assert_eq!(
+            upload_data_extensions(Vec::new(), Some(vec!["mcap".to_owned(), ".h5".to_owned()])),
+            vec!["mcap", "h5"]
+        );
+        assert_eq!(
+            upload_data_extensions(vec![".bag", "mcap"], Some(vec!["h5".to_owned()])),
+            vec!["bag", "mcap"]
+        );
+    }
+
+    #[test]
+    fn test_upload_multipart_threshold() {
+        assert_eq!(
+            upload_multipart_threshold(None).unwrap(),
+            commands::MULTIPART_FILESIZE_THRESHOLD
+        );
+        assert_eq!(
+            upload_multipart_threshold(Some(128)).unwrap(),
+            128 * MEBIBYTE as usize
+        );
+        assert_eq!(
+            upload_multipart_threshold(Some(5 * 1024)).unwrap(),
+            storage::MAX_PART_SIZE
+        );
+        assert_eq!(
+            upload_multipart_threshold(Some(1)).unwrap_err().to_string(),
+            "Config error: [performance] multipart_threshold_mib must be at least 5 (the minimum multipart upload part size in MiB), got 1"
+        );
+        assert_eq!(
+            upload_multipart_threshold(Some(100 * 1024))
+                .unwrap_err()
+                .to_string(),
+            "Config error: [performance] multipart_threshold_mib must be at most 5120 (the largest single-request upload in MiB), got 102400"
+        );
+    }
+
+    #[test]
+    fn test_upload_hash_jobs() {
+        assert_eq!(
+            upload_hash_jobs(None, None).unwrap(),
+            commands::MAX_FILES_HASHING_CONCURRENTLY
+        );
+        assert_eq!(upload_hash_jobs(None, Some(8)).unwrap(), 8);
+        assert_eq!(upload_hash_jobs(Some(2), Some(8)).unwrap(), 2);
+        assert_eq!(
+            upload_hash_jobs(None, Some(0)).unwrap_err().to_string(),
+            "Config error: [performance] hash_jobs must be at least 1"
+        );
     }
 
     #[test]
     fn test_data_pathkind_validation_nonexistent() {
         let path = Path::new("non-existent.bag");
-        PathKind::Data.validate(path).unwrap_err();
+        PathKind::Data(&["bag".to_owned()])
+            .validate(path)
+            .unwrap_err();
     }
 
     #[test]
@@ -724,6 +3498,219 @@ mod tests {
         clean_and_validate_path(path, PathKind::Plex).unwrap_err();
     }
 
+    #[tokio::test]
+    async fn test_pick_dataset_flows_into_download() {
+        let ids = [
+            "1415fe36-851f-4c62-a616-4f5e343ba5fc",
+            "d11cc371-f33b-4dad-ac2e-3c4cca30a256",
+        ];
+        let server = MockServer::start();
+        let datasets_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/datasets")
+                .query_param("order", "created_date.desc")
+                .query_param("limit", "20");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!(ids
+                    .iter()
+                    .map(|id| json!({
+                        "dataset_id": id,
+                        "created_date": "2021-02-03T21:21:57.713584+00:00",
+                        "system_id": "robot-1",
+                        "metadata": {},
+                        "files": [{"file_count": 1, "total_filesize": 4}],
+                    }))
+                    .collect::<Vec<_>>()));
+        });
+        let files_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/files")
+                .query_param("dataset_id", &format!("eq.{}", ids[1]));
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([]));
+        });
+        let db_config = DatabaseApiConfig::new_with_params(
+            reqwest::Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+
+        // Out-of-range and non-numeric choices prompt again
+        let input: &[u8] = b"3\nfoo\n2\n";
+        let mut output = Vec::new();
+        let dataset_id = pick_dataset(&db_config, input, &mut output).await.unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        datasets_mock.assert();
+        assert_eq!(dataset_id, Uuid::parse_str(ids[1]).unwrap());
+        assert!(output.contains(&format!("  2) {}", ids[1])));
+        assert!(output.contains("Invalid selection: 3"));
+        assert!(output.contains("Invalid selection: foo"));
+
+        // The picked dataset is the one whose files are fetched for download
+        // (it has none, which is an error)
+        let error = commands::list_files(&db_config, dataset_id, Vec::new())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains(ids[1]));
+        files_mock.assert();
+
+        // Ending input without picking is an error
+        let input: &[u8] = b"";
+        pick_dataset(&db_config, input, Vec::new())
+            .await
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_upload_max_files() {
+        assert_eq!(
+            upload_max_files(None, None).unwrap(),
+            UPLOAD_MAX_FILES_ALLOWED
+        );
+        assert_eq!(upload_max_files(None, Some(500)).unwrap(), 500);
+        assert_eq!(upload_max_files(Some(1000), Some(500)).unwrap(), 1000);
+        assert_eq!(
+            upload_max_files(Some(UPLOAD_MAX_FILES_CEILING), None).unwrap(),
+            UPLOAD_MAX_FILES_CEILING
+        );
+        upload_max_files(Some(0), None).expect_err("Zero file limit should be rejected");
+        upload_max_files(None, Some(UPLOAD_MAX_FILES_CEILING + 1))
+            .expect_err("File limit above ceiling should be rejected");
+    }
+
+    #[test]
+    fn test_collect_data_files_skips_hidden() {
+        let tmp_dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        let data_dir = tmp_dir.join(".data");
+        for path in &["a.bag", ".b.bag", ".git/c.bag", "sub/d.bag", "sub/.e.bag"] {
+            let path = data_dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"").unwrap();
+        }
+        let explicit_hidden = data_dir.join(".b.bag").display().to_string();
+        let paths = vec![data_dir.display().to_string(), explicit_hidden.clone()];
+        let filter = PathFilter::new(Vec::new(), Vec::new()).unwrap();
+        let relative = |files: Vec<String>| -> Vec<String> {
+            let mut files: Vec<String> = files
+                .iter()
+                .map(|file| {
+                    Path::new(file)
+                        .strip_prefix(&data_dir)
+                        .unwrap()
+                        .display()
+                        .to_string()
+                })
+                .collect();
+            files.sort();
+            files
+        };
+
+        // The hidden data folder is walked, and the hidden file passed
+        // directly is kept
+        assert_eq!(
+            relative(collect_data_files(&paths, &filter, DataWalkOptions::default()).unwrap()),
+            [".b.bag", "a.bag", "sub/d.bag"]
+        );
+        assert_eq!(
+            relative(
+                collect_data_files(
+                    &paths,
+                    &filter,
+                    DataWalkOptions {
+                        include_hidden: true,
+                        ..Default::default()
+                    }
+                )
+                .unwrap()
+            ),
+            [
+                ".b.bag",
+                ".b.bag",
+                ".git/c.bag",
+                "a.bag",
+                "sub/.e.bag",
+                "sub/d.bag"
+            ]
+        );
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_collect_data_files_follows_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let tmp_dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        let data_dir = tmp_dir.join("data");
+        std::fs::create_dir_all(tmp_dir.join("elsewhere")).unwrap();
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(tmp_dir.join("elsewhere/a.bag"), b"").unwrap();
+        std::fs::write(data_dir.join("b.bag"), b"").unwrap();
+        symlink(tmp_dir.join("elsewhere"), data_dir.join("linked_dir")).unwrap();
+        symlink(tmp_dir.join("elsewhere/a.bag"), data_dir.join("linked.bag")).unwrap();
+        let paths = vec![data_dir.display().to_string()];
+        let filter = PathFilter::new(Vec::new(), Vec::new()).unwrap();
+        let follow = DataWalkOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let collect = |walk_options| {
+            let mut files = collect_data_files(&paths, &filter, walk_options).unwrap();
+            files.sort();
+            files
+        };
+
+        assert_eq!(
+            collect(DataWalkOptions::default()),
+            [data_dir.join("b.bag").display().to_string()]
+        );
+        // Files keep their path through the symlink
+        assert_eq!(
+            collect(follow),
+            [
+                data_dir.join("b.bag").display().to_string(),
+                data_dir.join("linked.bag").display().to_string(),
+                data_dir.join("linked_dir/a.bag").display().to_string(),
+            ]
+        );
+
+        symlink(&data_dir, data_dir.join("loop")).unwrap();
+        let error = collect_data_files(&paths, &filter, follow).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Symlink loop: {} links back to {}, remove the link or don't pass --follow-symlinks",
+                data_dir.join("loop").display(),
+                data_dir.display()
+            )
+        );
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let filter = PathFilter::new(Vec::new(), Vec::new()).unwrap();
+        assert!(filter.is_match(Path::new("anything/at/all.txt")));
+
+        let filter =
+            PathFilter::new(vec!["*.png", "*.log"], vec!["**/.DS_Store", "big*.log"]).unwrap();
+        assert!(filter.is_match(Path::new("0001.png")));
+        assert!(filter.is_match(Path::new("cam0/0001.png")));
+        assert!(filter.is_match(Path::new("run.log")));
+        // Not included
+        assert!(!filter.is_match(Path::new("notes.txt")));
+        assert!(!filter.is_match(Path::new(".DS_Store")));
+        assert!(!filter.is_match(Path::new("cam0/.DS_Store")));
+        // Excludes take precedence over includes
+        assert!(!filter.is_match(Path::new("big-run.log")));
+
+        PathFilter::new(vec!["a[b"], Vec::new()).unwrap_err();
+    }
+
     // Other CLI-related tests are in tests/test_cli.rs and act as integration
     // tests (running the whole bolster binary) so they can properly test the
     // ClapError.exit functionality when CLI args are malformed.
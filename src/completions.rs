@@ -0,0 +1,68 @@
+//! Shell completion scripts generated from the CLI definition
+//!
+//! The scripts are generated by [clap_generate] from the same [clap::App]
+//! that parses the command line, so they can't drift out of date as
+//! subcommands and arguments change.
+
+use std::io::Write;
+
+use clap::App;
+use clap_generate::generators::{Bash, Fish, PowerShell, Zsh};
+use strum_macros::{EnumString, EnumVariantNames};
+
+/// Shells that completion scripts can be generated for.
+#[derive(EnumString, EnumVariantNames, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// Write a completion script for `shell` covering every subcommand of `app`.
+pub fn generate(shell: Shell, app: &mut App, out: &mut dyn Write) {
+    let bin = app.get_name().to_owned();
+    match shell {
+        Shell::Bash => clap_generate::generate::<Bash, _>(app, bin, out),
+        Shell::Zsh => clap_generate::generate::<Zsh, _>(app, bin, out),
+        Shell::Fish => clap_generate::generate::<Fish, _>(app, bin, out),
+        Shell::Powershell => clap_generate::generate::<PowerShell, _>(app, bin, out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate_string(shell: Shell) -> String {
+        let mut out = vec![];
+        generate(shell, &mut crate::cli::cli_app(), &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_generate_bash() {
+        let script = generate_string(Shell::Bash);
+        assert!(script.contains("complete -F _bolster -o bashdefault -o default bolster"));
+        for subcommand in &[
+            "upload",
+            "upload-batch",
+            "ls",
+            "tag",
+            "download",
+            "export",
+            "object-space",
+            "config",
+            "completions",
+        ] {
+            assert!(
+                script.contains(&format!("{})", subcommand)),
+                "missing {}",
+                subcommand
+            );
+        }
+        // Nested subcommands
+        assert!(script.contains("bolster__tag__add)"));
+    }
+}
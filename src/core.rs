@@ -3,6 +3,8 @@
 //!
 //! For overall architecture, see [ARCHITECTURE.md](https://gitlab.com/tangram-vision/oss/bolster/-/blob/main/ARCHITECTURE.md)
 
-pub(crate) mod api;
-pub(crate) mod commands;
-pub(crate) mod models;
+pub mod api;
+pub mod commands;
+pub mod compression;
+pub mod models;
+pub mod progress;
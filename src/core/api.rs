@@ -2,5 +2,6 @@
 //!
 //! For overall architecture, see [ARCHITECTURE.md](https://gitlab.com/tangram-vision/oss/bolster/-/blob/main/ARCHITECTURE.md)
 
-pub(crate) mod datasets;
-pub(crate) mod storage;
+pub mod datasets;
+pub mod proxy;
+pub mod storage;
@@ -2,17 +2,34 @@
 //!
 //! The datasets database stores datasets, their files, and associated metadata.
 
-use std::time::Duration;
+use std::{fmt, str::FromStr, time::Duration};
 
 use anyhow::{anyhow, bail, Context, Error, Result};
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, TimeZone, Utc};
 use log::debug;
-use reqwest::{header, Response, StatusCode, Url};
+use reqwest::{header, RequestBuilder, Response, StatusCode, Url};
 use serde_json::json;
 use strum_macros::{Display, EnumString, EnumVariantNames};
 use uuid::Uuid;
 
-use crate::core::models::{Dataset, DatasetNoFiles, UploadedFile};
+use super::proxy::ProxyConfig;
+use crate::core::models::{Dataset, DatasetNoFiles, DatasetSummary, UploadedFile};
+
+/// Seconds to wait for a database request before it times out, unless
+/// configured otherwise.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// How many times a database request is attempted before giving up, unless
+/// configured otherwise.
+pub const DEFAULT_REQUEST_ATTEMPTS: usize = 4;
+
+/// Delay before retrying a failed database request, doubled for each later
+/// retry.
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Default for [DatabaseApiConfig::notify_attempts]. With the default
+/// backoff, retries continue for about a minute.
+pub const DEFAULT_NOTIFY_ATTEMPTS: usize = 8;
 
 /// Configuration for interacting with the datasets database.
 pub struct DatabaseApiConfig {
@@ -20,14 +37,23 @@ pub struct DatabaseApiConfig {
     pub base_url: Url,
     /// HTTP client
     pub client: reqwest::Client,
+    /// How many times a request is attempted before giving up (see
+    /// [send_with_retry])
+    pub request_attempts: usize,
+    /// Delay before the first retry of a failed request
+    pub retry_backoff: Duration,
+    /// Minimum number of times an upload-complete notification is attempted
+    /// (see [datasets_notify_upload_complete])
+    pub notify_attempts: usize,
 }
 
 impl DatabaseApiConfig {
-    /// Configure HTTP client with endpoint, auth, and timeout.
+    /// Configure HTTP client with endpoint, auth, timeout, and proxies.
     pub fn new_with_params(
         base_url: Url,
         bearer_access_token: String,
         timeout: u64,
+        proxy: &ProxyConfig,
     ) -> Result<Self> {
         let user_agent = format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"),);
         let mut headers = header::HeaderMap::new();
@@ -44,20 +70,29 @@ impl DatabaseApiConfig {
                 .user_agent(user_agent)
                 .default_headers(headers)
                 .timeout(Duration::from_secs(timeout))
+                .proxy(proxy.reqwest_proxy())
                 .build()?,
             base_url,
+            request_attempts: DEFAULT_REQUEST_ATTEMPTS,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            notify_attempts: DEFAULT_NOTIFY_ATTEMPTS,
         })
     }
 
-    /// Configure HTTP client with endpoint, auth, and default 30-second timeout;
+    /// Configure HTTP client with endpoint, auth, default 30-second timeout,
+    /// and proxies from env vars (see [ProxyConfig]).
     pub fn new(base_url: Url, bearer_access_token: String) -> Result<Self> {
-        let timeout = 30;
-        Self::new_with_params(base_url, bearer_access_token, timeout)
+        Self::new_with_params(
+            base_url,
+            bearer_access_token,
+            DEFAULT_TIMEOUT_SECS,
+            &ProxyConfig::new(None)?,
+        )
     }
 }
 
 /// Available dataset sorting options
-#[derive(EnumString, EnumVariantNames, Display, Debug)]
+#[derive(EnumString, EnumVariantNames, Display, Debug, Clone)]
 pub enum DatasetOrdering {
     /// Sort by dataset creation date, ascending (i.e. oldest first)
     #[strum(serialize = "created_date.asc")]
@@ -75,17 +110,85 @@ impl DatasetOrdering {
     }
 }
 
+/// Available file sorting options
+#[derive(EnumString, EnumVariantNames, Display, Debug, Clone)]
+pub enum FileOrdering {
+    /// Sort by file size, ascending (i.e. smallest first)
+    #[strum(serialize = "filesize.asc")]
+    FilesizeAsc,
+    /// Sort by file size, descending (i.e. largest first)
+    #[strum(serialize = "filesize.desc")]
+    FilesizeDesc,
+    /// Sort by file creation date, ascending (i.e. oldest first)
+    #[strum(serialize = "created_date.asc")]
+    CreatedDateAsc,
+    /// Sort by file creation date, descending (i.e. most recent first)
+    #[strum(serialize = "created_date.desc")]
+    CreatedDateDesc,
+    /// Sort by the file's key (its path in the dataset), ascending
+    #[strum(serialize = "key.asc")]
+    KeyAsc,
+    /// Sort by the file's key (its path in the dataset), descending
+    #[strum(serialize = "key.desc")]
+    KeyDesc,
+}
+
+impl FileOrdering {
+    /// Translates between CLI sorting option value (e.g. "key") and database
+    /// column (e.g. "filepath"), if necessary.
+    pub fn to_database_field(&self) -> String {
+        match self {
+            FileOrdering::KeyAsc => "filepath.asc".to_owned(),
+            FileOrdering::KeyDesc => "filepath.desc".to_owned(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Bound on dataset creation time, parsed from either a date (`YYYY-mm-dd`,
+/// meaning 00:00 UTC of that date) or an RFC 3339 datetime
+/// (`YYYY-mm-ddTHH:MM:SSZ`).
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct CreatedDateBound(pub DateTime<Utc>);
+
+impl FromStr for CreatedDateBound {
+    type Err = chrono::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('T') {
+            Ok(Self(DateTime::parse_from_rfc3339(s)?.with_timezone(&Utc)))
+        } else {
+            let date = NaiveDate::from_str(s)?;
+            Ok(Self(Utc.from_utc_datetime(
+                &date.and_time(NaiveTime::from_hms(0, 0, 0)),
+            )))
+        }
+    }
+}
+
+impl fmt::Display for CreatedDateBound {
+    /// Formats midnight as a plain date (as older bolster versions sent it),
+    /// and other times as an RFC 3339 datetime in UTC.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.time() == NaiveTime::from_hms(0, 0, 0) {
+            write!(f, "{}", self.0.naive_utc().date())
+        } else {
+            write!(f, "{}", self.0.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        }
+    }
+}
+
 /// Options for filtering dataset list query.
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct DatasetGetRequest {
     /// Filter to a specific dataset
     pub dataset_id: Option<Uuid>,
     /// Filter to a specific system/device/robot/installation
     pub system_id: Option<String>,
-    /// Filter to datasets before a date
-    pub before_date: Option<NaiveDate>,
-    /// Filter to datasets after a date
-    pub after_date: Option<NaiveDate>,
+    /// Filter to datasets created before a date/time
+    pub before_date: Option<CreatedDateBound>,
+    /// Filter to datasets created on or after a date/time
+    pub after_date: Option<CreatedDateBound>,
     /// Order query results by a field (e.g. created_date) and direction (e.g.
     /// ascending).
     pub order: Option<DatasetOrdering>,
@@ -96,14 +199,83 @@ pub struct DatasetGetRequest {
     /// Warning: Results may shift between subsequent bolster invocations if new
     /// datasets are being added at the same time.
     pub offset: Option<usize>,
-    // TODO: Implement metadata CLI input
-    // Related to
-    // - https://gitlab.com/tangram-vision/oss/bolster/-/issues/1
-    // - https://gitlab.com/tangram-vision/oss/bolster/-/issues/4
+    /// Filter to datasets with a tag
+    pub tag: Option<String>,
+    /// Filter to datasets whose metadata contains all of these key-value pairs
+    pub metadata: Vec<(String, String)>,
+    /// Filter to datasets whose name contains this text (case-insensitive)
+    pub name_contains: Option<String>,
+}
+
+/// Sends a request, retrying failures that may be transient up to
+/// [DatabaseApiConfig::request_attempts] times in total, with exponential
+/// backoff.
+///
+/// 5xx responses and connection failures are retried. Timeouts and other
+/// failures while sending are only retried if the request is `idempotent`,
+/// because the server may have already acted on it (e.g. created a dataset).
+/// 4xx responses are never retried.
+///
+/// # Errors
+///
+/// Returns the last error if every attempt fails with a transient error, or
+/// the first non-transient error. After the last attempt, a 5xx response is
+/// returned as-is for [check_response] to report.
+pub async fn send_with_retry(
+    configuration: &DatabaseApiConfig,
+    req_builder: RequestBuilder,
+    idempotent: bool,
+) -> Result<Response> {
+    send_with_attempts(
+        configuration,
+        req_builder,
+        idempotent,
+        configuration.request_attempts,
+    )
+    .await
+}
+
+/// Like [send_with_retry], but attempts the request up to `attempts` times.
+async fn send_with_attempts(
+    configuration: &DatabaseApiConfig,
+    req_builder: RequestBuilder,
+    idempotent: bool,
+    attempts: usize,
+) -> Result<Response> {
+    let mut attempt = 1;
+    loop {
+        // Requests with streaming bodies can't be cloned, so are sent once
+        let (this_attempt, can_retry) = match req_builder.try_clone() {
+            Some(clone) => (clone, attempt < attempts),
+            None => return Ok(req_builder.send().await?),
+        };
+        match this_attempt.send().await {
+            Ok(response) if can_retry && response.status().is_server_error() => {
+                debug!(
+                    "Database request attempt {} got {}, retrying",
+                    attempt,
+                    response.status()
+                );
+            }
+            Ok(response) => return Ok(response),
+            Err(e)
+                if can_retry
+                    && (e.is_connect() || (idempotent && (e.is_timeout() || e.is_request()))) =>
+            {
+                debug!(
+                    "Database request attempt {} failed, retrying: {:?}",
+                    attempt, e
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+        tokio::time::sleep(configuration.retry_backoff * 2u32.pow(attempt as u32 - 1)).await;
+        attempt += 1;
+    }
 }
 
 /// Responses with any of these [StatusCode]s show extra detail.
-const ERROR_STATUSES_TO_SHOW_DETAIL: [StatusCode; 3] = [
+pub const ERROR_STATUSES_TO_SHOW_DETAIL: [StatusCode; 3] = [
     StatusCode::BAD_REQUEST,
     StatusCode::UNAUTHORIZED,
     StatusCode::FORBIDDEN,
@@ -111,12 +283,12 @@ const ERROR_STATUSES_TO_SHOW_DETAIL: [StatusCode; 3] = [
 
 /// Returns response json or an error with extra context/detail.
 ///
-/// For responses with a status code in [ERROR_STATUSES_TO_SHOW_DETAIL], return
-/// an error message that includes contents of "message", "detail", and "hint"
-/// fields in the API response, if they're provided. This will be used to inform
-/// users if they're providing bad input to the API or if a particular API
-/// endpoint is disabled/retired (and the user should upgrade to a newer version
-/// of bolster).
+/// For responses with a status code in [ERROR_STATUSES_TO_SHOW_DETAIL], add
+/// the contents of "message", "detail", and "hint" fields in the API response
+/// (if they're provided) as context to the status error. This will be used to
+/// inform users if they're providing bad input to the API or if a particular
+/// API endpoint is disabled/retired (and the user should upgrade to a newer
+/// version of bolster).
 pub async fn check_response(response: Response) -> Result<serde_json::Value> {
     let status = response.status();
     debug!("check_response status: {}", status);
@@ -135,43 +307,87 @@ pub async fn check_response(response: Response) -> Result<serde_json::Value> {
     if status_err.status().is_some()
         && ERROR_STATUSES_TO_SHOW_DETAIL.contains(&status_err.status().unwrap())
     {
-        response.json::<serde_json::Value>().await.map(|js| {
-            // Build up error to show user from error message and any message,
-            // detail, and hint fields that are populated.
-            let mut err_msg = format!("{}", status_err);
-            if let Some(Some(msg)) = js.get("message").map(|v| v.as_str()) {
-                err_msg.push_str(&format!("\n\tMessage: {}", msg))
-            }
-            if let Some(Some(details)) = js.get("details").map(|v| v.as_str()) {
-                err_msg.push_str(&format!("\n\tDetails: {}", details))
-            }
-            if let Some(Some(hint)) = js.get("hint").map(|v| v.as_str()) {
-                err_msg.push_str(&format!("\n\tHint: {}", hint))
-            }
-            bail!(err_msg);
-        })?
+        let js = response.json::<serde_json::Value>().await?;
+        Err(status_error_with_detail(status_err, &js))
     } else {
         Err(Error::new(status_err))
     }
 }
 
-/// Get a list of datasets and their files.
+/// Builds an error for a failed response from its status error and JSON body,
+/// adding the body's "message", "details", and "hint" fields (if any) as
+/// context while keeping the status error as its cause.
+fn status_error_with_detail(status_err: reqwest::Error, js: &serde_json::Value) -> Error {
+    let mut detail = Vec::new();
+    if let Some(Some(msg)) = js.get("message").map(|v| v.as_str()) {
+        detail.push(format!("Message: {}", msg))
+    }
+    if let Some(Some(details)) = js.get("details").map(|v| v.as_str()) {
+        detail.push(format!("Details: {}", details))
+    }
+    if let Some(Some(hint)) = js.get("hint").map(|v| v.as_str()) {
+        detail.push(format!("Hint: {}", hint))
+    }
+    let error = Error::new(status_err);
+    if detail.is_empty() {
+        error
+    } else {
+        error.context(detail.join("\n\t"))
+    }
+}
+
+/// Adds advice for fixing the likely cause of an error response from the
+/// datasets API, based on its status code.
 ///
-/// # Errors
+/// The cause of a 409 response depends on the request, so `conflict_advice`
+/// (if any) is added for those. The status error stays in the error's chain,
+/// so it can still be downcast to a [reqwest::Error].
+fn with_status_advice(error: Error, conflict_advice: Option<&'static str>) -> Error {
+    let status = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .and_then(|status_err| status_err.status());
+    let advice = match status {
+        Some(StatusCode::UNAUTHORIZED) => Some(
+            "The datasets API didn't accept your credentials. Check that the jwt in the \
+             [database] section of your bolster config file is correct, or request a new \
+             config file if it has expired.",
+        ),
+        Some(StatusCode::FORBIDDEN) => Some(
+            "The datasets API denied access. Your bolster config doesn't have permission to \
+             access this dataset or perform this operation.",
+        ),
+        Some(StatusCode::CONFLICT) => conflict_advice,
+        _ => None,
+    };
+    match advice {
+        Some(advice) => error.context(advice),
+        None => error,
+    }
+}
+
+/// Columns requested when listing datasets with all of their files embedded.
+const DATASET_SELECT: &str = "*,files(*)";
+
+/// Columns requested when listing dataset summaries.
 ///
-/// Returns an error if the datasets server returns a non-200 response (e.g. if
-/// auth credentials are invalid, if server is unreachable) or if the returned
-/// data is malformed (e.g. not json).
-pub async fn datasets_get(
+/// Uses PostgREST aggregate functions, so the datasets API computes each
+/// dataset's file count and total filesize instead of returning every file.
+const DATASET_SUMMARY_SELECT: &str = "dataset_id,system_id,created_date,metadata,\
+                                      files(file_count:count(),total_filesize:filesize.sum())";
+
+/// Build a datasets list request selecting `select` and filtered by `params`.
+fn build_datasets_get(
     configuration: &DatabaseApiConfig,
     params: &DatasetGetRequest,
-) -> Result<Vec<Dataset>> {
+    select: &str,
+) -> RequestBuilder {
     debug!("building get request for: {:?}", params);
     let client = &configuration.client;
 
     let mut api_url = configuration.base_url.clone();
     api_url.set_path("datasets");
-    api_url.set_query(Some("select=*,files(*)"));
+    api_url.set_query(Some(&format!("select={}", select)));
     let mut req_builder = client.get(api_url.as_str());
 
     if let Some(dataset_id) = &params.dataset_id {
@@ -186,10 +402,22 @@ pub async fn datasets_get(
     if let Some(after_date) = &params.after_date {
         req_builder = req_builder.query(&[("created_date", format!("gte.{}", after_date))]);
     }
-    // TODO: Implement metadata CLI input
-    // Related to
-    // - https://gitlab.com/tangram-vision/oss/bolster/-/issues/1
-    // - https://gitlab.com/tangram-vision/oss/bolster/-/issues/4
+    // Tags are stored as an array in metadata, so use a jsonb containment
+    // query, e.g. metadata=cs.{"tags":["outdoor"]}
+    if let Some(tag) = &params.tag {
+        req_builder =
+            req_builder.query(&[("metadata", format!("cs.{}", json!({ "tags": [tag] })))]);
+    }
+    // Multiple filters on the same request are AND'd together by PostgREST,
+    // e.g. metadata->>location=eq.lab&metadata->>operator=eq.alice
+    for (key, value) in &params.metadata {
+        req_builder =
+            req_builder.query(&[(format!("metadata->>{}", key), format!("eq.{}", value))]);
+    }
+    // * is PostgREST's wildcard for like/ilike, e.g. metadata->>name=ilike.*lab*
+    if let Some(name) = &params.name_contains {
+        req_builder = req_builder.query(&[("metadata->>name", format!("ilike.*{}*", name))]);
+    }
 
     if let Some(order) = &params.order {
         req_builder = req_builder.query(&[("order", order.to_database_field())]);
@@ -201,10 +429,27 @@ pub async fn datasets_get(
         req_builder = req_builder.query(&[("offset", offset)]);
     }
 
-    let response = req_builder.send().await?;
+    req_builder
+}
+
+/// Get a list of datasets and their files.
+///
+/// # Errors
+///
+/// Returns an error if the datasets server returns a non-200 response (e.g. if
+/// auth credentials are invalid, if server is unreachable) or if the returned
+/// data is malformed (e.g. not json).
+pub async fn datasets_get(
+    configuration: &DatabaseApiConfig,
+    params: &DatasetGetRequest,
+) -> Result<Vec<Dataset>> {
+    let req_builder = build_datasets_get(configuration, params, DATASET_SELECT);
+    let response = send_with_retry(configuration, req_builder, true).await?;
 
     debug!("status: {}", response.status());
-    let content: serde_json::Value = check_response(response).await?;
+    let content: serde_json::Value = check_response(response)
+        .await
+        .map_err(|e| with_status_advice(e, None))?;
     debug!("content: {}", content);
 
     let datasets: Vec<Dataset> = serde_json::from_value(content.clone())
@@ -212,6 +457,34 @@ pub async fn datasets_get(
     Ok(datasets)
 }
 
+/// Get a list of datasets with the number and total size of their files.
+///
+/// Unlike [datasets_get], files aren't returned, so this stays fast for
+/// datasets with many files.
+///
+/// # Errors
+///
+/// Returns an error if the datasets server returns a non-200 response (e.g. if
+/// auth credentials are invalid, if server is unreachable) or if the returned
+/// data is malformed (e.g. not json).
+pub async fn datasets_get_summaries(
+    configuration: &DatabaseApiConfig,
+    params: &DatasetGetRequest,
+) -> Result<Vec<DatasetSummary>> {
+    let req_builder = build_datasets_get(configuration, params, DATASET_SUMMARY_SELECT);
+    let response = send_with_retry(configuration, req_builder, true).await?;
+
+    debug!("status: {}", response.status());
+    let content: serde_json::Value = check_response(response)
+        .await
+        .map_err(|e| with_status_advice(e, None))?;
+    debug!("content: {}", content);
+
+    let summaries: Vec<DatasetSummary> = serde_json::from_value(content.clone())
+        .with_context(|| format!("JSON from Datasets API was malformed: {}", content))?;
+    Ok(summaries)
+}
+
 /// Create a new dataset in the datasets database.
 ///
 /// The returned dataset contains the dataset's id, which should be recorded to
@@ -240,10 +513,13 @@ pub async fn datasets_post(
     });
     req_builder = req_builder.json(&req_body);
 
-    let response = req_builder.send().await?;
+    // Not idempotent: a timed-out request may still have been applied
+    let response = send_with_retry(configuration, req_builder, false).await?;
 
     debug!("status: {}", response.status());
-    let content: serde_json::Value = check_response(response).await?;
+    let content: serde_json::Value = check_response(response)
+        .await
+        .map_err(|e| with_status_advice(e, None))?;
     debug!("content: {}", content);
 
     let mut datasets: Vec<DatasetNoFiles> = serde_json::from_value(content.clone())
@@ -255,8 +531,54 @@ pub async fn datasets_post(
         .ok_or_else(|| anyhow!("Database returned no info for newly-created Dataset!"))
 }
 
+/// Replace the metadata of an existing dataset.
+///
+/// # Errors
+///
+/// Returns an error if the datasets server returns a non-200 response (e.g. if
+/// auth credentials are invalid, if server is unreachable) or if the returned
+/// data is malformed (e.g. not json).
+///
+/// Returns an error if no dataset matches the given dataset id.
+pub async fn datasets_patch(
+    configuration: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    metadata: serde_json::Value,
+) -> Result<DatasetNoFiles> {
+    debug!("Building patch request for: {} {:?}", dataset_id, metadata);
+    let client = &configuration.client;
+
+    let mut api_url = configuration.base_url.clone();
+    api_url.set_path("datasets");
+    let mut req_builder = client
+        .patch(api_url.as_str())
+        .query(&[("dataset_id", format!("eq.{}", dataset_id))]);
+
+    let req_body = json!({
+        "metadata": metadata,
+    });
+    req_builder = req_builder.json(&req_body);
+
+    let response = send_with_retry(configuration, req_builder, true).await?;
+
+    debug!("status: {}", response.status());
+    let content: serde_json::Value = check_response(response)
+        .await
+        .map_err(|e| with_status_advice(e, None))?;
+    debug!("content: {}", content);
+
+    let mut datasets: Vec<DatasetNoFiles> = serde_json::from_value(content.clone())
+        .with_context(|| format!("JSON from Datasets API was malformed: {}", content))?;
+    datasets.pop().ok_or_else(|| {
+        anyhow!(
+            "Database returned no info for updated Dataset {}!",
+            dataset_id
+        )
+    })
+}
+
 /// Get a list of files in a specified dataset, optionally filtered by
-/// prefix(es).
+/// prefix(es) and sorted by `order`.
 ///
 /// # Errors
 ///
@@ -267,6 +589,7 @@ pub async fn files_get(
     configuration: &DatabaseApiConfig,
     dataset_id: Uuid,
     prefixes: Vec<String>,
+    order: Option<FileOrdering>,
 ) -> Result<Vec<UploadedFile>> {
     debug!(
         "building files get request for: {} {:?}",
@@ -278,7 +601,10 @@ pub async fn files_get(
     api_url.set_path("files");
     let req_builder = client.get(api_url.as_str());
 
-    let req_builder = req_builder.query(&[("dataset_id", format!("eq.{}", dataset_id))]);
+    let mut req_builder = req_builder.query(&[("dataset_id", format!("eq.{}", dataset_id))]);
+    if let Some(order) = &order {
+        req_builder = req_builder.query(&[("order", order.to_database_field())]);
+    }
 
     // Example query strings:
     // bolster.tangramvision.com/files/?dataset_id={dataset-uuid}
@@ -300,10 +626,123 @@ pub async fn files_get(
         )])
     };
 
-    let response = req_builder.send().await?;
+    let response = send_with_retry(configuration, req_builder, true).await?;
+
+    debug!("status: {}", response.status());
+    let content: serde_json::Value = check_response(response)
+        .await
+        .map_err(|e| with_status_advice(e, None))?;
+    debug!("content: {}", content);
+
+    let files: Vec<UploadedFile> = serde_json::from_value(content.clone())
+        .with_context(|| format!("JSON from Files API was malformed: {}", content))?;
+    Ok(files)
+}
+
+/// Get one page of files in a specified dataset, sorted by `order` (oldest
+/// first by default), skipping the first `offset` files and returning at most
+/// `limit`.
+///
+/// Files are ordered by id after `order`, so files that sort equally (e.g.
+/// files created together) don't shift between pages.
+///
+/// # Errors
+///
+/// Returns an error if the datasets server returns a non-200 response (e.g. if
+/// auth credentials are invalid, if server is unreachable) or if the returned
+/// data is malformed (e.g. not json).
+pub async fn files_get_page(
+    configuration: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    order: Option<&FileOrdering>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<UploadedFile>> {
+    debug!(
+        "building files get page request for: {} (limit {}, offset {})",
+        dataset_id, limit, offset
+    );
+    let client = &configuration.client;
+
+    let mut api_url = configuration.base_url.clone();
+    api_url.set_path("files");
+    let req_builder = client.get(api_url.as_str()).query(&[
+        ("dataset_id", format!("eq.{}", dataset_id)),
+        (
+            "order",
+            format!(
+                "{},file_id.asc",
+                order
+                    .unwrap_or(&FileOrdering::CreatedDateAsc)
+                    .to_database_field()
+            ),
+        ),
+        ("limit", limit.to_string()),
+        ("offset", offset.to_string()),
+    ]);
+
+    let response = send_with_retry(configuration, req_builder, true).await?;
+
+    debug!("status: {}", response.status());
+    let content: serde_json::Value = check_response(response)
+        .await
+        .map_err(|e| with_status_advice(e, None))?;
+    debug!("content: {}", content);
+
+    let files: Vec<UploadedFile> = serde_json::from_value(content.clone())
+        .with_context(|| format!("JSON from Files API was malformed: {}", content))?;
+    Ok(files)
+}
+
+/// Get one page of files across every dataset the user can access, optionally
+/// filtered to files whose path contains `path_contains` (case-insensitive).
+///
+/// Files are sorted by `order` (oldest first by default) then by id, skipping
+/// the first `offset` files and returning at most `limit`.
+///
+/// # Errors
+///
+/// Returns an error if the datasets server returns a non-200 response (e.g. if
+/// auth credentials are invalid, if server is unreachable) or if the returned
+/// data is malformed (e.g. not json).
+pub async fn files_search(
+    configuration: &DatabaseApiConfig,
+    path_contains: Option<&str>,
+    order: Option<&FileOrdering>,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<UploadedFile>> {
+    debug!(
+        "building files search request for: {:?} (limit {}, offset {})",
+        path_contains, limit, offset
+    );
+    let client = &configuration.client;
+
+    let mut api_url = configuration.base_url.clone();
+    api_url.set_path("files");
+    let mut req_builder = client.get(api_url.as_str()).query(&[
+        (
+            "order",
+            format!(
+                "{},file_id.asc",
+                order
+                    .unwrap_or(&FileOrdering::CreatedDateAsc)
+                    .to_database_field()
+            ),
+        ),
+        ("limit", limit.to_string()),
+        ("offset", offset.to_string()),
+    ]);
+    if let Some(text) = path_contains {
+        req_builder = req_builder.query(&[("filepath", format!("ilike.*{}*", text))]);
+    }
+
+    let response = send_with_retry(configuration, req_builder, true).await?;
 
     debug!("status: {}", response.status());
-    let content: serde_json::Value = check_response(response).await?;
+    let content: serde_json::Value = check_response(response)
+        .await
+        .map_err(|e| with_status_advice(e, None))?;
     debug!("content: {}", content);
 
     let files: Vec<UploadedFile> = serde_json::from_value(content.clone())
@@ -342,12 +781,18 @@ pub async fn files_post(
     });
     req_builder = req_builder.json(&req_body);
 
-    let response = req_builder.send().await?;
-    // TODO: Add context to 409 response (dataset doesn't exist) OR validate it
-    // does before uploading to storage provider.
+    // Not idempotent: a timed-out request may still have been applied
+    let response = send_with_retry(configuration, req_builder, false).await?;
 
     debug!("status: {}", response.status());
-    let content: serde_json::Value = check_response(response).await?;
+    let content: serde_json::Value = check_response(response).await.map_err(|e| {
+        with_status_advice(
+            e,
+            Some(
+                "The datasets API rejected the file. The dataset may not exist -- check its UUID.",
+            ),
+        )
+    })?;
     debug!("response content: {}", content);
 
     let mut uploaded_files: Vec<UploadedFile> = serde_json::from_value(content.clone())
@@ -359,18 +804,34 @@ pub async fn files_post(
 
 /// Notify backend that uploading a dataset is complete.
 ///
-/// This API call may trigger backend processing or notifications.
+/// This API call may trigger backend processing or notifications. If provided,
+/// `processing_options` are passed along to control how the backend processes
+/// the dataset.
+///
+/// Uncalibrated datasets, uploaded without a plex or object-space file, leave
+/// out the corresponding file id.
+///
+/// A lost notification means the dataset is never processed, so it's attempted
+/// at least [DatabaseApiConfig::notify_attempts] times and is retried even
+/// after timeouts. Notifying is idempotent: if the backend responds that it was
+/// already notified about the dataset (e.g. by an earlier attempt that timed
+/// out), [NotifyOutcome::AlreadyNotified] is returned rather than an error.
+/// Other conflicts (e.g. an unknown dataset or file id) are errors.
 ///
 /// # Errors
 ///
 /// Returns an error if the datasets server returns a non-200 response (e.g. if
 /// auth credentials are invalid, if server is unreachable).
+///
+/// Returns an error if the response body reports an error status (see
+/// [check_notify_status]), even if the response itself was a 200.
 pub async fn datasets_notify_upload_complete(
     configuration: &DatabaseApiConfig,
     dataset_id: Uuid,
-    plex_file_id: Uuid,
-    object_space_file_id: Uuid,
-) -> Result<()> {
+    plex_file_id: Option<Uuid>,
+    object_space_file_id: Option<Uuid>,
+    processing_options: Option<serde_json::Value>,
+) -> Result<NotifyOutcome> {
     debug!(
         "Building datasets_notify_upload_complete post request for: {}",
         dataset_id
@@ -381,20 +842,96 @@ pub async fn datasets_notify_upload_complete(
     api_url.set_path("rpc/dataset_upload_complete");
     let mut req_builder = client.post(api_url.as_str());
 
-    let req_body = json!({
-        "dataset_id": dataset_id,
-        "plex_file_id": plex_file_id,
-        "object_space_file_id": object_space_file_id,
-    });
+    let mut req_body = json!({ "dataset_id": dataset_id });
+    if let Some(plex_file_id) = plex_file_id {
+        req_body["plex_file_id"] = json!(plex_file_id);
+    }
+    if let Some(object_space_file_id) = object_space_file_id {
+        req_body["object_space_file_id"] = json!(object_space_file_id);
+    }
+    if let Some(processing_options) = processing_options {
+        req_body["processing_options"] = processing_options;
+    }
     req_builder = req_builder.json(&req_body);
 
-    let response = req_builder.send().await?;
+    // Retried even after timeouts: a repeated notification is better than
+    // a lost one, which would mean the dataset is never processed
+    let attempts = configuration
+        .request_attempts
+        .max(configuration.notify_attempts);
+    let response = send_with_attempts(configuration, req_builder, true, attempts).await?;
 
     debug!("status: {}", response.status());
-    let content: serde_json::Value = check_response(response).await?;
+    if response.status() == StatusCode::CONFLICT {
+        // The backend may report an earlier notification as a conflict, but
+        // PostgREST also reports constraint violations (e.g. an unknown file
+        // id) as conflicts, so only the body tells them apart
+        let status_err = response.error_for_status_ref().unwrap_err();
+        let content: serde_json::Value = response.json().await.unwrap_or_default();
+        debug!("content: {}", content);
+        if let Ok(NotifyOutcome::AlreadyNotified) = check_notify_status(&content) {
+            return Ok(NotifyOutcome::AlreadyNotified);
+        }
+        return Err(with_status_advice(
+            status_error_with_detail(status_err, &content),
+            Some(NOTIFY_CONFLICT_ADVICE),
+        ));
+    }
+    let content: serde_json::Value = check_response(response)
+        .await
+        .map_err(|e| with_status_advice(e, None))?;
     debug!("content: {}", content);
 
-    Ok(())
+    check_notify_status(&content)
+}
+
+/// Advice for a conflict when notifying that an upload is complete, other than
+/// the backend having already been notified.
+const NOTIFY_CONFLICT_ADVICE: &str =
+    "The datasets API rejected the notification. Check that the dataset and its plex and \
+     object-space files exist.";
+
+/// Result of a successful upload-complete notification.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotifyOutcome {
+    /// The backend accepted the notification and will process the dataset.
+    Notified,
+    /// The backend had already been notified that the dataset's upload was
+    /// complete, so nothing new was triggered.
+    AlreadyNotified,
+}
+
+/// Checks the body of an upload-complete notification response for a failure
+/// status.
+///
+/// The backend responds with `[{"status": "ok"}]` on success, or with
+/// `[{"status": "already_notified"}]` if it was notified about the dataset
+/// before. It may respond with `{"status": "error", "message": "..."}`
+/// (optionally wrapped in an array) when processing couldn't be started,
+/// despite returning a 200.
+///
+/// # Errors
+///
+/// Returns an error containing the backend's message if the status is "error".
+pub fn check_notify_status(content: &serde_json::Value) -> Result<NotifyOutcome> {
+    let result = match content {
+        serde_json::Value::Array(results) => results.first(),
+        result => Some(result),
+    };
+    let status = result
+        .and_then(|r| r.get("status"))
+        .and_then(|s| s.as_str());
+    if status == Some("error") {
+        let message = result
+            .and_then(|r| r.get("message"))
+            .and_then(|m| m.as_str())
+            .unwrap_or("no message provided");
+        bail!("Backend failed to handle completed upload: {}", message);
+    }
+    if status == Some("already_notified") {
+        return Ok(NotifyOutcome::AlreadyNotified);
+    }
+    Ok(NotifyOutcome::Notified)
 }
 
 #[cfg(test)]
@@ -402,7 +939,7 @@ mod tests {
     use std::str::FromStr;
 
     use httpmock::{
-        Method::{GET, POST},
+        Method::{GET, PATCH, POST},
         MockServer,
     };
 
@@ -426,6 +963,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let req_builder = config.client.get(config.base_url.clone().as_str());
@@ -438,6 +976,37 @@ mod tests {
         mock.assert();
     }
 
+    #[tokio::test]
+    async fn test_requests_sent_through_proxy() {
+        // For http urls, the proxy receives the request itself (with the
+        // full url), so the mock server can stand in for the proxy
+        let proxy_server = MockServer::start();
+        let mock = proxy_server.mock(|when, then| {
+            when.method(GET).path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([]));
+        });
+
+        let proxy = ProxyConfig::new(Some(&proxy_server.base_url())).unwrap();
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse("http://database.invalid").unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &proxy,
+        )
+        .unwrap();
+        let response = config
+            .client
+            .get(config.base_url.join("datasets").unwrap())
+            .send()
+            .await
+            .unwrap();
+
+        check_response(response).await.unwrap();
+        mock.assert();
+    }
+
     #[tokio::test]
     async fn test_check_response_400_message_details_hint() {
         let server = MockServer::start();
@@ -456,6 +1025,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let req_builder = config.client.get(config.base_url.clone().as_str());
@@ -488,6 +1058,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let req_builder = config.client.get(config.base_url.clone().as_str());
@@ -522,6 +1093,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let req_builder = config.client.get(config.base_url.clone().as_str());
@@ -555,6 +1127,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let req_builder = config.client.get(config.base_url.clone().as_str());
@@ -588,6 +1161,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let req_builder = config.client.get(config.base_url.clone().as_str());
@@ -628,6 +1202,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let params = DatasetGetRequest::default();
@@ -642,6 +1217,41 @@ mod tests {
         assert_eq!(result.len(), 1);
     }
 
+    #[tokio::test]
+    async fn test_files_get_page_order() {
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .query_param("dataset_id", &format!("eq.{}", dataset_id))
+                // Keys are stored in the filepath column, and ties are broken
+                // by id so pages don't shift
+                .query_param("order", "filepath.desc,file_id.asc")
+                .query_param("limit", "10")
+                .query_param("offset", "0")
+                .path("/files");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([]));
+        });
+        let config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TEST".to_owned())
+                .unwrap();
+
+        let files = files_get_page(&config, dataset_id, Some(&FileOrdering::KeyDesc), 10, 0)
+            .await
+            .unwrap();
+
+        mock.assert();
+        assert!(files.is_empty());
+        assert_eq!(
+            FileOrdering::from_str("filesize.desc")
+                .unwrap()
+                .to_database_field(),
+            "filesize.desc"
+        );
+    }
+
     #[tokio::test]
     async fn test_datasets_get_query_params() {
         let server = MockServer::start();
@@ -670,10 +1280,11 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let params = DatasetGetRequest {
-            after_date: Some(NaiveDate::from_str("2021-01-01").unwrap()),
+            after_date: Some(CreatedDateBound::from_str("2021-01-01").unwrap()),
             order: Some(DatasetOrdering::CreatedDateDesc),
             limit: Some(17),
             ..Default::default()
@@ -689,35 +1300,348 @@ mod tests {
         assert_eq!(result.len(), 1);
     }
 
+    #[test]
+    fn test_created_date_bound_parsing() {
+        let midnight = CreatedDateBound::from_str("2021-01-01").unwrap();
+        assert_eq!(midnight.0, Utc.ymd(2021, 1, 1).and_hms(0, 0, 0));
+        assert_eq!(midnight.to_string(), "2021-01-01");
+
+        let afternoon = CreatedDateBound::from_str("2021-01-01T14:00:00+02:00").unwrap();
+        assert_eq!(afternoon.0, Utc.ymd(2021, 1, 1).and_hms(12, 0, 0));
+        assert_eq!(afternoon.to_string(), "2021-01-01T12:00:00Z");
+        assert!(midnight < afternoon);
+
+        let precise = CreatedDateBound::from_str("2021-01-01T14:00:00.25Z").unwrap();
+        assert_eq!(precise.to_string(), "2021-01-01T14:00:00.250Z");
+
+        assert!(CreatedDateBound::from_str("2021-01-01T14:00").is_err());
+        assert!(CreatedDateBound::from_str("01/01/2021").is_err());
+    }
+
     #[tokio::test]
-    async fn test_datasets_get_wrong_structure_json() {
+    async fn test_datasets_get_datetime_bounds() {
         let server = MockServer::start();
         let mock = server.mock(|when, then| {
             when.method(GET)
-                .header("Authorization", "Bearer TEST-TOKEN")
+                .query_param("created_date", "gte.2021-01-01T14:00:00Z")
+                .query_param("created_date", "lt.2021-01-02")
                 .path("/datasets");
             then.status(200)
                 .header("Content-Type", "application/json")
-                .json_body(json!({
-                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
-                    "created_date": "2021-02-03T21:21:57.713584+00:00",
-                    "url": "https://example.com/afd56ecf-9d87-4053-8c80-0d924f06da52/hello.txt",
-                    "metadata": {
-                        "description": "Test"
-                    }
-                }));
+                .json_body(json!([]));
         });
 
         let config = DatabaseApiConfig::new_with_params(
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
-        let params = DatasetGetRequest::default();
+        let params = DatasetGetRequest {
+            after_date: Some(CreatedDateBound::from_str("2021-01-01T14:00:00Z").unwrap()),
+            before_date: Some(CreatedDateBound::from_str("2021-01-02").unwrap()),
+            ..Default::default()
+        };
 
-        let result = datasets_get(&config, &params)
-            .await
+        datasets_get(&config, &params).await.unwrap();
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_datasets_get_summaries() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .header("Authorization", "Bearer TEST-TOKEN")
+                .query_param("system_id", "eq.robot-1")
+                .query_param(
+                    "select",
+                    "dataset_id,system_id,created_date,metadata,\
+                     files(file_count:count(),total_filesize:filesize.sum())",
+                )
+                .path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([
+                    {
+                        "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                        "created_date": "2021-02-03T21:21:57.713584+00:00",
+                        "system_id": "robot-1",
+                        "metadata": {},
+                        "files": [{"file_count": 3, "total_filesize": 3072}],
+                    },
+                    {
+                        "dataset_id": "d11cc371-f33b-4dad-ac2e-3c4cca30a256",
+                        "created_date": "2021-02-04T21:21:57.713584+00:00",
+                        "system_id": "robot-1",
+                        "metadata": {},
+                        "files": [{"file_count": 0, "total_filesize": null}],
+                    },
+                ]));
+        });
+        // The summary query must not embed every file
+        let files_mock = server.mock(|when, then| {
+            when.method(GET)
+                .query_param("select", "*,files(*)")
+                .path("/datasets");
+            then.status(500);
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let params = DatasetGetRequest {
+            system_id: Some("robot-1".to_owned()),
+            ..Default::default()
+        };
+
+        let result = datasets_get_summaries(&config, &params).await.unwrap();
+
+        mock.assert();
+        assert_eq!(files_mock.hits(), 0);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].files.file_count, 3);
+        assert_eq!(result[0].files.total_filesize, 3072);
+        assert_eq!(result[1].files.file_count, 0);
+        assert_eq!(result[1].files.total_filesize, 0);
+    }
+
+    #[tokio::test]
+    async fn test_datasets_get_tag_query_param() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .header("Authorization", "Bearer TEST-TOKEN")
+                .query_param("metadata", r#"cs.{"tags":["outdoor"]}"#)
+                .path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "system_id": "robot-1",
+                    "metadata": {
+                        "tags": ["outdoor"]
+                    },
+                    "files": [],
+                }]));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let params = DatasetGetRequest {
+            tag: Some("outdoor".to_owned()),
+            ..Default::default()
+        };
+
+        let result = datasets_get(&config, &params).await.unwrap();
+
+        mock.assert();
+        assert_eq!(result.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_datasets_get_name_contains_query_param() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .header("Authorization", "Bearer TEST-TOKEN")
+                .query_param("metadata->>name", "ilike.*parking*")
+                .path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "system_id": "robot-1",
+                    "metadata": {
+                        "name": "Parking lot loop"
+                    },
+                    "files": [],
+                }]));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let params = DatasetGetRequest {
+            name_contains: Some("parking".to_owned()),
+            ..Default::default()
+        };
+
+        let result = datasets_get(&config, &params).await.unwrap();
+
+        mock.assert();
+        assert_eq!(result.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_files_search_query_params() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/files")
+                .query_param("filepath", "ilike.*parking*")
+                .query_param("order", "filesize.desc,file_id.asc")
+                .query_param("limit", "20")
+                .query_param("offset", "40")
+                .matches(|req| {
+                    !req.query_params
+                        .iter()
+                        .flatten()
+                        .any(|(key, _)| key == "dataset_id")
+                });
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "file_id": "16fb2ac2-642a-4d7e-8233-b1835623b46b",
+                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "url": "https://bucket.s3.us-west-1.amazonaws.com/afd56ecf-9d87-4053-8c80-0d924f06da52/parking.bag",
+                    "filesize": 12,
+                    "version": "v1",
+                    "metadata": {},
+                }]));
+        });
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+
+        let files = files_search(
+            &config,
+            Some("parking"),
+            Some(&FileOrdering::FilesizeDesc),
+            20,
+            40,
+        )
+        .await
+        .unwrap();
+
+        mock.assert();
+        assert_eq!(files.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_datasets_get_metadata_query_params() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .header("Authorization", "Bearer TEST-TOKEN")
+                .query_param("metadata->>location", "eq.lab")
+                .query_param("metadata->>operator", "eq.alice")
+                .path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([]));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let params = DatasetGetRequest {
+            metadata: vec![
+                ("location".to_owned(), "lab".to_owned()),
+                ("operator".to_owned(), "alice".to_owned()),
+            ],
+            ..Default::default()
+        };
+
+        let result = datasets_get(&config, &params).await.unwrap();
+
+        mock.assert();
+        assert!(result.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_datasets_patch() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(PATCH)
+                .header("Authorization", "Bearer TEST-TOKEN")
+                .query_param("dataset_id", "eq.afd56ecf-9d87-4053-8c80-0d924f06da52")
+                .json_body(json!({"metadata": {"tags": ["outdoor"]}}))
+                .path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "system_id": "robot-1",
+                    "metadata": {
+                        "tags": ["outdoor"]
+                    },
+                }]));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+
+        let result = datasets_patch(&config, dataset_id, json!({"tags": ["outdoor"]}))
+            .await
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(result.metadata, json!({"tags": ["outdoor"]}));
+    }
+
+    #[tokio::test]
+    async fn test_datasets_get_wrong_structure_json() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .header("Authorization", "Bearer TEST-TOKEN")
+                .path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "url": "https://example.com/afd56ecf-9d87-4053-8c80-0d924f06da52/hello.txt",
+                    "metadata": {
+                        "description": "Test"
+                    }
+                }));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let params = DatasetGetRequest::default();
+
+        let result = datasets_get(&config, &params)
+            .await
             .expect_err("Expected json parsing error");
         let downcast = result.downcast_ref::<serde_json::Error>().unwrap();
 
@@ -745,6 +1669,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let params = DatasetGetRequest::default();
@@ -776,6 +1701,7 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let params = DatasetGetRequest::default();
@@ -787,9 +1713,78 @@ mod tests {
         mock.assert();
         assert!(result
             .to_string()
-            .contains("HTTP status client error (401 Unauthorized) for url"));
-        // Could add `.map_err` and `.with_context` to `.error_for_status` calls
-        // to prompt user to check their API key for 401 responses.
+            .contains("Check that the jwt in the [database] section of your bolster config file"));
+        assert!(format!("{:#}", result).contains("Message: JWSError JWSInvalidSignature"));
+        assert!(
+            format!("{:#}", result).contains("HTTP status client error (401 Unauthorized) for url")
+        );
+        let downcast = result.downcast_ref::<reqwest::Error>().unwrap();
+        assert_eq!(downcast.status(), Some(StatusCode::UNAUTHORIZED));
+    }
+
+    #[tokio::test]
+    async fn test_datasets_post_403_response() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/datasets");
+            then.status(403)
+                .header("Content-Type", "application/json")
+                .json_body(json!({"message": "permission denied for table datasets"}));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+
+        let result = datasets_post(&config, "robot-1".to_owned(), json!({}))
+            .await
+            .expect_err("Expected status code error");
+
+        mock.assert();
+        assert!(result
+            .to_string()
+            .contains("Your bolster config doesn't have permission"));
+        assert!(format!("{:#}", result).contains("permission denied for table datasets"));
+        let downcast = result.downcast_ref::<reqwest::Error>().unwrap();
+        assert_eq!(downcast.status(), Some(StatusCode::FORBIDDEN));
+    }
+
+    #[tokio::test]
+    async fn test_files_post_409_response() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/files");
+            then.status(409)
+                .header("Content-Type", "application/json")
+                .json_body(json!({"message": "insert or update on table \"files\" violates foreign key constraint"}));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let dataset_id = Uuid::new_v4();
+        let url = Url::parse(&format!(
+            "https://bucket.s3.us-west-1.amazonaws.com/{}/test.bag",
+            dataset_id
+        ))
+        .unwrap();
+
+        let result = files_post(&config, dataset_id, &url, 12, "v1".to_owned(), json!({}))
+            .await
+            .expect_err("Expected status code error");
+
+        mock.assert();
+        assert!(result.to_string().contains("The dataset may not exist"));
+        let downcast = result.downcast_ref::<reqwest::Error>().unwrap();
+        assert_eq!(downcast.status(), Some(StatusCode::CONFLICT));
     }
 
     #[tokio::test]
@@ -805,12 +1800,15 @@ mod tests {
                 .body("Should never see this due to timeout");
         });
 
-        let config = DatabaseApiConfig::new_with_params(
+        let mut config = DatabaseApiConfig::new_with_params(
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             1,
+            &ProxyConfig::default(),
         )
         .unwrap();
+        config.request_attempts = 2;
+        config.retry_backoff = Duration::from_millis(1);
         let params = DatasetGetRequest::default();
 
         let result = datasets_get(&config, &params)
@@ -818,11 +1816,131 @@ mod tests {
             .expect_err("Expected timeout error");
         let downcast = result.downcast_ref::<reqwest::Error>().unwrap();
 
-        mock.assert();
+        // Timeouts of reads are retried
+        mock.assert_hits(2);
         assert!(downcast.is_timeout());
         assert!(result.to_string().contains("operation timed out"));
     }
 
+    /// Config for a mock server that retries quickly.
+    fn fast_retry_config(server: &MockServer, timeout: u64) -> DatabaseApiConfig {
+        let mut config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            timeout,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        config.retry_backoff = Duration::from_millis(1);
+        config
+    }
+
+    #[tokio::test]
+    async fn test_datasets_get_retries_server_errors() {
+        let server = MockServer::start();
+        let mut failing = server.mock(|when, then| {
+            when.method(GET).path("/datasets");
+            then.status(503);
+        });
+        let config = fast_retry_config(&server, 10);
+
+        // Every attempt fails, so the last response is reported
+        let result = datasets_get(&config, &DatasetGetRequest::default())
+            .await
+            .expect_err("Every attempt failed");
+        failing.assert_hits(DEFAULT_REQUEST_ATTEMPTS);
+        let downcast = result.downcast_ref::<reqwest::Error>().unwrap();
+        assert_eq!(downcast.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        failing.delete();
+
+        // Client errors aren't retried
+        let rejected = server.mock(|when, then| {
+            when.method(GET).path("/datasets");
+            then.status(400).json_body(json!({"message": "bad"}));
+        });
+        datasets_get(&config, &DatasetGetRequest::default())
+            .await
+            .expect_err("Request was rejected");
+        rejected.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_recovers_after_server_error() {
+        let server = MockServer::start();
+        let mut failing = server.mock(|when, then| {
+            when.method(GET).path("/flaky");
+            then.status(502);
+        });
+        let mut config = fast_retry_config(&server, 10);
+        // Long enough to swap the mocks before the retry
+        config.retry_backoff = Duration::from_millis(300);
+        let url = format!("{}/flaky", server.base_url());
+
+        // The first attempt fails, then the endpoint recovers before the retry
+        let send = tokio::spawn(async move {
+            send_with_retry(&config, config.client.get(&url), true)
+                .await
+                .map(|response| response.status())
+        });
+        while failing.hits() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        failing.delete();
+        let recovered = server.mock(|when, then| {
+            when.method(GET).path("/flaky");
+            then.status(200);
+        });
+        assert_eq!(send.await.unwrap().unwrap(), StatusCode::OK);
+        recovered.assert();
+    }
+
+    #[tokio::test]
+    async fn test_datasets_post_isnt_retried_after_timeout() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/datasets");
+            then.status(201)
+                .delay(Duration::from_millis(1200))
+                .json_body(json!([]));
+        });
+        let config = fast_retry_config(&server, 1);
+
+        let result = datasets_post(&config, "robot-1".to_owned(), json!({}))
+            .await
+            .expect_err("Expected timeout error");
+        assert!(result
+            .downcast_ref::<reqwest::Error>()
+            .unwrap()
+            .is_timeout());
+        // The dataset may have been created, so it isn't created again
+        mock.assert_hits(1);
+    }
+
+    #[tokio::test]
+    async fn test_datasets_notify_upload_complete_retries_timeouts() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(200)
+                .delay(Duration::from_millis(1200))
+                .json_body(json!([{"status": "ok"}]));
+        });
+        let mut config = fast_retry_config(&server, 1);
+        config.request_attempts = 2;
+        config.notify_attempts = 1;
+
+        datasets_notify_upload_complete(
+            &config,
+            Uuid::new_v4(),
+            Some(Uuid::new_v4()),
+            Some(Uuid::new_v4()),
+            None,
+        )
+        .await
+        .expect_err("Expected timeout error");
+        mock.assert_hits(2);
+    }
+
     #[tokio::test]
     async fn test_datasets_notify_upload_complete() {
         let server = MockServer::start();
@@ -842,15 +1960,216 @@ mod tests {
             Url::parse(&server.base_url()).unwrap(),
             "TEST-TOKEN".to_owned(),
             10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let plex_file_id = Uuid::parse_str("bfd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let object_space_file_id = Uuid::parse_str("cb0daadc-554d-49d7-ba77-967754b15667").unwrap();
+
+        datasets_notify_upload_complete(
+            &config,
+            dataset_id,
+            Some(plex_file_id),
+            Some(object_space_file_id),
+            None,
+        )
+        .await
+        .unwrap();
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_datasets_notify_upload_complete_error_body() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!({
+                    "status": "error",
+                    "message": "Plex file could not be parsed",
+                }));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
         )
         .unwrap();
         let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
         let plex_file_id = Uuid::parse_str("bfd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
         let object_space_file_id = Uuid::parse_str("cb0daadc-554d-49d7-ba77-967754b15667").unwrap();
 
-        datasets_notify_upload_complete(&config, dataset_id, plex_file_id, object_space_file_id)
+        let error = datasets_notify_upload_complete(
+            &config,
+            dataset_id,
+            Some(plex_file_id),
+            Some(object_space_file_id),
+            None,
+        )
+        .await
+        .expect_err("Error status in body should be reported");
+        assert!(
+            error.to_string().contains(
+                "Backend failed to handle completed upload: Plex file could not be parsed"
+            ),
+            "{}",
+            error
+        );
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_datasets_notify_upload_complete_retries_at_least_notify_attempts() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(503);
+        });
+        let mut config = fast_retry_config(&server, 10);
+        config.request_attempts = 1;
+        config.notify_attempts = 4;
+
+        datasets_notify_upload_complete(&config, Uuid::new_v4(), None, None, None)
+            .await
+            .expect_err("Every attempt failed");
+        mock.assert_hits(4);
+    }
+
+    #[tokio::test]
+    async fn test_datasets_notify_upload_complete_already_notified() {
+        let server = MockServer::start();
+        let mut conflict = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(409)
+                .json_body(json!({"status": "already_notified"}));
+        });
+        let config = fast_retry_config(&server, 10);
+
+        let outcome = datasets_notify_upload_complete(&config, Uuid::new_v4(), None, None, None)
             .await
             .unwrap();
+        assert_eq!(outcome, NotifyOutcome::AlreadyNotified);
+        conflict.assert_hits(1);
+        conflict.delete();
+
+        let already = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(200)
+                .json_body(json!([{"status": "already_notified"}]));
+        });
+        let outcome = datasets_notify_upload_complete(&config, Uuid::new_v4(), None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(outcome, NotifyOutcome::AlreadyNotified);
+        already.assert();
+    }
+
+    #[tokio::test]
+    async fn test_datasets_notify_upload_complete_other_conflict() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(409).json_body(json!({
+                "code": "23503",
+                "message": "insert or update on table \"upload_notifications\" violates foreign key constraint \"upload_notifications_plex_file_id_fkey\"",
+                "details": "Key (plex_file_id) is not present in table \"files\".",
+                "hint": null,
+            }));
+        });
+        let config = fast_retry_config(&server, 10);
+
+        let error = datasets_notify_upload_complete(
+            &config,
+            Uuid::new_v4(),
+            Some(Uuid::new_v4()),
+            None,
+            None,
+        )
+        .await
+        .expect_err("A foreign key violation isn't a repeated notification");
+        mock.assert();
+        let message = format!("{:#}", error);
+        assert!(message.contains(NOTIFY_CONFLICT_ADVICE), "{}", message);
+        assert!(
+            message.contains("Details: Key (plex_file_id) is not present"),
+            "{}",
+            message
+        );
+        let downcast = error.downcast_ref::<reqwest::Error>().unwrap();
+        assert_eq!(downcast.status(), Some(StatusCode::CONFLICT));
+    }
+
+    #[test]
+    fn test_check_notify_status() {
+        assert_eq!(
+            check_notify_status(&json!([{"status": "ok"}])).unwrap(),
+            NotifyOutcome::Notified
+        );
+        check_notify_status(&json!([])).unwrap();
+        check_notify_status(&json!({"status": "ok"})).unwrap();
+        assert_eq!(
+            check_notify_status(&json!({"status": "already_notified"})).unwrap(),
+            NotifyOutcome::AlreadyNotified
+        );
+
+        let error = check_notify_status(&json!([{"status": "error", "message": "oops"}]))
+            .expect_err("Error status should be an error");
+        assert!(error.to_string().contains("oops"), "{}", error);
+        let error = check_notify_status(&json!({"status": "error"}))
+            .expect_err("Error status should be an error");
+        assert!(
+            error.to_string().contains("no message provided"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_datasets_notify_upload_complete_with_processing_options() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .header("Authorization", "Bearer TEST-TOKEN")
+                .json_body(json!({
+                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "plex_file_id": "bfd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "object_space_file_id": "cb0daadc-554d-49d7-ba77-967754b15667",
+                    "processing_options": {"pipeline": "fast", "iterations": 3},
+                }))
+                .path("/rpc/dataset_upload_complete");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "status": "ok",
+                }]));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &ProxyConfig::default(),
+        )
+        .unwrap();
+        let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let plex_file_id = Uuid::parse_str("bfd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let object_space_file_id = Uuid::parse_str("cb0daadc-554d-49d7-ba77-967754b15667").unwrap();
+
+        datasets_notify_upload_complete(
+            &config,
+            dataset_id,
+            Some(plex_file_id),
+            Some(object_space_file_id),
+            Some(json!({"pipeline": "fast", "iterations": 3})),
+        )
+        .await
+        .unwrap();
 
         mock.assert();
     }
@@ -0,0 +1,516 @@
+//! Sending database and cloud storage requests through an HTTP proxy.
+//!
+//! Proxies are read from the `[network]` section of the bolster config or the
+//! standard `HTTP_PROXY`/`HTTPS_PROXY`/`ALL_PROXY`/`NO_PROXY` env vars (see
+//! [ProxyConfig]). Database requests are proxied by reqwest (see
+//! [ProxyConfig::reqwest_proxy]), while cloud storage requests are tunneled
+//! through the proxy by [ProxyConnector].
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::{bail, Context as _, Result};
+use hyper::{client::HttpConnector, service::Service, Uri};
+use log::debug;
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::app_config::NetworkConfig;
+
+/// Proxies that requests are sent through.
+///
+/// A proxy configured in the `[network]` section of the bolster config is used
+/// for all requests. Otherwise, requests to http urls use `HTTP_PROXY` and
+/// requests to https urls use `HTTPS_PROXY`, falling back to `ALL_PROXY`
+/// (lowercase names take precedence, as with curl).
+///
+/// Hosts listed in `NO_PROXY` (comma-separated, matching subdomains too, or
+/// `*` for all hosts) are always connected to directly.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+    /// Proxy for requests to http urls
+    http: Option<Url>,
+    /// Proxy for requests to https urls
+    https: Option<Url>,
+    /// Hosts (and their subdomains) that are connected to directly
+    no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Reads proxies from the `[network]` section of the bolster config and
+    /// from env vars.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `[network]` section is invalid.
+    ///
+    /// Wraps [ProxyConfig::new] -- see its documentation for other possible
+    /// errors.
+    pub fn from_config(config: &config::Config) -> Result<Self> {
+        let network = config
+            .clone()
+            .try_into::<NetworkConfig>()
+            .context("Config error: invalid [network] section")?
+            .network;
+        Self::new(network.proxy.as_deref())
+    }
+
+    /// Uses the `configured` proxy for all requests if given, otherwise reads
+    /// proxies from env vars.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a proxy isn't a valid http:// url (a url without a
+    /// scheme is assumed to be http).
+    pub fn new(configured: Option<&str>) -> Result<Self> {
+        Self::from_vars(configured, |name| std::env::var(name).ok())
+    }
+
+    /// See [ProxyConfig::new], reading env vars with `var`.
+    fn from_vars<F>(configured: Option<&str>, var: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        // Returns the name of the var that's set along with its value
+        let read = |name: &str| {
+            let lowercase = name.to_lowercase();
+            [lowercase.as_str(), name]
+                .iter()
+                .find_map(|name| Some((name.to_string(), var(name)?)))
+                .filter(|(_, value)| !value.is_empty())
+        };
+        let parse = |source: Option<(String, String)>| {
+            source
+                .map(|(name, value)| parse_proxy_url(&value, &name))
+                .transpose()
+        };
+
+        let (http, https) = match configured {
+            Some(proxy) => {
+                let url = parse_proxy_url(proxy, "[network] proxy")?;
+                (Some(url.clone()), Some(url))
+            }
+            None => {
+                let all = read("ALL_PROXY");
+                (
+                    parse(read("HTTP_PROXY").or_else(|| all.clone()))?,
+                    parse(read("HTTPS_PROXY").or(all))?,
+                )
+            }
+        };
+        let no_proxy = read("NO_PROXY")
+            .map(|(_, value)| {
+                value
+                    .split(',')
+                    .map(|host| {
+                        host.trim()
+                            .trim_start_matches("*.")
+                            .trim_start_matches('.')
+                            .to_lowercase()
+                    })
+                    .filter(|host| !host.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(ProxyConfig {
+            http,
+            https,
+            no_proxy,
+        })
+    }
+
+    /// Whether requests to `host` bypass the proxy (see `NO_PROXY`).
+    fn bypasses(&self, host: &str) -> bool {
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_lowercase();
+        self.no_proxy.iter().any(|entry| {
+            entry == "*"
+                || host == *entry
+                || (host.ends_with(entry.as_str())
+                    && host[..host.len() - entry.len()].ends_with('.'))
+        })
+    }
+
+    /// The proxy (if any) that requests to `host` using `scheme` (http or
+    /// https) are sent through.
+    pub fn proxy_for(&self, scheme: &str, host: &str) -> Option<&Url> {
+        let proxy = match scheme {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        }?;
+        if self.bypasses(host) {
+            None
+        } else {
+            Some(proxy)
+        }
+    }
+
+    /// A reqwest proxy that picks the proxy for each request with
+    /// [ProxyConfig::proxy_for].
+    ///
+    /// Once a proxy is added to a reqwest client, reqwest stops reading
+    /// proxies from env vars itself, so this config is the only source of
+    /// proxies.
+    pub fn reqwest_proxy(&self) -> reqwest::Proxy {
+        let config = self.clone();
+        reqwest::Proxy::custom(move |url| config.proxy_for(url.scheme(), url.host_str()?).cloned())
+    }
+}
+
+/// Parses a proxy url, assuming http if it has no scheme. `source` names where
+/// the proxy came from, for error messages.
+fn parse_proxy_url(value: &str, source: &str) -> Result<Url> {
+    let value_with_scheme = if value.contains("://") {
+        value.to_owned()
+    } else {
+        format!("http://{}", value)
+    };
+    let url = match Url::parse(&value_with_scheme) {
+        Ok(url) => url,
+        Err(e) => bail!("Invalid {} {} -- {}", source, value, e),
+    };
+    if url.scheme() != "http" {
+        bail!(
+            "Invalid {} {} -- only http:// proxies are supported",
+            source,
+            value
+        );
+    }
+    if url.host_str().is_none() {
+        bail!("Invalid {} {} -- the proxy has no host", source, value);
+    }
+    Ok(url)
+}
+
+/// Error returned by [ProxyConnector].
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Most bytes read while waiting for the proxy's response to a CONNECT
+/// request, to avoid buffering forever if the proxy misbehaves.
+const MAX_CONNECT_RESPONSE_BYTES: usize = 16 * 1024;
+
+/// Connects to cloud storage directly, or through the proxy that
+/// [ProxyConfig::proxy_for] gives for the destination.
+///
+/// Connections through a proxy are tunneled with an HTTP CONNECT request, so
+/// TLS is still negotiated with cloud storage itself (by wrapping this
+/// connector in an
+/// [HttpsConnector](https://docs.rs/hyper-tls/0.5.0/hyper_tls/struct.HttpsConnector.html)).
+#[derive(Debug, Clone)]
+pub struct ProxyConnector {
+    /// Opens TCP connections
+    http: HttpConnector,
+    /// Proxies to connect through
+    proxy: Arc<ProxyConfig>,
+}
+
+impl ProxyConnector {
+    /// Creates a connector that uses the proxies in `proxy`.
+    pub fn new(proxy: ProxyConfig) -> Self {
+        let mut http = HttpConnector::new();
+        // https urls are allowed, since TLS is handled by the wrapping
+        // HttpsConnector
+        http.enforce_http(false);
+        ProxyConnector {
+            http,
+            proxy: Arc::new(proxy),
+        }
+    }
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = TcpStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<TcpStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let proxy = self
+            .proxy
+            .proxy_for(dst.scheme_str().unwrap_or(""), dst.host().unwrap_or(""))
+            .cloned();
+        let mut http = self.http.clone();
+        Box::pin(async move {
+            match proxy {
+                None => Ok(http.call(dst).await?),
+                Some(proxy) => {
+                    let proxy_uri: Uri = format!(
+                        "http://{}:{}",
+                        proxy.host_str().unwrap_or_default(),
+                        proxy.port_or_known_default().unwrap_or(80)
+                    )
+                    .parse()?;
+                    debug!("Connecting to {} through proxy {}", dst, proxy_uri);
+                    let mut stream = http.call(proxy_uri).await?;
+                    tunnel(&mut stream, &dst, &proxy).await?;
+                    Ok(stream)
+                }
+            }
+        })
+    }
+}
+
+/// Asks the proxy that `stream` is connected to to open a tunnel to `dst`,
+/// authenticating with the proxy url's username and password (if any).
+///
+/// # Errors
+///
+/// Returns an error if reading from or writing to the proxy fails, or if the
+/// proxy doesn't respond with a 2xx status.
+async fn tunnel<S>(stream: &mut S, dst: &Uri, proxy: &Url) -> Result<(), BoxError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let host = dst.host().ok_or("Destination url has no host")?;
+    let port = dst
+        .port_u16()
+        .unwrap_or(if dst.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+    let mut request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+        host = host,
+        port = port
+    );
+    if !proxy.username().is_empty() {
+        let credentials = format!(
+            "{}:{}",
+            percent_decode_str(proxy.username()).decode_utf8_lossy(),
+            percent_decode_str(proxy.password().unwrap_or_default()).decode_utf8_lossy()
+        );
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64::encode(credentials)
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Nothing is sent through the tunnel until the first request, so the
+    // response is all that's read here
+    let mut response = Vec::new();
+    let mut buf = [0; 1024];
+    while !response.windows(4).any(|window| window == b"\r\n\r\n") {
+        if response.len() > MAX_CONNECT_RESPONSE_BYTES {
+            return Err("Proxy response to CONNECT request is too long".into());
+        }
+        let bytes_read = stream.read(&mut buf).await?;
+        if bytes_read == 0 {
+            return Err("Proxy closed the connection before responding".into());
+        }
+        response.extend_from_slice(&buf[..bytes_read]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    let is_success = status_line
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|status| status.starts_with('2'));
+    if !is_success {
+        return Err(format!(
+            "Proxy refused to connect to {}:{} -- {}",
+            host, port, status_line
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashMap;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn from_env(configured: Option<&str>, vars: &[(&str, &str)]) -> Result<ProxyConfig> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        ProxyConfig::from_vars(configured, |name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_proxy_config_from_env() {
+        let config = from_env(
+            None,
+            &[
+                ("HTTP_PROXY", "http://ignored:8080"),
+                ("http_proxy", "proxy.example.com:3128"),
+                ("ALL_PROXY", "http://all.example.com"),
+                ("NO_PROXY", "localhost, .internal.example.com,"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            config
+                .proxy_for("http", "s3.amazonaws.com")
+                .unwrap()
+                .as_str(),
+            "http://proxy.example.com:3128/"
+        );
+        assert_eq!(
+            config
+                .proxy_for("https", "s3.amazonaws.com")
+                .unwrap()
+                .as_str(),
+            "http://all.example.com/"
+        );
+        assert_eq!(config.proxy_for("http", "localhost"), None);
+        assert_eq!(config.proxy_for("https", "db.internal.example.com"), None);
+        assert_eq!(config.proxy_for("https", "internal.example.com"), None);
+        assert!(config
+            .proxy_for("https", "notinternal.example.com")
+            .is_some());
+
+        assert_eq!(from_env(None, &[]).unwrap(), ProxyConfig::default());
+        let no_proxy_all = from_env(None, &[("HTTPS_PROXY", "proxy:3128"), ("no_proxy", "*")]);
+        assert_eq!(
+            no_proxy_all.unwrap().proxy_for("https", "example.com"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_proxy_config_configured_overrides_env() {
+        let config = from_env(
+            Some("http://proxy.corp:8080"),
+            &[
+                ("HTTPS_PROXY", "http://env:3128"),
+                ("NO_PROXY", "localhost"),
+            ],
+        )
+        .unwrap();
+        for scheme in ["http", "https"].iter() {
+            assert_eq!(
+                config.proxy_for(scheme, "example.com").unwrap().as_str(),
+                "http://proxy.corp:8080/"
+            );
+        }
+        assert_eq!(config.proxy_for("https", "localhost"), None);
+    }
+
+    #[test]
+    fn test_proxy_config_invalid() {
+        assert_eq!(
+            from_env(Some("socks5://proxy:1080"), &[])
+                .unwrap_err()
+                .to_string(),
+            "Invalid [network] proxy socks5://proxy:1080 -- only http:// proxies are supported"
+        );
+        assert!(from_env(None, &[("https_proxy", "http://")])
+            .unwrap_err()
+            .to_string()
+            .starts_with("Invalid https_proxy http://"));
+    }
+
+    /// Accepts one connection as a proxy, returning the CONNECT request it
+    /// received. The tunnel is connected to `target`, or the request is
+    /// refused if there's no target.
+    pub(crate) async fn serve_proxy(listener: TcpListener, target: Option<String>) -> String {
+        let (mut client, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let bytes_read = client.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..bytes_read]);
+        }
+        match target {
+            Some(target) => {
+                let mut server = TcpStream::connect(target).await.unwrap();
+                client
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                    .await
+                    .unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+            }
+            None => {
+                client
+                    .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                    .await
+                    .unwrap();
+            }
+        }
+        String::from_utf8(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_proxy_connector_tunnels_through_proxy() {
+        let server = httpmock::MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/file");
+            then.status(200).body("through the proxy");
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_url = format!("http://user:p%40ss@{}", listener.local_addr().unwrap());
+        let proxy = tokio::spawn(serve_proxy(listener, Some(server.address().to_string())));
+
+        let config = from_env(Some(&proxy_url), &[]).unwrap();
+        let client = hyper::Client::builder().build::<_, hyper::Body>(ProxyConnector::new(config));
+        // The destination doesn't resolve, so it must be reached through the
+        // proxy
+        let resp = client
+            .get("http://storage.invalid:9000/file".parse().unwrap())
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        drop(client);
+        let request = proxy.await.unwrap();
+
+        assert_eq!(body, "through the proxy");
+        assert!(
+            request.starts_with(
+                "CONNECT storage.invalid:9000 HTTP/1.1\r\nHost: storage.invalid:9000\r\n"
+            ),
+            "{}",
+            request
+        );
+        assert!(
+            request.contains(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64::encode("user:p@ss")
+            )),
+            "{}",
+            request
+        );
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_proxy_connector_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_url = format!("http://{}", listener.local_addr().unwrap());
+        let proxy = tokio::spawn(serve_proxy(listener, None));
+
+        let config = from_env(Some(&proxy_url), &[]).unwrap();
+        let mut connector = ProxyConnector::new(config);
+        let error = connector
+            .call("https://storage.invalid/file".parse().unwrap())
+            .await
+            .unwrap_err();
+        proxy.await.unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "Proxy refused to connect to storage.invalid:443 -- HTTP/1.1 407 Proxy Authentication Required"
+        );
+    }
+}
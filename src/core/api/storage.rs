@@ -1,29 +1,59 @@
 //! Upload and download files to/from cloud storage.
 
-use std::cmp::{max, min};
+use std::{
+    cmp::{max, min},
+    collections::VecDeque,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use anyhow::{anyhow, bail, Context, Result};
-use byte_unit::{GIBIBYTE, MEBIBYTE};
-use futures::stream::{
-    futures_unordered::FuturesUnordered, try_unfold, Stream, StreamExt, TryStreamExt,
+use async_trait::async_trait;
+use byte_unit::{Byte, GIBIBYTE, MEBIBYTE};
+use bytes::Bytes;
+use futures::{
+    future::{self, Either},
+    stream::{
+        self, futures_unordered::FuturesUnordered, try_unfold, Stream, StreamExt, TryStreamExt,
+    },
 };
-use indicatif::{MultiProgress, ProgressBar};
+use hyper_tls::HttpsConnector;
 use log::debug;
 use read_progress_stream::ReadProgressStream;
 use reqwest::Url;
-use rusoto_core::Region;
-use rusoto_credential::StaticProvider;
+use rusoto_core::{Region, RusotoError};
+use rusoto_credential::{
+    AwsCredentials, CredentialsError, EnvironmentProvider, ProfileProvider, ProvideAwsCredentials,
+    StaticProvider,
+};
+use rusoto_s3::util::{PreSignedRequest, PreSignedRequestOption};
 use rusoto_s3::{
     CompleteMultipartUploadRequest, CompletedMultipartUpload, CompletedPart,
-    CreateMultipartUploadRequest, GetObjectRequest, PutObjectRequest, S3Client, StreamingBody,
+    CreateMultipartUploadRequest, GetBucketVersioningRequest, GetObjectRequest, HeadObjectError,
+    HeadObjectRequest, PutObjectRequest, S3Client, StreamingBody, UploadPartOutput,
     UploadPartRequest, S3,
 };
-use tokio::io::{AsyncRead, AsyncReadExt};
+use sha2::{Digest, Sha256};
+use strum_macros::{AsRefStr, EnumString, EnumVariantNames};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::Semaphore,
+};
 use tokio_util::codec;
 
 use crate::{
-    app_config::{AwsS3Config, DigitalOceanSpacesConfig, StorageProviderChoices},
-    core::commands,
+    app_config::{
+        AwsS3Config, ChecksumAlgorithm, CredentialSource, DigitalOceanSpacesConfig,
+        S3CompatibleConfig, StorageApiKeys, StorageProviderChoices,
+    },
+    core::{
+        api::proxy::{ProxyConfig, ProxyConnector},
+        progress::ProgressReporter,
+    },
 };
 
 /// Controls how many requests can be in-flight at a time (for one multipart
@@ -33,37 +63,441 @@ use crate::{
 /// (chunk size also plays a part).
 pub const CONCURRENT_REQUEST_LIMIT: usize = 10;
 
+/// How many times a storage request is attempted before giving up.
+pub const REQUEST_ATTEMPTS: usize = 4;
+
+/// How many consecutive failed requests an S3Client may have before it is
+/// discarded and replaced with a new one.
+///
+/// Retrying on a client whose connection is wedged tends to keep failing, so
+/// a fresh client (with a fresh connection pool) is used instead.
+pub const FAILURES_BEFORE_NEW_CLIENT: usize = 2;
+
+/// Delay before retrying a failed request, multiplied by the attempt number.
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Running totals of bytes transferred, shared by concurrent transfers (clones
+/// share the same totals).
+///
+/// File bytes count file data once, as each file (or part of a multipart
+/// upload) transfers successfully. Wire bytes count all request and response
+/// body data actually sent or received, including retried parts and failed
+/// transfers, so on an unreliable connection they exceed file bytes. HTTP
+/// headers and TLS framing aren't counted.
+#[derive(Clone, Debug, Default)]
+pub struct TransferStats {
+    /// Bytes of file data transferred successfully
+    file_bytes: Arc<AtomicU64>,
+    /// Bytes of body data sent or received, including retries
+    wire_bytes: Arc<AtomicU64>,
+}
+
+impl TransferStats {
+    /// Records file data that transferred successfully.
+    pub fn add_file_bytes(&self, bytes: u64) {
+        self.file_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Records body data sent or received over the network.
+    pub fn add_wire_bytes(&self, bytes: u64) {
+        self.wire_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Bytes of file data transferred successfully so far.
+    pub fn file_bytes(&self) -> u64 {
+        self.file_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Bytes of body data sent or received over the network so far.
+    pub fn wire_bytes(&self) -> u64 {
+        self.wire_bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// Builds a new S3Client, used to replace clients that keep failing.
+pub type ClientFactory = Arc<dyn Fn() -> S3Client + Send + Sync>;
+
+/// An S3Client that retries failed requests and replaces itself after
+/// repeated failures.
+///
+/// Only failures that may be transient are retried: dispatch errors (e.g.
+/// timeouts, dropped connections), 5xx responses, and expired-signature
+/// responses. After an expired signature, the client is replaced right away so
+/// the retry is signed with freshly loaded credentials.
+pub struct RetryingClient {
+    /// Client that requests are currently made with
+    client: S3Client,
+    /// Builds replacement clients
+    new_client: ClientFactory,
+    /// Number of failed requests since the current client last succeeded
+    consecutive_failures: usize,
+}
+
+impl RetryingClient {
+    /// Create a retrying client, using the factory to build its first client.
+    pub fn new(new_client: ClientFactory) -> Self {
+        RetryingClient {
+            client: new_client(),
+            new_client,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// Make a request, retrying up to [REQUEST_ATTEMPTS] times in total.
+    ///
+    /// `make_request` is called for each attempt with the client to use, so it
+    /// must build a fresh request every time (request bodies can only be sent
+    /// once).
+    ///
+    /// # Errors
+    ///
+    /// Returns the last error if the request fails with a non-transient error
+    /// or if every attempt fails.
+    pub async fn request<T, E, F, Fut>(&mut self, make_request: F) -> Result<T, RusotoError<E>>
+    where
+        F: Fn(S3Client) -> Fut,
+        Fut: Future<Output = Result<T, RusotoError<E>>>,
+        E: std::fmt::Debug,
+    {
+        let mut attempt = 1;
+        loop {
+            match make_request(self.client.clone()).await {
+                Ok(resp) => {
+                    self.consecutive_failures = 0;
+                    return Ok(resp);
+                }
+                Err(e) => {
+                    self.consecutive_failures += 1;
+                    let signature_expired = is_signature_expired(&e);
+                    if attempt >= REQUEST_ATTEMPTS || !(signature_expired || is_transient(&e)) {
+                        return Err(e);
+                    }
+                    debug!("Request attempt {} failed, retrying: {:?}", attempt, e);
+                    if signature_expired {
+                        debug!("Replacing S3Client to re-sign request with fresh credentials");
+                        self.client = (self.new_client)();
+                        self.consecutive_failures = 0;
+                    } else if self.consecutive_failures >= FAILURES_BEFORE_NEW_CLIENT {
+                        debug!(
+                            "Replacing S3Client after {} consecutive failures",
+                            self.consecutive_failures
+                        );
+                        self.client = (self.new_client)();
+                        self.consecutive_failures = 0;
+                    }
+                    tokio::time::sleep(RETRY_BACKOFF * attempt as u32).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Whether a failed request is worth retrying.
+fn is_transient<E>(error: &RusotoError<E>) -> bool {
+    match error {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(resp) => resp.status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// Error codes that cloud storage responds with when a request's signature or
+/// temporary credentials have expired.
+const SIGNATURE_EXPIRED_CODES: [&str; 3] = [
+    "<Code>ExpiredToken</Code>",
+    "<Code>RequestExpired</Code>",
+    "<Code>TokenRefreshRequired</Code>",
+];
+
+/// Whether a request failed because its signature or credentials expired, so
+/// it should succeed if signed again with fresh credentials.
+fn is_signature_expired<E>(error: &RusotoError<E>) -> bool {
+    match error {
+        RusotoError::Unknown(resp) if resp.status.is_client_error() => {
+            let body = String::from_utf8_lossy(&resp.body);
+            SIGNATURE_EXPIRED_CODES
+                .iter()
+                .any(|code| body.contains(code))
+                || body.contains("Request has expired")
+        }
+        _ => false,
+    }
+}
+
+/// Details of a failed request, as described by the XML error body that
+/// cloud storage responds with.
+///
+/// See [S3 error responses](https://docs.aws.amazon.com/AmazonS3/latest/API/ErrorResponses.html).
+#[derive(Debug, PartialEq)]
+struct StorageErrorDetails {
+    /// Error code, e.g. "AccessDenied" or "SignatureDoesNotMatch"
+    code: String,
+    /// Human-readable description of the error
+    message: Option<String>,
+    /// Identifier of the failed request, for support requests to the
+    /// storage provider
+    request_id: Option<String>,
+}
+
+impl std::fmt::Display for StorageErrorDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (RequestId: {})", request_id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Header that S3 (and compatible providers) identify responses by.
+const S3_REQUEST_ID_HEADER: &str = "x-amz-request-id";
+
+/// Text content of the first `<name>` element in an XML document, with the
+/// predefined XML entities unescaped.
+fn xml_element_text(xml: &str, name: &str) -> Option<String> {
+    let start_tag = format!("<{}>", name);
+    let end_tag = format!("</{}>", name);
+    let start = xml.find(&start_tag)? + start_tag.len();
+    let len = xml[start..].find(&end_tag)?;
+    let text = xml[start..start + len]
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    Some(text)
+}
+
+/// Parse the code, message, and request id out of the XML body of a failed
+/// request, returning `None` if there's no error body (e.g. for HEAD
+/// requests) or the error was already parsed by rusoto.
+///
+/// Rusoto only parses the few errors it models for each request (e.g.
+/// NoSuchKey for GetObject), leaving everything else -- including auth
+/// errors -- as a status code and raw body.
+fn storage_error_details<E>(error: &RusotoError<E>) -> Option<StorageErrorDetails> {
+    match error {
+        RusotoError::Unknown(resp) => {
+            let body = String::from_utf8_lossy(&resp.body);
+            let code = xml_element_text(&body, "Code")?;
+            let request_id = xml_element_text(&body, "RequestId").or_else(|| {
+                resp.headers
+                    .get(S3_REQUEST_ID_HEADER)
+                    .map(ToOwned::to_owned)
+            });
+            Some(StorageErrorDetails {
+                code,
+                message: xml_element_text(&body, "Message"),
+                request_id,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Convert the error of a failed cloud storage request, attaching the error
+/// details from its XML body (see [storage_error_details]) as context so the
+/// user sees e.g. "AccessDenied: Access Denied" instead of just a status code.
+fn with_error_details<T, E>(result: Result<T, RusotoError<E>>) -> Result<T>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    result.map_err(|error| match storage_error_details(&error) {
+        Some(details) => anyhow::Error::new(error).context(details.to_string()),
+        None => error.into(),
+    })
+}
+
+/// Build a [ClientFactory] for the given storage config, optionally setting
+/// the read buffer size of each client's HTTP connections.
+fn client_factory(config: &StorageConfig, read_buf_size: Option<usize>) -> ClientFactory {
+    let credentials = config.credentials.clone();
+    let region = config.region.clone();
+    let proxy = config.proxy.clone();
+    Arc::new(move || {
+        let mut http_config = rusoto_core::HttpConfig::new();
+        if let Some(size) = read_buf_size {
+            http_config.read_buf_size(size);
+        }
+        let connector = HttpsConnector::new_with_connector(ProxyConnector::new(proxy.clone()));
+        let dispatcher =
+            rusoto_core::HttpClient::from_connector_with_config(connector, http_config);
+        // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
+        S3Client::new_with(dispatcher, credentials.clone(), region.clone())
+    })
+}
+
+/// Source of credentials for authenticating with cloud storage.
+#[derive(Debug, Clone)]
+pub enum StorageCredentials {
+    /// Access and secret keys provided directly in the bolster config file
+    Static(StaticProvider),
+    /// Keys read from a profile in the AWS credentials file
+    Profile(ProfileProvider),
+    /// Keys read from the standard AWS env vars
+    Environment(EnvironmentProvider),
+}
+
+#[async_trait]
+impl ProvideAwsCredentials for StorageCredentials {
+    async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        match self {
+            StorageCredentials::Static(provider) => provider.credentials().await,
+            StorageCredentials::Profile(provider) => provider.credentials().await,
+            StorageCredentials::Environment(provider) => provider.credentials().await,
+        }
+    }
+}
+
+impl StorageCredentials {
+    /// Build a credentials source from the keys in a storage config section,
+    /// according to its `credential_source` (see [CredentialSource]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the section contains both keys and a profile, if
+    /// only one of the keys is present, or if static credentials are used but
+    /// neither keys nor a profile are present.
+    ///
+    /// Returns an error if the credential source is `environment` but keys or
+    /// a profile are present (they would be ignored).
+    ///
+    /// Returns an error if a profile is used but the default location of the
+    /// AWS credentials file can't be determined.
+    pub fn from_keys(keys: StorageApiKeys, section: &str) -> Result<StorageCredentials> {
+        let source = keys.credential_source.unwrap_or(if keys.profile.is_some() {
+            CredentialSource::Profile
+        } else {
+            CredentialSource::Static
+        });
+        let has_keys = keys.access_key.is_some() || keys.secret_key.is_some();
+        match source {
+            CredentialSource::Static => match (keys.access_key, keys.secret_key, keys.profile) {
+                (Some(access_key), Some(secret_key), None) => Ok(StorageCredentials::Static(
+                    StaticProvider::new_minimal(access_key, secret_key),
+                )),
+                (_, _, Some(_)) => bail!(
+                    "Config error: [{}] must contain either a profile or access_key/secret_key, not both",
+                    section
+                ),
+                _ => bail!(
+                    "Config error: [{}] must contain both access_key and secret_key (or a profile)",
+                    section
+                ),
+            },
+            CredentialSource::Profile => {
+                if has_keys {
+                    bail!(
+                        "Config error: [{}] must contain either a profile or access_key/secret_key, not both",
+                        section
+                    );
+                }
+                let provider = match keys.profile {
+                    Some(profile) => ProfileProvider::with_default_credentials(profile),
+                    None => ProfileProvider::new(),
+                }
+                .with_context(|| {
+                    format!("Unable to locate AWS credentials file for [{}]", section)
+                })?;
+                Ok(StorageCredentials::Profile(provider))
+            }
+            CredentialSource::Environment => {
+                if has_keys || keys.profile.is_some() {
+                    bail!(
+                        "Config error: [{}] reads credentials from the environment, so it must not contain access_key, secret_key, or profile",
+                        section
+                    );
+                }
+                Ok(StorageCredentials::Environment(
+                    EnvironmentProvider::default(),
+                ))
+            }
+        }
+    }
+}
+
+/// Prefix of `--region` values that give an S3-compatible endpoint instead
+/// of an AWS region name.
+const CUSTOM_REGION_PREFIX: &str = "custom:";
+
+/// Parse a region override: either the name of an AWS region (e.g.
+/// `us-east-2`) or `custom:ENDPOINT` for an S3-compatible endpoint (defaulting
+/// to https if the endpoint has no scheme).
+///
+/// # Errors
+///
+/// Returns an error if the region isn't a known AWS region, or if the custom
+/// endpoint isn't a valid url with a host.
+pub fn parse_region_override(value: &str) -> Result<Region> {
+    match value.strip_prefix(CUSTOM_REGION_PREFIX) {
+        Some(endpoint) => {
+            let endpoint_str = if endpoint.contains("://") {
+                endpoint.to_owned()
+            } else {
+                format!("https://{}", endpoint)
+            };
+            let url = Url::parse(&endpoint_str)
+                .with_context(|| format!("Invalid custom region endpoint {}", endpoint))?;
+            if url.host_str().is_none() {
+                bail!("Custom region endpoint {} doesn't contain a host", endpoint);
+            }
+            Ok(Region::Custom {
+                name: String::new(),
+                endpoint: url.as_str().trim_end_matches('/').to_owned(),
+            })
+        }
+        None => value.parse::<Region>().map_err(|_| {
+            anyhow!(
+                "Unknown region {} -- give an AWS region name (e.g. us-east-2) or custom:ENDPOINT for S3-compatible storage",
+                value
+            )
+        }),
+    }
+}
+
 /// Configuration for interacting with S3-compatible cloud storage.
 #[derive(Debug, Clone)]
 pub struct StorageConfig {
-    /// Access and secret keys for storage provider
-    credentials: StaticProvider,
+    /// Credentials for storage provider
+    credentials: StorageCredentials,
     /// Bucket name
     bucket: String,
     /// Region/endpoint (use
     /// [Region::Custom](https://docs.rs/rusoto_core/0.46.0/rusoto_core/enum.Region.html#variant.Custom)
     /// for non-S3 providers)
     region: Region,
+    /// Whether file urls include the bucket in the path
+    /// (`https://endpoint/bucket/key`) rather than in the domain
+    /// (`https://bucket.endpoint/key`)
+    path_style_urls: bool,
+    /// Proxies that requests are sent through
+    proxy: ProxyConfig,
 }
 
 impl StorageConfig {
     /// Initialize storage config from bolster config and a selected provider.
     pub fn new(config: config::Config, provider: StorageProviderChoices) -> Result<StorageConfig> {
+        // Checked after the provider's section, so problems with either are
+        // reported
+        let proxy = ProxyConfig::from_config(&config);
         match provider {
             StorageProviderChoices::DigitalOcean => {
                 let do_config = config
                     .try_into::<DigitalOceanSpacesConfig>().with_context(|| "Config file must contain a [digitalocean_spaces] section to upload to DigitalOcean Spaces.")?
                     .digitalocean_spaces;
                 Ok(StorageConfig {
-                    credentials: StaticProvider::new_minimal(
-                        do_config.access_key,
-                        do_config.secret_key,
-                    ),
+                    credentials: StorageCredentials::from_keys(do_config, "digitalocean_spaces")?,
                     bucket: String::from("tangs-stage"),
                     region: Region::Custom {
                         name: "sfo2".to_owned(),
                         endpoint: "sfo2.digitaloceanspaces.com".to_owned(),
                     },
+                    path_style_urls: false,
+                    proxy: proxy?,
                 })
             }
             StorageProviderChoices::Aws => {
@@ -74,45 +508,260 @@ impl StorageConfig {
                     })?
                     .aws_s3;
                 Ok(StorageConfig {
-                    credentials: StaticProvider::new_minimal(
-                        aws_config.access_key,
-                        aws_config.secret_key,
-                    ),
+                    credentials: StorageCredentials::from_keys(aws_config, "aws_s3")?,
                     bucket: String::from("tangram-vision-datasets"),
                     region: Region::UsWest1,
+                    path_style_urls: false,
+                    proxy: proxy?,
+                })
+            }
+            StorageProviderChoices::S3Compatible => {
+                let settings = config
+                    .try_into::<S3CompatibleConfig>()
+                    .with_context(|| {
+                        "Config file must contain a [s3_compatible] section (with endpoint, bucket, and credentials) to use S3-compatible storage."
+                    })?
+                    .s3_compatible;
+                let endpoint = settings.endpoint_url()?;
+                Ok(StorageConfig {
+                    credentials: StorageCredentials::from_keys(settings.keys, "s3_compatible")?,
+                    bucket: settings.bucket,
+                    region: Region::Custom {
+                        name: settings.region,
+                        endpoint: endpoint.as_str().trim_end_matches('/').to_owned(),
+                    },
+                    path_style_urls: true,
+                    proxy: proxy?,
                 })
             }
         }
     }
+
+    /// Replace the configured bucket and/or region (e.g. from `upload --bucket`
+    /// and `--region`), such as to test against a staging bucket.
+    ///
+    /// A [Region::Custom] override (see [parse_region_override]) keeps the
+    /// configured region's name for signing requests and switches to
+    /// path-style urls, as for S3-compatible storage.
+    pub fn with_overrides(mut self, bucket: Option<String>, region: Option<Region>) -> Self {
+        if let Some(bucket) = bucket {
+            self.bucket = bucket;
+        }
+        match region {
+            Some(Region::Custom { endpoint, .. }) => {
+                self.region = Region::Custom {
+                    name: self.region.name().to_owned(),
+                    endpoint,
+                };
+                self.path_style_urls = true;
+            }
+            Some(region) => {
+                self.region = region;
+                self.path_style_urls = false;
+            }
+            None => {}
+        }
+        self
+    }
+
+    /// Name of the bucket files are stored in.
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Bucket and region (plus endpoint, for non-AWS providers) that files
+    /// are stored in, for showing to the user.
+    pub fn location(&self) -> String {
+        match &self.region {
+            Region::Custom { name, endpoint } => {
+                format!("bucket {} in region {} at {}", self.bucket, name, endpoint)
+            }
+            region => format!("bucket {} in region {}", self.bucket, region.name()),
+        }
+    }
+
+    /// Url that identifies the file stored at `key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the resulting url is malformed.
+    pub fn object_url(&self, key: &str) -> Result<Url> {
+        let url_str = match &self.region {
+            Region::Custom { endpoint, .. } if self.path_style_urls => {
+                format!("{}/{}/{}", endpoint, self.bucket, key)
+            }
+            Region::Custom { endpoint, .. } => {
+                format!("https://{}.{}/{}", self.bucket, endpoint, key)
+            }
+            r => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                self.bucket,
+                r.name(),
+                key
+            ),
+        };
+        Ok(Url::parse(&url_str)?)
+    }
+
+    /// Extracts the object key from the url of a file in cloud storage (see
+    /// [StorageConfig::object_url]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the url's path doesn't start with `/` (or with the
+    /// bucket, for path-style urls).
+    fn key_from_url<'a>(&self, url: &'a Url) -> Result<&'a str> {
+        let path = url
+            .path()
+            .strip_prefix('/')
+            .ok_or_else(|| anyhow!("URL path didn't start with /: {}", url.path()))?;
+        if self.path_style_urls {
+            path.strip_prefix(&format!("{}/", self.bucket))
+                .ok_or_else(|| anyhow!("URL path isn't in bucket {}: {}", self.bucket, url))
+        } else {
+            Ok(path)
+        }
+    }
+}
+
+/// Incrementally computes a checksum with a chosen [ChecksumAlgorithm].
+pub enum ChecksumContext {
+    /// In-progress md5 hash
+    Md5(md5::Context),
+    /// In-progress sha256 hash
+    Sha256(Sha256),
+}
+
+impl ChecksumContext {
+    /// Starts a checksum with the given algorithm.
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => ChecksumContext::Md5(md5::Context::new()),
+            ChecksumAlgorithm::Sha256 => ChecksumContext::Sha256(Sha256::new()),
+        }
+    }
+
+    /// Feeds data into the checksum.
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            ChecksumContext::Md5(ctx) => ctx.consume(data),
+            ChecksumContext::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Finishes the checksum, returning the base64-encoded digest (the
+    /// encoding S3 uses for checksum headers).
+    pub fn finish(self) -> String {
+        match self {
+            ChecksumContext::Md5(ctx) => base64::encode(*ctx.compute()),
+            ChecksumContext::Sha256(hasher) => base64::encode(hasher.finalize()),
+        }
+    }
+}
+
+/// Checksums buffered data on tokio's blocking thread pool, at most `jobs`
+/// buffers at a time.
+///
+/// Hashing is CPU-bound, so hashing inline would stall the other uploads
+/// driven by the same task. Hashing on other threads instead lets it overlap
+/// with network-bound uploads, and lets several files be hashed at once.
+///
+/// Cloning is cheap, and clones share the same limit.
+#[derive(Clone, Debug)]
+pub struct HashingPool {
+    /// Permits for buffers being hashed.
+    permits: Arc<Semaphore>,
+}
+
+impl HashingPool {
+    /// Creates a pool that hashes up to `jobs` buffers at the same time (at
+    /// least 1).
+    pub fn new(jobs: usize) -> Self {
+        HashingPool {
+            permits: Arc::new(Semaphore::new(max(1, jobs))),
+        }
+    }
+
+    /// Checksums `data` with `algorithm`, returning the base64-encoded digest
+    /// (see [ChecksumContext::finish]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the hashing thread panics.
+    pub async fn checksum(&self, data: Bytes, algorithm: ChecksumAlgorithm) -> Result<String> {
+        let _permit = self.permits.acquire().await?;
+        let checksum = tokio::task::spawn_blocking(move || {
+            let mut ctx = ChecksumContext::new(algorithm);
+            ctx.update(&data);
+            ctx.finish()
+        })
+        .await?;
+        Ok(checksum)
+    }
 }
 
-/// Get the md5 hash (for checksumming) of a file.
+/// Get the checksum of a file with the given algorithm, base64-encoded.
 ///
 /// # Errors
 ///
 /// Returns an error if reading the file fails.
-pub async fn md5_file(path: &str) -> Result<String> {
+pub async fn checksum_file(path: &str, algorithm: ChecksumAlgorithm) -> Result<String> {
     let tokio_file = tokio::fs::File::open(path).await?;
-    // Feed file to md5 without reading whole file into RAM
-    let md5_ctx = codec::FramedRead::new(tokio_file, codec::BytesCodec::new())
-        .try_fold(md5::Context::new(), |mut ctx, chunk| async move {
-            ctx.consume(chunk);
-            Ok(ctx)
-        })
-        .await?;
-    let md5_digest = md5_ctx.compute();
-    let md5_bytes: [u8; 16] = md5_digest.into();
-    let md5_str = format!("{:x}", md5_digest);
-    debug!("Got md5 hash for {:?}: {}", path, md5_str);
-    let encoded = base64::encode(md5_bytes);
-    debug!("Base64-encoded md5 hash to: {}", encoded);
+    let encoded = checksum_reader(tokio_file, algorithm, |_| {}).await?;
+    debug!(
+        "Got base64-encoded {} hash for {:?}: {}",
+        algorithm.as_str(),
+        path,
+        encoded
+    );
     Ok(encoded)
 }
 
+/// Get the checksum of everything read from `reader` with the given
+/// algorithm, base64-encoded.
+///
+/// Calls `progress` with the total number of bytes hashed so far after each
+/// chunk is hashed, so hashing large files can show progress.
+///
+/// # Errors
+///
+/// Returns an error if reading fails.
+pub async fn checksum_reader<R, F>(
+    reader: R,
+    algorithm: ChecksumAlgorithm,
+    mut progress: F,
+) -> Result<String>
+where
+    R: AsyncRead + Unpin,
+    F: FnMut(u64),
+{
+    // Feed data to the hasher without reading it all into RAM
+    let mut chunks = codec::FramedRead::new(reader, codec::BytesCodec::new());
+    let mut ctx = ChecksumContext::new(algorithm);
+    let mut hashed = 0;
+    while let Some(chunk) = chunks.try_next().await? {
+        ctx.update(&chunk);
+        hashed += chunk.len() as u64;
+        progress(hashed);
+    }
+    Ok(ctx.finish())
+}
+
 /// Upload a file to cloud storage in a single request.
 ///
 /// Uses the [S3 PutObject API](https://docs.aws.amazon.com/AmazonS3/latest/API/API_PutObject.html).
 ///
+/// The file is checksummed with `checksum_algorithm` from the same read as
+/// the upload, rather than in a separate pass. Since an md5 must be sent
+/// before the body it covers (so cloud storage can verify the upload), the
+/// file is read into RAM before sending it. This is only used for files
+/// under the multipart threshold (64 MiB by default), so reading the file
+/// once is worth holding it in RAM.
+///
+/// The file is hashed by `hashing` (see [HashingPool]), so hashing overlaps
+/// with other files' uploads. The request is retried (see
+/// [RetryingClient::request]) and sent through the configured proxy, if any.
+///
 /// # Errors
 ///
 /// Returns an error if reading the file fails.
@@ -120,72 +769,99 @@ pub async fn md5_file(path: &str) -> Result<String> {
 /// Returns an error if cloud storage returns a non-200 response (e.g. if auth
 /// credentials are invalid, if server is unreachable, if checksum doesn't
 /// match) or if the returned data is malformed.
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_file_oneshot(
     config: StorageConfig,
     path: String,
     filesize: usize,
     key: String,
-    multi_progress: &MultiProgress,
-) -> Result<(Url, String)> {
+    checksum_algorithm: ChecksumAlgorithm,
+    hashing: &HashingPool,
+    progress: Arc<dyn ProgressReporter>,
+    stats: &TransferStats,
+) -> Result<UploadedStream> {
     // Async oneshot upload references
     // https://github.com/softprops/elblogs/blob/96df314db92216a769dc92d90a5cb0ae42bb13da/src/main.rs#L212-L223
     // https://stackoverflow.com/questions/57810173/streamed-upload-to-s3-with-rusoto
     // https://github.com/rusoto/rusoto/issues/1771
     // https://stackoverflow.com/questions/59318460/what-is-the-best-way-to-convert-an-asyncread-to-a-trystream-of-bytes
-    let region_endpoint = match &config.region {
-        Region::Custom { endpoint, .. } => endpoint.clone(),
-        r => format!("s3.{}.amazonaws.com", r.name()),
-    };
-
     // Constructing url here to avoid borrow errors if we try to construct it at
     // the bottom of the function
-    let url_str = format!("https://{}.{}/{}", config.bucket, region_endpoint, key);
-    let url = Url::parse(&url_str)?;
-    let md5_hash = md5_file(&path).await?;
-
-    let dispatcher = rusoto_core::HttpClient::new().unwrap();
-    // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
-    let client = S3Client::new_with(dispatcher, config.credentials, config.region);
-
-    let tokio_file = tokio::fs::File::open(&path).await?;
-    let byte_stream =
-        codec::FramedRead::new(tokio_file, codec::BytesCodec::new()).map_ok(|bytes| bytes.freeze());
-
-    let progress_bar = multi_progress.add(ProgressBar::new(filesize as u64));
-    progress_bar.set_style(commands::get_default_progress_bar_style());
-    progress_bar.set_prefix(path);
-    progress_bar.set_position(0);
-
-    let pgbar = progress_bar.clone();
-    // Let progress bar follow along with # bytes read
-    let progress = Box::new(move |_bytes_read: u64, total_bytes_read: u64| {
-        pgbar.set_position(total_bytes_read);
-    });
-    let read_wrapper = ReadProgressStream::new(byte_stream, progress);
-
-    let byte_stream = StreamingBody::new(read_wrapper);
-    let req = PutObjectRequest {
-        bucket: config.bucket,
-        body: Some(byte_stream),
-        // Required when body is a stream (will change for multipart upload)
-        content_length: Some(filesize as i64),
-        content_md5: Some(md5_hash),
-        key,
-        ..Default::default()
+    let url = config.object_url(&key)?;
+    let mut client = RetryingClient::new(client_factory(&config, None));
+
+    let data = Bytes::from(tokio::fs::read(&path).await?);
+    if data.len() != filesize {
+        bail!(
+            "{} changed size while uploading (expected {} bytes, read {})",
+            path,
+            filesize,
+            data.len()
+        );
+    }
+    let checksum = hashing.checksum(data.clone(), checksum_algorithm).await?;
+    // rusoto can't send other checksum headers, so the upload is only
+    // verified by cloud storage when using md5
+    let content_md5 = match checksum_algorithm {
+        ChecksumAlgorithm::Md5 => Some(checksum.clone()),
+        ChecksumAlgorithm::Sha256 => None,
     };
-    debug!("upload_file_oneshot request {:?}", req);
-    // just spawn tokio here and use it, instead of async-ing everything yet
+    // Each attempt sends the buffered file in slices so the progress bar
+    // moves as it's sent
+    let make_request = |client: S3Client| {
+        let data = data.clone();
+        let byte_stream = stream::iter(
+            (0..data.len())
+                .step_by(ONESHOT_SEND_SLICE_SIZE)
+                .map(move |start| {
+                    Ok::<_, std::io::Error>(
+                        data.slice(start..min(start + ONESHOT_SEND_SLICE_SIZE, data.len())),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
 
-    // https://www.rusoto.org/futures.html mentions turning futures into blocking calls
-    let resp = client.put_object(req).await?;
+        let local_progress = progress.clone();
+        let wire_stats = stats.clone();
+        // Let progress follow along with # bytes read (the body is read as
+        // it's sent)
+        let on_read = Box::new(move |bytes_read: u64, total_bytes_read: u64| {
+            local_progress.set_position(total_bytes_read);
+            wire_stats.add_wire_bytes(bytes_read);
+        });
+        let read_wrapper = ReadProgressStream::new(byte_stream, on_read);
+
+        let req = PutObjectRequest {
+            bucket: config.bucket.clone(),
+            body: Some(StreamingBody::new(read_wrapper)),
+            // Required when body is a stream (will change for multipart upload)
+            content_length: Some(filesize as i64),
+            content_md5: content_md5.clone(),
+            key: key.clone(),
+            ..Default::default()
+        };
+        debug!("upload_file_oneshot request {:?}", req);
+        async move { client.put_object(req).await }
+    };
+    let resp = with_error_details(client.request(make_request).await)?;
     debug!("upload_file_oneshot response {:?}", resp);
-    progress_bar.finish();
+    progress.finish();
+    stats.add_file_bytes(filesize as u64);
     let version = resp
         .version_id
         .ok_or_else(|| anyhow!("Uploaded file wasn't versioned by storage provider"))?;
-    Ok((url, version))
+    Ok(UploadedStream {
+        url,
+        version,
+        size: filesize,
+        checksum,
+    })
 }
 
+/// Size of the slices a file buffered for [upload_file_oneshot] is sent in,
+/// which sets how often its progress bar updates.
+const ONESHOT_SEND_SLICE_SIZE: usize = 64 * 1024;
+
 /// A single chunk of a larger file, identified by index number.
 #[derive(Debug)]
 pub struct FileChunk {
@@ -195,6 +871,68 @@ pub struct FileChunk {
     part_number: i64,
 }
 
+/// How the size of each part of a multipart upload is chosen.
+#[derive(AsRefStr, EnumString, EnumVariantNames, Debug, Default, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum PartSizeStrategy {
+    /// Every part (except the last) is the chunk size
+    #[default]
+    Fixed,
+    /// Parts start small, so a failure early in the upload loses little, then
+    /// double in size up to [ADAPTIVE_PART_SIZE_GROWTH] times the chunk size
+    /// for throughput
+    Adaptive,
+}
+
+/// How many times the chunk size parts of an adaptive multipart upload may
+/// grow to (see [PartSizeStrategy::Adaptive]).
+pub const ADAPTIVE_PART_SIZE_GROWTH: usize = 4;
+
+impl PartSizeStrategy {
+    /// Size of the first part of a file split into `chunk_size` chunks.
+    fn first_part_size(&self, chunk_size: usize) -> usize {
+        match self {
+            PartSizeStrategy::Fixed => chunk_size,
+            PartSizeStrategy::Adaptive => min(MIN_PART_SIZE, chunk_size),
+        }
+    }
+
+    /// Size of the part following a part of `part_size`, for a file split into
+    /// `chunk_size` chunks.
+    ///
+    /// Adaptive parts never exceed [MAX_PART_SIZE] unless the chunk size does.
+    fn next_part_size(&self, part_size: usize, chunk_size: usize) -> usize {
+        match self {
+            PartSizeStrategy::Fixed => chunk_size,
+            PartSizeStrategy::Adaptive => {
+                let largest = max(
+                    chunk_size,
+                    min(
+                        chunk_size.saturating_mul(ADAPTIVE_PART_SIZE_GROWTH),
+                        MAX_PART_SIZE,
+                    ),
+                );
+                min(part_size.saturating_mul(2), largest)
+            }
+        }
+    }
+
+    /// Sizes of the parts a file of `filesize` bytes is split into, given its
+    /// chunk size (see [multipart_chunk_size]). The last part may be smaller.
+    pub fn part_sizes(&self, chunk_size: usize, filesize: usize) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        let mut remaining = filesize;
+        let mut part_size = self.first_part_size(chunk_size);
+        while remaining > 0 {
+            let size = min(part_size, remaining);
+            sizes.push(size);
+            remaining -= size;
+            part_size = self.next_part_size(part_size, chunk_size);
+        }
+        sizes
+    }
+}
+
 /// Tracks how much of the file we've read.
 #[derive(Debug)]
 pub struct FileReadState<F>
@@ -203,7 +941,11 @@ where
 {
     /// The file being read.
     f: F,
-    /// Size of the file in bytes.
+    /// How each part's size is chosen.
+    strategy: PartSizeStrategy,
+    /// Chunk size the part sizes are based on.
+    chunk_size: usize,
+    /// Size of the next part to read in bytes.
     size_in_bytes: usize,
     /// Number of bytes remaining in the file.
     // Tracked so we can size buffer to match last chunk (needed by read_exact).
@@ -212,22 +954,24 @@ where
     part_number: i64,
 }
 
-/// Produce a stream of `size_in_bytes`-size chunks from file.
+/// Produce a stream of chunks from file, sized according to `strategy` and
+/// `chunk_size` (see [PartSizeStrategy::part_sizes]).
 ///
 /// # Examples
 ///
 /// Example is ignored because no bolster modules are public. Update this
 /// doctest if modules are changed to be public.
 ///
-/// ```ignore
+/// ```
 /// # use futures::stream::StreamExt;
 /// # use log::debug;
-/// # use bolster::core::api::storage::read_file_chunks;
+/// # use bolster::core::api::storage::{read_file_chunks, PartSizeStrategy};
 /// # async fn dox() -> std::io::Result<()> {
 /// # let chunk_size: usize = 1;
 /// # let filesize: usize = 1;
 /// let tokio_file = tokio::fs::File::open("foo.txt").await?;
-/// let mut stream = read_file_chunks(tokio_file, chunk_size, filesize);
+/// let mut stream =
+///     read_file_chunks(tokio_file, PartSizeStrategy::Fixed, chunk_size, filesize);
 /// while let Some(maybe_chunk) = stream.next().await {
 ///     if let Ok(chunk) = maybe_chunk {
 ///         debug!("Got chunk from file!");
@@ -246,7 +990,8 @@ where
 /// Returns an error in the stream if reading the file fails.
 pub fn read_file_chunks<F>(
     f: F,
-    size_in_bytes: usize,
+    strategy: PartSizeStrategy,
+    chunk_size: usize,
     filesize: usize,
 ) -> impl Stream<Item = Result<FileChunk, std::io::Error>> + Unpin + Send
 where
@@ -254,7 +999,9 @@ where
 {
     let seed = FileReadState {
         f,
-        size_in_bytes,
+        strategy,
+        chunk_size,
+        size_in_bytes: strategy.first_part_size(chunk_size),
         remaining_bytes: filesize,
         part_number: 1,
     };
@@ -288,6 +1035,9 @@ where
 
                 state.part_number += 1;
                 state.remaining_bytes -= n;
+                state.size_in_bytes = state
+                    .strategy
+                    .next_part_size(state.size_in_bytes, state.chunk_size);
 
                 Ok(Some((chunk, state)))
             }
@@ -295,50 +1045,143 @@ where
     }))
 }
 
+/// Most parts a multipart upload can have (an S3 limit).
+pub const MAX_PART_COUNT: i64 = 10_000;
+
+/// Tracks how much of a stream of unknown length we've read.
+#[derive(Debug)]
+struct StreamReadState<R> {
+    /// The stream being read.
+    reader: R,
+    /// Size of each chunk in bytes.
+    size_in_bytes: usize,
+    /// Identifying index of the next part to be read from the stream.
+    part_number: i64,
+    /// Whether the end of the stream has been reached.
+    eof: bool,
+}
+
+/// Produce a stream of `size_in_bytes`-size chunks from a reader whose length
+/// isn't known up front (e.g. stdin), reading until it reaches EOF. The last
+/// chunk may be smaller.
+///
+/// # Errors
+///
+/// Returns an error in the stream if reading fails, or if the reader holds
+/// more than [MAX_PART_COUNT] chunks.
+pub fn read_chunks_until_eof<R>(
+    reader: R,
+    size_in_bytes: usize,
+) -> impl Stream<Item = Result<FileChunk, std::io::Error>> + Unpin + Send
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let seed = StreamReadState {
+        reader,
+        size_in_bytes,
+        part_number: 1,
+        eof: false,
+    };
+    Box::pin(try_unfold(seed, |mut state| async move {
+        if state.eof {
+            return Ok(None);
+        }
+        // Reads may return less than asked for (e.g. from a pipe), so keep
+        // reading until the chunk is full or the stream ends
+        let mut buf = vec![0; state.size_in_bytes];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match state.reader.read(&mut buf[filled..]).await? {
+                0 => {
+                    state.eof = true;
+                    break;
+                }
+                n => filled += n,
+            }
+        }
+        if filled == 0 {
+            return Ok(None);
+        }
+        if state.part_number > MAX_PART_COUNT {
+            return Err(std::io::Error::other(format!(
+                "Input is larger than the {} parts of {} that can be uploaded",
+                MAX_PART_COUNT,
+                Byte::from_bytes(state.size_in_bytes as u128).get_appropriate_unit(true)
+            )));
+        }
+        buf.truncate(filled);
+        let chunk = FileChunk {
+            data: buf,
+            part_number: state.part_number,
+        };
+        state.part_number += 1;
+        Ok(Some((chunk, state)))
+    }))
+}
+
 /// Upload a single part/chunk to cloud storage.
 ///
 /// Uses the [S3 UploadPart API](https://docs.aws.amazon.com/AmazonS3/latest/API/API_UploadPart.html).
 ///
+/// `new_request` is called to build the request for part `part_number` on
+/// each attempt (see [RetryingClient::request]).
+///
 /// # Errors
 ///
 /// Returns an error if cloud storage returns a non-200 response (e.g. if auth
 /// credentials are invalid, if server is unreachable, if checksum doesn't
 /// match) or if the returned data is malformed.
-pub async fn upload_completed_part(
-    client: &S3Client,
-    req: UploadPartRequest,
-) -> Result<CompletedPart> {
-    let part_number = req.part_number;
-    debug!("Making part {} upload_part request {:?}", part_number, req);
-    let resp = client.upload_part(req).await;
+pub async fn upload_completed_part<F>(
+    client: &mut RetryingClient,
+    part_number: i64,
+    new_request: F,
+) -> Result<CompletedPart>
+where
+    F: Fn() -> UploadPartRequest,
+{
+    let resp = client
+        .request(|client| {
+            let req = new_request();
+            debug!("Making part {} upload_part request {:?}", part_number, req);
+            async move { client.upload_part(req).await }
+        })
+        .await;
     debug!("Result of part {} upload_part {:?}", part_number, resp);
 
     match resp {
-        Ok(response) => {
-            if let Some(e_tag) = response.e_tag {
-                let part = CompletedPart {
-                    e_tag: Some(e_tag),
-                    part_number: Some(part_number),
-                };
-                Ok(part)
-            } else {
-                bail!(
-                    "Response for upload part {} is missing ETag header!",
-                    part_number
-                );
-            }
-        }
+        Ok(UploadPartOutput {
+            e_tag: Some(e_tag), ..
+        }) => Ok(CompletedPart {
+            e_tag: Some(e_tag),
+            part_number: Some(part_number),
+        }),
+        Ok(_) => bail!(
+            "Response for upload part {} is missing ETag header!",
+            part_number
+        ),
         Err(e) => {
             debug!("Handling error in upload_completed_part: {}", e);
             // Timeout error is encompassed by HttpDispatchError
             // https://github.com/rusoto/rusoto/issues/1530
-            bail!("Upload part {} request failed: {}", part_number, e);
+            match storage_error_details(&e) {
+                Some(details) => bail!("Upload part {} request failed: {}", part_number, details),
+                None => bail!("Upload part {} request failed: {}", part_number, e),
+            }
         }
     }
 }
 
 /// Upload all parts/chunks of a file to cloud storage.
 ///
+/// Up to `concurrent_request_limit` parts are uploaded at a time. While those
+/// are in flight, up to `chunk_read_ahead` more chunks are read from the file
+/// so they're ready to send as soon as a request finishes. At most
+/// `concurrent_request_limit + chunk_read_ahead` chunks are held in RAM.
+///
+/// Parts are sized according to `part_size_strategy` (see
+/// [PartSizeStrategy::part_sizes]). The file is checksummed with
+/// `checksum_algorithm` as chunks are read.
+///
 /// # Errors
 ///
 /// Returns an error if cloud storage returns a non-200 response (e.g. if auth
@@ -346,7 +1189,7 @@ pub async fn upload_completed_part(
 /// match) or if the returned data is malformed.
 #[allow(clippy::too_many_arguments)]
 pub async fn upload_parts<F>(
-    client: &S3Client,
+    new_client: ClientFactory,
     tokio_file: F,
     bucket: String,
     key: String,
@@ -354,102 +1197,275 @@ pub async fn upload_parts<F>(
     filesize: usize,
     // TODO: Bundle these in a config object?
     chunk_size: usize,
+    part_size_strategy: PartSizeStrategy,
     concurrent_request_limit: usize,
-    progress_bar: ProgressBar,
-) -> Result<Vec<CompletedPart>>
+    chunk_read_ahead: usize,
+    checksum_algorithm: ChecksumAlgorithm,
+    progress: Arc<dyn ProgressReporter>,
+    stats: TransferStats,
+) -> Result<StreamedParts>
 where
     F: AsyncRead + AsyncReadExt + Unpin + Send + std::fmt::Debug,
 {
-    let expected_num_chunks = (filesize as f64 / chunk_size as f64).ceil() as usize;
+    let expected_num_chunks = part_size_strategy.part_sizes(chunk_size, filesize).len();
+    let mut ctx = ChecksumContext::new(checksum_algorithm);
+    let mut size = 0;
+    let chunks = read_file_chunks(tokio_file, part_size_strategy, chunk_size, filesize).inspect_ok(
+        |chunk| {
+            // Chunks are read in order, so the checksum covers the whole file
+            ctx.update(&chunk.data);
+            size += chunk.data.len();
+        },
+    );
+    let parts = upload_chunks(
+        new_client,
+        chunks,
+        bucket,
+        key,
+        upload_id,
+        concurrent_request_limit,
+        chunk_read_ahead,
+        checksum_algorithm,
+        progress,
+        stats,
+    )
+    .await?;
+    Ok(StreamedParts {
+        parts,
+        num_chunks: expected_num_chunks,
+        size,
+        checksum: ctx.finish(),
+    })
+}
 
-    // TODO: The below async work could be changed to a more functional approach,
-    // using try_buffer_unordered to limit concurrency while still exiting early
-    // in case of errors. For discussion, see:
-    // https://gitlab.com/tangram-vision/oss/bolster/-/issues/14
+/// Parts uploaded from a reader, checksummed as they were read (see
+/// [upload_parts] and [upload_parts_until_eof]).
+#[derive(Debug)]
+pub struct StreamedParts {
+    /// Completed parts, in order.
+    pub parts: Vec<CompletedPart>,
+    /// Number of chunks read, i.e. how many parts should have been uploaded
+    /// (see [verify_completed_parts]).
+    pub num_chunks: usize,
+    /// Number of bytes read.
+    pub size: usize,
+    /// Checksum of everything read, base64-encoded.
+    pub checksum: String,
+}
 
-    // Tokio threadpool spawns a thread per CPU and distributes tasks among
-    // available threads, so tasks should be completed as fast as possible. We
-    // use the concurrent_request_limit to limit how much of the file we read
-    // into RAM at a time (having no limit leads to system freezes and
-    // OOM-killing).
-    let mut futs = FuturesUnordered::new();
-    let mut parts: Vec<CompletedPart> = Vec::with_capacity(expected_num_chunks);
-    // Pool of S3Client clones that are checked-out and checked-in by each task.
-    let mut client_pool: Vec<S3Client> = (0..concurrent_request_limit)
-        .map(|_idx| client.clone())
-        .collect();
-    let mut stream = read_file_chunks(tokio_file, chunk_size, filesize as usize);
-    while let Some(maybe_chunk) = stream.next().await {
-        if let Ok(chunk) = maybe_chunk {
-            debug!("Sending chunk {} of {} to task", chunk.part_number, key);
-            if let Some(local_client) = client_pool.pop() {
-                let bucket = bucket.clone();
-                let key = key.clone();
-                let upload_id = upload_id.clone();
-                let local_progress_bar = progress_bar.clone();
-                futs.push(tokio::spawn(async move {
-                    debug!("Spawned task for chunk {} of {}", chunk.part_number, key);
-                    let part_number = chunk.part_number;
-                    let md5 = base64::encode(*md5::compute(&chunk.data));
-                    let part_size = chunk.data.len();
-                    let streaming_body = StreamingBody::from(chunk.data);
-
-                    let req = UploadPartRequest {
-                        body: Some(streaming_body),
-                        bucket,
-                        key,
-                        upload_id,
-                        content_md5: Some(md5),
-                        part_number,
-                        ..Default::default()
-                    };
-                    let part: CompletedPart = upload_completed_part(&local_client, req).await?;
+/// Upload everything read from `reader` until EOF to cloud storage, for
+/// readers whose length isn't known up front (e.g. stdin).
+///
+/// Since the data can't be read twice, it's checksummed as chunks are read
+/// rather than before uploading. Parts are uploaded as for [upload_parts].
+///
+/// # Errors
+///
+/// Returns an error if reading fails (see [read_chunks_until_eof]), or if
+/// cloud storage returns a non-200 response (e.g. if auth credentials are
+/// invalid, if server is unreachable, if checksum doesn't match) or if the
+/// returned data is malformed.
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_parts_until_eof<R>(
+    new_client: ClientFactory,
+    reader: R,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    chunk_size: usize,
+    concurrent_request_limit: usize,
+    chunk_read_ahead: usize,
+    checksum_algorithm: ChecksumAlgorithm,
+    progress: Arc<dyn ProgressReporter>,
+    stats: TransferStats,
+) -> Result<StreamedParts>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut ctx = ChecksumContext::new(checksum_algorithm);
+    let mut size = 0;
+    let mut num_chunks = 0;
+    let chunks = read_chunks_until_eof(reader, chunk_size).inspect_ok(|chunk| {
+        // Chunks are read in order, so the checksum covers the whole stream
+        ctx.update(&chunk.data);
+        size += chunk.data.len();
+        num_chunks += 1;
+    });
+    let parts = upload_chunks(
+        new_client,
+        chunks,
+        bucket,
+        key,
+        upload_id,
+        concurrent_request_limit,
+        chunk_read_ahead,
+        checksum_algorithm,
+        progress,
+        stats,
+    )
+    .await?;
+    Ok(StreamedParts {
+        parts,
+        num_chunks,
+        size,
+        checksum: ctx.finish(),
+    })
+}
+
+/// Upload chunks from `chunks` as parts of a multipart upload, returning the
+/// completed parts sorted by part number (see [upload_parts]).
+#[allow(clippy::too_many_arguments)]
+async fn upload_chunks<S>(
+    new_client: ClientFactory,
+    mut stream: S,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    concurrent_request_limit: usize,
+    chunk_read_ahead: usize,
+    checksum_algorithm: ChecksumAlgorithm,
+    progress: Arc<dyn ProgressReporter>,
+    stats: TransferStats,
+) -> Result<Vec<CompletedPart>>
+where
+    S: Stream<Item = Result<FileChunk, std::io::Error>> + Unpin,
+{
+    // TODO: The below async work could be changed to a more functional approach,
+    // using try_buffer_unordered to limit concurrency while still exiting early
+    // in case of errors. For discussion, see:
+    // https://gitlab.com/tangram-vision/oss/bolster/-/issues/14
 
-                    // TODO: Progress bar updates are "chunky" (only updates
-                    // after each chunk/part finishes). Is there a way to make
-                    // this more smooth/fine-grained?
-                    // Related to https://gitlab.com/tangram-vision/bolster/-/issues/2
-                    local_progress_bar.inc(part_size as u64);
+    // Tokio threadpool spawns a thread per CPU and distributes tasks among
+    // available threads, so tasks should be completed as fast as possible. We
+    // use the concurrent_request_limit and chunk_read_ahead to limit how much
+    // of the file we read into RAM at a time (having no limit leads to system
+    // freezes and OOM-killing).
+    let mut futs = FuturesUnordered::new();
+    let mut parts: Vec<CompletedPart> = Vec::new();
+    // Pool of clients that are checked-out and checked-in by each task.
+    let mut client_pool: Vec<RetryingClient> = (0..concurrent_request_limit)
+        .map(|_idx| RetryingClient::new(new_client.clone()))
+        .collect();
+    // Chunks read from the file that are waiting for a free client.
+    let mut read_ahead: VecDeque<FileChunk> = VecDeque::with_capacity(chunk_read_ahead);
+    let mut finished_reading = false;
+
+    let spawn_upload = |chunk: FileChunk, mut local_client: RetryingClient| {
+        let bucket = bucket.clone();
+        let key = key.clone();
+        let upload_id = upload_id.clone();
+        let local_progress = progress.clone();
+        let local_stats = stats.clone();
+        tokio::spawn(async move {
+            debug!("Spawned task for chunk {} of {}", chunk.part_number, key);
+            let part_number = chunk.part_number;
+            // rusoto can't send other checksum headers, so parts are
+            // only checksummed by cloud storage when using md5
+            let content_md5 = match checksum_algorithm {
+                ChecksumAlgorithm::Md5 => Some(base64::encode(*md5::compute(&chunk.data))),
+                ChecksumAlgorithm::Sha256 => None,
+            };
+            let part_size = chunk.data.len();
+            let data = Bytes::from(chunk.data);
+
+            let part: CompletedPart = upload_completed_part(&mut local_client, part_number, || {
+                // Count the part each time it's sent, so retries show up
+                // in the wire bytes
+                let wire_stats = local_stats.clone();
+                UploadPartRequest {
+                    body: Some(StreamingBody::new_with_size(
+                        stream::once(future::ready(Ok(data.clone()))).inspect_ok(
+                            move |bytes: &Bytes| wire_stats.add_wire_bytes(bytes.len() as u64),
+                        ),
+                        part_size,
+                    )),
+                    bucket: bucket.clone(),
+                    key: key.clone(),
+                    upload_id: upload_id.clone(),
+                    content_md5: content_md5.clone(),
+                    part_number,
+                    ..Default::default()
+                }
+            })
+            .await?;
+
+            // TODO: Progress bar updates are "chunky" (only updates
+            // after each chunk/part finishes). Is there a way to make
+            // this more smooth/fine-grained?
+            // Related to https://gitlab.com/tangram-vision/bolster/-/issues/2
+            local_progress.inc(part_size as u64);
+            local_stats.add_file_bytes(part_size as u64);
+
+            Ok::<_, anyhow::Error>((part, local_client))
+        })
+    };
 
-                    Ok::<_, anyhow::Error>((part, local_client))
-                }));
+    loop {
+        // Send waiting chunks while there are free clients
+        if !read_ahead.is_empty() && futs.len() < concurrent_request_limit {
+            let chunk = read_ahead.pop_front().unwrap();
+            debug!("Sending chunk {} of {} to task", chunk.part_number, key);
+            if let Some(local_client) = client_pool.pop() {
+                futs.push(spawn_upload(chunk, local_client));
             } else {
                 debug!("S3Client pool ran dry somehow!");
                 bail!("S3Client pool ran dry somehow!");
             }
+            continue;
+        }
 
-            if futs.len() >= concurrent_request_limit {
-                debug!(
-                    "At concurrent_request_limit for {}... awaiting request completion",
-                    key
-                );
-                // This won't return None because futs is not empty, so we can safely unwrap.
-                // The ? operator can throw:
-                //   - a JoinError (if the tokio::spawn task panics)
-                //   - an error from upload_completed_part (i.e. making the upload_part request)
-                let (part, local_client) = futs.next().await.unwrap()??;
-                client_pool.push(local_client);
-                debug!(
-                    "Returning client to pool, current size = {}",
-                    client_pool.len()
-                );
-                parts.push(part);
-                debug!("Parts of {} finished = {}", key, parts.len());
-            }
+        // Keep reading while there's room for another chunk: either a client
+        // is free to send it right away, or the read-ahead buffer isn't full.
+        let can_read = !finished_reading
+            && (futs.len() < concurrent_request_limit || read_ahead.len() < chunk_read_ahead);
+        if !can_read && futs.is_empty() {
+            break;
+        }
+        if !can_read {
+            debug!(
+                "At concurrent_request_limit for {}... awaiting request completion",
+                key
+            );
+        }
+
+        // Read the next chunk and/or wait for an upload to finish, whichever
+        // happens first.
+        let (maybe_chunk, maybe_finished) = if !can_read {
+            (None, futs.next().await)
+        } else if futs.is_empty() {
+            (Some(stream.next().await), None)
         } else {
-            debug!("Error reading file: {:?}", maybe_chunk);
-            bail!("Error reading file: {:?}", maybe_chunk);
+            match future::select(stream.next(), futs.next()).await {
+                Either::Left((maybe_chunk, _)) => (Some(maybe_chunk), None),
+                Either::Right((maybe_finished, _)) => (None, maybe_finished),
+            }
+        };
+
+        match maybe_chunk {
+            Some(Some(Ok(chunk))) => read_ahead.push_back(chunk),
+            Some(Some(Err(e))) => {
+                debug!("Error reading file: {:?}", e);
+                bail!("Error reading file: {:?}", e);
+            }
+            Some(None) => {
+                debug!("All file chunks for {} read", key);
+                finished_reading = true;
+            }
+            None => {}
+        }
+        if let Some(result) = maybe_finished {
+            // The ? operator can throw:
+            //   - a JoinError (if the tokio::spawn task panics)
+            //   - an error from upload_completed_part (i.e. making the upload_part request)
+            let (part, local_client) = result??;
+            client_pool.push(local_client);
+            debug!(
+                "Returning client to pool, current size = {}",
+                client_pool.len()
+            );
+            parts.push(part);
+            debug!("Parts of {} finished = {}", key, parts.len());
         }
-    }
-    debug!("All file chunks for {} dispatched to tasks", key);
-    while let Some(result) = futs.next().await {
-        // The ? operator can throw:
-        //   - a JoinError (if the tokio::spawn task panics)
-        //   - an error from upload_completed_part (i.e. making the upload_part request)
-        // Also, we don't care about returning S3Clients to the pool anymore.
-        let (part, _) = result??;
-        parts.push(part);
-        debug!("Parts of {} finished = {}", key, parts.len());
     }
 
     // Parts must be returned in order to AWS S3.
@@ -458,9 +1474,57 @@ where
     Ok(parts)
 }
 
+/// Check that completed parts form a contiguous `1..=N` sequence.
+///
+/// Parts must already be sorted by part number. Catching a missing or
+/// duplicated part locally gives a much clearer error than the one returned by
+/// cloud storage for a bad CompleteMultipartUpload request.
+///
+/// # Errors
+///
+/// Returns an error if any part number in `1..=expected_num_chunks` is missing
+/// or if there are more parts than expected.
+pub fn verify_completed_parts(parts: &[CompletedPart], expected_num_chunks: usize) -> Result<()> {
+    for (idx, part) in parts.iter().enumerate() {
+        let expected_part_number = idx as i64 + 1;
+        match part.part_number {
+            Some(n) if n == expected_part_number => {}
+            Some(n) if n > expected_part_number => {
+                bail!(
+                    "Multipart upload is missing part {} (of {} expected parts)",
+                    expected_part_number,
+                    expected_num_chunks
+                );
+            }
+            other => {
+                bail!(
+                    "Multipart upload has unexpected part number {:?} at position {}",
+                    other,
+                    expected_part_number
+                );
+            }
+        }
+    }
+    if parts.len() < expected_num_chunks {
+        bail!(
+            "Multipart upload is missing part {} (of {} expected parts)",
+            parts.len() + 1,
+            expected_num_chunks
+        );
+    }
+    if parts.len() > expected_num_chunks {
+        bail!(
+            "Multipart upload has {} parts but expected {}",
+            parts.len(),
+            expected_num_chunks
+        );
+    }
+    Ok(())
+}
+
 /// Size of each file chunk when uploading large files.
 ///
-/// S3 has some limits for multipart uploads: https://docs.aws.amazon.com/AmazonS3/latest/userguide/qfacts.html
+/// S3 has some limits for multipart uploads: <https://docs.aws.amazon.com/AmazonS3/latest/userguide/qfacts.html>
 ///
 /// To summarize:
 /// - Part numbers can go from 1-10,000
@@ -504,6 +1568,9 @@ pub const DEFAULT_CHUNK_SIZE: usize = 16 * (MEBIBYTE as usize);
 /// spend the time/effort to support more than 1000 parts.
 pub const MAX_FILE_SIZE: usize = 5000 * (GIBIBYTE as usize);
 
+/// Maximum length of an object key in bytes, as required by S3.
+pub const MAX_KEY_LENGTH: usize = 1024;
+
 /// Derive chunk size based on filesize, scaling to never need more than 1000
 /// parts/chunks.
 ///
@@ -524,43 +1591,194 @@ pub fn derive_chunk_size(filesize: usize) -> Result<usize> {
     ))
 }
 
+/// Minimum size of each part of a multipart upload (except the last part),
+/// as required by S3.
+pub const MIN_PART_SIZE: usize = 5 * (MEBIBYTE as usize);
+
+/// Maximum size of each part of a multipart upload, as required by S3.
+pub const MAX_PART_SIZE: usize = 5 * (GIBIBYTE as usize);
+
+/// Chunk size that splits a file into exactly `num_parts` parts (e.g. for
+/// benchmarking), rather than the chunk size from [derive_chunk_size].
+///
+/// # Errors
+///
+/// Returns an error if the file is over the [MAX_FILE_SIZE] or if the
+/// resulting chunk size is outside the [MIN_PART_SIZE]-[MAX_PART_SIZE] limits.
+pub fn chunk_size_for_num_parts(filesize: usize, num_parts: usize) -> Result<usize> {
+    if filesize > MAX_FILE_SIZE {
+        bail!("File is too large to upload! Limit is {}", MAX_FILE_SIZE);
+    }
+    if num_parts == 0 {
+        bail!("Number of parts must be at least 1");
+    }
+    let chunk_size = filesize.div_ceil(num_parts);
+    if !(MIN_PART_SIZE..=MAX_PART_SIZE).contains(&chunk_size) {
+        bail!(
+            "Splitting {} into {} parts gives parts of {}, but parts must be between {} and {}",
+            Byte::from_bytes(filesize as u128).get_appropriate_unit(true),
+            num_parts,
+            Byte::from_bytes(chunk_size as u128).get_appropriate_unit(true),
+            Byte::from_bytes(MIN_PART_SIZE as u128).get_appropriate_unit(true),
+            Byte::from_bytes(MAX_PART_SIZE as u128).get_appropriate_unit(true),
+        );
+    }
+    Ok(chunk_size)
+}
+
+/// Chunk size for a multipart upload: from [chunk_size_for_num_parts] if a
+/// part count is requested, else from [derive_chunk_size].
+///
+/// # Errors
+///
+/// See [chunk_size_for_num_parts] and [derive_chunk_size].
+pub fn multipart_chunk_size(filesize: usize, num_parts: Option<usize>) -> Result<usize> {
+    match num_parts {
+        Some(num_parts) => chunk_size_for_num_parts(filesize, num_parts),
+        None => derive_chunk_size(filesize),
+    }
+}
+
+/// Whether a failed request was for a multipart upload that doesn't exist,
+/// e.g. because it was already completed or aborted.
+fn is_no_such_upload<E>(error: &RusotoError<E>) -> bool {
+    match error {
+        RusotoError::Unknown(resp) if resp.status.as_u16() == 404 => {
+            String::from_utf8_lossy(&resp.body).contains("<Code>NoSuchUpload</Code>")
+        }
+        _ => false,
+    }
+}
+
+/// Assemble the uploaded parts of a multipart upload into an object, returning
+/// the object's version.
+///
+/// Uses the [S3 CompleteMultipartUpload API](https://docs.aws.amazon.com/AmazonS3/latest/API/API_CompleteMultipartUpload.html).
+///
+/// The request is retried (see [RetryingClient::request]), since failing here
+/// would waste every part already uploaded. If an attempt completed the upload
+/// but its response was lost, cloud storage answers the retry with
+/// NoSuchUpload, so the completed object's version is read with a HEAD request
+/// instead.
+///
+/// If `expected_parts` is given, `parts` are first checked to be exactly parts
+/// `1..=expected_parts` (see [verify_completed_parts]), so a lost part is
+/// reported locally instead of as an opaque error from cloud storage.
+///
+/// # Errors
+///
+/// Returns an error, without sending the request, if a part is missing.
+///
+/// Returns an error if cloud storage returns a non-200 response (e.g. if auth
+/// credentials are invalid, if server is unreachable, if a part's ETag doesn't
+/// match) or if the object wasn't versioned.
+pub async fn complete_multipart_upload(
+    client: &mut RetryingClient,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    parts: Vec<CompletedPart>,
+    expected_parts: Option<usize>,
+) -> Result<String> {
+    if let Some(expected_parts) = expected_parts {
+        verify_completed_parts(&parts, expected_parts)?;
+    }
+    let attempts = AtomicUsize::new(0);
+    let resp = client
+        .request(|client| {
+            attempts.fetch_add(1, Ordering::Relaxed);
+            let req = CompleteMultipartUploadRequest {
+                bucket: bucket.to_owned(),
+                key: key.to_owned(),
+                upload_id: upload_id.to_owned(),
+                multipart_upload: Some(CompletedMultipartUpload {
+                    parts: Some(parts.clone()),
+                }),
+                ..Default::default()
+            };
+            debug!("complete_multipart_upload request {:?}", req);
+            async move { client.complete_multipart_upload(req).await }
+        })
+        .await;
+    debug!("complete_multipart_upload response {:?}", resp);
+    // resp.location is s3.us-west-1.amazonaws.com/tangram-vision-datasets/
+    // whereas url is tangram-vision-datasets.s3.us-west-1.amazonaws.com/
+    // So they won't match, but the caller can just use the url value.
+    let version = match resp {
+        Ok(output) => output.version_id,
+        Err(e) if attempts.load(Ordering::Relaxed) > 1 && is_no_such_upload(&e) => {
+            debug!("Multipart upload of {} was already completed", key);
+            let resp = client
+                .request(|client| {
+                    let req = HeadObjectRequest {
+                        bucket: bucket.to_owned(),
+                        key: key.to_owned(),
+                        ..Default::default()
+                    };
+                    async move { client.head_object(req).await }
+                })
+                .await;
+            let resp = with_error_details(resp)?;
+            debug!("head_object response {:?}", resp);
+            resp.version_id
+        }
+        Err(e) => return with_error_details(Err(e)),
+    };
+    let version =
+        version.ok_or_else(|| anyhow!("Uploaded file wasn't versioned by storage provider"))?;
+    debug!("Resulting version for {}: {}", key, version);
+    Ok(version)
+}
+
 /// Upload a file to cloud storage in chunks, using many requests.
 ///
 /// Uses [S3 Multipart Upload APIs](https://docs.aws.amazon.com/AmazonS3/latest/userguide/mpuoverview.html).
 ///
 /// See [Performance][crate#performance] for details on upload concurrency.
 ///
+/// The file is split into `num_parts` parts if given, otherwise the chunk size
+/// is derived from the filesize (see [multipart_chunk_size]). Parts are then
+/// sized according to `part_size_strategy`.
+///
+/// The whole file is checksummed with `checksum_algorithm` as its parts are
+/// read for uploading, so the file is only read once.
+///
+/// If `verify_parts` is set, every part is checked to have been uploaded
+/// before the upload is completed (see [complete_multipart_upload]).
+///
 /// # Errors
 ///
 /// Returns an error if reading the file fails.
 ///
-/// Returns an error if the file is over the [MAX_FILE_SIZE].
+/// Returns an error if the file is over the [MAX_FILE_SIZE], or if `num_parts`
+/// gives parts that are too small or too large.
 ///
 /// Returns an error if cloud storage returns a non-200 response (e.g. if auth
 /// credentials are invalid, if server is unreachable, if checksum doesn't
 /// match) or if the returned data is malformed.
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_file_multipart(
     config: StorageConfig,
     path: String,
     filesize: usize,
     key: String,
-    multi_progress: &MultiProgress,
-) -> Result<(Url, String)> {
+    checksum_algorithm: ChecksumAlgorithm,
+    chunk_read_ahead: usize,
+    num_parts: Option<usize>,
+    part_size_strategy: PartSizeStrategy,
+    verify_parts: bool,
+    progress: Arc<dyn ProgressReporter>,
+    stats: &TransferStats,
+) -> Result<UploadedStream> {
     // Multipart upload references
     // https://docs.rs/s3-ext/0.2.2/s3_ext/trait.S3Ext.html#tymethod.upload_from_file_multipart
     // https://stackoverflow.com/questions/66558012/rust-aws-multipart-upload-using-rusoto-multithreaded-rayon-panicked-at-there
     // https://gist.github.com/ivormetcalf/f2b8e6abfece4328c86ad1ee34363caf
-    let region_endpoint = match &config.region {
-        Region::Custom { endpoint, .. } => endpoint.clone(),
-        r => format!("s3.{}.amazonaws.com", r.name()),
-    };
+    let url = config.object_url(&key)?;
 
-    let url_str = format!("https://{}.{}/{}", config.bucket, region_endpoint, key);
-    let url = Url::parse(&url_str)?;
-
-    let dispatcher = rusoto_core::HttpClient::new().unwrap();
-    // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
-    let client = S3Client::new_with(dispatcher, config.credentials, config.region);
+    let new_client = client_factory(&config, None);
+    let client = new_client();
+    let mut retrying_client = RetryingClient::new(new_client.clone());
 
     // ======
     // Create multipart upload (to get the upload_id)
@@ -571,7 +1789,7 @@ pub async fn upload_file_multipart(
         ..Default::default()
     };
     debug!("create_multipart_upload request {:?}", req);
-    let resp = client.create_multipart_upload(req).await?;
+    let resp = with_error_details(client.create_multipart_upload(req).await)?;
     debug!("create_multipart_upload response {:?}", resp);
     let upload_id = resp
         .upload_id
@@ -580,57 +1798,152 @@ pub async fn upload_file_multipart(
     // ======
     // Upload parts
     // ======
-    let chunk_size = derive_chunk_size(filesize)?;
+    let chunk_size = multipart_chunk_size(filesize, num_parts)?;
     let tokio_file = tokio::fs::File::open(&path).await?;
 
-    let progress_bar = multi_progress.add(ProgressBar::new(filesize as u64));
-    progress_bar.set_style(commands::get_default_progress_bar_style());
-    progress_bar.set_prefix(path);
-    progress_bar.set_position(0);
-    let pgbar = progress_bar.clone();
-
-    let completed_parts = upload_parts(
-        &client,
+    let uploaded = upload_parts(
+        new_client,
         tokio_file,
         config.bucket.clone(),
         key.clone(),
         upload_id.clone(),
         filesize,
         chunk_size,
+        part_size_strategy,
         CONCURRENT_REQUEST_LIMIT,
-        pgbar,
+        chunk_read_ahead,
+        checksum_algorithm,
+        progress.clone(),
+        stats.clone(),
     )
     .await?;
 
-    progress_bar.finish();
+    progress.finish();
 
     // ======
     // Complete multipart upload
     // ======
-    let req = CompleteMultipartUploadRequest {
+    let version = complete_multipart_upload(
+        &mut retrying_client,
+        &config.bucket,
+        &key,
+        &upload_id,
+        uploaded.parts,
+        verify_parts.then_some(uploaded.num_chunks),
+    )
+    .await?;
+
+    Ok(UploadedStream {
+        url,
+        version,
+        size: uploaded.size,
+        checksum: uploaded.checksum,
+    })
+}
+
+/// A file or stream uploaded to cloud storage, checksummed while it was
+/// uploaded (see [upload_file_oneshot], [upload_file_multipart], and
+/// [upload_stream_multipart]).
+#[derive(Debug)]
+pub struct UploadedStream {
+    /// Url of the uploaded object.
+    pub url: Url,
+    /// Version identifier given by cloud storage provider.
+    pub version: String,
+    /// Number of bytes uploaded.
+    pub size: usize,
+    /// Checksum of the uploaded data, base64-encoded.
+    pub checksum: String,
+}
+
+/// Upload everything read from `reader` (e.g. stdin) to cloud storage under
+/// `key`, using a multipart upload since the size isn't known up front.
+///
+/// Data is uploaded in [DEFAULT_CHUNK_SIZE] parts, so up to [MAX_PART_COUNT]
+/// of those (about 156 GiB) can be uploaded. The data is checksummed while
+/// it's uploaded (see [upload_parts_until_eof]). Parts are checked as for
+/// [upload_file_multipart] if `verify_parts` is set.
+///
+/// # Errors
+///
+/// Returns an error if reading fails, if nothing is read (an object can't be
+/// assembled from zero parts), or if the data is too large.
+///
+/// Returns an error if cloud storage returns a non-200 response (e.g. if auth
+/// credentials are invalid, if server is unreachable, if checksum doesn't
+/// match) or if the returned data is malformed.
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_stream_multipart<R>(
+    config: StorageConfig,
+    reader: R,
+    name: String,
+    key: String,
+    checksum_algorithm: ChecksumAlgorithm,
+    chunk_read_ahead: usize,
+    verify_parts: bool,
+    progress: Arc<dyn ProgressReporter>,
+    stats: &TransferStats,
+) -> Result<UploadedStream>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let url = config.object_url(&key)?;
+
+    let new_client = client_factory(&config, None);
+    let client = new_client();
+    let mut retrying_client = RetryingClient::new(new_client.clone());
+
+    let req = CreateMultipartUploadRequest {
         bucket: config.bucket.clone(),
         key: key.clone(),
-        upload_id,
-        multipart_upload: Some(CompletedMultipartUpload {
-            parts: Some(completed_parts),
-        }),
         ..Default::default()
     };
-    debug!("complete_multipart_upload request {:?}", req);
-    let resp = client.complete_multipart_upload(req).await?;
-    debug!("complete_multipart_upload response {:?}", resp);
-    // resp.location is s3.us-west-1.amazonaws.com/tangram-vision-datasets/
-    // whereas url is tangram-vision-datasets.s3.us-west-1.amazonaws.com/
-    // So they won't match, but we can just use the url value.
-    let version = resp
-        .version_id
-        .ok_or_else(|| anyhow!("Uploaded file wasn't versioned by storage provider"))?;
-    debug!("Resulting version for {}: {}", key, version);
+    debug!("create_multipart_upload request {:?}", req);
+    let resp = with_error_details(client.create_multipart_upload(req).await)?;
+    debug!("create_multipart_upload response {:?}", resp);
+    let upload_id = resp
+        .upload_id
+        .ok_or_else(|| anyhow!("Multipart upload is missing an UploadId"))?;
+
+    let streamed = upload_parts_until_eof(
+        new_client,
+        reader,
+        config.bucket.clone(),
+        key.clone(),
+        upload_id.clone(),
+        DEFAULT_CHUNK_SIZE,
+        CONCURRENT_REQUEST_LIMIT,
+        chunk_read_ahead,
+        checksum_algorithm,
+        progress.clone(),
+        stats.clone(),
+    )
+    .await?;
+    progress.finish();
+    if streamed.parts.is_empty() {
+        bail!("Nothing to upload for {}: no data was read", name);
+    }
+
+    let version = complete_multipart_upload(
+        &mut retrying_client,
+        &config.bucket,
+        &key,
+        &upload_id,
+        streamed.parts,
+        verify_parts.then_some(streamed.num_chunks),
+    )
+    .await?;
 
-    Ok((url, version))
+    Ok(UploadedStream {
+        url,
+        version,
+        size: streamed.size,
+        checksum: streamed.checksum,
+    })
 }
 
-/// Download a file from cloud storage.
+/// Download a file (and optionally a specific version of it) from cloud
+/// storage.
 ///
 /// Uses the [S3 GetObject API](https://docs.aws.amazon.com/AmazonS3/latest/API/API_GetObject.html).
 ///
@@ -641,41 +1954,232 @@ pub async fn upload_file_multipart(
 /// Returns an error if cloud storage returns a non-200 response (e.g. if auth
 /// credentials are invalid, if server is unreachable, if checksum doesn't
 /// match) or if the returned data is malformed.
-pub async fn download_file(config: StorageConfig, url: &Url) -> Result<rusoto_core::ByteStream> {
-    let key = url
-        .path()
-        .strip_prefix('/')
-        .ok_or_else(|| anyhow!("URL path didn't start with /: {}", url.path()))?;
+pub async fn download_file(
+    config: StorageConfig,
+    url: &Url,
+    version: Option<&str>,
+) -> Result<rusoto_core::ByteStream> {
+    let key = config.key_from_url(url)?;
 
     // Increase read buffer size in rusoto:
     // https://www.rusoto.org/performance.html
-    let mut http_config = rusoto_core::HttpConfig::new();
-    http_config.read_buf_size(2 * (MEBIBYTE as usize));
-    let dispatcher = rusoto_core::HttpClient::new_with_config(http_config).unwrap();
-    let client = S3Client::new_with(dispatcher, config.credentials, config.region);
-    let req = GetObjectRequest {
-        bucket: config.bucket,
-        key: key.to_owned(),
-        ..Default::default()
-    };
-    debug!("making download_file request {:?}", req);
-
-    let resp = client.get_object(req).await?;
+    let mut client = RetryingClient::new(client_factory(&config, Some(2 * (MEBIBYTE as usize))));
+    let resp = client
+        .request(|client| {
+            let req = GetObjectRequest {
+                bucket: config.bucket.clone(),
+                key: key.to_owned(),
+                version_id: version.map(str::to_owned),
+                ..Default::default()
+            };
+            debug!("making download_file request {:?}", req);
+            async move { client.get_object(req).await }
+        })
+        .await;
+    let resp = with_error_details(resp)?;
     debug!("download_file response {:?}", resp);
 
     let body = resp.body.ok_or_else(|| anyhow!("Empty file! {}", url))?;
     Ok(body)
 }
 
+/// Size, ETag, and other metadata of a file in cloud storage, as reported
+/// without downloading the file.
+#[derive(Debug, Default, PartialEq)]
+pub struct StoredObject {
+    /// Size of the file in bytes
+    pub content_length: Option<i64>,
+    /// ETag of the file (for files uploaded in one request, the quoted hex
+    /// md5 of the file)
+    pub e_tag: Option<String>,
+    /// When the file (version) was stored, as an HTTP date
+    pub last_modified: Option<String>,
+    /// MIME type of the file
+    pub content_type: Option<String>,
+    /// Storage class of the file (not reported for the STANDARD class)
+    pub storage_class: Option<String>,
+    /// Version identifier of the file
+    pub version_id: Option<String>,
+}
+
+/// Fetch the size, ETag, and other metadata of a file (and optionally a
+/// specific version of it) from cloud storage without downloading it,
+/// returning `None` if the file doesn't exist.
+///
+/// Uses the [S3 HeadObject API](https://docs.aws.amazon.com/AmazonS3/latest/API/API_HeadObject.html).
+///
+/// # Errors
+///
+/// Returns an error if the url is malformed.
+///
+/// Returns an error if cloud storage returns a non-200 response other than
+/// 404 Not Found (e.g. if auth credentials are invalid or if the server is
+/// unreachable).
+pub async fn head_object(
+    config: &StorageConfig,
+    url: &Url,
+    version: Option<&str>,
+) -> Result<Option<StoredObject>> {
+    let key = config.key_from_url(url)?;
+    let mut client = RetryingClient::new(client_factory(config, None));
+    let resp = client
+        .request(|client| {
+            let req = HeadObjectRequest {
+                bucket: config.bucket.clone(),
+                key: key.to_owned(),
+                version_id: version.map(str::to_owned),
+                ..Default::default()
+            };
+            debug!("making head_object request {:?}", req);
+            async move { client.head_object(req).await }
+        })
+        .await;
+    debug!("head_object response {:?}", resp);
+    match resp {
+        Ok(output) => Ok(Some(StoredObject {
+            content_length: output.content_length,
+            e_tag: output.e_tag,
+            last_modified: output.last_modified,
+            content_type: output.content_type,
+            storage_class: output.storage_class,
+            version_id: output.version_id,
+        })),
+        // HEAD responses have no body, so rusoto can't always tell it's a
+        // NoSuchKey error
+        Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(None),
+        Err(RusotoError::Unknown(resp)) if resp.status.as_u16() == 404 => Ok(None),
+        Err(e) => with_error_details(Err(e)),
+    }
+}
+
+/// Whether object versioning is enabled on the configured bucket.
+///
+/// Uploads record the version id that cloud storage assigns to each file,
+/// which it only does for buckets with versioning enabled (see
+/// [upload_file_oneshot]).
+///
+/// Uses the [S3 GetBucketVersioning API](https://docs.aws.amazon.com/AmazonS3/latest/API/API_GetBucketVersioning.html).
+///
+/// # Errors
+///
+/// Returns an error if cloud storage returns a non-200 response (e.g. if auth
+/// credentials are invalid or don't allow reading the bucket's versioning
+/// configuration, or if the server is unreachable).
+pub async fn bucket_versioning_enabled(config: &StorageConfig) -> Result<bool> {
+    let mut client = RetryingClient::new(client_factory(config, None));
+    let resp = client
+        .request(|client| {
+            let req = GetBucketVersioningRequest {
+                bucket: config.bucket.clone(),
+                ..Default::default()
+            };
+            async move { client.get_bucket_versioning(req).await }
+        })
+        .await;
+    let resp = with_error_details(resp)?;
+    debug!("get_bucket_versioning response {:?}", resp);
+    // Buckets that never had versioning enabled have no status, and buckets
+    // with versioning suspended have status "Suspended"
+    Ok(resp.status.as_deref() == Some("Enabled"))
+}
+
+/// How long presigned download urls remain valid.
+///
+/// 7 days is the longest expiration allowed by S3 for SigV4-signed urls.
+pub const PRESIGNED_URL_EXPIRATION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Create a presigned url that allows anyone holding it to download a
+/// specific version of a file (without needing credentials), until
+/// [PRESIGNED_URL_EXPIRATION] elapses.
+///
+/// # Errors
+///
+/// Returns an error if the url to presign is malformed.
+///
+/// Returns an error if credentials can't be loaded from the configured
+/// credentials source.
+pub async fn presign_download_url(
+    config: &StorageConfig,
+    url: &Url,
+    version: Option<&str>,
+) -> Result<String> {
+    let key = config.key_from_url(url)?;
+    let credentials = config
+        .credentials
+        .credentials()
+        .await
+        .context("Unable to load storage credentials to presign url")?;
+    let req = GetObjectRequest {
+        bucket: config.bucket.clone(),
+        key: key.to_owned(),
+        version_id: version.map(str::to_owned),
+        ..Default::default()
+    };
+    let options = PreSignedRequestOption {
+        expires_in: PRESIGNED_URL_EXPIRATION,
+    };
+    Ok(req.get_presigned_url(&config.region, &credentials, &options))
+}
+
 #[cfg(test)]
-mod tests {
-    use httpmock::{Method::GET, MockServer};
+pub(crate) mod tests {
+    use std::{
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        task::{Context, Poll},
+    };
+
+    use httpmock::{
+        Method::{GET, HEAD, PUT},
+        MockServer,
+    };
     use predicates::prelude::*;
+    use reqwest::StatusCode;
+    use rusoto_core::{
+        request::{BufferedHttpResponse, DispatchSignedRequestFuture, HttpResponse},
+        signature::{SignedRequest, SignedRequestPayload},
+        DispatchSignedRequest,
+    };
     use rusoto_mock::{MockCredentialsProvider, MockRequestDispatcher};
+    use tokio::io::ReadBuf;
     use tokio_test::io::Builder;
 
+    use indicatif::ProgressBar;
+
     use super::*;
 
+    /// Build a [ClientFactory] whose clients use dispatchers from the given
+    /// function.
+    fn mock_client_factory<F>(new_dispatcher: F) -> ClientFactory
+    where
+        F: Fn() -> MockRequestDispatcher + Send + Sync + 'static,
+    {
+        Arc::new(move || {
+            // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
+            S3Client::new_with(
+                new_dispatcher(),
+                MockCredentialsProvider,
+                Default::default(),
+            )
+        })
+    }
+
+    /// Build an UploadPartRequest for part 1 with a small body.
+    fn test_upload_part_request() -> UploadPartRequest {
+        let body: Vec<u8> = vec![1, 2, 3];
+        UploadPartRequest {
+            body: Some(StreamingBody::from(body)),
+            bucket: "test".to_owned(),
+            key: "test".to_owned(),
+            upload_id: "test".to_owned(),
+            part_number: 1,
+            ..Default::default()
+        }
+    }
+
     #[tokio::test]
     async fn test_download_file_403_forbidden() {
         let bucket = "tangram-test".to_owned();
@@ -683,9 +2187,7 @@ mod tests {
         let server = MockServer::start();
         let mock = server.mock(|when, then| {
             when.method(GET).path(format!("/{}/{}", bucket, key));
-            then.status(403).body("AccessDenied");
-            // Rusoto doesn't seem to parse the error xml anyway, so just use the simpler response body
-            // .body(r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><BucketName>tangs-stage</BucketName><RequestId>tx00000000000001970993c-0060245383-5ed52e8-sfo2a</RequestId><HostId>5ed52e8-sfo2a-sfo</HostId></Error>"#);
+            then.status(403).body(r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>AccessDenied</Code><BucketName>tangs-stage</BucketName><RequestId>tx00000000000001970993c-0060245383-5ed52e8-sfo2a</RequestId><HostId>5ed52e8-sfo2a-sfo</HostId></Error>"#);
         });
         let test_region = Region::Custom {
             name: "test".to_owned(),
@@ -695,14 +2197,23 @@ mod tests {
         let url = Url::parse(&url_str).unwrap();
 
         let config = StorageConfig {
-            credentials: StaticProvider::new_minimal("abc".to_owned(), "def".to_owned()),
+            credentials: StorageCredentials::Static(StaticProvider::new_minimal(
+                "abc".to_owned(),
+                "def".to_owned(),
+            )),
             region: test_region,
             bucket,
+            path_style_urls: false,
+            proxy: ProxyConfig::default(),
         };
 
-        let error = download_file(config, &url)
+        let error = download_file(config, &url, None)
             .await
             .expect_err("403 Forbidden response expected");
+        assert_eq!(
+            error.to_string(),
+            "AccessDenied (RequestId: tx00000000000001970993c-0060245383-5ed52e8-sfo2a)"
+        );
         match error.downcast_ref::<rusoto_core::RusotoError<rusoto_s3::GetObjectError>>() {
             Some(rusoto_core::RusotoError::Unknown(b)) => assert_eq!(b.status, 403),
             e => panic!("Unexpected error: {:?}", e),
@@ -712,17 +2223,106 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_read_file_chunks() {
-        let mock_string = String::from("test");
-        let reader = Builder::new().read(mock_string.as_bytes()).build();
+    async fn test_download_file_version() {
+        let bucket = "tangram-test".to_owned();
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/{}/test-file", bucket))
+                .query_param("versionId", "v2");
+            then.status(200).body("version 2");
+        });
+        let config = StorageConfig {
+            credentials: StorageCredentials::Static(StaticProvider::new_minimal(
+                "abc".to_owned(),
+                "def".to_owned(),
+            )),
+            region: Region::Custom {
+                name: "test".to_owned(),
+                endpoint: server.base_url(),
+            },
+            bucket,
+            path_style_urls: false,
+            proxy: ProxyConfig::default(),
+        };
 
-        let chunk_size = 2;
-        let filesize = 4;
+        let url = Url::parse(&format!("{}/test-file", server.base_url())).unwrap();
+        let body = download_file(config, &url, Some("v2")).await.unwrap();
+        let mut contents = String::new();
+        body.into_async_read()
+            .read_to_string(&mut contents)
+            .await
+            .unwrap();
+        assert_eq!(contents, "version 2");
+
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_head_object() {
+        let bucket = "tangs-stage".to_owned();
+        let server = MockServer::start();
+        let found = server.mock(|when, then| {
+            when.method(HEAD)
+                .path(format!("/{}/present", bucket))
+                .query_param("versionId", "v1");
+            then.status(200)
+                .header("Content-Length", "12")
+                .header("ETag", "\"6d1a9c2c6f0d6c6e1c8b3d8d2c0e5f1a\"")
+                .header("Last-Modified", "Wed, 03 Feb 2021 21:21:58 GMT")
+                .header("Content-Type", "application/octet-stream")
+                .header("x-amz-storage-class", "STANDARD_IA")
+                .header("x-amz-version-id", "v1");
+        });
+        let missing = server.mock(|when, then| {
+            when.method(HEAD).path(format!("/{}/missing", bucket));
+            then.status(404);
+        });
+        let config = StorageConfig {
+            credentials: StorageCredentials::Static(StaticProvider::new_minimal(
+                "abc".to_owned(),
+                "def".to_owned(),
+            )),
+            region: Region::Custom {
+                name: "test".to_owned(),
+                endpoint: server.base_url(),
+            },
+            bucket,
+            path_style_urls: false,
+            proxy: ProxyConfig::default(),
+        };
+
+        let url = Url::parse(&format!("{}/present", server.base_url())).unwrap();
+        assert_eq!(
+            head_object(&config, &url, Some("v1")).await.unwrap(),
+            Some(StoredObject {
+                content_length: Some(12),
+                e_tag: Some("\"6d1a9c2c6f0d6c6e1c8b3d8d2c0e5f1a\"".to_owned()),
+                last_modified: Some("Wed, 03 Feb 2021 21:21:58 GMT".to_owned()),
+                content_type: Some("application/octet-stream".to_owned()),
+                storage_class: Some("STANDARD_IA".to_owned()),
+                version_id: Some("v1".to_owned()),
+            })
+        );
+        let url = Url::parse(&format!("{}/missing", server.base_url())).unwrap();
+        assert_eq!(head_object(&config, &url, None).await.unwrap(), None);
+
+        found.assert();
+        missing.assert();
+    }
+
+    #[tokio::test]
+    async fn test_read_file_chunks() {
+        let mock_string = String::from("test");
+        let reader = Builder::new().read(mock_string.as_bytes()).build();
+
+        let chunk_size = 2;
+        let filesize = 4;
 
         let expected_parts: [i64; 2] = [1, 2];
-        let expected_chunks = vec!["te".as_bytes(), "st".as_bytes()];
+        let expected_chunks = ["te".as_bytes(), "st".as_bytes()];
 
-        let mut s = read_file_chunks(reader, chunk_size, filesize);
+        let mut s = read_file_chunks(reader, PartSizeStrategy::Fixed, chunk_size, filesize);
         let mut i = 0;
         while let Some(item) = s.next().await {
             let item = item.expect("Did not receive a valid chunk.");
@@ -742,9 +2342,9 @@ mod tests {
         let filesize = 5;
 
         let expected_parts: [i64; 3] = [1, 2, 3];
-        let expected_chunks = vec!["te".as_bytes(), "st".as_bytes(), "1".as_bytes()];
+        let expected_chunks = ["te".as_bytes(), "st".as_bytes(), "1".as_bytes()];
 
-        let mut s = read_file_chunks(reader, chunk_size, filesize);
+        let mut s = read_file_chunks(reader, PartSizeStrategy::Fixed, chunk_size, filesize);
         let mut i = 0;
         while let Some(item) = s.next().await {
             let item = item.expect("Did not receive a valid chunk.");
@@ -766,7 +2366,7 @@ mod tests {
 
         let chunk_size = 2;
         let filesize = 8;
-        let mut s = read_file_chunks(reader, chunk_size, filesize);
+        let mut s = read_file_chunks(reader, PartSizeStrategy::Fixed, chunk_size, filesize);
         if let Some(item) = s.next().await {
             assert!(
                 item.is_err(),
@@ -791,7 +2391,8 @@ mod tests {
 
         let chunk_size = 8;
         let filesize = 10;
-        let mut part_requests = read_file_chunks(reader, chunk_size, filesize);
+        let mut part_requests =
+            read_file_chunks(reader, PartSizeStrategy::Fixed, chunk_size, filesize);
 
         let r1 = part_requests
             .try_next()
@@ -822,9 +2423,9 @@ mod tests {
         let filesize = 8;
 
         let expected_parts: [i64; 2] = [1, 2];
-        let expected_chunks = vec!["ohnooh".as_bytes(), "no".as_bytes()];
+        let expected_chunks = ["ohnooh".as_bytes(), "no".as_bytes()];
 
-        let mut s = read_file_chunks(reader, chunk_size, filesize);
+        let mut s = read_file_chunks(reader, PartSizeStrategy::Fixed, chunk_size, filesize);
         let mut i = 0;
         while let Some(item) = s.next().await {
             let item = item.expect("Did not receive a valid chunk.");
@@ -835,26 +2436,47 @@ mod tests {
         assert_eq!(i, 2);
     }
 
+    #[tokio::test]
+    async fn test_read_chunks_until_eof() {
+        // Pipes return whatever is available, so reads are often short
+        let reader = Builder::new()
+            .read("te".as_bytes())
+            .read("s".as_bytes())
+            .read("t1".as_bytes())
+            .build();
+
+        let chunks: Vec<FileChunk> = read_chunks_until_eof(reader, 2)
+            .try_collect()
+            .await
+            .unwrap();
+        let parts: Vec<(i64, &[u8])> = chunks
+            .iter()
+            .map(|chunk| (chunk.part_number, chunk.data.as_slice()))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                (1, "te".as_bytes()),
+                (2, "st".as_bytes()),
+                (3, "1".as_bytes())
+            ]
+        );
+
+        let empty = Builder::new().build();
+        let mut s = read_chunks_until_eof(empty, 2);
+        assert!(s.try_next().await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_upload_completed_part_success() {
-        // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
-        let client = S3Client::new_with(
+        let mut client = RetryingClient::new(mock_client_factory(|| {
             MockRequestDispatcher::default()
                 .with_body("blah")
-                .with_header("ETag", "testvalue"),
-            MockCredentialsProvider,
-            Default::default(),
-        );
-        let body: Vec<u8> = vec![1, 2, 3];
-        let req = UploadPartRequest {
-            body: Some(StreamingBody::from(body)),
-            bucket: "test".to_owned(),
-            key: "test".to_owned(),
-            upload_id: "test".to_owned(),
-            part_number: 1,
-            ..Default::default()
-        };
-        let part = upload_completed_part(&client, req).await.unwrap();
+                .with_header("ETag", "testvalue")
+        }));
+        let part = upload_completed_part(&mut client, 1, test_upload_part_request)
+            .await
+            .unwrap();
         assert_eq!(
             part,
             CompletedPart {
@@ -866,22 +2488,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_upload_completed_part_missing_etag() {
-        // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
-        let client = S3Client::new_with(
-            MockRequestDispatcher::default().with_body("blah"),
-            MockCredentialsProvider,
-            Default::default(),
-        );
-        let body: Vec<u8> = vec![1, 2, 3];
-        let req = UploadPartRequest {
-            body: Some(StreamingBody::from(body)),
-            bucket: "test".to_owned(),
-            key: "test".to_owned(),
-            upload_id: "test".to_owned(),
-            part_number: 1,
-            ..Default::default()
-        };
-        let e = upload_completed_part(&client, req)
+        let mut client = RetryingClient::new(mock_client_factory(|| {
+            MockRequestDispatcher::default().with_body("blah")
+        }));
+        let e = upload_completed_part(&mut client, 1, test_upload_part_request)
             .await
             .unwrap_err()
             .to_string();
@@ -892,33 +2502,574 @@ mod tests {
 
     #[tokio::test]
     async fn test_upload_completed_part_timeout() {
-        // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
-        let client = S3Client::new_with(
+        let mut client = RetryingClient::new(mock_client_factory(|| {
             MockRequestDispatcher::with_dispatch_error(
                 rusoto_core::request::HttpDispatchError::new("my timeout message".to_owned()),
-            ),
-            MockCredentialsProvider,
-            Default::default(),
-        );
-        let body: Vec<u8> = vec![1, 2, 3];
-        let req = UploadPartRequest {
-            body: Some(StreamingBody::from(body)),
-            bucket: "test".to_owned(),
-            key: "test".to_owned(),
-            upload_id: "test".to_owned(),
-            part_number: 1,
-            ..Default::default()
-        };
+            )
+        }));
 
-        // First request will fail with HttpDispatchError (can indicate a timeout).
-        // Function should retry and succeed on second request.
-        let e = upload_completed_part(&client, req)
+        // Every attempt fails with HttpDispatchError (can indicate a timeout),
+        // so the error is returned once retries are exhausted.
+        let e = upload_completed_part(&mut client, 1, test_upload_part_request)
             .await
             .unwrap_err()
             .to_string();
         assert!(predicate::str::contains("my timeout message").eval(&e));
     }
 
+    #[tokio::test]
+    async fn test_retrying_client_replaces_failing_client() {
+        // The first client's connection is wedged, every later client works
+        let clients_built = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = clients_built.clone();
+        let mut client = RetryingClient::new(mock_client_factory(move || {
+            if counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                MockRequestDispatcher::with_dispatch_error(
+                    rusoto_core::request::HttpDispatchError::new("wedged".to_owned()),
+                )
+            } else {
+                MockRequestDispatcher::default().with_header("ETag", "testvalue")
+            }
+        }));
+
+        let part = upload_completed_part(&mut client, 1, test_upload_part_request)
+            .await
+            .unwrap();
+        assert_eq!(part.e_tag, Some("testvalue".to_owned()));
+        // The wedged client is discarded after FAILURES_BEFORE_NEW_CLIENT
+        // failures and the retry goes through a newly built client.
+        assert_eq!(clients_built.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retrying_client_does_not_retry_client_errors() {
+        let clients_built = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = clients_built.clone();
+        let mut client = RetryingClient::new(mock_client_factory(move || {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            MockRequestDispatcher::with_status(403)
+        }));
+
+        let e = upload_completed_part(&mut client, 1, test_upload_part_request)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(predicate::str::contains("Upload part 1 request failed").eval(&e));
+        assert_eq!(clients_built.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_upload_completed_part_error_details() {
+        let mut client = RetryingClient::new(mock_client_factory(|| {
+            MockRequestDispatcher::with_status(403).with_body(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+  <Code>SignatureDoesNotMatch</Code>
+  <Message>The request signature we calculated does not match the signature you provided. Check your key &amp; signing method.</Message>
+  <RequestId>4442587FB7D0A2F9</RequestId>
+</Error>"#,
+            )
+        }));
+
+        let e = upload_completed_part(&mut client, 1, test_upload_part_request)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Upload part 1 request failed: SignatureDoesNotMatch: The request signature we \
+             calculated does not match the signature you provided. Check your key & signing \
+             method. (RequestId: 4442587FB7D0A2F9)"
+        );
+    }
+
+    #[test]
+    fn test_storage_error_details() {
+        let error: RusotoError<rusoto_s3::GetObjectError> =
+            RusotoError::Unknown(BufferedHttpResponse {
+                status: StatusCode::FORBIDDEN,
+                body: Bytes::from_static(b"AccessDenied"),
+                headers: Default::default(),
+            });
+        assert_eq!(storage_error_details(&error), None);
+
+        let mut headers = reqwest::header::HeaderMap::<String>::default();
+        headers.insert(S3_REQUEST_ID_HEADER, "ABC123".to_owned());
+        let error: RusotoError<rusoto_s3::GetObjectError> = RusotoError::Unknown(BufferedHttpResponse {
+            status: StatusCode::BAD_REQUEST,
+            body: Bytes::from_static(
+                b"<Error><Code>AuthorizationHeaderMalformed</Code><Message>The region 'us-east-1' is wrong; expecting 'us-west-1'</Message></Error>",
+            ),
+            headers,
+        });
+        let details = storage_error_details(&error).unwrap();
+        assert_eq!(
+            details,
+            StorageErrorDetails {
+                code: "AuthorizationHeaderMalformed".to_owned(),
+                message: Some("The region 'us-east-1' is wrong; expecting 'us-west-1'".to_owned()),
+                request_id: Some("ABC123".to_owned()),
+            }
+        );
+        assert_eq!(
+            details.to_string(),
+            "AuthorizationHeaderMalformed: The region 'us-east-1' is wrong; expecting \
+             'us-west-1' (RequestId: ABC123)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retrying_client_resigns_expired_requests() {
+        // The first client's requests were signed with credentials that have
+        // since expired, a new client loads fresh credentials
+        let clients_built = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = clients_built.clone();
+        let mut client = RetryingClient::new(mock_client_factory(move || {
+            if counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                MockRequestDispatcher::with_status(400).with_body(
+                    "<Error><Code>ExpiredToken</Code>\
+                     <Message>The provided token has expired.</Message></Error>",
+                )
+            } else {
+                MockRequestDispatcher::default().with_body("bolster test")
+            }
+        }));
+
+        let resp = client
+            .request(|client| async move {
+                client
+                    .get_object(GetObjectRequest {
+                        bucket: "test".to_owned(),
+                        key: "test".to_owned(),
+                        ..Default::default()
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+        let body = resp.body.unwrap().map_ok(|b| b.to_vec()).try_concat().await;
+        assert_eq!(body.unwrap(), b"bolster test");
+        assert_eq!(clients_built.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    /// Reader that counts how many bytes have been read from it.
+    #[derive(Debug)]
+    struct CountingReader {
+        /// Data to read
+        inner: std::io::Cursor<Vec<u8>>,
+        /// Number of bytes read so far
+        bytes_read: Arc<AtomicUsize>,
+    }
+
+    impl AsyncRead for CountingReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let before = buf.filled().len();
+            let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+            self.bytes_read
+                .fetch_add(buf.filled().len() - before, Ordering::SeqCst);
+            result
+        }
+    }
+
+    /// Dispatcher that takes a while to upload each part, recording how many
+    /// chunks have been read from the file but not yet uploaded when each part
+    /// finishes.
+    struct SlowDispatcher {
+        /// Bytes read from the file so far
+        bytes_read: Arc<AtomicUsize>,
+        /// Size of each chunk
+        chunk_size: usize,
+        /// Parts uploaded so far
+        parts_uploaded: Arc<AtomicUsize>,
+        /// Chunks held in RAM when each part finished uploading
+        chunks_held: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl DispatchSignedRequest for SlowDispatcher {
+        fn dispatch(
+            &self,
+            _request: SignedRequest,
+            _timeout: Option<Duration>,
+        ) -> DispatchSignedRequestFuture {
+            let bytes_read = self.bytes_read.clone();
+            let chunk_size = self.chunk_size;
+            let parts_uploaded = self.parts_uploaded.clone();
+            let chunks_held = self.chunks_held.clone();
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let chunks_read = bytes_read.load(Ordering::SeqCst) / chunk_size;
+                let uploaded = parts_uploaded.fetch_add(1, Ordering::SeqCst);
+                chunks_held.lock().unwrap().push(chunks_read - uploaded);
+
+                let mut response = HttpResponse {
+                    status: StatusCode::OK,
+                    body: rusoto_core::ByteStream::from(Vec::new()),
+                    headers: Default::default(),
+                };
+                response.headers.insert("ETag", "testvalue".to_owned());
+                Ok(response)
+            })
+        }
+    }
+
+    /// Upload a 6-chunk file one part at a time, returning the most chunks
+    /// held in RAM while uploading.
+    async fn max_chunks_held_while_uploading(chunk_read_ahead: usize) -> usize {
+        let chunk_size = 4;
+        let filesize = 6 * chunk_size;
+        let bytes_read = Arc::new(AtomicUsize::new(0));
+        let chunks_held = Arc::new(Mutex::new(Vec::new()));
+        let reader = CountingReader {
+            inner: std::io::Cursor::new(vec![0; filesize]),
+            bytes_read: bytes_read.clone(),
+        };
+        let dispatcher_bytes_read = bytes_read.clone();
+        let dispatcher_chunks_held = chunks_held.clone();
+        let parts_uploaded = Arc::new(AtomicUsize::new(0));
+        let new_client: ClientFactory = Arc::new(move || {
+            S3Client::new_with(
+                SlowDispatcher {
+                    bytes_read: dispatcher_bytes_read.clone(),
+                    chunk_size,
+                    parts_uploaded: parts_uploaded.clone(),
+                    chunks_held: dispatcher_chunks_held.clone(),
+                },
+                MockCredentialsProvider,
+                Default::default(),
+            )
+        });
+
+        let parts = upload_parts(
+            new_client,
+            reader,
+            "test".to_owned(),
+            "test".to_owned(),
+            "test".to_owned(),
+            filesize,
+            chunk_size,
+            PartSizeStrategy::Fixed,
+            1,
+            chunk_read_ahead,
+            ChecksumAlgorithm::Md5,
+            Arc::new(ProgressBar::hidden()),
+            TransferStats::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(parts.parts.len(), 6);
+
+        let max_held = chunks_held.lock().unwrap().iter().max().copied();
+        max_held.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upload_parts_chunk_read_ahead() {
+        // Without read-ahead, only the chunk being uploaded is read
+        assert_eq!(max_chunks_held_while_uploading(0).await, 1);
+        // With read-ahead, the next chunks are prefetched while the current one
+        // uploads, but no more than requested
+        assert_eq!(max_chunks_held_while_uploading(2).await, 3);
+    }
+
+    /// Dispatcher that reads each request's body (like a real connection
+    /// would), failing the first request with a 500 so its part is retried.
+    struct FlakyDispatcher {
+        /// Requests dispatched so far
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl DispatchSignedRequest for FlakyDispatcher {
+        fn dispatch(
+            &self,
+            request: SignedRequest,
+            _timeout: Option<Duration>,
+        ) -> DispatchSignedRequestFuture {
+            let first_request = self.requests.fetch_add(1, Ordering::SeqCst) == 0;
+            Box::pin(async move {
+                if let Some(SignedRequestPayload::Stream(body)) = request.payload {
+                    body.map_ok(|b| b.to_vec()).try_concat().await.unwrap();
+                }
+                let mut response = HttpResponse {
+                    status: if first_request {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    } else {
+                        StatusCode::OK
+                    },
+                    body: rusoto_core::ByteStream::from(Vec::new()),
+                    headers: Default::default(),
+                };
+                response.headers.insert("ETag", "testvalue".to_owned());
+                Ok(response)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_parts_counts_retried_wire_bytes() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let dispatcher_requests = requests.clone();
+        let new_client: ClientFactory = Arc::new(move || {
+            S3Client::new_with(
+                FlakyDispatcher {
+                    requests: dispatcher_requests.clone(),
+                },
+                MockCredentialsProvider,
+                Default::default(),
+            )
+        });
+        let stats = TransferStats::default();
+
+        let parts = upload_parts(
+            new_client,
+            std::io::Cursor::new(vec![0; 12]),
+            "test".to_owned(),
+            "test".to_owned(),
+            "test".to_owned(),
+            12,
+            4,
+            PartSizeStrategy::Fixed,
+            1,
+            0,
+            ChecksumAlgorithm::Md5,
+            Arc::new(ProgressBar::hidden()),
+            stats.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(parts.parts.len(), 3);
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        // The file is checksummed once as it's read, despite the retry
+        assert_eq!(parts.size, 12);
+        assert_eq!(parts.checksum, base64::encode(*md5::compute([0; 12])));
+        // The failed part was sent twice, but only counts once as file data
+        assert_eq!(stats.file_bytes(), 12);
+        assert_eq!(stats.wire_bytes(), 16);
+    }
+
+    /// Status, body, and headers of a scripted response.
+    type ScriptedResponse = (u16, &'static str, Vec<(&'static str, &'static str)>);
+
+    /// Dispatcher that answers each request with the next of a list of
+    /// responses, recording the HTTP method of each request.
+    struct ScriptedDispatcher {
+        /// Responses to give, in order
+        responses: Arc<Vec<ScriptedResponse>>,
+        /// Methods of requests dispatched so far
+        methods: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl DispatchSignedRequest for ScriptedDispatcher {
+        fn dispatch(
+            &self,
+            request: SignedRequest,
+            _timeout: Option<Duration>,
+        ) -> DispatchSignedRequestFuture {
+            let mut methods = self.methods.lock().unwrap();
+            let (status, body, headers) = self.responses[methods.len()].clone();
+            methods.push(request.method.clone());
+            Box::pin(async move {
+                let mut response = HttpResponse {
+                    status: StatusCode::from_u16(status).unwrap(),
+                    body: rusoto_core::ByteStream::from(body.as_bytes().to_vec()),
+                    headers: Default::default(),
+                };
+                for (name, value) in headers {
+                    response.headers.insert(name, value.to_owned());
+                }
+                Ok(response)
+            })
+        }
+    }
+
+    /// Complete a multipart upload through a client answering with the given
+    /// responses, returning the result and the methods of requests made.
+    async fn complete_with_responses(
+        responses: Vec<ScriptedResponse>,
+    ) -> (Result<String>, Vec<String>) {
+        let responses = Arc::new(responses);
+        let methods = Arc::new(Mutex::new(Vec::new()));
+        let dispatcher_methods = methods.clone();
+        let mut client = RetryingClient::new(Arc::new(move || {
+            S3Client::new_with(
+                ScriptedDispatcher {
+                    responses: responses.clone(),
+                    methods: dispatcher_methods.clone(),
+                },
+                MockCredentialsProvider,
+                Default::default(),
+            )
+        }));
+        let parts = vec![CompletedPart {
+            e_tag: Some("testvalue".to_owned()),
+            part_number: Some(1),
+        }];
+
+        let result =
+            complete_multipart_upload(&mut client, "test", "test", "test", parts, Some(1)).await;
+        let methods = methods.lock().unwrap().clone();
+        (result, methods)
+    }
+
+    const COMPLETED_UPLOAD_BODY: &str = "<CompleteMultipartUploadResult>\
+        <Bucket>test</Bucket><Key>test</Key><ETag>\"testvalue-1\"</ETag>\
+        </CompleteMultipartUploadResult>";
+
+    const NO_SUCH_UPLOAD_BODY: &str = "<Error><Code>NoSuchUpload</Code>\
+        <Message>The specified upload does not exist.</Message></Error>";
+
+    #[tokio::test]
+    async fn test_complete_multipart_upload_retries_transient_failure() {
+        let (version, methods) = complete_with_responses(vec![
+            (500, "", vec![]),
+            (200, COMPLETED_UPLOAD_BODY, vec![("x-amz-version-id", "v1")]),
+        ])
+        .await;
+        assert_eq!(version.unwrap(), "v1");
+        assert_eq!(methods, vec!["POST", "POST"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_multipart_upload_already_completed() {
+        // The first attempt completed the upload but its response was lost, so
+        // the completed object's version is looked up instead
+        let (version, methods) = complete_with_responses(vec![
+            (500, "", vec![]),
+            (404, NO_SUCH_UPLOAD_BODY, vec![]),
+            (200, "", vec![("x-amz-version-id", "v1")]),
+        ])
+        .await;
+        assert_eq!(version.unwrap(), "v1");
+        assert_eq!(methods, vec!["POST", "POST", "HEAD"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_multipart_upload_missing_upload() {
+        // Without an earlier attempt, a missing upload is an error
+        let (version, methods) =
+            complete_with_responses(vec![(404, NO_SUCH_UPLOAD_BODY, vec![])]).await;
+        let e = version.unwrap_err().to_string();
+        assert!(predicate::str::contains("NoSuchUpload").eval(&e));
+        assert_eq!(methods, vec!["POST"]);
+    }
+
+    #[tokio::test]
+    async fn test_complete_multipart_upload_detects_dropped_part() {
+        let new_client: ClientFactory = Arc::new(move || {
+            S3Client::new_with(
+                FlakyDispatcher {
+                    requests: Arc::new(AtomicUsize::new(1)),
+                },
+                MockCredentialsProvider,
+                Default::default(),
+            )
+        });
+        let mut streamed = upload_parts_until_eof(
+            new_client,
+            std::io::Cursor::new(b"bolster test".to_vec()),
+            "test".to_owned(),
+            "test".to_owned(),
+            "test".to_owned(),
+            5,
+            2,
+            0,
+            ChecksumAlgorithm::Md5,
+            Arc::new(ProgressBar::hidden()),
+            TransferStats::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(streamed.num_chunks, 3);
+        // Simulate an upload task that was silently dropped
+        streamed.parts.remove(1);
+
+        let methods = Arc::new(Mutex::new(Vec::new()));
+        let dispatcher_methods = methods.clone();
+        let responses = Arc::new(vec![(
+            200,
+            COMPLETED_UPLOAD_BODY,
+            vec![("x-amz-version-id", "v1")],
+        )]);
+        let mut client = RetryingClient::new(Arc::new(move || {
+            S3Client::new_with(
+                ScriptedDispatcher {
+                    responses: responses.clone(),
+                    methods: dispatcher_methods.clone(),
+                },
+                MockCredentialsProvider,
+                Default::default(),
+            )
+        }));
+        let e = complete_multipart_upload(
+            &mut client,
+            "test",
+            "test",
+            "test",
+            streamed.parts.clone(),
+            Some(streamed.num_chunks),
+        )
+        .await
+        .unwrap_err()
+        .to_string();
+        // No CompleteMultipartUpload request is made
+        assert!(methods.lock().unwrap().is_empty());
+        assert!(
+            predicate::str::contains("missing part 2 (of 3").eval(&e),
+            "{}",
+            e
+        );
+
+        // Without the check, the request is sent as is
+        let version =
+            complete_multipart_upload(&mut client, "test", "test", "test", streamed.parts, None)
+                .await
+                .unwrap();
+        assert_eq!(version, "v1");
+        assert_eq!(*methods.lock().unwrap(), vec!["POST"]);
+    }
+
+    #[tokio::test]
+    async fn test_upload_parts_until_eof() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let dispatcher_requests = requests.clone();
+        let new_client: ClientFactory = Arc::new(move || {
+            S3Client::new_with(
+                FlakyDispatcher {
+                    requests: dispatcher_requests.clone(),
+                },
+                MockCredentialsProvider,
+                Default::default(),
+            )
+        });
+        let data = b"bolster test".to_vec();
+
+        let streamed = upload_parts_until_eof(
+            new_client,
+            std::io::Cursor::new(data.clone()),
+            "test".to_owned(),
+            "test".to_owned(),
+            "test".to_owned(),
+            5,
+            2,
+            0,
+            ChecksumAlgorithm::Md5,
+            Arc::new(ProgressBar::hidden()),
+            TransferStats::default(),
+        )
+        .await
+        .unwrap();
+
+        let part_numbers: Vec<_> = streamed.parts.iter().map(|p| p.part_number).collect();
+        assert_eq!(part_numbers, vec![Some(1), Some(2), Some(3)]);
+        assert_eq!(streamed.size, data.len());
+        // The checksum covers all parts, even though a part was retried
+        assert_eq!(streamed.checksum, base64::encode(*md5::compute(&data)));
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
     #[tokio::test]
     async fn test_upload_parts_file_read_err_exits_early() {
         let reader = Builder::new()
@@ -929,25 +3080,24 @@ mod tests {
             ))
             .build();
 
-        // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
-        let client = S3Client::new_with(
-            MockRequestDispatcher::default().with_body("blah"),
-            MockCredentialsProvider,
-            Default::default(),
-        );
+        let new_client = mock_client_factory(|| MockRequestDispatcher::default().with_body("blah"));
 
         // Error reading file throws immediately
-        let progress_bar = ProgressBar::hidden();
+        let progress = Arc::new(ProgressBar::hidden());
         let e = upload_parts(
-            &client,
+            new_client,
             reader,
             "test".to_owned(),
             "test".to_owned(),
             "test".to_owned(),
             8,
             4,
+            PartSizeStrategy::Fixed,
             2,
-            progress_bar,
+            0,
+            ChecksumAlgorithm::Md5,
+            progress,
+            TransferStats::default(),
         )
         .await
         .unwrap_err()
@@ -963,30 +3113,31 @@ mod tests {
             .read("ohno".as_bytes())
             .build();
 
-        // credential docs: https://github.com/rusoto/rusoto/blob/master/AWS-CREDENTIALS.md
-        let client = S3Client::new_with(
+        let new_client = mock_client_factory(|| {
             MockRequestDispatcher::with_dispatch_error(
                 rusoto_core::request::HttpDispatchError::new("my timeout message".to_owned()),
-            ),
-            MockCredentialsProvider,
-            Default::default(),
-        );
+            )
+        });
 
         // Error reading networ throws immediately
-        let progress_bar = ProgressBar::hidden();
+        let progress = Arc::new(ProgressBar::hidden());
         let e = upload_parts(
-            &client,
+            new_client,
             reader,
             "test".to_owned(),
             "test".to_owned(),
             "test".to_owned(),
             12,
             4,
+            PartSizeStrategy::Fixed,
             // concurrent_request_limit must be >= num_chunks to exhaust the
             // reader mock before the network error is handled, otherwise the
             // mock panics with "There is still data left to read"
             4,
-            progress_bar,
+            0,
+            ChecksumAlgorithm::Md5,
+            progress,
+            TransferStats::default(),
         )
         .await
         .unwrap_err()
@@ -994,6 +3145,417 @@ mod tests {
         assert!(predicate::str::contains("my timeout message").eval(&e));
     }
 
+    fn config_from_str(toml: &str) -> config::Config {
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(toml, config::FileFormat::Toml))
+            .unwrap();
+        config
+    }
+
+    /// Storage config for S3-compatible storage at `endpoint` with a
+    /// `datasets` bucket and test credentials. `extra` TOML is appended, so
+    /// it can add `[s3_compatible]` keys or other sections.
+    pub(crate) fn s3_compatible_test_config(endpoint: &str, extra: &str) -> StorageConfig {
+        let config = config_from_str(&format!(
+            "[s3_compatible]\nendpoint = \"{}\"\nbucket = \"datasets\"\naccess_key = \"abc\"\nsecret_key = \"def\"\n{}",
+            endpoint, extra
+        ));
+        StorageConfig::new(config, StorageProviderChoices::S3Compatible).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upload_file_oneshot_through_proxy() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(PUT).path("/datasets/robot-1/a.csv");
+            then.status(200).header("x-amz-version-id", "v1");
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let storage_config = s3_compatible_test_config(
+            "http://storage.invalid:9000",
+            &format!(
+                "[network]\nproxy = \"http://{}\"",
+                listener.local_addr().unwrap()
+            ),
+        );
+        let proxy = tokio::spawn(crate::core::api::proxy::tests::serve_proxy(
+            listener,
+            Some(server.address().to_string()),
+        ));
+        let path = std::env::temp_dir().join(format!("bolster-test-{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "bolster test").unwrap();
+
+        // The endpoint doesn't resolve, so it must be reached through the
+        // proxy
+        let uploaded = upload_file_oneshot(
+            storage_config,
+            path.to_str().unwrap().to_owned(),
+            "bolster test".len(),
+            "robot-1/a.csv".to_owned(),
+            ChecksumAlgorithm::Md5,
+            &HashingPool::new(1),
+            Arc::new(ProgressBar::hidden()),
+            &TransferStats::default(),
+        )
+        .await;
+        std::fs::remove_file(&path).unwrap();
+        let request = proxy.await.unwrap();
+
+        assert_eq!(uploaded.unwrap().version, "v1");
+        assert!(
+            request.starts_with("CONNECT storage.invalid:9000 HTTP/1.1\r\n"),
+            "{}",
+            request
+        );
+        mock.assert();
+    }
+
+    #[test]
+    fn test_storage_config_s3_compatible() {
+        let storage_config =
+            s3_compatible_test_config("http://localhost:9000", "region = \"local\"");
+        assert_eq!(
+            storage_config.region,
+            Region::Custom {
+                name: "local".to_owned(),
+                endpoint: "http://localhost:9000".to_owned(),
+            }
+        );
+        let url = storage_config.object_url("user/dataset/file.bag").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:9000/datasets/user/dataset/file.bag"
+        );
+        assert_eq!(
+            storage_config.key_from_url(&url).unwrap(),
+            "user/dataset/file.bag"
+        );
+        storage_config
+            .key_from_url(&Url::parse("http://localhost:9000/other/file.bag").unwrap())
+            .expect_err("Url in another bucket should be rejected");
+
+        let e = StorageConfig::new(config_from_str(""), StorageProviderChoices::S3Compatible)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            predicate::str::contains("must contain a [s3_compatible] section").eval(&e),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn test_storage_config_object_url() {
+        let config = config_from_str("[aws_s3]\naccess_key = \"abc\"\nsecret_key = \"def\"");
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+        let url = storage_config.object_url("user/dataset/file.bag").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://tangram-vision-datasets.s3.us-west-1.amazonaws.com/user/dataset/file.bag"
+        );
+        assert_eq!(
+            storage_config.key_from_url(&url).unwrap(),
+            "user/dataset/file.bag"
+        );
+    }
+
+    #[test]
+    fn test_parse_region_override() {
+        assert_eq!(parse_region_override("us-east-2").unwrap(), Region::UsEast2);
+        assert_eq!(
+            parse_region_override("custom:localhost:9000").unwrap(),
+            Region::Custom {
+                name: String::new(),
+                endpoint: "https://localhost:9000".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse_region_override("custom:http://127.0.0.1:9000/").unwrap(),
+            Region::Custom {
+                name: String::new(),
+                endpoint: "http://127.0.0.1:9000".to_owned(),
+            }
+        );
+        assert!(parse_region_override("moon-west-1")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unknown region moon-west-1"));
+        parse_region_override("custom:").unwrap_err();
+    }
+
+    #[test]
+    fn test_storage_config_with_overrides() {
+        let config = config_from_str("[aws_s3]\naccess_key = \"abc\"\nsecret_key = \"def\"");
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+
+        let unchanged = storage_config.clone().with_overrides(None, None);
+        assert_eq!(
+            unchanged.location(),
+            "bucket tangram-vision-datasets in region us-west-1"
+        );
+
+        let staging = storage_config
+            .clone()
+            .with_overrides(Some("staging".to_owned()), Some(Region::UsEast2));
+        assert_eq!(
+            staging.object_url("file.bag").unwrap().as_str(),
+            "https://staging.s3.us-east-2.amazonaws.com/file.bag"
+        );
+
+        let custom = storage_config.with_overrides(
+            None,
+            Some(parse_region_override("custom:http://localhost:9000").unwrap()),
+        );
+        assert_eq!(
+            custom.location(),
+            "bucket tangram-vision-datasets in region us-west-1 at http://localhost:9000"
+        );
+        let url = custom.object_url("file.bag").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:9000/tangram-vision-datasets/file.bag"
+        );
+        assert_eq!(custom.key_from_url(&url).unwrap(), "file.bag");
+    }
+
+    #[tokio::test]
+    async fn test_storage_config_explicit_keys() {
+        let config = config_from_str("[aws_s3]\naccess_key = \"abc\"\nsecret_key = \"def\"");
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+        match &storage_config.credentials {
+            StorageCredentials::Static(provider) => {
+                assert_eq!(provider.get_aws_access_key_id(), "abc");
+                assert_eq!(provider.get_aws_secret_access_key(), "def");
+            }
+            c => panic!("Unexpected credentials: {:?}", c),
+        }
+        let creds = storage_config.credentials.credentials().await.unwrap();
+        assert_eq!(creds.aws_access_key_id(), "abc");
+    }
+
+    #[test]
+    fn test_storage_config_profile() {
+        let config = config_from_str("[aws_s3]\nprofile = \"bolster-test\"");
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+        match &storage_config.credentials {
+            StorageCredentials::Profile(provider) => {
+                assert_eq!(provider.profile(), "bolster-test");
+            }
+            c => panic!("Unexpected credentials: {:?}", c),
+        }
+    }
+
+    #[test]
+    fn test_storage_config_credential_source() {
+        let config = config_from_str(
+            "[aws_s3]\ncredential_source = \"static\"\naccess_key = \"abc\"\nsecret_key = \"def\"",
+        );
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+        assert!(matches!(
+            storage_config.credentials,
+            StorageCredentials::Static(_)
+        ));
+
+        let config = config_from_str("[aws_s3]\ncredential_source = \"profile\"");
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+        assert!(matches!(
+            storage_config.credentials,
+            StorageCredentials::Profile(_)
+        ));
+
+        let config = config_from_str("[aws_s3]\ncredential_source = \"environment\"");
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+        assert!(matches!(
+            storage_config.credentials,
+            StorageCredentials::Environment(_)
+        ));
+
+        let config = config_from_str(
+            "[aws_s3]\ncredential_source = \"environment\"\naccess_key = \"abc\"\nsecret_key = \"def\"",
+        );
+        let e = StorageConfig::new(config, StorageProviderChoices::Aws)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            predicate::str::contains("reads credentials from the environment").eval(&e),
+            "{}",
+            e
+        );
+
+        let config = config_from_str(
+            "[aws_s3]\ncredential_source = \"profile\"\naccess_key = \"abc\"\nsecret_key = \"def\"",
+        );
+        let e = StorageConfig::new(config, StorageProviderChoices::Aws)
+            .unwrap_err()
+            .to_string();
+        assert!(predicate::str::contains("not both").eval(&e), "{}", e);
+
+        let config = config_from_str("[aws_s3]\ncredential_source = \"bogus\"");
+        StorageConfig::new(config, StorageProviderChoices::Aws)
+            .expect_err("Unknown credential source should be rejected");
+    }
+
+    #[test]
+    fn test_storage_config_bad_key_combinations() {
+        let config = config_from_str(
+            "[aws_s3]\naccess_key = \"abc\"\nsecret_key = \"def\"\nprofile = \"bolster-test\"",
+        );
+        let e = StorageConfig::new(config, StorageProviderChoices::Aws)
+            .unwrap_err()
+            .to_string();
+        assert!(predicate::str::contains("not both").eval(&e), "{}", e);
+
+        let config = config_from_str("[aws_s3]\naccess_key = \"abc\"");
+        let e = StorageConfig::new(config, StorageProviderChoices::Aws)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            predicate::str::contains("must contain both access_key and secret_key").eval(&e),
+            "{}",
+            e
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checksum_file() {
+        let path = "fixtures/test_full_config.toml";
+        let contents = std::fs::read(path).unwrap();
+        assert_eq!(
+            checksum_file(path, ChecksumAlgorithm::Md5).await.unwrap(),
+            base64::encode(*md5::compute(&contents))
+        );
+        assert_eq!(
+            checksum_file(path, ChecksumAlgorithm::Sha256)
+                .await
+                .unwrap(),
+            base64::encode(Sha256::digest(&contents))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hashing_pool_checksum() {
+        let contents = Bytes::from(vec![7u8; 3 * MEBIBYTE as usize]);
+        // A pool of 0 jobs still hashes one buffer at a time
+        for hashing in [HashingPool::new(0), HashingPool::new(4)] {
+            let checksums = futures::future::try_join_all(
+                [ChecksumAlgorithm::Md5, ChecksumAlgorithm::Sha256]
+                    .iter()
+                    .map(|algorithm| hashing.checksum(contents.clone(), *algorithm)),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                checksums,
+                vec![
+                    base64::encode(*md5::compute(&contents)),
+                    base64::encode(Sha256::digest(&contents)),
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checksum_reader_reports_progress() {
+        let contents = vec![7u8; 3 * MEBIBYTE as usize];
+        let mut progress = Vec::new();
+        let checksum = checksum_reader(&contents[..], ChecksumAlgorithm::Md5, |hashed| {
+            progress.push(hashed)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(checksum, base64::encode(*md5::compute(&contents)));
+        assert!(progress.len() > 1, "{:?}", progress);
+        assert!(progress.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(progress.last(), Some(&(contents.len() as u64)));
+    }
+
+    #[test]
+    fn test_verify_completed_parts() {
+        let make_parts = |nums: &[i64]| -> Vec<CompletedPart> {
+            nums.iter()
+                .map(|n| CompletedPart {
+                    e_tag: Some(format!("etag{}", n)),
+                    part_number: Some(*n),
+                })
+                .collect()
+        };
+
+        verify_completed_parts(&make_parts(&[1, 2, 3]), 3).unwrap();
+
+        let e = verify_completed_parts(&make_parts(&[1, 3]), 3)
+            .unwrap_err()
+            .to_string();
+        assert!(predicate::str::contains("missing part 2").eval(&e), "{}", e);
+
+        let e = verify_completed_parts(&make_parts(&[1, 2]), 3)
+            .unwrap_err()
+            .to_string();
+        assert!(predicate::str::contains("missing part 3").eval(&e), "{}", e);
+
+        let e = verify_completed_parts(&make_parts(&[1, 1, 2]), 3)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            predicate::str::contains("unexpected part number").eval(&e),
+            "{}",
+            e
+        );
+
+        let e = verify_completed_parts(&make_parts(&[1, 2, 3]), 2)
+            .unwrap_err()
+            .to_string();
+        assert!(predicate::str::contains("has 3 parts but expected 2").eval(&e));
+    }
+
+    #[test]
+    fn test_adaptive_part_sizes_increase_within_s3_limits() {
+        for filesize in [
+            100 * (MEBIBYTE as usize),
+            20 * (GIBIBYTE as usize),
+            MAX_FILE_SIZE,
+        ] {
+            let chunk_size = derive_chunk_size(filesize).unwrap();
+            let sizes = PartSizeStrategy::Adaptive.part_sizes(chunk_size, filesize);
+
+            assert_eq!(sizes.iter().sum::<usize>(), filesize);
+            assert!(sizes.len() as i64 <= MAX_PART_COUNT);
+            assert_eq!(sizes[0], MIN_PART_SIZE);
+            // Every part but the last grows (or stays at the largest size)
+            let (last, rest) = sizes.split_last().unwrap();
+            assert!(rest.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(rest
+                .iter()
+                .all(|size| (MIN_PART_SIZE..=MAX_PART_SIZE).contains(size)));
+            assert!(*last <= MAX_PART_SIZE);
+            // Parts grow, but no larger than the growth limit
+            let largest = min(chunk_size * ADAPTIVE_PART_SIZE_GROWTH, MAX_PART_SIZE);
+            assert!(rest.iter().all(|size| *size <= largest));
+            assert!(rest.iter().any(|size| *size >= chunk_size));
+        }
+    }
+
+    #[test]
+    fn test_fixed_part_sizes() {
+        assert_eq!(PartSizeStrategy::Fixed.part_sizes(4, 10), vec![4, 4, 2]);
+        assert!(PartSizeStrategy::Fixed.part_sizes(4, 0).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_read_file_chunks_adaptive() {
+        let reader = Builder::new().read(&[0; 30]).build();
+
+        let mut s = read_file_chunks(reader, PartSizeStrategy::Adaptive, 4, 30);
+        let mut sizes = Vec::new();
+        while let Some(item) = s.next().await {
+            sizes.push(item.unwrap().data.len());
+        }
+        // Parts double up to 4 times the chunk size
+        assert_eq!(sizes, vec![4, 8, 16, 2]);
+        assert_eq!(sizes, PartSizeStrategy::Adaptive.part_sizes(4, 30));
+    }
+
     #[test]
     fn test_derive_chunk_size() {
         assert_eq!(
@@ -1023,4 +3585,32 @@ mod tests {
             .to_string();
         assert!(predicate::str::contains("File is too large to upload").eval(&e));
     }
+
+    #[test]
+    fn test_chunk_size_for_num_parts() {
+        let gib = GIBIBYTE as usize;
+        assert_eq!(chunk_size_for_num_parts(gib, 4).unwrap(), gib / 4);
+        // Chunk size is ceiled, so the last part is the smallest
+        assert_eq!(chunk_size_for_num_parts(gib + 1, 4).unwrap(), gib / 4 + 1);
+        assert_eq!(chunk_size_for_num_parts(10 * gib, 2).unwrap(), 5 * gib);
+        assert_eq!(multipart_chunk_size(gib, Some(8)).unwrap(), gib / 8);
+        assert_eq!(multipart_chunk_size(gib, None).unwrap(), DEFAULT_CHUNK_SIZE);
+
+        let e = chunk_size_for_num_parts(gib, 1000).unwrap_err().to_string();
+        assert!(
+            predicate::str::contains("parts must be between 5.00 MiB and 5.00 GiB").eval(&e),
+            "{}",
+            e
+        );
+        let e = chunk_size_for_num_parts(11 * gib, 2)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            predicate::str::contains("gives parts of 5.50 GiB").eval(&e),
+            "{}",
+            e
+        );
+        chunk_size_for_num_parts(gib, 0).expect_err("Zero parts should be rejected");
+        chunk_size_for_num_parts(5001 * gib, 1000).expect_err("Huge files should be rejected");
+    }
 }
@@ -4,33 +4,52 @@
 
 use std::{
     clone::Clone,
-    cmp::Eq,
+    cmp::{max, Eq},
+    collections::{BTreeMap, HashMap, HashSet},
     convert::TryInto,
     fmt::{Debug, Display},
+    future::Future,
     iter,
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::{Instant, SystemTime},
 };
 
-use anyhow::{anyhow, Result};
-use byte_unit::MEBIBYTE;
-use futures::{stream, stream::StreamExt};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use byte_unit::{Byte, MEBIBYTE};
+use bytes::Bytes;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::{
+    future::{self, Either},
+    stream,
+    stream::{Stream, StreamExt, TryStreamExt},
+};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use jsonschema::JSONSchema;
 use log::debug;
 use read_progress_stream::ReadProgressStream;
 use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use strum_macros::{AsRefStr, EnumString, EnumVariantNames};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    sync::{Mutex, Semaphore, SemaphorePermit},
+};
 use uuid::Uuid;
+use walkdir::WalkDir;
 
 use super::{
     api::{
-        datasets::{self, DatabaseApiConfig, DatasetGetRequest},
+        datasets::{self, DatabaseApiConfig, DatasetGetRequest, DatasetOrdering, FileOrdering},
         storage,
-        storage::StorageConfig,
+        storage::{PartSizeStrategy, StorageConfig},
     },
-    models::{Dataset, UploadedFile},
+    compression::{self, CompressionFormat},
+    models::{Dataset, DatasetSummary, UploadedFile},
+    progress::{Progress, ProgressMode, ProgressReporter},
 };
-use crate::app_config::CompleteAppConfig;
+use crate::app_config::{strip_bom, ChecksumAlgorithm, CompleteAppConfig};
 
 /// Number of files allowed to upload at the same time.
 pub const MAX_FILES_UPLOADING_CONCURRENTLY: usize = 4;
@@ -38,6 +57,29 @@ pub const MAX_FILES_UPLOADING_CONCURRENTLY: usize = 4;
 /// Number of files allowed to download at the same time.
 pub const MAX_FILES_DOWNLOADING_CONCURRENTLY: usize = 4;
 
+/// Number of files checked in cloud storage at the same time when verifying a
+/// dataset (each check is a single small request).
+pub const MAX_FILES_VERIFYING_CONCURRENTLY: usize = 10;
+
+/// Metadata key set to `true` on datasets uploaded without a plex or
+/// object-space file, which can't be calibrated.
+pub const UNCALIBRATED_METADATA_KEY: &str = "uncalibrated";
+
+/// Metadata key holding a dataset's human-readable name (see
+/// [resolve_dataset_name]).
+pub const NAME_METADATA_KEY: &str = "name";
+
+/// Number of local files checksummed at the same time when finding which
+/// files a dataset already has (see [find_uploaded_files]).
+pub const MAX_FILES_HASHING_CONCURRENTLY: usize = 4;
+
+/// Maximum amount of downloaded data held in RAM at once when writing files
+/// sequentially (see [DownloadOptions::sequential_writes]).
+///
+/// A single file larger than this limit isn't buffered; it's streamed to disk
+/// while no other file is being written.
+pub const SEQUENTIAL_WRITES_MAX_BUFFERED_BYTES: usize = 512 * (MEBIBYTE as usize);
+
 /// Files with sizes under this threshold use one-shot upload, all other files
 /// use multipart upload.
 ///
@@ -48,377 +90,5483 @@ pub const MAX_FILES_DOWNLOADING_CONCURRENTLY: usize = 4;
 /// slower.
 pub const MULTIPART_FILESIZE_THRESHOLD: usize = 64 * (MEBIBYTE as usize);
 
-/// Provides the default progress bar style
+/// Whether a file of the given size is uploaded in chunks with a multipart
+/// upload (rather than a oneshot upload), given the size at or above which
+/// files are uploaded in chunks (see [UploadOptions::multipart_threshold]).
 ///
-/// For a list of template fields (e.g. elapsed time, bytes remaining), see
-/// [indicatif's documentation on
-/// Templates](https://docs.rs/indicatif/0.16.2/indicatif/#templates).
-pub fn get_default_progress_bar_style() -> ProgressStyle {
-    ProgressStyle::default_bar()
-    .template("{prefix} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ({eta})")
-    .progress_chars("#>-")
+/// All files in a dataset (including the plex and object-space file) are
+/// uploaded the same way, so a large object-space file gets the same chunked
+/// treatment as a large data file.
+pub fn uses_multipart_upload(filesize: usize, threshold: usize) -> bool {
+    filesize >= threshold
 }
 
-/// Creates a dataset and returns its id.
-///
-/// Thin wrapper around [datasets::datasets_post] -- see its documentation for
-/// behavior and possible errors.
-pub async fn create_dataset(config: &DatabaseApiConfig, system_id: String) -> Result<Uuid> {
-    let dataset = datasets::datasets_post(config, system_id, json!({})).await?;
-    Ok(dataset.dataset_id)
-}
+/// Placeholder shown in place of the dataset id when planning an upload with
+/// [UploadOptions::dry_run] (no dataset is created, so there's no real id).
+pub const DRY_RUN_DATASET_ID_PLACEHOLDER: &str = "<DATASET_UUID>";
 
-/// Eases usage of [multiple progress
-/// bars](https://docs.rs/indicatif/0.16.2/indicatif/struct.MultiProgress.html)
-/// in an async environment.
-///
-/// Manages annoyances with indicatif, namely that:
-/// - some thread of execution needs to join the MultiProgress to get progress
-/// bars to render
-/// - joining the MultiProgress immediately returns if there aren't ProgressBars
-/// attached, so we add a hidden/bogus one
-/// - the hidden/bogus ProgressBar needs to be cleaned up (by Drop, in this
-/// implementation) when we don't need to update progress bars anymore
-pub struct MultiProgressGuard {
-    /// Pointer to the multi-progress bar, cloned internally and passed to a
-    /// tokio task to join to the bar so it renders.
-    inner: Arc<MultiProgress>,
-    /// Hidden spinner progress bar to ensure the multi-progress bar stays alive
-    /// until this guard is dropped.
-    hidden_spinner: ProgressBar,
+/// Options that change how files are uploaded.
+#[derive(Debug, Default)]
+pub struct UploadOptions {
+    /// Print what would be uploaded without creating a dataset or uploading
+    /// any files.
+    pub dry_run: bool,
+    /// Options passed to the backend to control processing of the dataset
+    /// (e.g. which pipeline to run). Must be a JSON object.
+    pub processing_options: Option<serde_json::Value>,
+    /// Metadata to attach to the created dataset (e.g. weather, operator, or
+    /// firmware version).
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    /// After uploading, print info for sharing each uploaded file (see
+    /// [share_info]).
+    pub share: bool,
+    /// Share presigned download urls instead of `bolster download` commands.
+    /// Implies [UploadOptions::share].
+    pub presign: bool,
+    /// Hide progress bars and print only the created dataset's id.
+    pub quiet: bool,
+    /// How transfer progress is shown (see [UploadOptions::progress_mode]).
+    pub progress: ProgressMode,
+    /// Message printed when the dataset is created, with `{uuid}` replaced by
+    /// the dataset's id. An empty message prints nothing.
+    pub created_message: Option<String>,
+    /// Shell command run after the dataset is created, with the dataset's id
+    /// as its last argument (see [run_on_create_hook]).
+    pub on_create: Option<String>,
+    /// Algorithm used to checksum uploaded files.
+    pub checksum_algorithm: ChecksumAlgorithm,
+    /// Number of chunks of a large file to read ahead while earlier chunks
+    /// are uploading (see [storage::upload_parts]).
+    pub chunk_read_ahead: usize,
+    /// Split each multipart-uploaded file into exactly this many parts (e.g.
+    /// for benchmarking), instead of deriving the chunk size from the
+    /// filesize (see [storage::multipart_chunk_size]).
+    pub num_parts: Option<usize>,
+    /// Write a JSON report of files that failed to upload to this path (see
+    /// [ErrorReport]).
+    pub error_report: Option<PathBuf>,
+    /// Totals of file and network bytes uploaded (see [transfer_summary]).
+    pub transfer_stats: storage::TransferStats,
+    /// How long each phase of the upload took, for `--timings-json`.
+    pub timings: Timings,
+    /// Upload to this existing dataset instead of creating one, skipping
+    /// files it already has (see [find_uploaded_files]).
+    pub resume: Option<Uuid>,
+    /// Keep uploading the remaining files after a file fails, rather than
+    /// abandoning them at the first failure.
+    pub keep_going: bool,
+    /// When resuming, upload local files that differ from the dataset's file
+    /// at the same path instead of skipping them (see [DatasetFileMatches]).
+    pub force: bool,
+    /// Number of files checksummed at the same time while uploading or when
+    /// resuming (see [UploadOptions::hash_jobs]).
+    pub hash_jobs: Option<usize>,
+    /// Also upload everything read from stdin as a data file at this path in
+    /// the dataset (see [upload_stream]).
+    pub stdin_name: Option<String>,
+    /// Files at least this large (in bytes) are uploaded with a multipart
+    /// upload (defaults to [MULTIPART_FILESIZE_THRESHOLD]).
+    pub multipart_threshold: Option<usize>,
+    /// How the parts of multipart uploads are sized.
+    pub part_size_strategy: PartSizeStrategy,
+    /// Complete multipart uploads without first checking that every part was
+    /// uploaded (see [storage::complete_multipart_upload]).
+    pub skip_part_check: bool,
+    /// Compress data files with this format before uploading them (see
+    /// [UploadOptions::compression_for]).
+    pub compression: Option<CompressionFormat>,
+    /// Compress every data file, including those whose extensions are in
+    /// [compression::DEFAULT_SKIP_COMPRESSION_EXTENSIONS].
+    pub compress_all: bool,
 }
 
-impl MultiProgressGuard {
-    /// Initializes a
-    /// [MultiProgress](https://docs.rs/indicatif/0.16.2/indicatif/struct.MultiProgress.html)
-    /// (with a hidden progress bar) and joins it to begin rendering.
-    pub async fn new() -> Self {
-        let mp = Arc::new(MultiProgress::new());
-        let spinner = mp.add(ProgressBar::hidden());
-        let guard = MultiProgressGuard {
-            inner: mp,
-            hidden_spinner: spinner,
-        };
-        let mp2 = guard.inner.clone();
-        tokio::spawn(async move {
-            mp2.join().unwrap();
-        });
-        guard
+impl UploadOptions {
+    /// Size (in bytes) at or above which files are uploaded with a multipart
+    /// upload.
+    pub fn multipart_threshold(&self) -> usize {
+        self.multipart_threshold
+            .unwrap_or(MULTIPART_FILESIZE_THRESHOLD)
     }
-}
 
-impl Drop for MultiProgressGuard {
-    fn drop(&mut self) {
-        // Calling `spinner.finish` makes it appear for some reason, so we use
-        // `finish_and_clear` instead.
-        self.hidden_spinner.finish_and_clear();
+    /// How transfer progress is shown, which is [ProgressMode::None] if
+    /// [UploadOptions::quiet] is set.
+    pub fn progress_mode(&self) -> ProgressMode {
+        if self.quiet {
+            ProgressMode::None
+        } else {
+            self.progress
+        }
     }
-}
-
-/// Creates a dataset and async uploads all provided files.
-///
-/// See [Performance][crate#performance] for details on upload concurrency.
-///
-/// Wraps [create_dataset] and [upload_file] -- see those functions for behavior
-/// and possible errors.
-pub async fn create_and_upload_dataset<P>(
-    config: StorageConfig,
-    db_config: &DatabaseApiConfig,
-    system_id: String,
-    prefix: &str,
-    plex_file_path: P,
-    object_space_file_path: P,
-    file_paths: Vec<P>,
-) -> Result<()>
-where
-    P: AsRef<Path> + Debug + Display + Clone + Eq,
-{
-    let dataset_id: Uuid = create_dataset(db_config, system_id).await?;
-
-    println!("Created new dataset with UUID: {}", dataset_id);
-    debug!("paths: {:?}", file_paths);
-
-    let guard = MultiProgressGuard::new().await;
-    let multi_progress = guard.inner.clone();
-    let mut maybe_plex_file_id = None;
-    let mut maybe_object_space_file_id = None;
 
-    // Add plex + object_space file paths to front of list that will become
-    // upload futures.
-    let mut all_file_paths = file_paths.clone();
-    all_file_paths.insert(0, object_space_file_path.clone());
-    all_file_paths.insert(0, plex_file_path.clone());
+    /// Number of files checksummed at the same time, defaulting to
+    /// [MAX_FILES_HASHING_CONCURRENTLY].
+    pub fn hash_jobs(&self) -> usize {
+        self.hash_jobs.unwrap_or(MAX_FILES_HASHING_CONCURRENTLY)
+    }
 
-    let mut futs = stream::iter(all_file_paths)
-        .map(|path| async {
-            // Returns tuple of (is_plex, Result<UploadedFile, Error>)
-            (
-                // If path is the plex path, mark this as the plex so we can
-                // pull out the plex's file_id to associate as the input plex
-                // when triggering calibration.
-                path == plex_file_path,
-                // Do the same with the object_space path
-                path == object_space_file_path,
-                // Uploads to storage AND registers to database
-                upload_file(
-                    config.clone(),
-                    db_config,
-                    dataset_id,
+    /// Format the data file at `path` is compressed with before uploading,
+    /// if any.
+    ///
+    /// Files that are usually already compressed (see
+    /// [compression::DEFAULT_SKIP_COMPRESSION_EXTENSIONS]) aren't compressed
+    /// unless [UploadOptions::compress_all] is set.
+    pub fn compression_for(&self, path: &Path) -> Option<CompressionFormat> {
+        self.compression.filter(|_| {
+            self.compress_all
+                || compression::should_compress(
                     path,
-                    prefix,
-                    &multi_progress,
+                    &compression::DEFAULT_SKIP_COMPRESSION_EXTENSIONS,
                 )
-                .await,
-            )
         })
-        .buffer_unordered(MAX_FILES_UPLOADING_CONCURRENTLY);
-    while let Some((is_plex, is_object_space, res)) = futs.next().await {
-        let uploaded_file = res?;
-        if is_plex {
-            maybe_plex_file_id = Some(uploaded_file.file_id);
-        }
-        if is_object_space {
-            maybe_object_space_file_id = Some(uploaded_file.file_id);
-        }
     }
 
-    // After all uploads are complete, notify the backend so it can begin
-    // processing, send notifications, etc.
-    debug!("Upload(s) complete, notifying backend of completion");
-
-    let plex_file_id = maybe_plex_file_id
-        .ok_or_else(|| anyhow!("Unable to retrieve file_id for uploaded plex file!"))?;
-    let object_space_file_id = maybe_object_space_file_id
-        .ok_or_else(|| anyhow!("Unable to retrieve file_id for uploaded object space file!"))?;
-    datasets::datasets_notify_upload_complete(
-        db_config,
-        dataset_id,
-        plex_file_id,
-        object_space_file_id,
-    )
-    .await?;
-
-    Ok(())
+    /// Message to print when a dataset is created, if any.
+    ///
+    /// Defaults to just the dataset id if [UploadOptions::quiet] is set, so
+    /// scripts can capture it.
+    pub fn created_message(&self, dataset_id: Uuid) -> Option<String> {
+        let template = match &self.created_message {
+            Some(template) => template.as_str(),
+            None if self.quiet => "{uuid}",
+            None => "Created new dataset with UUID: {uuid}",
+        };
+        if template.is_empty() {
+            None
+        } else {
+            Some(template.replace("{uuid}", &dataset_id.to_string()))
+        }
+    }
 }
 
-/// List all datasets, optionally filtered by options in [DatasetGetRequest].
+/// Runs a user-provided hook command after a dataset is created (e.g. to
+/// record the dataset's id in a lab database).
 ///
-/// Thin wrapper around [datasets::datasets_get] -- see its documentation for
-/// behavior and possible errors.
-pub async fn list_datasets(
-    config: &DatabaseApiConfig,
-    params: &DatasetGetRequest,
-) -> Result<Vec<Dataset>> {
-    let datasets = datasets::datasets_get(config, params).await?;
-
-    Ok(datasets)
-}
-
-/// Registers uploaded file (critically, its url) in the datasets database.
+/// The command is run by the system shell with the dataset id appended as its
+/// last argument, and inherits bolster's stdout and stderr.
 ///
-/// Thin wrapper around [datasets::files_post] -- see its documentation for
-/// behavior and possible errors.
-pub async fn add_file_to_dataset(
-    config: &DatabaseApiConfig,
-    dataset_id: Uuid,
-    url: &Url,
-    filesize: usize,
-    version: String,
-    metadata: serde_json::Value,
-) -> Result<UploadedFile> {
-    datasets::files_post(config, dataset_id, url, filesize, version, metadata).await
+/// # Errors
+///
+/// Returns an error if the command can't be started or exits unsuccessfully.
+pub async fn run_on_create_hook(command: &str, dataset_id: Uuid) -> Result<()> {
+    let dataset_id = dataset_id.to_string();
+    let mut hook = if cfg!(windows) {
+        let mut hook = tokio::process::Command::new("cmd");
+        hook.arg("/C").arg(format!("{} {}", command, dataset_id));
+        hook
+    } else {
+        // "$1" is the dataset id (passed separately to avoid quoting issues)
+        let mut hook = tokio::process::Command::new("sh");
+        hook.arg("-c")
+            .arg(format!("{} \"$1\"", command))
+            .arg("bolster")
+            .arg(&dataset_id);
+        hook
+    };
+    debug!("Running on-create hook {:?}", hook);
+    let status = hook
+        .status()
+        .await
+        .with_context(|| format!("Unable to run on-create hook `{}`", command))?;
+    if !status.success() {
+        bail!(
+            "On-create hook `{}` failed ({}) for dataset {}",
+            command,
+            status,
+            dataset_id
+        );
+    }
+    Ok(())
 }
 
-/// Uploads a single file at the given path to the cloud storage provider
-/// indicated in `config` and registers the uploaded file in the datasets
-/// database.
-///
-/// Folder structure is preserved when uploading, so uploading `dir/file` is
-/// different from doing `cd dir` then uploading `file`.
-///
-/// Dispatches to [storage::upload_file_oneshot] if the file is < 64 MB or
-/// [storage::upload_file_multipart] otherwise.
+/// Parses and validates processing options given as a JSON string.
 ///
 /// # Errors
 ///
-/// Returns an error if the file is unreadable.
-///
-/// Invokes [storage::upload_file_oneshot], [storage::upload_file_multipart],
-/// and [add_file_to_dataset] -- see those functions' documentation for
-/// additional behavior and possible errors.
-pub async fn upload_file<P>(
-    config: StorageConfig,
-    db_config: &DatabaseApiConfig,
-    dataset_id: Uuid,
-    path: P,
-    prefix: &str,
-    multi_progress: &MultiProgress,
-) -> Result<UploadedFile>
-where
-    P: AsRef<Path> + Clone,
-{
-    // We retain any directories in the path
-    let path_str = path
-        .as_ref()
-        .to_str()
-        .ok_or_else(|| anyhow!("Path was not UTF8"))?
-        .to_owned();
-    let key = format!("{}/{}/{}", prefix, dataset_id, path_str);
-    debug!("key {}", key);
+/// Returns an error if the string isn't valid JSON or isn't a JSON object.
+pub fn parse_processing_options(json_str: &str) -> Result<serde_json::Value> {
+    let value: serde_json::Value =
+        serde_json::from_str(json_str).context("Processing options must be valid JSON")?;
+    if !value.is_object() {
+        bail!(
+            "Processing options must be a JSON object (e.g. '{{\"pipeline\": \"default\"}}'), got: {}",
+            value
+        );
+    }
+    Ok(value)
+}
 
-    debug!("Got path {:?}", path_str);
-    let filesize: usize = tokio::fs::metadata(path.clone())
-        .await?
-        .len()
-        .try_into()
-        .unwrap();
+/// Options that change how files are downloaded.
+#[derive(Clone, Debug, Default)]
+pub struct DownloadOptions {
+    /// Skip verifying downloaded files against the checksum recorded when
+    /// they were uploaded.
+    pub no_verify: bool,
+    /// Directory to download files into (defaults to the current working
+    /// directory).
+    pub output_dir: Option<PathBuf>,
+    /// Fetch files concurrently into RAM but write them to disk one at a time,
+    /// which avoids thrashing spinning disks with interleaved writes.
+    pub sequential_writes: bool,
+    /// Hide progress bars.
+    pub quiet: bool,
+    /// How transfer progress is shown (see [DownloadOptions::progress_mode]).
+    pub progress: ProgressMode,
+    /// Keep downloading the remaining files after a file fails, rather than
+    /// stopping at the first failure.
+    pub keep_going: bool,
+    /// Write a JSON report of files that failed to download to this path (see
+    /// [ErrorReport]).
+    pub error_report: Option<PathBuf>,
+    /// Totals of file and network bytes downloaded (see [transfer_summary]).
+    pub transfer_stats: storage::TransferStats,
+    /// How long each file took to download, for `--timings-json`.
+    pub timings: Timings,
+    /// Number of files downloaded at the same time (defaults to
+    /// [MAX_FILES_DOWNLOADING_CONCURRENTLY]).
+    pub concurrency: Option<usize>,
+    /// Template for each file's local path (see [render_output_template]),
+    /// instead of mirroring the file's path in the dataset.
+    pub output_template: Option<String>,
+    /// Keep files that were compressed before uploading (see
+    /// [UploadOptions::compression]) compressed, instead of decompressing them
+    /// as they're downloaded.
+    pub no_decompress: bool,
+}
 
-    let metadata = json!({});
+impl DownloadOptions {
+    /// How transfer progress is shown, which is [ProgressMode::None] if
+    /// [DownloadOptions::quiet] is set.
+    pub fn progress_mode(&self) -> ProgressMode {
+        if self.quiet {
+            ProgressMode::None
+        } else {
+            self.progress
+        }
+    }
 
-    if filesize < MULTIPART_FILESIZE_THRESHOLD {
-        debug!(
-            "Filesize {} < threshold {} so doing oneshot",
-            filesize, MULTIPART_FILESIZE_THRESHOLD
-        );
-        let (url, version) =
-            storage::upload_file_oneshot(config, path_str, filesize, key, multi_progress).await?;
-        // Register uploaded file to database
-        add_file_to_dataset(db_config, dataset_id, &url, filesize, version, metadata).await
-    } else {
-        debug!(
-            "Filesize {} > threshold {} so doing multipart",
-            filesize, MULTIPART_FILESIZE_THRESHOLD
-        );
-        let (url, version) = storage::upload_file_multipart(
-            config,
-            path_str,
-            filesize as usize,
-            key,
-            multi_progress,
-        )
-        .await?;
-        // Register uploaded file to database
-        add_file_to_dataset(db_config, dataset_id, &url, filesize, version, metadata).await
+    /// Format an uploaded file is decompressed from as it's downloaded, if it
+    /// was compressed before uploading and [DownloadOptions::no_decompress]
+    /// isn't set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file's compression isn't supported (see
+    /// [UploadedFile::compression]).
+    pub fn decompression(&self, uploaded_file: &UploadedFile) -> Result<Option<CompressionFormat>> {
+        if self.no_decompress {
+            Ok(None)
+        } else {
+            uploaded_file.compression()
+        }
+    }
+
+    /// Local path that an uploaded file will be downloaded to.
+    ///
+    /// Files that will be decompressed (see [DownloadOptions::decompression])
+    /// lose the compression's extension (e.g. `.gz`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file's url is malformed -- see
+    /// [UploadedFile::filepath_from_url] -- if the output template can't be
+    /// rendered (see [render_output_template]), or if the file's compression
+    /// isn't supported.
+    pub fn destination(&self, uploaded_file: &UploadedFile) -> Result<PathBuf> {
+        let mut filepath = match &self.output_template {
+            Some(template) => render_output_template(template, uploaded_file)?,
+            None => uploaded_file.filepath_from_url()?,
+        };
+        if let Some(format) = self.decompression(uploaded_file)? {
+            filepath = compression::decompressed_path(&filepath, format);
+        }
+        Ok(match &self.output_dir {
+            Some(dir) => dir.join(filepath),
+            None => filepath,
+        })
     }
 }
 
-/// List all files in the given dataset, optionally filtered by prefixes.
+/// Placeholders that can be used in an output template.
+const OUTPUT_TEMPLATE_PLACEHOLDERS: [&str; 5] =
+    ["dataset_id", "file_id", "created_date", "path", "filename"];
+
+/// A piece of a parsed output template.
+enum TemplatePart<'a> {
+    /// Text copied into the path as is.
+    Literal(&'a str),
+    /// Name of a placeholder replaced by a value from the file.
+    Placeholder(&'a str),
+}
+
+/// Splits an output template into literal text and placeholders.
+fn parse_output_template(template: &str) -> Result<Vec<TemplatePart<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(TemplatePart::Literal(&rest[..start]));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Output template {:?} has an unclosed {{", template))?;
+        let name = &rest[start + 1..start + end];
+        if !OUTPUT_TEMPLATE_PLACEHOLDERS.contains(&name) {
+            bail!(
+                "Output template {:?} has an unknown placeholder {{{}}} (expected one of {})",
+                template,
+                name,
+                OUTPUT_TEMPLATE_PLACEHOLDERS
+                    .iter()
+                    .map(|placeholder| format!("{{{}}}", placeholder))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        parts.push(TemplatePart::Placeholder(name));
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Literal(rest));
+    }
+    Ok(parts)
+}
+
+/// Ensures an output template only uses known placeholders, so a typo is
+/// reported before anything is downloaded.
 ///
-/// If multiple prefixes are provided, all files matching any prefix are
-/// returned (i.e. it's a union).
+/// # Errors
 ///
-/// Thin wrapper around [datasets::files_get] -- see its documentation for
-/// behavior and possible errors.
-pub async fn list_files(
-    config: &DatabaseApiConfig,
-    dataset_id: Uuid,
-    prefixes: Vec<String>,
-) -> Result<Vec<UploadedFile>> {
-    datasets::files_get(config, dataset_id, prefixes).await
+/// Returns an error if a placeholder is unknown or a `{` is never closed.
+pub fn validate_output_template(template: &str) -> Result<()> {
+    parse_output_template(template).map(|_| ())
 }
 
-/// Download all files specified in `uploaded_files`.
+/// Renders an output template into the local path (relative to the output
+/// directory) that a file will be downloaded to.
 ///
-/// See [Performance][crate#performance] for details on download concurrency.
+/// Supported placeholders are `{dataset_id}`, `{file_id}`, `{created_date}`
+/// (as `YYYY-MM-DD`, in UTC), `{path}` (the file's path in the dataset), and
+/// `{filename}` (the last component of `{path}`). Both `/` and `\` separate
+/// directories. Empty and `.` components are dropped, so the rendered path
+/// always stays inside the output directory.
 ///
 /// # Errors
 ///
-/// Returns an error if the url doesn't match a configured cloud storage provider.
-///
-/// Wraps [download_file] -- see its documentation for other possible errors.
-pub async fn download_files(
-    storage_config: StorageConfig,
-    uploaded_files: Vec<UploadedFile>,
-) -> Result<()> {
-    if uploaded_files.is_empty() {
-        Ok(())
-    } else {
-        let guard = MultiProgressGuard::new().await;
-        let multi_progress = guard.inner.clone();
-
-        let mut futs = stream::iter(
-            uploaded_files
-                .iter()
-                .zip(iter::repeat_with(|| storage_config.clone()))
-                .map(|(uploaded_file, local_storage_config)| {
-                    download_file(local_storage_config, uploaded_file, &multi_progress)
-                }),
-        )
-        .buffer_unordered(MAX_FILES_DOWNLOADING_CONCURRENTLY);
-        while let Some(res) = futs.next().await {
-            res?;
+/// Returns an error if the template is invalid (see
+/// [validate_output_template]), if the file's url is malformed (see
+/// [UploadedFile::filepath_from_url]), or if the rendered path contains a
+/// `..` component or no components at all.
+pub fn render_output_template(template: &str, uploaded_file: &UploadedFile) -> Result<PathBuf> {
+    let filepath = uploaded_file.filepath_from_url()?;
+    let mut rendered = String::new();
+    for part in parse_output_template(template)? {
+        match part {
+            TemplatePart::Literal(text) => rendered.push_str(text),
+            TemplatePart::Placeholder("dataset_id") => {
+                rendered.push_str(&uploaded_file.dataset_id.to_string())
+            }
+            TemplatePart::Placeholder("file_id") => {
+                rendered.push_str(&uploaded_file.file_id.to_string())
+            }
+            TemplatePart::Placeholder("created_date") => {
+                rendered.push_str(&uploaded_file.created_date.format("%Y-%m-%d").to_string())
+            }
+            TemplatePart::Placeholder("path") => {
+                let components: Vec<_> = filepath
+                    .iter()
+                    .map(|component| component.to_string_lossy())
+                    .collect();
+                rendered.push_str(&components.join("/"));
+            }
+            TemplatePart::Placeholder("filename") => {
+                // filepath_from_url ensures there's a file name
+                let filename = filepath.file_name().unwrap_or_default();
+                rendered.push_str(&filename.to_string_lossy());
+            }
+            TemplatePart::Placeholder(name) => unreachable!("Unknown placeholder {{{}}}", name),
         }
+    }
 
-        Ok(())
+    let mut path = PathBuf::new();
+    for component in rendered.split(&['/', '\\'][..]) {
+        match component {
+            "" | "." => continue,
+            ".." => bail!(
+                "Output template {:?} renders to a path outside the output directory: {}",
+                template,
+                rendered
+            ),
+            _ => path.push(component),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        bail!(
+            "Output template {:?} renders to an empty path for {}",
+            template,
+            filepath.display()
+        );
     }
+    Ok(path)
 }
 
-/// Downloads a single file.
-///
-/// Folder structure is preserved when downloading, so downloading `dir/file`
-/// will create a folder named `dir` (if it doesn't already exist) and download
-/// `file` into that folder.
+/// Ensures no two files would be downloaded to the same local path, which
+/// can happen when an output template drops part of the files' paths.
 ///
 /// # Errors
 ///
-/// Returns an error if the url is malformed or if the destination file cannot
-/// be opened or written.
-///
-/// Wraps [storage::download_file] -- see its documentation for other possible
-/// errors.
-pub async fn download_file(
-    storage_config: StorageConfig,
-    uploaded_file: &UploadedFile,
-    multi_progress: &MultiProgress,
+/// Returns an error naming both files if their destinations collide, or if
+/// a destination can't be worked out (see [DownloadOptions::destination]).
+pub fn check_unique_destinations(
+    uploaded_files: &[UploadedFile],
+    options: &DownloadOptions,
 ) -> Result<()> {
-    debug!("Downloading file: {}", uploaded_file.url);
-    let filepath = uploaded_file.filepath_from_url()?;
-    if let Some(dir) = filepath.parent() {
-        tokio::fs::create_dir_all(dir).await?;
+    let mut seen: HashMap<PathBuf, &UploadedFile> = HashMap::new();
+    for uploaded_file in uploaded_files {
+        let destination = options.destination(uploaded_file)?;
+        if let Some(other) = seen.get(&destination) {
+            bail!(
+                "Files {} and {} would both be downloaded to {}",
+                other.filepath_from_url()?.display(),
+                uploaded_file.filepath_from_url()?.display(),
+                destination.display()
+            );
+        }
+        seen.insert(destination, uploaded_file);
     }
+    Ok(())
+}
 
-    let progress_bar = multi_progress.add(ProgressBar::new(uploaded_file.filesize));
-    progress_bar.set_style(get_default_progress_bar_style());
-    progress_bar.set_prefix(filepath.to_string_lossy().into_owned());
-    progress_bar.set_position(0);
-    let pgbar = progress_bar.clone();
-    // Let progress bar follow along with # bytes read
-    let progress = Box::new(move |_bytes_read: u64, total_bytes_read: u64| {
-        pgbar.set_position(total_bytes_read);
-    });
+/// Broad category of a file transfer failure, so automated pipelines can react
+/// to failures without parsing error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FailureCategory {
+    /// Downloaded data didn't match the checksum recorded at upload time.
+    Checksum,
+    /// A request to cloud storage or the database couldn't be sent or didn't
+    /// get a response.
+    Network,
+    /// Reading or writing a file or data stream failed.
+    Io,
+    /// Any other error (e.g. an error response from cloud storage).
+    Other,
+}
 
-    let async_data = storage::download_file(storage_config, &uploaded_file.url).await?;
-    let mut file = tokio::fs::File::create(filepath.clone()).await?;
-    let read_wrapper = ReadProgressStream::new(async_data, progress);
+impl FailureCategory {
+    /// Categorizes an error by the most specific cause found in its chain.
+    pub fn of(error: &anyhow::Error) -> Self {
+        let has_cause =
+            |is_match: fn(&(dyn std::error::Error + 'static)) -> bool| error.chain().any(is_match);
+        if has_cause(|cause| cause.is::<ChecksumMismatch>()) {
+            FailureCategory::Checksum
+        } else if has_cause(|cause| {
+            cause.is::<reqwest::Error>() || cause.is::<rusoto_core::request::HttpDispatchError>()
+        }) {
+            FailureCategory::Network
+        } else if has_cause(|cause| cause.is::<std::io::Error>()) {
+            FailureCategory::Io
+        } else {
+            FailureCategory::Other
+        }
+    }
+}
 
-    let mut wrapper = tokio_util::io::StreamReader::new(read_wrapper);
-    tokio::io::copy(&mut wrapper, &mut file).await?;
-    debug!("Downloaded file copied to destination: {:?}", filepath);
-    progress_bar.finish();
+/// A file that failed to upload or download.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileFailure {
+    /// Local path of an uploaded file, or path in the dataset of a downloaded
+    /// file.
+    pub path: String,
+    /// Error message, including its causes.
+    pub error: String,
+    /// Category of the error.
+    pub category: FailureCategory,
+}
 
-    Ok(())
+impl FileFailure {
+    /// Records the failure of the file at `path`.
+    pub fn new(path: impl Display, error: &anyhow::Error) -> Self {
+        FileFailure {
+            path: path.to_string(),
+            error: format!("{:#}", error),
+            category: FailureCategory::of(error),
+        }
+    }
 }
 
-/// Show current configuration.
-pub fn print_config(config: config::Config) -> Result<()> {
-    let storage_config: CompleteAppConfig = config.try_into()?;
-    println!("{}", toml::to_string(&storage_config)?);
+/// Machine-readable report of the files that failed in a multi-file upload or
+/// download.
+///
+/// If the transfer stops at the first failure, only that file is listed.
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    /// Either "upload" or "download".
+    pub operation: &'static str,
+    /// Number of files that were to be transferred.
+    pub total_files: usize,
+    /// Files that failed.
+    pub failures: Vec<FileFailure>,
+}
 
-    Ok(())
+impl ErrorReport {
+    /// Writes the report as JSON to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Unable to write error report to {}", path.display()))
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use chrono::Utc;
+/// Error returned when downloaded data doesn't match the checksum recorded
+/// when the file was uploaded.
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    /// Algorithm used to compute both checksums.
+    algorithm: ChecksumAlgorithm,
+    /// Checksum recorded when the file was uploaded.
+    expected: String,
+    /// Checksum of the downloaded data.
+    actual: String,
+}
 
-    use super::*;
-    use crate::{
-        app_config::{DatabaseConfig, StorageProviderChoices},
-        core::api::datasets::DatabaseApiConfig,
+impl Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Checksum mismatch: expected {} {} but downloaded data has {} {}",
+            self.algorithm.as_str(),
+            self.expected,
+            self.algorithm.as_str(),
+            self.actual
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+/// Creates the download output directory if it doesn't exist.
+///
+/// # Errors
+///
+/// Returns an error if the path exists but isn't a directory or if the
+/// directory can't be created.
+pub fn prepare_output_dir(dir: &Path) -> Result<()> {
+    if dir.exists() && !dir.is_dir() {
+        bail!(
+            "Output path {} exists but is not a directory",
+            dir.display()
+        );
+    }
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Unable to create output directory {}", dir.display()))?;
+    Ok(())
+}
+
+/// Where a file would be downloaded, as reported by a dry run.
+#[derive(Debug, PartialEq)]
+pub struct PlannedDownload {
+    /// Local path the file would be written to.
+    pub path: PathBuf,
+    /// Size of the file in bytes.
+    pub filesize: u64,
+    /// Whether a file already exists at `path` (downloading asks before
+    /// overwriting it, or refuses in quiet mode).
+    pub overwrites: bool,
+}
+
+/// Works out where each file would be downloaded without downloading
+/// anything.
+///
+/// # Errors
+///
+/// Returns an error if a file's url is malformed -- see
+/// [DownloadOptions::destination].
+pub fn plan_download(
+    uploaded_files: &[UploadedFile],
+    options: &DownloadOptions,
+) -> Result<Vec<PlannedDownload>> {
+    uploaded_files
+        .iter()
+        .map(|uploaded_file| {
+            let path = options.destination(uploaded_file)?;
+            // Decompressed files take up their uncompressed size on disk
+            let filesize = match options.decompression(uploaded_file)? {
+                Some(_) => uploaded_file.uncompressed_size(),
+                None => uploaded_file.filesize,
+            };
+            Ok(PlannedDownload {
+                overwrites: path.exists(),
+                path,
+                filesize,
+            })
+        })
+        .collect()
+}
+
+/// Free disk space, in bytes, available for downloading into `dir`.
+///
+/// If `dir` doesn't exist yet (it's created when downloading), the space
+/// available in its nearest existing ancestor is returned.
+///
+/// # Errors
+///
+/// Returns an error if the free space can't be read.
+pub fn available_download_space(dir: &Path) -> Result<u64> {
+    let existing = dir
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        // Relative paths run out of ancestors at the working directory
+        .unwrap_or_else(|| Path::new("."));
+    fs2::available_space(existing)
+        .with_context(|| format!("Unable to read free disk space of {}", existing.display()))
+}
+
+/// Prints the plan produced by [plan_download], noting whether
+/// `available_space` bytes are enough to hold the downloaded files.
+pub fn print_download_plan(plan: &[PlannedDownload], available_space: u64) {
+    println!("Dry run: no files will be downloaded.\n");
+    for planned in plan {
+        println!(
+            "{} ({}){}",
+            planned.path.display(),
+            Byte::from_bytes(planned.filesize as u128).get_appropriate_unit(false),
+            if planned.overwrites {
+                " [overwrites existing file]"
+            } else {
+                ""
+            }
+        );
+    }
+    let total_bytes = plan.iter().fold(0, |acc, p| acc + p.filesize);
+    let overwrites = plan.iter().filter(|p| p.overwrites).count();
+    println!(
+        "\nTotal: {} file(s), {}",
+        plan.len(),
+        Byte::from_bytes(total_bytes as u128).get_appropriate_unit(false)
+    );
+    if overwrites > 0 {
+        println!(
+            "{} existing file(s) would be overwritten (bolster asks before \
+             overwriting each one, and refuses to in quiet mode)",
+            overwrites
+        );
+    }
+    let available = Byte::from_bytes(available_space as u128).get_appropriate_unit(false);
+    if total_bytes > available_space {
+        println!("Not enough free disk space: only {} available", available);
+    } else {
+        println!("Enough free disk space: {} available", available);
+    }
+}
+
+/// Provides the default progress bar style
+///
+/// For a list of template fields (e.g. elapsed time, bytes remaining), see
+/// [indicatif's documentation on
+/// Templates](https://docs.rs/indicatif/0.16.2/indicatif/#templates).
+pub fn get_default_progress_bar_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+    .template("{prefix} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} {bytes_per_sec} ({eta})")
+    .progress_chars("#>-")
+}
+
+/// Creates a dataset with the given metadata and returns its id.
+///
+/// Thin wrapper around [datasets::datasets_post] -- see its documentation for
+/// behavior and possible errors.
+pub async fn create_dataset(
+    config: &DatabaseApiConfig,
+    system_id: String,
+    metadata: serde_json::Map<String, serde_json::Value>,
+) -> Result<Uuid> {
+    let dataset = datasets::datasets_post(config, system_id, metadata.into()).await?;
+    Ok(dataset.dataset_id)
+}
+
+/// Converts a metadata value from the command line into JSON.
+///
+/// Values that look like integers, floats, or booleans are stored as such, so
+/// `firmware=12` is stored as the number 12. Everything else is stored as a
+/// string.
+pub fn coerce_metadata_value(value: &str) -> serde_json::Value {
+    if let Ok(int) = value.parse::<i64>() {
+        json!(int)
+    } else if let Ok(bool_value) = value.parse::<bool>() {
+        json!(bool_value)
+    } else if let Some(float) = value
+        .parse::<f64>()
+        .ok()
+        .and_then(serde_json::Number::from_f64)
+    {
+        serde_json::Value::Number(float)
+    } else {
+        json!(value)
+    }
+}
+
+/// Collects `KEY=VALUE` pairs into a metadata object (see
+/// [coerce_metadata_value]).
+///
+/// If a key is repeated, the last value wins.
+pub fn metadata_from_pairs(
+    pairs: Vec<(String, String)>,
+) -> serde_json::Map<String, serde_json::Value> {
+    pairs
+        .into_iter()
+        .map(|(key, value)| {
+            let value = coerce_metadata_value(&value);
+            (key, value)
+        })
+        .collect()
+}
+
+/// Reads and compiles a JSON Schema that dataset metadata must conform to.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read or isn't a valid JSON Schema.
+pub fn read_metadata_schema(path: &Path) -> Result<JSONSchema> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read metadata schema {:?}", path))?;
+    let schema: serde_json::Value = serde_json::from_str(&contents)
+        .with_context(|| format!("Metadata schema {:?} isn't valid JSON", path))?;
+    JSONSchema::compile(&schema).map_err(|err| {
+        anyhow!(
+            "Metadata schema {:?} isn't a valid JSON Schema: {}",
+            path,
+            err
+        )
+    })
+}
+
+/// Checks dataset metadata against a schema (see [read_metadata_schema]), so
+/// non-conforming metadata is rejected before anything is created or
+/// uploaded.
+///
+/// # Errors
+///
+/// Returns an error listing every field that doesn't conform to the schema.
+pub fn validate_metadata(
+    schema: &JSONSchema,
+    metadata: &serde_json::Map<String, serde_json::Value>,
+) -> Result<()> {
+    let metadata = serde_json::Value::Object(metadata.clone());
+    if let Err(errors) = schema.validate(&metadata) {
+        let problems: Vec<String> = errors
+            .map(|err| {
+                let field = err.instance_path.to_string();
+                let field = field.trim_start_matches('/');
+                if field.is_empty() {
+                    format!("metadata: {}", err)
+                } else {
+                    format!("{}: {}", field, err)
+                }
+            })
+            .collect();
+        bail!(
+            "Dataset metadata doesn't match the metadata schema:\n\t{}",
+            problems.join("\n\t")
+        );
+    }
+    Ok(())
+}
+
+/// Eases usage of [multiple progress
+/// bars](https://docs.rs/indicatif/0.16.2/indicatif/struct.MultiProgress.html)
+/// in an async environment.
+///
+/// Manages annoyances with indicatif, namely that:
+/// - some thread of execution needs to join the MultiProgress to get progress
+///   bars to render
+/// - joining the MultiProgress immediately returns if there aren't ProgressBars
+///   attached, so we add a hidden/bogus one
+/// - the hidden/bogus ProgressBar needs to be cleaned up (by Drop, in this
+///   implementation) when we don't need to update progress bars anymore
+///
+/// Only [ProgressMode::Bar] uses a multi-progress bar, so for other modes
+/// this just holds the [Progress] that creates reporters.
+pub struct MultiProgressGuard {
+    /// Creates progress reporters. For [ProgressMode::Bar], it holds a
+    /// pointer to the multi-progress bar, which is cloned internally and
+    /// passed to a tokio task to join to the bar so it renders.
+    inner: Progress,
+    /// Hidden spinner progress bar to ensure the multi-progress bar (if any)
+    /// stays alive until this guard is dropped.
+    hidden_spinner: Option<ProgressBar>,
+}
+
+impl MultiProgressGuard {
+    /// Initializes a
+    /// [MultiProgress](https://docs.rs/indicatif/0.16.2/indicatif/struct.MultiProgress.html)
+    /// (with a hidden progress bar) and joins it to begin rendering, if
+    /// `mode` is [ProgressMode::Bar].
+    pub async fn new(mode: ProgressMode) -> Self {
+        match mode {
+            ProgressMode::Bar => {
+                let mp = Arc::new(MultiProgress::new());
+                let spinner = mp.add(ProgressBar::hidden());
+                let mp2 = mp.clone();
+                tokio::spawn(async move {
+                    mp2.join().unwrap();
+                });
+                MultiProgressGuard {
+                    inner: Progress::Bar(mp),
+                    hidden_spinner: Some(spinner),
+                }
+            }
+            ProgressMode::Plain => MultiProgressGuard {
+                inner: Progress::Plain,
+                hidden_spinner: None,
+            },
+            ProgressMode::None => MultiProgressGuard {
+                inner: Progress::None,
+                hidden_spinner: None,
+            },
+        }
+    }
+}
+
+impl Drop for MultiProgressGuard {
+    fn drop(&mut self) {
+        // Calling `spinner.finish` makes it appear for some reason, so we use
+        // `finish_and_clear` instead.
+        if let Some(spinner) = &self.hidden_spinner {
+            spinner.finish_and_clear();
+        }
+    }
+}
+
+/// Progress summarizing all files of an upload or download (e.g. "12/47
+/// files, 3.2/18 GB"), shown above the per-file progress.
+///
+/// Progress advances by a file's size when the whole file finishes
+/// transferring. If it's dropped before every file finished (e.g. after a
+/// failure), it's left showing how far the transfer got.
+pub struct OverallProgress {
+    /// Reports bytes of completed files.
+    reporter: Arc<dyn ProgressReporter>,
+    /// Number of files that finished transferring.
+    completed_files: usize,
+    /// Number of files in the transfer.
+    total_files: usize,
+    /// Whether every file finished transferring.
+    finished: bool,
+}
+
+impl OverallProgress {
+    /// Reports overall progress of `total_files` files totalling
+    /// `total_bytes` with `progress`.
+    pub fn new(progress: &Progress, total_files: usize, total_bytes: u64) -> Self {
+        let reporter = progress.bar(
+            "Total",
+            total_bytes,
+            ProgressStyle::default_bar()
+            .template("{prefix} [{elapsed_precise}] [{wide_bar:.green/blue}] {bytes}/{total_bytes} ({msg})")
+            .progress_chars("#>-"),
+        );
+        OverallProgress::with_reporter(reporter, total_files)
+    }
+
+    /// Reports overall progress of `total_files` files with `reporter`.
+    fn with_reporter(reporter: Arc<dyn ProgressReporter>, total_files: usize) -> Self {
+        let overall = OverallProgress {
+            reporter,
+            completed_files: 0,
+            total_files,
+            finished: false,
+        };
+        overall.reporter.set_message(overall.files_message());
+        overall
+    }
+
+    /// Records that a file of `filesize` bytes finished transferring,
+    /// finishing the overall progress once every file has.
+    pub fn file_done(&mut self, filesize: u64) {
+        self.completed_files += 1;
+        self.reporter.inc(filesize);
+        self.reporter.set_message(self.files_message());
+        if self.completed_files == self.total_files {
+            self.reporter.finish();
+            self.finished = true;
+        }
+    }
+
+    /// Files counter shown in the progress message, e.g. "12/47 files".
+    fn files_message(&self) -> String {
+        format!("{}/{} files", self.completed_files, self.total_files)
+    }
+}
+
+impl Drop for OverallProgress {
+    fn drop(&mut self) {
+        // Dropping an unfinished bar would fill it up, so leave it where it
+        // is instead
+        if !self.finished {
+            self.reporter.abandon();
+        }
+    }
+}
+
+/// Builds the cloud storage key for a file in a dataset.
+///
+/// Keys follow the pattern `{prefix}/{dataset_id}/{path}`.
+pub fn object_key(prefix: &str, dataset_id: impl Display, path: &str) -> String {
+    format!("{}/{}/{}", prefix, dataset_id, path)
+}
+
+/// Checks that the configured bucket has object versioning enabled, returning
+/// a warning to show the user if it doesn't.
+///
+/// Without versioning, cloud storage doesn't assign uploaded files a version
+/// id, so every file upload would fail (see
+/// [storage::bucket_versioning_enabled]). If the versioning status can't be
+/// read (e.g. the credentials aren't allowed to), no warning is returned and
+/// uploads go ahead as usual.
+pub async fn versioning_warning(config: &StorageConfig) -> Option<String> {
+    match storage::bucket_versioning_enabled(config).await {
+        Ok(true) => None,
+        Ok(false) => Some(format!(
+            "Warning: object versioning isn't enabled on bucket {}. Bolster records \
+             the version of each uploaded file, so uploads will fail, and downloads \
+             can't be verified against a specific version, until versioning is \
+             enabled on the bucket.",
+            config.bucket()
+        )),
+        Err(e) => {
+            debug!("Unable to check bucket versioning: {:?}", e);
+            None
+        }
+    }
+}
+
+/// Checks that `key`, the cloud storage key for the file at `path`, is no
+/// longer than [storage::MAX_KEY_LENGTH].
+///
+/// # Errors
+///
+/// Returns an error naming `path` if the key is too long.
+pub fn check_key_length(key: &str, path: &str) -> Result<()> {
+    if key.len() > storage::MAX_KEY_LENGTH {
+        bail!(
+            "Unable to upload {}: its cloud storage key would be {} bytes long, over the {}-byte limit. Upload it from a shorter path.",
+            path,
+            key.len(),
+            storage::MAX_KEY_LENGTH
+        );
+    }
+    Ok(())
+}
+
+/// How a file would be uploaded, as reported by a dry run.
+#[derive(Debug, PartialEq)]
+pub struct PlannedUpload {
+    /// Local path of the file.
+    pub path: String,
+    /// Cloud storage key the file would be uploaded to.
+    pub key: String,
+    /// Size of the file in bytes.
+    pub filesize: usize,
+    /// Chunk size for multipart uploads, or `None` for oneshot uploads.
+    pub chunk_size: Option<usize>,
+    /// How multipart upload parts are sized from the chunk size.
+    pub part_size_strategy: PartSizeStrategy,
+    /// Number of requests needed to upload the file's data (at most, for
+    /// compressed files).
+    pub num_parts: usize,
+    /// Format the file would be compressed with before uploading, if any.
+    pub compression: Option<CompressionFormat>,
+}
+
+impl PlannedUpload {
+    /// Plans to compress the file with `format`, which streams it in parts
+    /// under a key with the compression's extension (see [upload_file]).
+    pub fn compress(&mut self, format: CompressionFormat) {
+        self.key = format!("{}.{}", self.key, format.extension());
+        self.chunk_size = Some(storage::DEFAULT_CHUNK_SIZE);
+        // Compressed data is no larger than the file (give or take a header)
+        self.num_parts = max(1, self.filesize.div_ceil(storage::DEFAULT_CHUNK_SIZE));
+        self.compression = Some(format);
+    }
+}
+
+/// Works out the key and upload strategy for each file without uploading
+/// anything.
+///
+/// Files of at least `multipart_threshold` bytes use multipart uploads (see
+/// [uses_multipart_upload]), which are split into `num_parts` parts if given
+/// (see [UploadOptions::num_parts]) and sized according to
+/// `part_size_strategy`.
+///
+/// # Errors
+///
+/// Returns an error if any file is unreadable, is not valid UTF-8, is over the
+/// [storage::MAX_FILE_SIZE], or has a key that's too long (see
+/// [check_key_length]), or if `num_parts` gives parts that are too small or
+/// too large (see [storage::chunk_size_for_num_parts]).
+pub async fn plan_upload<P>(
+    prefix: &str,
+    paths: &[P],
+    num_parts: Option<usize>,
+    multipart_threshold: usize,
+    part_size_strategy: PartSizeStrategy,
+) -> Result<Vec<PlannedUpload>>
+where
+    P: AsRef<Path>,
+{
+    let mut plan = Vec::with_capacity(paths.len());
+    for path in paths {
+        let path_str = path
+            .as_ref()
+            .to_str()
+            .ok_or_else(|| anyhow!("Path was not UTF8"))?
+            .to_owned();
+        // The placeholder is shorter than a real dataset id, so check the key
+        // length with a real-length id
+        check_key_length(&object_key(prefix, Uuid::nil(), &path_str), &path_str)?;
+        let filesize: usize = tokio::fs::metadata(path.as_ref())
+            .await
+            .with_context(|| format!("Unable to read {}", path_str))?
+            .len()
+            .try_into()
+            .unwrap();
+        let (chunk_size, num_parts) = if uses_multipart_upload(filesize, multipart_threshold) {
+            let chunk_size = storage::multipart_chunk_size(filesize, num_parts)
+                .with_context(|| format!("Unable to upload {}", path_str))?;
+            (
+                Some(chunk_size),
+                part_size_strategy.part_sizes(chunk_size, filesize).len(),
+            )
+        } else {
+            (None, 1)
+        };
+        plan.push(PlannedUpload {
+            key: object_key(prefix, DRY_RUN_DATASET_ID_PLACEHOLDER, &path_str),
+            path: path_str,
+            filesize,
+            chunk_size,
+            part_size_strategy,
+            num_parts,
+            compression: None,
+        });
+    }
+    Ok(plan)
+}
+
+/// Prints the plan produced by [plan_upload].
+pub fn print_upload_plan(plan: &[PlannedUpload]) {
+    println!("Dry run: no dataset will be created and no files will be uploaded.\n");
+    for planned in plan {
+        let strategy = match (planned.chunk_size, planned.part_size_strategy) {
+            (Some(chunk_size), _) if planned.compression.is_some() => format!(
+                "{}, multipart (up to {} parts of {})",
+                planned.compression.as_ref().map_or("", AsRef::as_ref),
+                planned.num_parts,
+                Byte::from_bytes(chunk_size as u128).get_appropriate_unit(true)
+            ),
+            (Some(chunk_size), PartSizeStrategy::Fixed) => format!(
+                "multipart ({} parts of {})",
+                planned.num_parts,
+                Byte::from_bytes(chunk_size as u128).get_appropriate_unit(true)
+            ),
+            (Some(chunk_size), PartSizeStrategy::Adaptive) => format!(
+                "multipart ({} parts growing from {} chunks)",
+                planned.num_parts,
+                Byte::from_bytes(chunk_size as u128).get_appropriate_unit(true)
+            ),
+            (None, _) => "oneshot".to_owned(),
+        };
+        println!(
+            "{} ({}) -> {} [{}]",
+            planned.path,
+            Byte::from_bytes(planned.filesize as u128).get_appropriate_unit(false),
+            planned.key,
+            strategy
+        );
+    }
+    let total_bytes = plan.iter().fold(0, |acc, p| acc + p.filesize as u128);
+    let total_parts = plan.iter().fold(0, |acc, p| acc + p.num_parts);
+    println!(
+        "\nTotal: {} file(s), {}, {} upload request(s)",
+        plan.len(),
+        Byte::from_bytes(total_bytes).get_appropriate_unit(false),
+        total_parts
+    );
+}
+
+/// Creates a dataset and async uploads all provided files, returning the new
+/// dataset's id.
+///
+/// See [Performance][crate#performance] for details on upload concurrency.
+///
+/// If [UploadOptions::dry_run] is set, no dataset is created and nothing is
+/// uploaded -- the planned keys and upload strategies are printed instead (see
+/// [plan_upload]) and `None` is returned.
+///
+/// The plex and object-space file may be left out for raw data that isn't
+/// meant for calibration, in which case the dataset's metadata is tagged with
+/// [UNCALIBRATED_METADATA_KEY].
+///
+/// Wraps [create_dataset] and [upload_file] -- see those functions for behavior
+/// and possible errors.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_and_upload_dataset<P>(
+    config: StorageConfig,
+    db_config: &DatabaseApiConfig,
+    system_id: String,
+    prefix: &str,
+    plex_file_path: Option<P>,
+    object_space_file_path: Option<P>,
+    file_paths: Vec<P>,
+    options: &UploadOptions,
+) -> Result<Option<Uuid>>
+where
+    P: AsRef<Path> + Debug + Display + Clone + Eq,
+{
+    let mut metadata = options.metadata.clone();
+    if plex_file_path.is_none() || object_space_file_path.is_none() {
+        metadata.insert(UNCALIBRATED_METADATA_KEY.to_owned(), json!(true));
+    }
+
+    if options.dry_run {
+        // Only data files are compressed, which come after the plex and
+        // object-space file
+        let num_config_files =
+            plex_file_path.iter().count() + object_space_file_path.iter().count();
+        let all_file_paths: Vec<P> = plex_file_path
+            .into_iter()
+            .chain(object_space_file_path)
+            .chain(file_paths)
+            .collect();
+        let mut plan = plan_upload(
+            prefix,
+            &all_file_paths,
+            options.num_parts,
+            options.multipart_threshold(),
+            options.part_size_strategy,
+        )
+        .await?;
+        for planned in plan.iter_mut().skip(num_config_files) {
+            if let Some(format) = options.compression_for(Path::new(&planned.path)) {
+                planned.compress(format);
+            }
+        }
+        print_upload_plan(&plan);
+        println!("Destination: {}", config.location());
+        if !metadata.is_empty() {
+            println!("Dataset metadata: {}", serde_json::Value::from(metadata));
+        }
+        return Ok(None);
+    }
+
+    let (dataset_id, already_uploaded) = match options.resume {
+        Some(dataset_id) => {
+            let all_file_paths: Vec<&P> = plex_file_path
+                .iter()
+                .chain(&object_space_file_path)
+                .chain(&file_paths)
+                .collect();
+            let hash_jobs = options.hash_jobs();
+            let matches = find_uploaded_files(
+                db_config,
+                dataset_id,
+                &all_file_paths,
+                hash_jobs,
+                // A spinner would clutter plain progress output
+                options.progress_mode() != ProgressMode::Bar,
+            )
+            .await?;
+            if !options.quiet {
+                println!(
+                    "Resuming upload to dataset {}: {} of {} file(s) already uploaded",
+                    dataset_id,
+                    matches.uploaded.len(),
+                    all_file_paths.len()
+                );
+            }
+            let mut already_uploaded = matches.uploaded;
+            if !matches.changed.is_empty() {
+                let mut changed_paths: Vec<&String> = matches.changed.keys().collect();
+                changed_paths.sort();
+                let changed_list = changed_paths
+                    .iter()
+                    .map(|path| format!("\n\t{}", path))
+                    .collect::<String>();
+                if options.force {
+                    eprintln!(
+                        "Warning: uploading {} file(s) that differ from the dataset's file \
+                         at the same path, as new versions:{}",
+                        changed_paths.len(),
+                        changed_list
+                    );
+                } else {
+                    eprintln!(
+                        "Warning: skipping {} file(s) that differ from the dataset's file \
+                         at the same path (pass --force to upload them anyway):{}",
+                        changed_paths.len(),
+                        changed_list
+                    );
+                    // The dataset's existing files stand in for the skipped ones
+                    already_uploaded.extend(matches.changed);
+                }
+            }
+            (dataset_id, already_uploaded)
+        }
+        None => {
+            let dataset_id = create_dataset_for_upload(
+                db_config,
+                system_id,
+                metadata,
+                prefix,
+                plex_file_path.as_ref(),
+                object_space_file_path.as_ref(),
+                &file_paths,
+                options,
+            )
+            .await?;
+            (dataset_id, HashMap::new())
+        }
+    };
+
+    let guard = MultiProgressGuard::new(options.progress_mode()).await;
+    upload_to_dataset(
+        config,
+        db_config,
+        dataset_id,
+        prefix,
+        plex_file_path,
+        object_space_file_path,
+        file_paths,
+        &already_uploaded,
+        options,
+        &storage::HashingPool::new(options.hash_jobs()),
+        &guard.inner,
+    )
+    .await?;
+
+    Ok(Some(dataset_id))
+}
+
+/// Creates the dataset that files will be uploaded to, printing
+/// [UploadOptions::created_message] and running [UploadOptions::on_create].
+///
+/// If [UploadOptions::num_parts] is set, first checks that every file can be
+/// split into that many parts, so no dataset is created for an upload that
+/// can't succeed.
+#[allow(clippy::too_many_arguments)]
+async fn create_dataset_for_upload<P>(
+    db_config: &DatabaseApiConfig,
+    system_id: String,
+    metadata: serde_json::Map<String, serde_json::Value>,
+    prefix: &str,
+    plex_file_path: Option<&P>,
+    object_space_file_path: Option<&P>,
+    file_paths: &[P],
+    options: &UploadOptions,
+) -> Result<Uuid>
+where
+    P: AsRef<Path> + Debug + Display + Clone + Eq,
+{
+    let all_file_paths: Vec<P> = plex_file_path
+        .into_iter()
+        .chain(object_space_file_path)
+        .chain(file_paths)
+        .cloned()
+        .collect();
+    // Check that no key is too long before creating the dataset
+    for path_str in all_file_paths
+        .iter()
+        .map(ToString::to_string)
+        .chain(options.stdin_name.clone())
+    {
+        check_key_length(&object_key(prefix, Uuid::nil(), &path_str), &path_str)?;
+    }
+    if options.num_parts.is_some() {
+        // Check that every file can be split into the requested number of
+        // parts before creating the dataset
+        plan_upload(
+            prefix,
+            &all_file_paths,
+            options.num_parts,
+            options.multipart_threshold(),
+            options.part_size_strategy,
+        )
+        .await?;
+    }
+
+    let dataset_id: Uuid = options
+        .timings
+        .time(
+            "create_dataset",
+            None,
+            create_dataset(db_config, system_id, metadata),
+        )
+        .await?;
+
+    if let Some(message) = options.created_message(dataset_id) {
+        println!("{}", message);
+    }
+    if let Some(command) = &options.on_create {
+        run_on_create_hook(command, dataset_id).await?;
+    }
+    Ok(dataset_id)
+}
+
+/// Local files matched against the files a dataset already has (see
+/// [find_uploaded_files]), keyed by local path.
+#[derive(Debug, Default)]
+pub struct DatasetFileMatches {
+    /// Local files the dataset already has (same path, size, and checksum).
+    pub uploaded: HashMap<String, UploadedFile>,
+    /// Local files whose path matches one of the dataset's files but whose
+    /// contents differ, with the dataset's file.
+    pub changed: HashMap<String, UploadedFile>,
+}
+
+/// Finds which of the local files at `paths` are already in the dataset, e.g.
+/// because an earlier upload to it failed partway, and which differ from the
+/// dataset's file at the same path.
+///
+/// A local file counts as uploaded if the dataset has a file at the same path
+/// with the same size and checksum. Files uploaded by older versions of
+/// bolster, without a recorded checksum, are matched by size alone. Files
+/// compressed before uploading are matched by their size and checksum before
+/// compression. Up to
+/// `hash_jobs` local files are checksummed at the same time (see
+/// [checksum_files]). Unless `quiet`, a spinner shows that checksumming is
+/// underway, since hashing large files can take minutes.
+///
+/// # Errors
+///
+/// Returns an error if the dataset's files can't be listed or a local file
+/// can't be read.
+pub async fn find_uploaded_files<P>(
+    db_config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    paths: &[P],
+    hash_jobs: usize,
+    quiet: bool,
+) -> Result<DatasetFileMatches>
+where
+    P: AsRef<Path> + Display,
+{
+    let mut remote_files: HashMap<PathBuf, UploadedFile> = HashMap::new();
+    for uploaded_file in datasets::files_get(db_config, dataset_id, Vec::new(), None).await? {
+        // Files with malformed urls can't be matched to a local path
+        if let Ok(filepath) = uploaded_file.local_filepath() {
+            remote_files.insert(filepath, uploaded_file);
+        }
+    }
+
+    let mut matches = DatasetFileMatches::default();
+    // Local files whose checksum must match the remote file's, with the
+    // expected checksum
+    let mut to_checksum = Vec::new();
+    for path in paths {
+        let path_str = path.to_string();
+        let remote_file = match remote_files.get(Path::new(&path_str)) {
+            Some(remote_file) => remote_file,
+            None => continue,
+        };
+        let filesize = tokio::fs::metadata(path.as_ref())
+            .await
+            .with_context(|| format!("Unable to read {}", path_str))?
+            .len();
+        // Compressed files are matched by their size and checksum before
+        // compression
+        if filesize != remote_file.uncompressed_size() {
+            matches.changed.insert(path_str, remote_file.clone());
+            continue;
+        }
+        match remote_file.uncompressed_checksum() {
+            // Compare using the algorithm the file was uploaded with, which
+            // may differ from this upload's
+            Some((algorithm, expected)) => to_checksum.push((path_str, algorithm, expected)),
+            None => {
+                matches.uploaded.insert(path_str, remote_file.clone());
+            }
+        }
+    }
+
+    let files: Vec<(String, ChecksumAlgorithm)> = to_checksum
+        .iter()
+        .map(|(path_str, algorithm, _)| (path_str.clone(), *algorithm))
+        .collect();
+    let spinner = if quiet || files.is_empty() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
     };
+    spinner.set_style(
+        ProgressStyle::default_spinner().template("{spinner} {msg} [{elapsed_precise}]"),
+    );
+    spinner.set_message(format!(
+        "Checksumming {} file(s) to find which are already uploaded...",
+        files.len()
+    ));
+    spinner.enable_steady_tick(100);
+    let checksums = checksum_files(files, hash_jobs).await;
+    spinner.finish_and_clear();
+    let checksums = checksums?;
+    for ((path_str, _, expected), checksum) in to_checksum.into_iter().zip(checksums) {
+        let remote_file = remote_files[Path::new(&path_str)].clone();
+        if checksum == expected {
+            matches.uploaded.insert(path_str, remote_file);
+        } else {
+            matches.changed.insert(path_str, remote_file);
+        }
+    }
+    Ok(matches)
+}
+
+/// Checksums local files with the paired algorithms, checksumming up to
+/// `jobs` files at the same time. Checksums are returned in the same order
+/// as `files`.
+///
+/// Files are hashed as they're read, so memory use stays small no matter how
+/// large the files are.
+///
+/// # Errors
+///
+/// Returns an error if a file can't be read (see [storage::checksum_file]).
+pub async fn checksum_files(
+    files: Vec<(String, ChecksumAlgorithm)>,
+    jobs: usize,
+) -> Result<Vec<String>> {
+    checksum_files_with(files, jobs, |path, algorithm| async move {
+        storage::checksum_file(&path, algorithm).await
+    })
+    .await
+}
+
+/// Runs `checksum` on each of `files`, up to `jobs` at the same time (see
+/// [checksum_files]).
+async fn checksum_files_with<F, Fut>(
+    files: Vec<(String, ChecksumAlgorithm)>,
+    jobs: usize,
+    checksum: F,
+) -> Result<Vec<String>>
+where
+    F: Fn(String, ChecksumAlgorithm) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    stream::iter(files)
+        .map(|(path, algorithm)| checksum(path, algorithm))
+        .buffered(max(1, jobs))
+        .try_collect()
+        .await
+}
+
+/// Uploads the plex, object-space file, and data files to a dataset, then
+/// notifies the backend that the upload is complete.
+///
+/// Files in `already_uploaded` (see [find_uploaded_files]) are skipped but
+/// still count towards the notification and shared download info.
+///
+/// If a file fails, the remaining files are abandoned unless
+/// [UploadOptions::keep_going] is set. Either way, a summary of uploaded and
+/// failed files is printed (see [upload_failure_summary]) and the backend
+/// is *not* notified, so an incomplete dataset isn't processed -- resuming
+/// the upload (see [UploadOptions::resume]) uploads the missing files and
+/// then notifies the backend.
+///
+/// Files are hashed by `hashing` and progress is reported with `progress`, so
+/// several datasets can be uploaded sharing one hashing pool and one
+/// multi-progress bar (see [upload_datasets]).
+#[allow(clippy::too_many_arguments)]
+async fn upload_to_dataset<P>(
+    config: StorageConfig,
+    db_config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    prefix: &str,
+    plex_file_path: Option<P>,
+    object_space_file_path: Option<P>,
+    file_paths: Vec<P>,
+    already_uploaded: &HashMap<String, UploadedFile>,
+    options: &UploadOptions,
+    hashing: &storage::HashingPool,
+    progress: &Progress,
+) -> Result<()>
+where
+    P: AsRef<Path> + Debug + Display + Clone + Eq,
+{
+    debug!("paths: {:?}", file_paths);
+
+    let already_uploaded_id = |path: &Option<P>| {
+        path.as_ref()
+            .and_then(|path| already_uploaded.get(&path.to_string()))
+            .map(|uploaded_file| uploaded_file.file_id)
+    };
+    let mut maybe_plex_file_id = already_uploaded_id(&plex_file_path);
+    let mut maybe_object_space_file_id = already_uploaded_id(&object_space_file_path);
+    let mut uploaded_files: Vec<UploadedFile> = already_uploaded.values().cloned().collect();
+
+    // Add plex + object_space file paths to front of list that will become
+    // upload futures.
+    let mut all_file_paths: Vec<P> = plex_file_path
+        .iter()
+        .chain(&object_space_file_path)
+        .cloned()
+        .chain(file_paths.clone())
+        .collect();
+    all_file_paths.retain(|path| !already_uploaded.contains_key(&path.to_string()));
+
+    let total_files = all_file_paths.len() + options.stdin_name.iter().count();
+    let mut total_bytes = 0;
+    for path in &all_file_paths {
+        // Unreadable files fail when uploaded, so they needn't fail here
+        total_bytes += tokio::fs::metadata(path.as_ref())
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+    }
+    let mut overall_progress = OverallProgress::new(progress, total_files, total_bytes);
+    let mut futs = stream::iter(all_file_paths)
+        .map(|path| async {
+            // If path is the plex path, mark this as the plex so we can pull
+            // out the plex's file_id to associate as the input plex when
+            // triggering calibration.
+            let is_plex = plex_file_path.as_ref() == Some(&path);
+            // Do the same with the object_space path
+            let is_object_space = object_space_file_path.as_ref() == Some(&path);
+            // The backend reads the plex and object-space file, so only data
+            // files are compressed
+            let compression = if is_plex || is_object_space {
+                None
+            } else {
+                options.compression_for(path.as_ref())
+            };
+            // Returns tuple of (is_plex, is_object_space, path,
+            // Result<UploadedFile, Error>)
+            (
+                is_plex,
+                is_object_space,
+                path.to_string(),
+                // Uploads to storage AND registers to database
+                upload_file(
+                    config.clone(),
+                    db_config,
+                    dataset_id,
+                    path,
+                    prefix,
+                    progress,
+                    serde_json::Map::new(),
+                    options.checksum_algorithm,
+                    hashing,
+                    options.chunk_read_ahead,
+                    options.num_parts,
+                    options.multipart_threshold(),
+                    options.part_size_strategy,
+                    !options.skip_part_check,
+                    compression,
+                    &options.transfer_stats,
+                    &options.timings,
+                )
+                .await,
+            )
+        })
+        .buffer_unordered(MAX_FILES_UPLOADING_CONCURRENTLY);
+    let mut failures = Vec::new();
+    let mut uploaded_count = 0;
+    while let Some((is_plex, is_object_space, path, res)) = futs.next().await {
+        let uploaded_file = match res {
+            Ok(uploaded_file) => uploaded_file,
+            Err(error) => {
+                failures.push(FileFailure::new(path, &error));
+                if options.keep_going {
+                    continue;
+                }
+                break;
+            }
+        };
+        uploaded_count += 1;
+        // Overall progress counts local bytes, which differ from the stored
+        // size of compressed files
+        overall_progress.file_done(uploaded_file.uncompressed_size());
+        if is_plex {
+            maybe_plex_file_id = Some(uploaded_file.file_id);
+        }
+        if is_object_space {
+            maybe_object_space_file_id = Some(uploaded_file.file_id);
+        }
+        uploaded_files.push(uploaded_file);
+    }
+    // Aborts in-flight uploads if a file failed
+    drop(futs);
+    if let (Some(name), true) = (
+        &options.stdin_name,
+        failures.is_empty() || options.keep_going,
+    ) {
+        let result = upload_stream(
+            config.clone(),
+            db_config,
+            dataset_id,
+            tokio::io::stdin(),
+            name,
+            prefix,
+            progress,
+            options,
+        )
+        .await;
+        match result {
+            Ok(uploaded_file) => {
+                uploaded_count += 1;
+                overall_progress.file_done(uploaded_file.filesize);
+                uploaded_files.push(uploaded_file);
+            }
+            Err(error) => failures.push(FileFailure::new(name.clone(), &error)),
+        }
+    }
+    if let Some(report_path) = &options.error_report {
+        ErrorReport {
+            operation: "upload",
+            total_files,
+            failures: failures.clone(),
+        }
+        .write(report_path)?;
+    }
+    if !failures.is_empty() {
+        // The backend isn't notified of an incomplete dataset, so it won't be
+        // processed until a resumed upload completes it
+        eprintln!(
+            "{}",
+            upload_failure_summary(dataset_id, total_files, uploaded_count, &failures)
+        );
+        bail!(
+            "Upload to dataset {} is incomplete and won't be processed -- re-run the \
+             upload with --resume {} to upload the remaining files",
+            dataset_id,
+            dataset_id
+        );
+    }
+
+    // After all uploads are complete, notify the backend so it can begin
+    // processing, send notifications, etc.
+    debug!("Upload(s) complete, notifying backend of completion");
+
+    if plex_file_path.is_some() && maybe_plex_file_id.is_none() {
+        bail!("Unable to retrieve file_id for uploaded plex file!");
+    }
+    if object_space_file_path.is_some() && maybe_object_space_file_id.is_none() {
+        bail!("Unable to retrieve file_id for uploaded object space file!");
+    }
+    options
+        .timings
+        .time(
+            "notify",
+            None,
+            datasets::datasets_notify_upload_complete(
+                db_config,
+                dataset_id,
+                maybe_plex_file_id,
+                maybe_object_space_file_id,
+                options.processing_options.clone(),
+            ),
+        )
+        .await?;
+
+    if options.share || options.presign {
+        if options.presign {
+            println!(
+                "Presigned download urls (valid for {} days):",
+                storage::PRESIGNED_URL_EXPIRATION.as_secs() / (24 * 60 * 60)
+            );
+        } else {
+            println!("Download commands:");
+        }
+        for line in share_info(&config, &uploaded_files, options.presign).await? {
+            println!("\t{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-sends the notification that a dataset's upload is complete, so the
+/// backend starts processing it (e.g. if the notification at the end of
+/// `upload` was lost).
+///
+/// The plex and object-space files to notify about are the files at
+/// `plex_path` and `object_space_path` in the dataset if given. Otherwise,
+/// they're the dataset's only `.plex` file and only `.toml` file, unless the
+/// dataset was uploaded uncalibrated (see [UNCALIBRATED_METADATA_KEY]).
+///
+/// # Errors
+///
+/// Returns an error if the dataset doesn't exist, if a given path isn't a file
+/// in the dataset, or if the dataset has several files that could be its plex
+/// or object-space file and no path was given to choose between them.
+///
+/// Wraps [datasets::datasets_get] and [datasets::datasets_notify_upload_complete]
+/// -- see their documentation for other possible errors.
+pub async fn notify_upload_complete(
+    config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    plex_path: Option<&str>,
+    object_space_path: Option<&str>,
+    processing_options: Option<serde_json::Value>,
+) -> Result<datasets::NotifyOutcome> {
+    let params = DatasetGetRequest {
+        dataset_id: Some(dataset_id),
+        ..Default::default()
+    };
+    let dataset = datasets::datasets_get(config, &params)
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("No dataset found with UUID {}", dataset_id))?;
+    let uncalibrated = dataset.metadata[UNCALIBRATED_METADATA_KEY] == json!(true);
+
+    let plex_file_id = calibration_file_id(&dataset, "plex", plex_path, uncalibrated)?;
+    let object_space_file_id =
+        calibration_file_id(&dataset, "toml", object_space_path, uncalibrated)?;
+    datasets::datasets_notify_upload_complete(
+        config,
+        dataset_id,
+        plex_file_id,
+        object_space_file_id,
+        processing_options,
+    )
+    .await
+}
+
+/// Id of the file in `dataset` at `path` if given, otherwise of its only file
+/// ending in `.{extension}` (or None if it has none or is `uncalibrated`).
+fn calibration_file_id(
+    dataset: &Dataset,
+    extension: &str,
+    path: Option<&str>,
+    uncalibrated: bool,
+) -> Result<Option<Uuid>> {
+    let files_with_paths = dataset.files.iter().filter_map(|file| {
+        file.filepath_from_url()
+            .ok()
+            .map(|filepath| (file, filepath))
+    });
+    if let Some(path) = path {
+        return match files_with_paths
+            .filter(|(_, filepath)| filepath == Path::new(path))
+            .max_by_key(|(file, _)| file.created_date)
+        {
+            Some((file, _)) => Ok(Some(file.file_id)),
+            None => bail!("No file {} found in dataset {}", path, dataset.dataset_id),
+        };
+    }
+    if uncalibrated {
+        return Ok(None);
+    }
+    let mut candidates: Vec<(&UploadedFile, PathBuf)> = files_with_paths
+        .filter(|(_, filepath)| {
+            filepath
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+        })
+        .collect();
+    // Re-uploads of the same path are new versions of one file
+    candidates.sort_by(|(a, a_path), (b, b_path)| {
+        a_path.cmp(b_path).then(b.created_date.cmp(&a.created_date))
+    });
+    candidates.dedup_by(|(_, a_path), (_, b_path)| a_path == b_path);
+    match candidates.as_slice() {
+        [] => Ok(None),
+        [(file, _)] => Ok(Some(file.file_id)),
+        _ => bail!(
+            "Dataset {} has several .{} files ({}) -- pass the path of the one to use",
+            dataset.dataset_id,
+            extension,
+            candidates
+                .iter()
+                .map(|(_, filepath)| filepath.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Summarizes an upload to a dataset that had failed files: how many files
+/// were uploaded, each failed file with its error, and how many files weren't
+/// attempted because the upload stopped at the first failure.
+pub fn upload_failure_summary(
+    dataset_id: Uuid,
+    total_files: usize,
+    uploaded_count: usize,
+    failures: &[FileFailure],
+) -> String {
+    let mut summary = format!(
+        "Uploaded {} of {} file(s) to dataset {}, {} failed:",
+        uploaded_count,
+        total_files,
+        dataset_id,
+        failures.len()
+    );
+    for failure in failures {
+        summary.push_str(&format!("\n\t{}: {}", failure.path, failure.error));
+    }
+    let not_attempted = total_files.saturating_sub(uploaded_count + failures.len());
+    if not_attempted > 0 {
+        summary.push_str(&format!(
+            "\n{} file(s) weren't uploaded after the first failure (pass --keep-going \
+             to upload them anyway)",
+            not_attempted
+        ));
+    }
+    summary
+}
+
+/// Spec file describing several datasets to upload at once (see
+/// [read_batch_upload_spec] and [upload_datasets]).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchUploadSpec {
+    /// Datasets to upload, reported in this order.
+    #[serde(rename = "dataset", alias = "datasets")]
+    pub datasets: Vec<BatchDatasetSpec>,
+}
+
+/// One dataset in a [BatchUploadSpec], mirroring the arguments of `bolster
+/// upload`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchDatasetSpec {
+    /// Identifies the system that produced the dataset.
+    pub system_id: String,
+    /// Path to the .plex file describing the system's sensor configuration.
+    pub plex: PathBuf,
+    /// Path to the .toml file describing object space.
+    pub object_space: PathBuf,
+    /// Paths to data files or folders containing data.
+    pub paths: Vec<PathBuf>,
+    /// Metadata to attach to the dataset.
+    #[serde(default)]
+    pub meta: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Reads a [BatchUploadSpec] from a TOML file (or a JSON file, if the path
+/// ends in `.json`).
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read or parsed, or if it doesn't
+/// list any datasets.
+pub fn read_batch_upload_spec(path: &Path) -> Result<BatchUploadSpec> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read batch upload spec {:?}", path))?;
+    let contents = strip_bom(&contents);
+    let spec: BatchUploadSpec = if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+        serde_json::from_str(contents)
+            .with_context(|| format!("Batch upload spec {:?} is invalid", path))?
+    } else {
+        toml::from_str(contents)
+            .with_context(|| format!("Batch upload spec {:?} is invalid", path))?
+    };
+    if spec.datasets.is_empty() {
+        bail!("Batch upload spec {:?} doesn't list any datasets", path);
+    }
+    Ok(spec)
+}
+
+/// A dataset from a [BatchUploadSpec] whose paths have been validated (and
+/// data folders expanded into files), ready to upload with
+/// [upload_datasets].
+#[derive(Debug)]
+pub struct BatchUploadItem {
+    /// Identifies the system that produced the dataset.
+    pub system_id: String,
+    /// Path to the plex file.
+    pub plex_path: String,
+    /// Path to the object-space file.
+    pub object_space_path: String,
+    /// Paths to all data files.
+    pub file_paths: Vec<String>,
+    /// Metadata to attach to the dataset.
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Outcome of uploading one dataset of a batch (see [upload_datasets]).
+#[derive(Debug)]
+pub enum BatchUploadOutcome {
+    /// The dataset was created and all its files uploaded.
+    Uploaded(Uuid),
+    /// The dataset was created, but uploading its files failed.
+    UploadFailed(Uuid, Error),
+    /// The dataset couldn't be created, so nothing was uploaded.
+    CreateFailed(Error),
+}
+
+/// Creates and uploads several datasets, at most `max_concurrent_datasets` at
+/// a time, returning each dataset's outcome in the order of `items`.
+///
+/// A dataset failing doesn't stop the others from uploading. Each dataset
+/// uploads up to [MAX_FILES_UPLOADING_CONCURRENTLY] files at a time, and all
+/// progress bars share one multi-progress bar.
+///
+/// [UploadOptions::metadata] is ignored in favor of each item's metadata.
+pub async fn upload_datasets(
+    config: StorageConfig,
+    db_config: &DatabaseApiConfig,
+    prefix: &str,
+    items: Vec<BatchUploadItem>,
+    options: &UploadOptions,
+    max_concurrent_datasets: usize,
+) -> Vec<BatchUploadOutcome> {
+    let guard = MultiProgressGuard::new(options.progress_mode()).await;
+    let progress = &guard.inner;
+    let hashing = &storage::HashingPool::new(options.hash_jobs());
+    stream::iter(items)
+        .map(|item| {
+            let config = config.clone();
+            async move {
+                let dataset_id = match create_dataset_for_upload(
+                    db_config,
+                    item.system_id,
+                    item.metadata,
+                    prefix,
+                    Some(&item.plex_path),
+                    Some(&item.object_space_path),
+                    &item.file_paths,
+                    options,
+                )
+                .await
+                {
+                    Ok(dataset_id) => dataset_id,
+                    Err(error) => return BatchUploadOutcome::CreateFailed(error),
+                };
+                let result = upload_to_dataset(
+                    config,
+                    db_config,
+                    dataset_id,
+                    prefix,
+                    Some(item.plex_path),
+                    Some(item.object_space_path),
+                    item.file_paths,
+                    &HashMap::new(),
+                    options,
+                    hashing,
+                    progress,
+                )
+                .await;
+                match result {
+                    Ok(()) => BatchUploadOutcome::Uploaded(dataset_id),
+                    Err(error) => BatchUploadOutcome::UploadFailed(dataset_id, error),
+                }
+            }
+        })
+        .buffered(max_concurrent_datasets)
+        .collect()
+        .await
+}
+
+/// Build info for sharing uploaded files with others, one line per file
+/// (sorted by filepath).
+///
+/// Each line contains the file's path in the dataset and either a presigned
+/// url (if `presign` is true) that can be downloaded without credentials, or
+/// the `bolster download` command that fetches the file.
+///
+/// # Errors
+///
+/// Returns an error if a file's url is malformed.
+///
+/// Returns an error if presigning fails -- see [storage::presign_download_url].
+pub async fn share_info(
+    config: &StorageConfig,
+    uploaded_files: &[UploadedFile],
+    presign: bool,
+) -> Result<Vec<String>> {
+    let mut files = uploaded_files
+        .iter()
+        .map(|file| Ok((file.filepath_from_url()?, file)))
+        .collect::<Result<Vec<_>>>()?;
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut lines = Vec::with_capacity(files.len());
+    for (filepath, file) in files {
+        let info = if presign {
+            storage::presign_download_url(config, &file.url, Some(&file.version)).await?
+        } else {
+            format!(
+                "bolster download {} {}",
+                file.dataset_id,
+                filepath.display()
+            )
+        };
+        lines.push(format!("{}: {}", filepath.display(), info));
+    }
+    Ok(lines)
+}
+
+/// List all datasets, optionally filtered by options in [DatasetGetRequest].
+///
+/// Thin wrapper around [datasets::datasets_get] -- see its documentation for
+/// behavior and possible errors.
+pub async fn list_datasets(
+    config: &DatabaseApiConfig,
+    params: &DatasetGetRequest,
+) -> Result<Vec<Dataset>> {
+    let datasets = datasets::datasets_get(config, params).await?;
+
+    Ok(datasets)
+}
+
+/// Page size used when listing all datasets (the max the datasets API allows).
+pub const LIST_ALL_PAGE_SIZE: usize = 100;
+
+/// Number of datasets after which listing all datasets warns that it may be
+/// fetching more than intended.
+pub const LIST_ALL_WARNING_THRESHOLD: usize = 1000;
+
+/// List all datasets matching the filters in [DatasetGetRequest], fetching
+/// pages of [LIST_ALL_PAGE_SIZE] until a partial page is returned.
+///
+/// The `order`, `limit`, and `offset` of `params` are overridden: pages are
+/// ordered oldest-first so datasets created while paginating land on later
+/// pages instead of shifting earlier results. Datasets that appear on more
+/// than one page are only returned once.
+///
+/// Prints a warning (once) if more than [LIST_ALL_WARNING_THRESHOLD] datasets
+/// are fetched.
+///
+/// Wraps [datasets::datasets_get] -- see its documentation for behavior and
+/// possible errors.
+pub async fn list_all_datasets(
+    config: &DatabaseApiConfig,
+    params: DatasetGetRequest,
+) -> Result<Vec<Dataset>> {
+    stream_all_datasets(config, params).try_collect().await
+}
+
+/// Stream all datasets matching the filters in [DatasetGetRequest], yielding
+/// each page's datasets as soon as it's fetched (see [list_all_datasets]).
+pub fn stream_all_datasets(
+    config: &DatabaseApiConfig,
+    params: DatasetGetRequest,
+) -> impl Stream<Item = Result<Dataset>> + '_ {
+    stream_all_pages(
+        params,
+        move |page_params| async move { datasets::datasets_get(config, &page_params).await },
+        |dataset| dataset.dataset_id,
+    )
+}
+
+/// List datasets with the number and total size of their files, optionally
+/// filtered by options in [DatasetGetRequest].
+///
+/// Thin wrapper around [datasets::datasets_get_summaries] -- see its
+/// documentation for behavior and possible errors.
+pub async fn list_dataset_summaries(
+    config: &DatabaseApiConfig,
+    params: &DatasetGetRequest,
+) -> Result<Vec<DatasetSummary>> {
+    let summaries = datasets::datasets_get_summaries(config, params).await?;
+
+    Ok(summaries)
+}
+
+/// List summaries of all datasets matching the filters in
+/// [DatasetGetRequest], paginating the same way as [list_all_datasets].
+///
+/// Wraps [datasets::datasets_get_summaries] -- see its documentation for
+/// behavior and possible errors.
+pub async fn list_all_dataset_summaries(
+    config: &DatabaseApiConfig,
+    params: DatasetGetRequest,
+) -> Result<Vec<DatasetSummary>> {
+    stream_all_dataset_summaries(config, params)
+        .try_collect()
+        .await
+}
+
+/// Stream summaries of all datasets matching the filters in
+/// [DatasetGetRequest], yielding each page's summaries as soon as it's
+/// fetched (see [list_all_dataset_summaries]).
+pub fn stream_all_dataset_summaries(
+    config: &DatabaseApiConfig,
+    params: DatasetGetRequest,
+) -> impl Stream<Item = Result<DatasetSummary>> + '_ {
+    stream_all_pages(
+        params,
+        move |page_params| async move { datasets::datasets_get_summaries(config, &page_params).await },
+        |summary| summary.dataset_id,
+    )
+}
+
+/// Progress of paginating through datasets (see [stream_all_pages]).
+struct PageCursor {
+    /// Filters, with the order and page size used for every page
+    params: DatasetGetRequest,
+    /// Offset of the next page
+    offset: usize,
+    /// Ids of datasets yielded so far, so none is yielded twice
+    seen: HashSet<Uuid>,
+    /// Whether the warning about fetching many datasets was printed
+    warned: bool,
+    /// Whether the last page was fetched
+    done: bool,
+}
+
+/// Stream every page of datasets fetched with `fetch_page`, deduplicating
+/// results by the id returned by `dataset_id` (see [list_all_datasets]).
+///
+/// Pages are only fetched as the stream is consumed, so each page's datasets
+/// can be printed before the next page is requested.
+fn stream_all_pages<T, F, Fut>(
+    mut params: DatasetGetRequest,
+    fetch_page: F,
+    dataset_id: fn(&T) -> Uuid,
+) -> impl Stream<Item = Result<T>>
+where
+    F: Fn(DatasetGetRequest) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    params.order = Some(DatasetOrdering::CreatedDateAsc);
+    params.limit = Some(LIST_ALL_PAGE_SIZE);
+    let cursor = PageCursor {
+        params,
+        offset: 0,
+        seen: HashSet::new(),
+        warned: false,
+        done: false,
+    };
+
+    stream::try_unfold(cursor, move |mut cursor| {
+        let page = if cursor.done {
+            None
+        } else {
+            cursor.params.offset = Some(cursor.offset);
+            Some(fetch_page(cursor.params.clone()))
+        };
+        async move {
+            let page = match page {
+                Some(page) => page.await?,
+                None => return Ok::<_, Error>(None),
+            };
+            let page_len = page.len();
+            debug!("Fetched {} datasets at offset {}", page_len, cursor.offset);
+            let new_datasets: Vec<T> = page
+                .into_iter()
+                .filter(|dataset| cursor.seen.insert(dataset_id(dataset)))
+                .collect();
+            if !cursor.warned && cursor.seen.len() > LIST_ALL_WARNING_THRESHOLD {
+                eprintln!(
+                    "Warning: fetched over {} datasets so far, consider narrowing the results with filters",
+                    LIST_ALL_WARNING_THRESHOLD
+                );
+                cursor.warned = true;
+            }
+            // A partial page means there are no more datasets
+            cursor.done = page_len < LIST_ALL_PAGE_SIZE;
+            cursor.offset += page_len;
+            Ok(Some((new_datasets, cursor)))
+        }
+    })
+    .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+    .try_flatten()
+}
+
+/// Reads the list of tags out of dataset metadata.
+///
+/// Missing or non-string tags are ignored.
+pub fn tags_from_metadata(metadata: &serde_json::Value) -> Vec<String> {
+    metadata
+        .get("tags")
+        .and_then(|tags| tags.as_array())
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|tag| tag.as_str().map(|s| s.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads a dataset's human-readable name out of its metadata, if it has one.
+pub fn name_from_metadata(metadata: &serde_json::Value) -> Option<&str> {
+    metadata
+        .get(NAME_METADATA_KEY)
+        .and_then(|name| name.as_str())
+}
+
+/// Finds the dataset with the given human-readable name (set with `bolster
+/// upload --name`).
+///
+/// # Errors
+///
+/// Returns an error if no dataset has the name, or if several do (listing
+/// their UUIDs, so one can be picked).
+///
+/// Wraps [datasets::datasets_get_summaries] -- see its documentation for
+/// other possible errors.
+pub async fn resolve_dataset_name(config: &DatabaseApiConfig, name: &str) -> Result<Uuid> {
+    let params = DatasetGetRequest {
+        metadata: vec![(NAME_METADATA_KEY.to_owned(), name.to_owned())],
+        ..Default::default()
+    };
+    let matches = datasets::datasets_get_summaries(config, &params).await?;
+    match matches.as_slice() {
+        [] => bail!("No dataset named {:?}", name),
+        [dataset] => Ok(dataset.dataset_id),
+        _ => bail!(
+            "Dataset name {:?} is ambiguous, it matches datasets {} (pass a UUID instead)",
+            name,
+            matches
+                .iter()
+                .map(|dataset| dataset.dataset_id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Applies `update` to a dataset's tags and saves the result, returning the
+/// updated list of tags.
+async fn update_tags<F>(
+    config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    update: F,
+) -> Result<Vec<String>>
+where
+    F: FnOnce(&mut Vec<String>),
+{
+    let params = DatasetGetRequest {
+        dataset_id: Some(dataset_id),
+        ..Default::default()
+    };
+    let dataset = datasets::datasets_get(config, &params)
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("No dataset found with UUID {}", dataset_id))?;
+
+    let mut tags = tags_from_metadata(&dataset.metadata);
+    update(&mut tags);
+
+    let mut metadata = match dataset.metadata {
+        serde_json::Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    };
+    metadata.insert("tags".to_owned(), json!(tags));
+    let updated = datasets::datasets_patch(config, dataset_id, metadata.into()).await?;
+    Ok(tags_from_metadata(&updated.metadata))
+}
+
+/// Adds a tag to a dataset, returning the dataset's tags.
+///
+/// Adding a tag the dataset already has is a no-op.
+///
+/// # Errors
+///
+/// Returns an error if the dataset doesn't exist.
+///
+/// Wraps [datasets::datasets_get] and [datasets::datasets_patch] -- see their
+/// documentation for other possible errors.
+pub async fn add_tag(
+    config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    tag: String,
+) -> Result<Vec<String>> {
+    update_tags(config, dataset_id, |tags| {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    })
+    .await
+}
+
+/// Removes a tag from a dataset, returning the dataset's remaining tags.
+///
+/// Removing a tag the dataset doesn't have is a no-op.
+///
+/// # Errors
+///
+/// Returns an error if the dataset doesn't exist.
+///
+/// Wraps [datasets::datasets_get] and [datasets::datasets_patch] -- see their
+/// documentation for other possible errors.
+pub async fn remove_tag(
+    config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    tag: String,
+) -> Result<Vec<String>> {
+    update_tags(config, dataset_id, |tags| tags.retain(|t| *t != tag)).await
+}
+
+/// Registers uploaded file (critically, its url) in the datasets database.
+///
+/// Thin wrapper around [datasets::files_post] -- see its documentation for
+/// behavior and possible errors.
+pub async fn add_file_to_dataset(
+    config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    url: &Url,
+    filesize: usize,
+    version: String,
+    metadata: serde_json::Value,
+) -> Result<UploadedFile> {
+    datasets::files_post(config, dataset_id, url, filesize, version, metadata).await
+}
+
+/// Builds the metadata recorded for an uploaded file.
+///
+/// Caller-supplied `metadata` is extended with the file's original (relative)
+/// path under the `path` key, its modification time (RFC 3339, if known) under
+/// the `mtime` key, the checksum algorithm under the `checksum_algorithm` key,
+/// and the checksum under the algorithm's key (e.g. `md5`). These fields take
+/// precedence over caller-supplied fields with the same keys.
+pub fn file_metadata(
+    path: &str,
+    modified: Option<SystemTime>,
+    checksum_algorithm: ChecksumAlgorithm,
+    checksum: String,
+    mut metadata: serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Value {
+    metadata.insert("path".to_owned(), json!(path));
+    if let Some(modified) = modified {
+        let mtime = DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Secs, true);
+        metadata.insert("mtime".to_owned(), json!(mtime));
+    }
+    metadata.insert(
+        "checksum_algorithm".to_owned(),
+        json!(checksum_algorithm.as_str()),
+    );
+    metadata.insert(checksum_algorithm.as_str().to_owned(), json!(checksum));
+    serde_json::Value::from(metadata)
+}
+
+/// Metadata recorded for a file that was compressed before uploading: the
+/// `compression` format, and the file's size and checksum before compression
+/// under the `uncompressed_size` and e.g. `uncompressed_md5` keys (see
+/// [UploadedFile::uncompressed_checksum]).
+pub fn compression_metadata(
+    format: CompressionFormat,
+    checksum_algorithm: ChecksumAlgorithm,
+    uncompressed: &compression::Uncompressed,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut metadata = serde_json::Map::new();
+    metadata.insert("compression".to_owned(), json!(format.as_ref()));
+    metadata.insert("uncompressed_size".to_owned(), json!(uncompressed.size));
+    metadata.insert(
+        format!("uncompressed_{}", checksum_algorithm.as_str()),
+        json!(uncompressed.checksum),
+    );
+    metadata
+}
+
+/// Uploads a single file at the given path to the cloud storage provider
+/// indicated in `config` and registers the uploaded file in the datasets
+/// database.
+///
+/// Folder structure is preserved when uploading, so uploading `dir/file` is
+/// different from doing `cd dir` then uploading `file`.
+///
+/// Files smaller than `multipart_threshold` bytes are uploaded in one request
+/// with [storage::upload_file_oneshot]. Larger files use multipart uploads
+/// (see [uses_multipart_upload] and [storage::upload_file_multipart]), which
+/// are split into `num_parts` parts if given (see [UploadOptions::num_parts])
+/// and sized according to `part_size_strategy`. Unless `verify_parts` is
+/// unset, every part is checked to have been uploaded before a multipart
+/// upload is completed.
+///
+/// If `compression` is given, the file is compressed as it's read and the
+/// compressed data is uploaded in parts under the file's key with the
+/// compression's extension (e.g. `.gz`) appended, since its compressed size
+/// isn't known up front (see [storage::upload_stream_multipart]). The
+/// compression and the file's uncompressed size and checksum are recorded in
+/// its metadata (see [compression_metadata]), so downloads can decompress it
+/// and resumed uploads can match it to the local file.
+///
+/// The given `metadata` is recorded alongside the file, augmented with the
+/// file's path, modification time, and checksum (see [file_metadata]). The
+/// checksum allows downloads to be verified. It's computed from the same read
+/// of the file as the upload, so the file is only read once. md5 checksums
+/// are also sent to cloud storage so it can verify the upload.
+///
+/// # Errors
+///
+/// Returns an error if the file is unreadable or if its cloud storage key
+/// would be too long (see [check_key_length]).
+///
+/// Invokes [storage::upload_file_oneshot], [storage::upload_file_multipart],
+/// and [add_file_to_dataset] -- see those functions' documentation for
+/// additional behavior and possible errors.
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_file<P>(
+    config: StorageConfig,
+    db_config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    path: P,
+    prefix: &str,
+    progress: &Progress,
+    metadata: serde_json::Map<String, serde_json::Value>,
+    checksum_algorithm: ChecksumAlgorithm,
+    hashing: &storage::HashingPool,
+    chunk_read_ahead: usize,
+    num_parts: Option<usize>,
+    multipart_threshold: usize,
+    part_size_strategy: PartSizeStrategy,
+    verify_parts: bool,
+    compression: Option<CompressionFormat>,
+    stats: &storage::TransferStats,
+    timings: &Timings,
+) -> Result<UploadedFile>
+where
+    P: AsRef<Path> + Clone,
+{
+    // We retain any directories in the path
+    let path_str = path
+        .as_ref()
+        .to_str()
+        .ok_or_else(|| anyhow!("Path was not UTF8"))?
+        .to_owned();
+    let mut key = object_key(prefix, dataset_id, &path_str);
+    if let Some(format) = compression {
+        key = format!("{}.{}", key, format.extension());
+    }
+    debug!("key {}", key);
+    check_key_length(&key, &path_str)?;
+
+    debug!("Got path {:?}", path_str);
+    let fs_metadata = tokio::fs::metadata(path.clone()).await?;
+    let filesize: usize = fs_metadata.len().try_into().unwrap();
+
+    let mut metadata = metadata;
+    let uploaded = if let Some(format) = compression {
+        debug!("Compressing {} with {}", path_str, format.as_ref());
+        let (reader, uncompressed) =
+            compression::compress_file(path.as_ref(), format, checksum_algorithm);
+        let uploaded = timings
+            .time(
+                "transfer",
+                Some(&path_str),
+                storage::upload_stream_multipart(
+                    config,
+                    reader,
+                    path_str.clone(),
+                    key,
+                    checksum_algorithm,
+                    chunk_read_ahead,
+                    verify_parts,
+                    progress.spinner(&path_str),
+                    stats,
+                ),
+            )
+            .await?;
+        let uncompressed = uncompressed
+            .await?
+            .with_context(|| format!("Unable to compress {}", path_str))?;
+        metadata.extend(compression_metadata(
+            format,
+            checksum_algorithm,
+            &uncompressed,
+        ));
+        uploaded
+    } else if uses_multipart_upload(filesize, multipart_threshold) {
+        debug!(
+            "Filesize {} >= multipart threshold {} so doing multipart",
+            filesize, multipart_threshold
+        );
+        timings
+            .time(
+                "transfer",
+                Some(&path_str),
+                storage::upload_file_multipart(
+                    config,
+                    path_str.clone(),
+                    filesize as usize,
+                    key,
+                    checksum_algorithm,
+                    chunk_read_ahead,
+                    num_parts,
+                    part_size_strategy,
+                    verify_parts,
+                    progress.bar(&path_str, filesize as u64, get_default_progress_bar_style()),
+                    stats,
+                ),
+            )
+            .await?
+    } else {
+        debug!(
+            "Filesize {} < multipart threshold {} so doing oneshot",
+            filesize, multipart_threshold
+        );
+        timings
+            .time(
+                "transfer",
+                Some(&path_str),
+                storage::upload_file_oneshot(
+                    config,
+                    path_str.clone(),
+                    filesize,
+                    key,
+                    checksum_algorithm,
+                    hashing,
+                    progress.bar(&path_str, filesize as u64, get_default_progress_bar_style()),
+                    stats,
+                ),
+            )
+            .await?
+    };
+    let metadata = file_metadata(
+        &path_str,
+        fs_metadata.modified().ok(),
+        checksum_algorithm,
+        uploaded.checksum,
+        metadata,
+    );
+    // Register uploaded file to database
+    timings
+        .time(
+            "register",
+            Some(&path_str),
+            add_file_to_dataset(
+                db_config,
+                dataset_id,
+                &uploaded.url,
+                uploaded.size,
+                uploaded.version,
+                metadata,
+            ),
+        )
+        .await
+}
+
+/// Uploads everything read from `reader` (e.g. stdin) as a data file at path
+/// `name` in the dataset and registers it in the datasets database.
+///
+/// Like [upload_file], the data is checksummed while it uploads and the
+/// checksum is recorded when the file is registered. Since the data's size
+/// isn't known up front, it's always uploaded in parts (see
+/// [storage::upload_stream_multipart]).
+///
+/// # Errors
+///
+/// Returns an error if the object key would be too long, if nothing is read,
+/// or if uploading or registering the file fails.
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_stream<R>(
+    config: StorageConfig,
+    db_config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    reader: R,
+    name: &str,
+    prefix: &str,
+    progress: &Progress,
+    options: &UploadOptions,
+) -> Result<UploadedFile>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let key = object_key(prefix, dataset_id, name);
+    check_key_length(&key, name)?;
+
+    let uploaded = options
+        .timings
+        .time(
+            "transfer",
+            Some(name),
+            storage::upload_stream_multipart(
+                config,
+                reader,
+                name.to_owned(),
+                key,
+                options.checksum_algorithm,
+                options.chunk_read_ahead,
+                !options.skip_part_check,
+                progress.spinner(name),
+                &options.transfer_stats,
+            ),
+        )
+        .await?;
+    let metadata = file_metadata(
+        name,
+        None,
+        options.checksum_algorithm,
+        uploaded.checksum,
+        serde_json::Map::new(),
+    );
+    options
+        .timings
+        .time(
+            "register",
+            Some(name),
+            add_file_to_dataset(
+                db_config,
+                dataset_id,
+                &uploaded.url,
+                uploaded.size,
+                uploaded.version,
+                metadata,
+            ),
+        )
+        .await
+}
+
+/// Ensures a name given for data read from stdin is a relative path inside
+/// the dataset.
+///
+/// # Errors
+///
+/// Returns an error if the name is empty, absolute, or has an empty, `.`, or
+/// `..` component.
+pub fn validate_stdin_name(name: &str) -> Result<()> {
+    if name
+        .split('/')
+        .any(|component| matches!(component, "" | "." | ".."))
+    {
+        bail!(
+            "Name for stdin data ({:?}) must be a relative path without empty, \".\", or \"..\" components",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// List all files in the given dataset, optionally filtered by prefixes.
+///
+/// If multiple prefixes are provided, all files matching any prefix are
+/// returned (i.e. it's a union). A file matching several overlapping prefixes
+/// is only returned once, so it isn't downloaded twice.
+///
+/// # Errors
+///
+/// Returns an error if no files are found, since the database can't tell a
+/// dataset without (matching) files apart from one that doesn't exist or
+/// that the user can't access.
+///
+/// Wrapper around [datasets::files_get] -- see its documentation for
+/// behavior and other possible errors.
+pub async fn list_files(
+    config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    prefixes: Vec<String>,
+) -> Result<Vec<UploadedFile>> {
+    let mut seen_file_ids = HashSet::new();
+    let uploaded_files: Vec<UploadedFile> =
+        datasets::files_get(config, dataset_id, prefixes.clone(), None)
+            .await?
+            .into_iter()
+            .filter(|uploaded_file| seen_file_ids.insert(uploaded_file.file_id))
+            .collect();
+    if uploaded_files.is_empty() {
+        if prefixes.is_empty() {
+            bail!(
+                "No files found for dataset {} -- does it exist and do you have access?",
+                dataset_id
+            );
+        }
+        bail!(
+            "No files matching {} found for dataset {} -- does it exist and do you have access?",
+            prefixes.join(", "),
+            dataset_id
+        );
+    }
+    Ok(uploaded_files)
+}
+
+/// Path of an uploaded file in its dataset for showing to users, or its url if
+/// the url is malformed.
+pub fn display_path(uploaded_file: &UploadedFile) -> String {
+    match uploaded_file.filepath_from_url() {
+        Ok(filepath) => filepath.display().to_string(),
+        Err(_) => uploaded_file.url.to_string(),
+    }
+}
+
+/// Result of checking a file in cloud storage against what the database
+/// recorded when it was uploaded.
+#[derive(Debug, PartialEq)]
+pub enum VerifyStatus {
+    /// File is present with the expected size (and checksum, if it can be
+    /// compared).
+    Ok,
+    /// File (or the uploaded version of it) isn't in cloud storage.
+    Missing,
+    /// File's size in cloud storage differs from the recorded filesize.
+    SizeMismatch {
+        /// Filesize recorded in the database
+        expected: u64,
+        /// Size in cloud storage
+        actual: u64,
+    },
+    /// File's md5 (from its ETag) differs from the recorded md5 checksum.
+    ChecksumMismatch {
+        /// Hex md5 recorded in the database
+        expected: String,
+        /// Hex md5 from cloud storage
+        actual: String,
+    },
+}
+
+impl Display for VerifyStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyStatus::Ok => write!(f, "OK"),
+            VerifyStatus::Missing => write!(f, "missing"),
+            VerifyStatus::SizeMismatch { expected, actual } => write!(
+                f,
+                "size mismatch (expected {} bytes, found {} bytes)",
+                expected, actual
+            ),
+            VerifyStatus::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch (expected md5 {}, found md5 {})",
+                expected, actual
+            ),
+        }
+    }
+}
+
+/// Compares a file's size and ETag in cloud storage against the filesize and
+/// checksum recorded for it.
+///
+/// The ETag is only compared for files with a recorded md5 checksum that were
+/// uploaded in one request -- the ETags of multipart uploads aren't checksums
+/// of the whole file.
+pub fn verify_stored_object(
+    uploaded_file: &UploadedFile,
+    stored: Option<storage::StoredObject>,
+) -> VerifyStatus {
+    let stored = match stored {
+        Some(stored) => stored,
+        None => return VerifyStatus::Missing,
+    };
+    let actual_size = stored.content_length.unwrap_or_default().max(0) as u64;
+    if actual_size != uploaded_file.filesize {
+        return VerifyStatus::SizeMismatch {
+            expected: uploaded_file.filesize,
+            actual: actual_size,
+        };
+    }
+    let expected_md5 = match uploaded_file.expected_checksum() {
+        Some((ChecksumAlgorithm::Md5, checksum)) => base64::decode(checksum).ok().map(|digest| {
+            digest
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        }),
+        _ => None,
+    };
+    let e_tag = stored
+        .e_tag
+        .as_deref()
+        .map(|e_tag| e_tag.trim_matches('"').to_lowercase());
+    match (expected_md5, e_tag) {
+        (Some(expected), Some(actual)) if !actual.contains('-') && expected != actual => {
+            VerifyStatus::ChecksumMismatch { expected, actual }
+        }
+        _ => VerifyStatus::Ok,
+    }
+}
+
+/// Checks that each file is present in cloud storage with the size and
+/// checksum recorded when it was uploaded, without downloading any files.
+///
+/// Returns each file's path in the dataset (or url, if the url is malformed)
+/// and [VerifyStatus], in the same order as `uploaded_files`.
+///
+/// # Errors
+///
+/// Wraps [storage::head_object] -- see its documentation for possible errors.
+pub async fn verify_files(
+    storage_config: &StorageConfig,
+    uploaded_files: &[UploadedFile],
+) -> Result<Vec<(String, VerifyStatus)>> {
+    stream::iter(uploaded_files)
+        .map(|uploaded_file| async move {
+            let stored = storage::head_object(
+                storage_config,
+                &uploaded_file.url,
+                Some(&uploaded_file.version),
+            )
+            .await?;
+            Ok((
+                display_path(uploaded_file),
+                verify_stored_object(uploaded_file, stored),
+            ))
+        })
+        .buffered(MAX_FILES_VERIFYING_CONCURRENTLY)
+        .try_collect()
+        .await
+}
+
+/// Page size used when streaming a dataset's files (see [stream_files]).
+pub const LIST_FILES_PAGE_SIZE: usize = 100;
+
+/// Stream a dataset's files, sorted by `order` (oldest first by default),
+/// fetching pages of [LIST_FILES_PAGE_SIZE] as the stream is consumed so
+/// datasets with many thousands of files don't have to be held in memory at
+/// once.
+///
+/// Skips the first `offset` files and stops after `limit` files, if given.
+///
+/// Wraps [datasets::files_get_page] -- see its documentation for behavior and
+/// possible errors.
+pub fn stream_files(
+    config: &DatabaseApiConfig,
+    dataset_id: Uuid,
+    order: Option<FileOrdering>,
+    limit: Option<usize>,
+    offset: usize,
+) -> impl Stream<Item = Result<UploadedFile>> + '_ {
+    stream::try_unfold((offset, limit, false), move |(offset, remaining, done)| {
+        let order = order.clone();
+        async move {
+            if done || remaining == Some(0) {
+                return Ok::<_, Error>(None);
+            }
+            let page_size = remaining.map_or(LIST_FILES_PAGE_SIZE, |remaining| {
+                remaining.min(LIST_FILES_PAGE_SIZE)
+            });
+            let page =
+                datasets::files_get_page(config, dataset_id, order.as_ref(), page_size, offset)
+                    .await?;
+            let fetched = page.len();
+            // A partial page means there are no more files
+            let next = (
+                offset + fetched,
+                remaining.map(|remaining| remaining - fetched),
+                fetched < page_size,
+            );
+            Ok(Some((page, next)))
+        }
+    })
+    .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+    .try_flatten()
+}
+
+/// Finds the file at `path` (e.g. `data/a.bag`) among a dataset's files.
+pub fn find_file<'a>(uploaded_files: &'a [UploadedFile], path: &str) -> Option<&'a UploadedFile> {
+    uploaded_files.iter().find(|uploaded_file| {
+        uploaded_file
+            .filepath_from_url()
+            .is_ok_and(|filepath| filepath == Path::new(path))
+    })
+}
+
+/// Describes a file's metadata in cloud storage (see [storage::head_object]),
+/// one field per line.
+pub fn stored_object_lines(stored: &storage::StoredObject) -> Vec<String> {
+    let or_unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "unknown".to_owned());
+    vec![
+        format!(
+            "Size: {}",
+            stored
+                .content_length
+                .map_or_else(|| "unknown".to_owned(), |size| format!("{} bytes", size))
+        ),
+        format!("Last modified: {}", or_unknown(&stored.last_modified)),
+        format!("ETag: {}", or_unknown(&stored.e_tag)),
+        format!("Content type: {}", or_unknown(&stored.content_type)),
+        // S3 doesn't report the default storage class
+        format!(
+            "Storage class: {}",
+            stored.storage_class.as_deref().unwrap_or("STANDARD")
+        ),
+        format!("Version id: {}", or_unknown(&stored.version_id)),
+    ]
+}
+
+/// Differences between a local directory and a remote dataset. Each list holds
+/// file paths relative to the directory (and dataset), sorted.
+#[derive(Debug, Default, PartialEq)]
+pub struct DatasetDiff {
+    /// Files only in the local directory
+    pub only_local: Vec<String>,
+    /// Files only in the remote dataset
+    pub only_remote: Vec<String>,
+    /// Files in both places whose size or checksum differs
+    pub differing: Vec<String>,
+}
+
+/// Compares files in `local_dir` (including subfolders) against files in a
+/// remote dataset by path, size, and checksum, without transferring any files.
+///
+/// Local files are only checksummed if their size matches the remote file's
+/// size. Remote files without a recorded checksum are compared by size alone.
+///
+/// # Errors
+///
+/// Returns an error if `local_dir` isn't a directory, if a local path isn't
+/// valid UTF-8, or if a remote file's url is malformed.
+///
+/// Wraps [storage::checksum_file] -- see its documentation for other possible
+/// errors.
+pub async fn diff_dataset(
+    uploaded_files: &[UploadedFile],
+    local_dir: &Path,
+) -> Result<DatasetDiff> {
+    if !local_dir.is_dir() {
+        bail!("{:?} is not a directory", local_dir);
+    }
+    let mut remote_files = uploaded_files
+        .iter()
+        .map(|uploaded_file| {
+            Ok((
+                uploaded_file.local_filepath()?.display().to_string(),
+                uploaded_file,
+            ))
+        })
+        .collect::<Result<BTreeMap<String, &UploadedFile>>>()?;
+
+    let mut local_files = BTreeMap::new();
+    // WalkDir does not follow symlinks by default
+    for entry in WalkDir::new(local_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+    {
+        let relative_path = entry
+            .path()
+            .strip_prefix(local_dir)
+            .unwrap_or_else(|_| entry.path());
+        let relative_path = relative_path
+            .to_str()
+            .ok_or_else(|| anyhow!("File path isn't valid UTF-8: {:?}", entry.path()))?
+            .to_owned();
+        local_files.insert(relative_path, entry.into_path());
+    }
+
+    let mut diff = DatasetDiff::default();
+    for (relative_path, local_path) in local_files {
+        let uploaded_file = match remote_files.remove(&relative_path) {
+            Some(uploaded_file) => uploaded_file,
+            None => {
+                diff.only_local.push(relative_path);
+                continue;
+            }
+        };
+        let local_size = tokio::fs::metadata(&local_path).await?.len();
+        // Compressed files are compared with the file that was compressed
+        let differs = if local_size != uploaded_file.uncompressed_size() {
+            true
+        } else if let Some((algorithm, checksum)) = uploaded_file.uncompressed_checksum() {
+            let local_path = local_path
+                .to_str()
+                .ok_or_else(|| anyhow!("File path isn't valid UTF-8: {:?}", local_path))?;
+            storage::checksum_file(local_path, algorithm).await? != checksum
+        } else {
+            false
+        };
+        if differs {
+            diff.differing.push(relative_path);
+        }
+    }
+    diff.only_remote = remote_files.into_keys().collect();
+    Ok(diff)
+}
+
+/// Serializes writes of concurrently-fetched downloads.
+///
+/// Fetched data is buffered in RAM (bounded by a budget of bytes) and then
+/// committed to disk while holding a lock, so only one file is written at a
+/// time. Files larger than the whole budget aren't buffered; they're streamed
+/// to disk while holding the lock instead (see [SequentialWriteGate::fits]).
+pub struct SequentialWriteGate {
+    /// Held while a buffered file is written to disk.
+    commit_lock: Mutex<()>,
+    /// Budget of buffered data, with one permit per MiB.
+    buffer_budget: Semaphore,
+    /// Total number of permits in `buffer_budget`.
+    budget_permits: usize,
+    /// Most bytes that may be buffered at once.
+    max_buffered_bytes: usize,
+}
+
+impl SequentialWriteGate {
+    /// Creates a gate that buffers at most `max_buffered_bytes` (rounded up to
+    /// the nearest MiB) at a time.
+    pub fn new(max_buffered_bytes: usize) -> Self {
+        let budget_permits = max(1, Self::permits_for(max_buffered_bytes as u64));
+        SequentialWriteGate {
+            commit_lock: Mutex::new(()),
+            buffer_budget: Semaphore::new(budget_permits),
+            budget_permits,
+            max_buffered_bytes,
+        }
+    }
+
+    /// Whether a file of `size` bytes can be buffered within the budget.
+    pub fn fits(&self, size: u64) -> bool {
+        size <= self.max_buffered_bytes as u64
+    }
+
+    /// Number of MiB-sized permits needed to buffer `size` bytes.
+    fn permits_for(size: u64) -> usize {
+        (size as f64 / MEBIBYTE as f64).ceil() as usize
+    }
+
+    /// Waits until there's room in the budget to buffer `size` bytes.
+    ///
+    /// The returned permit releases the reserved room when dropped. Sizes
+    /// larger than the whole budget reserve the whole budget, though such
+    /// files shouldn't be buffered at all (see [SequentialWriteGate::fits]).
+    pub async fn reserve(&self, size: u64) -> Result<SemaphorePermit<'_>> {
+        let permits = Self::permits_for(size).clamp(1, self.budget_permits);
+        Ok(self.buffer_budget.acquire_many(permits as u32).await?)
+    }
+
+    /// Runs `commit` while no other commit is running.
+    pub async fn commit<F, Fut, T>(&self, commit: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = T>,
+    {
+        let _lock = self.commit_lock.lock().await;
+        commit().await
+    }
+}
+
+/// Download all files specified in `uploaded_files`.
+///
+/// Up to [DownloadOptions::concurrency] files are downloaded at the same time.
+/// See [Performance][crate#performance] for details on download concurrency.
+///
+/// If [DownloadOptions::sequential_writes] is set, files are still fetched
+/// concurrently but are written to disk one at a time (see
+/// [SequentialWriteGate]).
+///
+/// If [DownloadOptions::keep_going] is set, a failed file doesn't stop the
+/// remaining files from downloading. Failed files are listed in the report
+/// written to [DownloadOptions::error_report], if any.
+///
+/// When `cancel` completes (e.g. on Ctrl-C), no more files are started and
+/// in-flight downloads are aborted, removing their partially written files.
+/// Files that finished downloading are kept.
+///
+/// # Errors
+///
+/// Returns an error if the url doesn't match a configured cloud storage provider.
+///
+/// Returns the first file's error, or with [DownloadOptions::keep_going] an
+/// error summarizing how many files failed.
+///
+/// Returns an error summarizing how many files finished if `cancel` completes
+/// before all files are downloaded.
+///
+/// Wraps [download_file] -- see its documentation for other possible errors.
+pub async fn download_files<C>(
+    storage_config: StorageConfig,
+    uploaded_files: Vec<UploadedFile>,
+    options: &DownloadOptions,
+    cancel: C,
+) -> Result<()>
+where
+    C: Future<Output = ()>,
+{
+    if uploaded_files.is_empty() {
+        Ok(())
+    } else {
+        let guard = MultiProgressGuard::new(options.progress_mode()).await;
+        let progress = guard.inner.clone();
+        let mut overall_progress = OverallProgress::new(
+            &progress,
+            uploaded_files.len(),
+            uploaded_files.iter().map(|f| f.filesize).sum(),
+        );
+        let write_gate = if options.sequential_writes {
+            Some(SequentialWriteGate::new(
+                SEQUENTIAL_WRITES_MAX_BUFFERED_BYTES,
+            ))
+        } else {
+            None
+        };
+
+        let mut futs = stream::iter(
+            uploaded_files
+                .iter()
+                .zip(iter::repeat_with(|| storage_config.clone()))
+                .map(|(uploaded_file, local_storage_config)| {
+                    let download = download_file(
+                        local_storage_config,
+                        uploaded_file,
+                        &progress,
+                        options,
+                        write_gate.as_ref(),
+                    );
+                    async move {
+                        let path = display_path(uploaded_file);
+                        let result = options
+                            .timings
+                            .time("download", Some(&path), download)
+                            .await;
+                        (uploaded_file, result)
+                    }
+                }),
+        )
+        .buffer_unordered(
+            options
+                .concurrency
+                .unwrap_or(MAX_FILES_DOWNLOADING_CONCURRENTLY)
+                .max(1),
+        );
+        futures::pin_mut!(cancel);
+        let mut failures = Vec::new();
+        let mut first_error = None;
+        let mut completed_files = 0;
+        let mut cancelled = false;
+        loop {
+            // Downloads that already finished are handled before cancelling
+            let (uploaded_file, res) = match future::select(futs.next(), cancel.as_mut()).await {
+                Either::Left((Some(next), _)) => next,
+                Either::Left((None, _)) => break,
+                Either::Right(_) => {
+                    cancelled = true;
+                    break;
+                }
+            };
+            match res {
+                Ok(()) => {
+                    completed_files += 1;
+                    overall_progress.file_done(uploaded_file.filesize);
+                }
+                Err(error) => {
+                    failures.push(FileFailure::new(display_path(uploaded_file), &error));
+                    if !options.keep_going {
+                        first_error = Some(error);
+                        break;
+                    }
+                }
+            }
+        }
+        // Aborts in-flight downloads, which removes their partial files
+        drop(futs);
+
+        let failed_files = failures.len();
+        if let Some(report_path) = &options.error_report {
+            ErrorReport {
+                operation: "download",
+                total_files: uploaded_files.len(),
+                failures,
+            }
+            .write(report_path)?;
+        }
+        match first_error {
+            Some(error) => Err(error),
+            None if cancelled => bail!(
+                "Download interrupted: {} of {} file(s) finished downloading ({} failed), partially downloaded files were removed",
+                completed_files,
+                uploaded_files.len(),
+                failed_files
+            ),
+            None if failed_files > 0 => bail!(
+                "{} of {} file(s) failed to download",
+                failed_files,
+                uploaded_files.len()
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Downloads a single file.
+///
+/// Folder structure is preserved when downloading, so downloading `dir/file`
+/// will create a folder named `dir` (if it doesn't already exist, relative to
+/// the output directory in `options`) and download `file` into that folder.
+///
+/// The version of the file recorded in the database is downloaded, even if
+/// the file was overwritten in cloud storage since.
+///
+/// # Errors
+///
+/// Returns an error if the url is malformed or if the destination file cannot
+/// be opened or written.
+///
+/// Unless verification is disabled in `options`, returns an error if an md5
+/// hash was recorded for the file at upload time and the downloaded file's md5
+/// hash doesn't match it.
+///
+/// If a `write_gate` is provided, the file is buffered in RAM and written to
+/// disk through the gate rather than streamed directly to disk. Files too
+/// large to buffer (see [SequentialWriteGate::fits]) are streamed to disk
+/// while holding the gate's lock instead, so other files wait to be written.
+///
+/// The file is written to a temporary `.part` file and only moved to its
+/// destination once it's complete and verified. If writing or verifying the
+/// file fails, or if the returned future is dropped before it completes, the
+/// partially written file is removed (see [PartialDownload]).
+///
+/// Wraps [storage::download_file] -- see its documentation for other possible
+/// errors.
+pub async fn download_file(
+    storage_config: StorageConfig,
+    uploaded_file: &UploadedFile,
+    progress: &Progress,
+    options: &DownloadOptions,
+    write_gate: Option<&SequentialWriteGate>,
+) -> Result<()> {
+    debug!("Downloading file: {}", uploaded_file.url);
+    let filepath = options.destination(uploaded_file)?;
+    if let Some(dir) = filepath.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    let reporter = progress.bar(
+        &filepath.to_string_lossy(),
+        uploaded_file.filesize,
+        get_default_progress_bar_style(),
+    );
+    let local_reporter = reporter.clone();
+    let wire_stats = options.transfer_stats.clone();
+    // Let progress follow along with # bytes read
+    let on_read = Box::new(move |bytes_read: u64, total_bytes_read: u64| {
+        local_reporter.set_position(total_bytes_read);
+        wire_stats.add_wire_bytes(bytes_read);
+    });
+
+    let decompression = options.decompression(uploaded_file)?;
+    let verify = !options.no_verify;
+    // Decompressed files are verified against the checksum of the file that
+    // was compressed, so the whole round trip is checked
+    let expected_checksum = match (verify, decompression) {
+        (false, _) => None,
+        (true, Some(_)) => uploaded_file.uncompressed_checksum(),
+        (true, None) => uploaded_file.expected_checksum(),
+    };
+    if verify && expected_checksum.is_none() {
+        debug!(
+            "No checksum recorded for {:?}, skipping verification",
+            filepath
+        );
+    }
+
+    let size = uploaded_file.uncompressed_size();
+    match write_gate {
+        Some(gate) if gate.fits(size) => {
+            let _reservation = gate.reserve(size).await?;
+            let async_data = storage::download_file(
+                storage_config,
+                &uploaded_file.url,
+                Some(&uploaded_file.version),
+            )
+            .await?;
+            let read_wrapper =
+                decompressed(ReadProgressStream::new(async_data, on_read), decompression);
+            // Fits in the budget, so it also fits in a usize
+            let mut buffer: Vec<u8> = Vec::with_capacity(size.try_into()?);
+            write_and_verify_stream(read_wrapper, &mut buffer, expected_checksum)
+                .await
+                .map_err(|e| e.context(format!("Download of {} failed", filepath.display())))?;
+            gate.commit(|| async {
+                debug!("Committing buffered download to {:?}", filepath);
+                let partial = PartialDownload::new(&filepath);
+                tokio::fs::write(partial.path(), buffer).await?;
+                partial.finish().await
+            })
+            .await?;
+        }
+        Some(gate) => {
+            gate.commit(|| async {
+                debug!(
+                    "{:?} is too large to buffer, streaming it to disk",
+                    filepath
+                );
+                let async_data = storage::download_file(
+                    storage_config,
+                    &uploaded_file.url,
+                    Some(&uploaded_file.version),
+                )
+                .await?;
+                let read_wrapper =
+                    decompressed(ReadProgressStream::new(async_data, on_read), decompression);
+                stream_to_destination(read_wrapper, &filepath, expected_checksum).await
+            })
+            .await?;
+        }
+        None => {
+            let async_data = storage::download_file(
+                storage_config,
+                &uploaded_file.url,
+                Some(&uploaded_file.version),
+            )
+            .await?;
+            let read_wrapper =
+                decompressed(ReadProgressStream::new(async_data, on_read), decompression);
+            stream_to_destination(read_wrapper, &filepath, expected_checksum).await?;
+        }
+    }
+    debug!("Downloaded file copied to destination: {:?}", filepath);
+    reporter.finish();
+    options
+        .transfer_stats
+        .add_file_bytes(uploaded_file.filesize);
+
+    Ok(())
+}
+
+/// Writes a downloaded file's data to a [PartialDownload] as it's read, moving
+/// it to `filepath` once it's complete and verified (see
+/// [write_and_verify_stream]).
+async fn stream_to_destination<S>(
+    data: S,
+    filepath: &Path,
+    expected_checksum: Option<(ChecksumAlgorithm, &str)>,
+) -> Result<()>
+where
+    S: Stream<Item = std::io::Result<Bytes>> + Unpin,
+{
+    let partial = PartialDownload::new(filepath);
+    let mut file = tokio::fs::File::create(partial.path()).await?;
+    write_and_verify_stream(data, &mut file, expected_checksum)
+        .await
+        .map_err(|e| e.context(format!("Download of {} failed", filepath.display())))?;
+    // The file must be closed before it can be renamed on Windows
+    drop(file);
+    partial.finish().await?;
+    Ok(())
+}
+
+/// Decompresses a downloaded file's data as it's read, if it's compressed with
+/// `format` (see [compression::decompress_stream]).
+fn decompressed<S>(
+    data: S,
+    format: Option<CompressionFormat>,
+) -> impl Stream<Item = std::io::Result<Bytes>> + Unpin
+where
+    S: Stream<Item = std::io::Result<Bytes>> + Unpin,
+{
+    match format {
+        Some(format) => Either::Left(Box::pin(compression::decompress_stream(data, format))),
+        None => Either::Right(data),
+    }
+}
+
+/// Extension appended to the name of a file while it's being downloaded.
+pub const PARTIAL_DOWNLOAD_EXTENSION: &str = "part";
+
+/// A file being downloaded into a temporary `.part` file next to its
+/// destination, so an incomplete file never appears at the destination (and
+/// an existing file there is only replaced once the download completes).
+///
+/// [PartialDownload::finish] renames the `.part` file to the destination once
+/// it's complete. If that never happens (e.g. the download fails or is
+/// aborted), the `.part` file is removed when dropped.
+pub struct PartialDownload {
+    /// Temporary file that's written to
+    path: PathBuf,
+    /// Where the file ends up once it's complete
+    destination: PathBuf,
+    /// Whether the temporary file was moved to the destination
+    finished: bool,
+}
+
+impl PartialDownload {
+    /// Starts a download to `destination`, whose data should be written to
+    /// [PartialDownload::path].
+    pub fn new(destination: &Path) -> Self {
+        let mut path = destination.as_os_str().to_owned();
+        path.push(".");
+        path.push(PARTIAL_DOWNLOAD_EXTENSION);
+        PartialDownload {
+            path: PathBuf::from(path),
+            destination: destination.to_path_buf(),
+            finished: false,
+        }
+    }
+
+    /// Temporary file to write the downloaded data to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Atomically moves the complete file to its destination, replacing any
+    /// existing file there.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be renamed, in which case the
+    /// temporary file is removed.
+    pub async fn finish(mut self) -> std::io::Result<()> {
+        tokio::fs::rename(&self.path, &self.destination).await?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for PartialDownload {
+    fn drop(&mut self) {
+        if !self.finished {
+            debug!("Removing partially downloaded file {:?}", self.path);
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                debug!("Unable to remove {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+/// Writes all bytes from the stream to `writer`, hashing them along the way.
+///
+/// If `expected_checksum` (an algorithm and base64-encoded checksum) is
+/// provided, the checksum is computed incrementally (so the stream is never
+/// buffered in RAM) and compared against the expected one.
+///
+/// # Errors
+///
+/// Returns an error if reading from the stream or writing to `writer` fails,
+/// or if the checksum doesn't match `expected_checksum`.
+pub async fn write_and_verify_stream<S, W>(
+    mut stream: S,
+    writer: &mut W,
+    expected_checksum: Option<(ChecksumAlgorithm, &str)>,
+) -> Result<()>
+where
+    S: Stream<Item = std::io::Result<Bytes>> + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut checksum_ctx =
+        expected_checksum.map(|(algorithm, _)| storage::ChecksumContext::new(algorithm));
+    while let Some(bytes) = stream.try_next().await? {
+        if let Some(ctx) = checksum_ctx.as_mut() {
+            ctx.update(&bytes);
+        }
+        writer.write_all(&bytes).await?;
+    }
+    writer.flush().await?;
+
+    if let (Some((algorithm, expected)), Some(ctx)) = (expected_checksum, checksum_ctx) {
+        let actual = ctx.finish();
+        if actual != expected {
+            return Err(ChecksumMismatch {
+                algorithm,
+                expected: expected.to_owned(),
+                actual,
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Archive formats that a dataset can be exported to (see [export_dataset]).
+#[derive(AsRefStr, EnumString, EnumVariantNames, Debug, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+    /// Uncompressed tarball
+    Tar,
+    /// Zip archive, with each file deflated
+    Zip,
+}
+
+/// Name of the manifest written at the root of an exported dataset archive.
+pub const EXPORT_MANIFEST_NAME: &str = "manifest.json";
+
+/// Describes an exported dataset: its id, system, creation date, and metadata,
+/// plus each file's path in the archive, size, version, and upload metadata
+/// (which includes its checksum).
+///
+/// Paths and sizes are of the files as `options` downloads them (see
+/// [DownloadOptions::destination]), so files that are decompressed are listed
+/// without the compression's extension and with their uncompressed size.
+///
+/// # Errors
+///
+/// Returns an error if a file's url is malformed -- see
+/// [UploadedFile::filepath_from_url] -- or if its compression isn't supported.
+pub fn export_manifest(
+    dataset: &Dataset,
+    uploaded_files: &[UploadedFile],
+    options: &DownloadOptions,
+) -> Result<serde_json::Value> {
+    let mut files = uploaded_files
+        .iter()
+        .map(|file| {
+            let filesize = match options.decompression(file)? {
+                Some(_) => file.uncompressed_size(),
+                None => file.filesize,
+            };
+            Ok((options.destination(file)?, filesize, file))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    files.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+    let files = files
+        .into_iter()
+        .map(|(filepath, filesize, file)| {
+            json!({
+                "path": filepath,
+                "file_id": file.file_id,
+                "created_date": file.created_date.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "filesize": filesize,
+                "version": file.version,
+                "metadata": file.metadata,
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(json!({
+        "dataset_id": dataset.dataset_id,
+        "system_id": dataset.system_id,
+        "created_date": dataset.created_date.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "metadata": dataset.metadata,
+        "files": files,
+    }))
+}
+
+/// Downloads a dataset's files and packages them, along with a manifest (see
+/// [export_manifest]), into a single archive at `output`.
+///
+/// The plex and object-space file are uploaded as regular dataset files, so
+/// they're included like any other file.
+///
+/// Files are downloaded (and verified, unless disabled in `options`) into a
+/// staging directory next to `output`, which is removed afterwards whether or
+/// not exporting succeeds. The output directory and template in `options` are
+/// ignored, so the archive mirrors the dataset's folder structure.
+///
+/// # Errors
+///
+/// Returns an error if `output` already exists, if a dataset file would be
+/// written to the archive's root as [EXPORT_MANIFEST_NAME] (checked before
+/// anything is downloaded), or if the staging directory, manifest, or archive
+/// can't be written.
+///
+/// Wraps [download_files] -- see its documentation for other possible errors.
+pub async fn export_dataset<C>(
+    storage_config: StorageConfig,
+    dataset: &Dataset,
+    uploaded_files: Vec<UploadedFile>,
+    output: &Path,
+    format: ExportFormat,
+    options: &DownloadOptions,
+    cancel: C,
+) -> Result<()>
+where
+    C: Future<Output = ()>,
+{
+    if output.exists() {
+        bail!(
+            "{} already exists, refusing to overwrite it",
+            output.display()
+        );
+    }
+    let output = if output.is_absolute() {
+        output.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(output)
+    };
+    let file_name = output
+        .file_name()
+        .ok_or_else(|| anyhow!("Export path {} has no file name", output.display()))?;
+    let staging_dir = output.with_file_name(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        Uuid::new_v4()
+    ));
+    tokio::fs::create_dir_all(&staging_dir)
+        .await
+        .with_context(|| format!("Unable to create {}", staging_dir.display()))?;
+
+    let result = async {
+        let in_archive = DownloadOptions {
+            output_dir: None,
+            output_template: None,
+            ..options.clone()
+        };
+        for file in &uploaded_files {
+            if in_archive.destination(file)? == Path::new(EXPORT_MANIFEST_NAME) {
+                bail!(
+                    "Dataset file {} would overwrite the export's {} -- rename or delete it before exporting",
+                    file.url,
+                    EXPORT_MANIFEST_NAME
+                );
+            }
+        }
+        let manifest = export_manifest(dataset, &uploaded_files, &in_archive)?;
+        let staged = DownloadOptions {
+            output_dir: Some(staging_dir.clone()),
+            ..in_archive
+        };
+        download_files(storage_config, uploaded_files, &staged, cancel).await?;
+        tokio::fs::write(
+            staging_dir.join(EXPORT_MANIFEST_NAME),
+            serde_json::to_string_pretty(&manifest)?,
+        )
+        .await?;
+        archive_directory(&staging_dir, &output, format).await
+    }
+    .await;
+
+    if let Err(e) = tokio::fs::remove_dir_all(&staging_dir).await {
+        debug!("Unable to remove {:?}: {}", staging_dir, e);
+    }
+    result
+}
+
+/// Packages the contents of `dir` into an archive at `output`, streaming each
+/// file into the archive on a blocking thread.
+///
+/// Entries are named by their path relative to `dir`, with `/` separators.
+async fn archive_directory(dir: &Path, output: &Path, format: ExportFormat) -> Result<()> {
+    let dir = dir.to_path_buf();
+    let output = output.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        write_archive(&dir, &output, format)
+            .with_context(|| format!("Unable to write archive {}", output.display()))
+    })
+    .await?
+}
+
+fn write_archive(dir: &Path, output: &Path, format: ExportFormat) -> Result<()> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let name = entry
+            .path()
+            .strip_prefix(dir)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push((entry.into_path(), name));
+    }
+
+    let archive = std::fs::File::create(output)?;
+    match format {
+        ExportFormat::Tar => {
+            let mut builder = tar::Builder::new(archive);
+            for (path, name) in files {
+                builder.append_path_with_name(&path, &name)?;
+            }
+            builder.into_inner()?.sync_all()?;
+        }
+        ExportFormat::Zip => {
+            let mut writer = zip::ZipWriter::new(archive);
+            for (path, name) in files {
+                let mut file = std::fs::File::open(&path)?;
+                let options = zip::write::FileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .large_file(file.metadata()?.len() >= u32::MAX as u64);
+                writer.start_file(name, options)?;
+                std::io::copy(&mut file, &mut writer)?;
+            }
+            writer.finish()?.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+/// Describes how much data a transfer used, e.g. `Uploaded 1.50 GB of file
+/// data (1.62 GB sent over the network)`.
+///
+/// `verb` and `direction` describe the transfer, e.g. "Uploaded" and "sent".
+pub fn transfer_summary(verb: &str, direction: &str, stats: &storage::TransferStats) -> String {
+    format!(
+        "{} {} of file data ({} {} over the network)",
+        verb,
+        Byte::from_bytes(u128::from(stats.file_bytes())).get_appropriate_unit(false),
+        Byte::from_bytes(u128::from(stats.wire_bytes())).get_appropriate_unit(false),
+        direction
+    )
+}
+
+/// How long one phase of a command took, as written by [Timings::write].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PhaseTiming {
+    /// Phase name, e.g. "transfer" or "register".
+    pub phase: &'static str,
+    /// File the phase worked on, if it was for a single file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// When the phase started, in seconds since the command started.
+    pub start_seconds: f64,
+    /// How long the phase took, in seconds (whether or not it succeeded).
+    pub seconds: f64,
+}
+
+/// Timings of the phases of a command, for benchmarking and tracking
+/// performance regressions (clones share the same timings).
+///
+/// Uploads time creating the dataset, each file's "transfer" (reading,
+/// hashing, and sending it to cloud storage) and "register" (recording it in
+/// the datasets database), and notifying the backend that the upload is
+/// complete. Downloads time listing files and each file's
+/// "download" (fetching, writing, and verifying it).
+#[derive(Clone, Debug)]
+pub struct Timings {
+    /// When the command started
+    start: Instant,
+    /// Phases timed so far, in the order they finished (only locked briefly,
+    /// so a blocking mutex is fine)
+    phases: Arc<std::sync::Mutex<Vec<PhaseTiming>>>,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Timings {
+            start: Instant::now(),
+            phases: Default::default(),
+        }
+    }
+}
+
+impl Timings {
+    /// Runs `fut`, recording how long it took as `phase` (of `file`, if
+    /// given).
+    pub async fn time<T>(
+        &self,
+        phase: &'static str,
+        file: Option<&str>,
+        fut: impl Future<Output = T>,
+    ) -> T {
+        let started = Instant::now();
+        let output = fut.await;
+        self.phases.lock().unwrap().push(PhaseTiming {
+            phase,
+            file: file.map(str::to_owned),
+            start_seconds: started.duration_since(self.start).as_secs_f64(),
+            seconds: started.elapsed().as_secs_f64(),
+        });
+        output
+    }
+
+    /// Phases timed so far, in the order they finished.
+    pub fn phases(&self) -> Vec<PhaseTiming> {
+        self.phases.lock().unwrap().clone()
+    }
+
+    /// Writes the timings of `command` (e.g. "upload") as JSON to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written.
+    pub fn write(&self, path: &Path, command: &str) -> Result<()> {
+        let report = json!({
+            "command": command,
+            "total_seconds": self.start.elapsed().as_secs_f64(),
+            "phases": self.phases(),
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("Unable to write timings to {}", path.display()))
+    }
+}
+
+/// Show current configuration.
+///
+/// Unless `show_secrets` is set, credentials are masked (see
+/// [CompleteAppConfig::redact_secrets]) so the output can be shared. Either
+/// way, the output is valid TOML.
+pub fn print_config(config: config::Config, show_secrets: bool) -> Result<()> {
+    let mut storage_config: CompleteAppConfig = config.try_into()?;
+    if show_secrets {
+        eprintln!("Warning: this config includes secrets, don't share it");
+    } else {
+        storage_config.redact_secrets();
+        eprintln!("Secrets are redacted, pass --show-secrets to show them");
+    }
+    println!("{}", toml::to_string(&storage_config)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use httpmock::{
+        Method::{GET, PATCH, POST, PUT},
+        MockRef, MockServer,
+    };
+    use indicatif::ProgressDrawTarget;
+
+    use super::*;
+    use crate::{
+        app_config::{DatabaseConfig, StorageProviderChoices},
+        core::api::{datasets::DatabaseApiConfig, storage::tests::s3_compatible_test_config},
+    };
+
+    /// Mocks the datasets database creating dataset `dataset_id`.
+    fn mock_create_dataset(server: &MockServer, dataset_id: Uuid) -> MockRef<'_> {
+        server.mock(|when, then| {
+            when.method(POST).path("/datasets");
+            then.status(201)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "dataset_id": dataset_id,
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "metadata": {},
+                }]));
+        })
+    }
+
+    /// Mocks the datasets database registering uploaded files of
+    /// `dataset_id`.
+    fn mock_register_files(server: &MockServer, dataset_id: Uuid) -> MockRef<'_> {
+        server.mock(|when, then| {
+            when.method(POST).path("/files");
+            then.status(201)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "file_id": "16fb2ac2-642a-4d7e-8233-b1835623b46b",
+                    "dataset_id": dataset_id,
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "url": format!("{}/datasets/file", server.base_url()),
+                    "filesize": 0,
+                    "version": "v1",
+                    "metadata": {},
+                }]));
+        })
+    }
+
+    /// Mocks the versioned `datasets` bucket (see [s3_compatible_test_config])
+    /// accepting an upload to `key` as version `v1`.
+    fn mock_versioned_put<'a>(server: &'a MockServer, key: &str) -> MockRef<'a> {
+        server.mock(|when, then| {
+            when.method(PUT).path(format!("/datasets/{}", key));
+            then.status(200).header("x-amz-version-id", "v1");
+        })
+    }
+
+    /// Like [mock_versioned_put], for every upload under `prefix`.
+    fn mock_versioned_puts_under<'a>(server: &'a MockServer, prefix: &str) -> MockRef<'a> {
+        server.mock(|when, then| {
+            when.method(PUT)
+                .path_contains(format!("/datasets/{}/", prefix));
+            then.status(200).header("x-amz-version-id", "v1");
+        })
+    }
+
+    #[tokio::test]
+    async fn test_upload_missing_file() {
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(
+                include_str!("../../fixtures/test_full_config.toml"),
+                config::FileFormat::Toml,
+            ))
+            .unwrap();
+
+        let db = config
+            .clone()
+            .try_into::<DatabaseConfig>()
+            .unwrap()
+            .database;
+        let db_config = DatabaseApiConfig::new(db.url.clone(), db.jwt).unwrap();
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
+        let path = "nonexistent-file".to_owned();
+        let prefix = "";
+        let progress = Progress::None;
+        let error = upload_file(
+            storage_config,
+            &db_config,
+            dataset_id,
+            path,
+            prefix,
+            &progress,
+            serde_json::Map::new(),
+            ChecksumAlgorithm::Md5,
+            &storage::HashingPool::new(1),
+            0,
+            None,
+            MULTIPART_FILESIZE_THRESHOLD,
+            PartSizeStrategy::Fixed,
+            true,
+            None,
+            &storage::TransferStats::default(),
+            &Timings::default(),
+        )
+        .await
+        .expect_err("Loading nonexistent file should fail");
+        assert!(
+            error.to_string().contains("No such file or directory"),
+            "{}",
+            error.to_string()
+        );
+    }
+
+    fn share_test_files() -> Vec<UploadedFile> {
+        let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        ["data/b.bag", "a.plex"]
+            .iter()
+            .map(|path| UploadedFile {
+                file_id: Uuid::new_v4(),
+                dataset_id,
+                created_date: Utc::now(),
+                url: Url::parse(&format!(
+                    "https://tangram-vision-datasets.s3.us-west-1.amazonaws.com/user/{}/{}",
+                    dataset_id, path
+                ))
+                .unwrap(),
+                filesize: 12,
+                version: "v1".to_owned(),
+                metadata: json!({}),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_share_info_presigned_urls() {
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(
+                include_str!("../../fixtures/test_full_config.toml"),
+                config::FileFormat::Toml,
+            ))
+            .unwrap();
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+
+        let lines = share_info(&storage_config, &share_test_files(), true)
+            .await
+            .unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("a.plex: https://"), "{}", lines[0]);
+        assert!(lines[1].starts_with("data/b.bag: https://"), "{}", lines[1]);
+        for line in lines {
+            assert!(
+                line.contains("/d11cc371-f33b-4dad-ac2e-3c4cca30a256/"),
+                "{}",
+                line
+            );
+            assert!(line.contains("versionId=v1"), "{}", line);
+            assert!(line.contains("X-Amz-Expires=604800"), "{}", line);
+            assert!(line.contains("X-Amz-Signature="), "{}", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_share_info_download_commands() {
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(
+                include_str!("../../fixtures/test_full_config.toml"),
+                config::FileFormat::Toml,
+            ))
+            .unwrap();
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+
+        let lines = share_info(&storage_config, &share_test_files(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "a.plex: bolster download d11cc371-f33b-4dad-ac2e-3c4cca30a256 a.plex",
+                "data/b.bag: bolster download d11cc371-f33b-4dad-ac2e-3c4cca30a256 data/b.bag",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_metadata() {
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_620_000_000);
+        let mut extra = serde_json::Map::new();
+        extra.insert("camera".to_owned(), json!("left"));
+        extra.insert("md5".to_owned(), json!("bogus"));
+
+        let metadata = file_metadata(
+            "data/camera-1/img.png",
+            Some(modified),
+            ChecksumAlgorithm::Md5,
+            "XrY7u+Ae7tCTyyK7j1rNww==".to_owned(),
+            extra,
+        );
+        assert_eq!(
+            metadata,
+            json!({
+                "camera": "left",
+                "path": "data/camera-1/img.png",
+                "mtime": "2021-05-03T00:00:00Z",
+                "checksum_algorithm": "md5",
+                "md5": "XrY7u+Ae7tCTyyK7j1rNww==",
+            })
+        );
+
+        let metadata = file_metadata(
+            "a.bag",
+            None,
+            ChecksumAlgorithm::Sha256,
+            "abc".to_owned(),
+            serde_json::Map::new(),
+        );
+        assert_eq!(
+            metadata,
+            json!({"path": "a.bag", "checksum_algorithm": "sha256", "sha256": "abc"})
+        );
+    }
+
+    #[test]
+    fn test_upload_options_created_message() {
+        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
+        let mut options = UploadOptions::default();
+        assert_eq!(
+            options.created_message(dataset_id).unwrap(),
+            "Created new dataset with UUID: 619e0899-ec94-4d87-812c-71736c09c4d6"
+        );
+
+        options.quiet = true;
+        assert_eq!(
+            options.created_message(dataset_id).unwrap(),
+            "619e0899-ec94-4d87-812c-71736c09c4d6"
+        );
+
+        options.created_message = Some("dataset={uuid}".to_owned());
+        assert_eq!(
+            options.created_message(dataset_id).unwrap(),
+            "dataset=619e0899-ec94-4d87-812c-71736c09c4d6"
+        );
+
+        options.created_message = Some("".to_owned());
+        assert_eq!(options.created_message(dataset_id), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_on_create_hook() {
+        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out_path = dir.join("hook-args");
+
+        let command = format!("printf '%s,' --lab-db > '{}'", out_path.display());
+        run_on_create_hook(&command, dataset_id).await.unwrap();
+        let hook_args = std::fs::read_to_string(&out_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(hook_args, "--lab-db,619e0899-ec94-4d87-812c-71736c09c4d6,");
+
+        let error = run_on_create_hook("exit 3", dataset_id)
+            .await
+            .expect_err("Failing hook should be an error");
+        assert!(
+            error.to_string().contains("On-create hook `exit 3` failed"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_metadata_from_pairs() {
+        let metadata = metadata_from_pairs(vec![
+            ("operator".to_owned(), "alice".to_owned()),
+            ("firmware".to_owned(), "12".to_owned()),
+            ("temperature".to_owned(), "21.5".to_owned()),
+            ("raining".to_owned(), "false".to_owned()),
+            ("version".to_owned(), "1.2.3".to_owned()),
+            ("operator".to_owned(), "bob".to_owned()),
+        ]);
+        assert_eq!(
+            serde_json::Value::from(metadata),
+            json!({
+                "operator": "bob",
+                "firmware": 12,
+                "temperature": 21.5,
+                "raining": false,
+                "version": "1.2.3",
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_metadata_conforming() {
+        let schema = read_metadata_schema(Path::new("fixtures/metadata_schema.json")).unwrap();
+        let metadata = metadata_from_pairs(vec![
+            ("operator".to_owned(), "alice".to_owned()),
+            ("site".to_owned(), "lab".to_owned()),
+            ("firmware".to_owned(), "12".to_owned()),
+            ("weather".to_owned(), "sunny".to_owned()),
+        ]);
+        validate_metadata(&schema, &metadata).unwrap();
+    }
+
+    #[test]
+    fn test_validate_metadata_non_conforming() {
+        let schema = read_metadata_schema(Path::new("fixtures/metadata_schema.json")).unwrap();
+        let metadata = metadata_from_pairs(vec![
+            ("operator".to_owned(), "alice".to_owned()),
+            ("firmware".to_owned(), "0".to_owned()),
+        ]);
+        let msg = validate_metadata(&schema, &metadata)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            msg.starts_with("Dataset metadata doesn't match the metadata schema:"),
+            "{}",
+            msg
+        );
+        assert!(
+            msg.contains("\n\tmetadata: \"site\" is a required property"),
+            "{}",
+            msg
+        );
+        assert!(
+            msg.contains("\n\tfirmware: 0 is less than the minimum of 1"),
+            "{}",
+            msg
+        );
+    }
+
+    #[test]
+    fn test_read_metadata_schema_rejects_invalid_schema() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("schema.json");
+        std::fs::write(&path, r#"{"type": "no-such-type"}"#).unwrap();
+        let msg = read_metadata_schema(&path).unwrap_err().to_string();
+        assert!(msg.contains("isn't a valid JSON Schema"), "{}", msg);
+
+        std::fs::write(&path, "not json").unwrap();
+        let msg = read_metadata_schema(&path).unwrap_err().to_string();
+        assert!(msg.contains("isn't valid JSON"), "{}", msg);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_processing_options() {
+        assert_eq!(
+            parse_processing_options(r#"{"pipeline": "fast"}"#).unwrap(),
+            json!({"pipeline": "fast"})
+        );
+
+        let error = parse_processing_options("{not json").unwrap_err();
+        assert!(
+            error.to_string().contains("must be valid JSON"),
+            "{}",
+            error.to_string()
+        );
+
+        let error = parse_processing_options(r#"["fast"]"#).unwrap_err();
+        assert!(
+            error.to_string().contains("must be a JSON object"),
+            "{}",
+            error.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_upload() {
+        let plan = plan_upload(
+            "user",
+            &["fixtures/example.plex", "fixtures/empty.bag"],
+            None,
+            MULTIPART_FILESIZE_THRESHOLD,
+            PartSizeStrategy::Fixed,
+        )
+        .await
+        .unwrap();
+        let plex_size = std::fs::metadata("fixtures/example.plex").unwrap().len() as usize;
+        assert_eq!(
+            plan,
+            vec![
+                PlannedUpload {
+                    path: "fixtures/example.plex".to_owned(),
+                    key: "user/<DATASET_UUID>/fixtures/example.plex".to_owned(),
+                    filesize: plex_size,
+                    chunk_size: None,
+                    part_size_strategy: PartSizeStrategy::Fixed,
+                    num_parts: 1,
+                    compression: None,
+                },
+                PlannedUpload {
+                    path: "fixtures/empty.bag".to_owned(),
+                    key: "user/<DATASET_UUID>/fixtures/empty.bag".to_owned(),
+                    filesize: 0,
+                    chunk_size: None,
+                    part_size_strategy: PartSizeStrategy::Fixed,
+                    num_parts: 1,
+                    compression: None,
+                },
+            ]
+        );
+
+        let error = plan_upload(
+            "user",
+            &["nonexistent-file"],
+            None,
+            MULTIPART_FILESIZE_THRESHOLD,
+            PartSizeStrategy::Fixed,
+        )
+        .await
+        .expect_err("Planning nonexistent file should fail");
+        assert!(
+            error
+                .to_string()
+                .contains("Unable to read nonexistent-file"),
+            "{}",
+            error.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_without_plex_or_object_space() {
+        // The mock server is both the database and S3-compatible storage
+        let server = MockServer::start();
+        let prefix = "807cffe5-df6d-434a-86a4-d007cd746bd3";
+        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
+        let create = server.mock(|when, then| {
+            when.method(POST).path("/datasets").json_body(json!({
+                "system_id": "robot-01",
+                "metadata": {"operator": "sam", "uncalibrated": true},
+            }));
+            then.status(201)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "dataset_id": dataset_id,
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "metadata": {"operator": "sam", "uncalibrated": true},
+                }]));
+        });
+        let put = mock_versioned_put(
+            &server,
+            &format!("{}/{}/fixtures/empty.bag", prefix, dataset_id),
+        );
+        mock_register_files(&server, dataset_id);
+        // No plex or object-space file ids are sent for processing
+        let notify = server.mock(|when, then| {
+            when.method(POST)
+                .path("/rpc/dataset_upload_complete")
+                .json_body(json!({ "dataset_id": dataset_id }));
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{"status": "ok"}]));
+        });
+
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let db_config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TOKEN".to_owned())
+                .unwrap();
+        let options = UploadOptions {
+            quiet: true,
+            created_message: Some(String::new()),
+            metadata: metadata_from_pairs(vec![("operator".to_owned(), "sam".to_owned())]),
+            ..Default::default()
+        };
+
+        let created_id = create_and_upload_dataset(
+            storage_config,
+            &db_config,
+            "robot-01".to_owned(),
+            prefix,
+            None,
+            None,
+            vec!["fixtures/empty.bag"],
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(created_id, Some(dataset_id));
+        create.assert();
+        put.assert();
+        notify.assert();
+    }
+
+    #[tokio::test]
+    async fn test_upload_keep_going_reports_failures_without_notifying() {
+        // The mock server is both the database and S3-compatible storage
+        let server = MockServer::start();
+        let prefix = "807cffe5-df6d-434a-86a4-d007cd746bd3";
+        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
+        mock_create_dataset(&server, dataset_id);
+        let put = mock_versioned_put(
+            &server,
+            &format!("{}/{}/fixtures/empty.bag", prefix, dataset_id),
+        );
+        mock_register_files(&server, dataset_id);
+        let notify = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{"status": "ok"}]));
+        });
+
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let db_config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TOKEN".to_owned())
+                .unwrap();
+        let tmp_dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let report_path = tmp_dir.join("report.json");
+        let options = UploadOptions {
+            quiet: true,
+            created_message: Some(String::new()),
+            keep_going: true,
+            error_report: Some(report_path.clone()),
+            ..Default::default()
+        };
+
+        // The missing file fails first, but the other file is still uploaded
+        let error = create_and_upload_dataset(
+            storage_config,
+            &db_config,
+            "robot-01".to_owned(),
+            prefix,
+            None,
+            None,
+            vec!["fixtures/nonexistent.bag", "fixtures/empty.bag"],
+            &options,
+        )
+        .await
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains(&format!("Upload to dataset {} is incomplete", dataset_id)));
+        assert!(error
+            .to_string()
+            .contains(&format!("--resume {}", dataset_id)));
+        put.assert();
+        // An incomplete dataset isn't processed
+        assert_eq!(notify.hits(), 0);
+
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(report["total_files"], 2);
+        let failures = report["failures"].as_array().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0]["path"], "fixtures/nonexistent.bag");
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_upload_failure_summary() {
+        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
+        let failures = vec![FileFailure::new("data/a.bag", &anyhow!("Connection reset"))];
+
+        let summary = upload_failure_summary(dataset_id, 3, 2, &failures);
+        assert_eq!(
+            summary,
+            format!(
+                "Uploaded 2 of 3 file(s) to dataset {}, 1 failed:\n\tdata/a.bag: Connection reset",
+                dataset_id
+            )
+        );
+
+        // Files abandoned after the first failure are counted
+        let summary = upload_failure_summary(dataset_id, 5, 1, &failures);
+        assert!(summary.ends_with(
+            "\n3 file(s) weren't uploaded after the first failure (pass --keep-going \
+             to upload them anyway)"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_upload_timings_cover_each_file_and_phase() {
+        // The mock server is both the database and S3-compatible storage
+        let server = MockServer::start();
+        let prefix = "807cffe5-df6d-434a-86a4-d007cd746bd3";
+        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
+        let paths = [
+            "fixtures/example.plex",
+            "fixtures/checkerboard_detector.toml",
+            "fixtures/empty.bag",
+        ];
+        mock_create_dataset(&server, dataset_id);
+        mock_versioned_puts_under(&server, prefix);
+        mock_register_files(&server, dataset_id);
+        let notify = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{"status": "ok"}]));
+        });
+
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let db_config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TOKEN".to_owned())
+                .unwrap();
+        let options = UploadOptions {
+            quiet: true,
+            created_message: Some(String::new()),
+            ..Default::default()
+        };
+
+        create_and_upload_dataset(
+            storage_config,
+            &db_config,
+            "robot-01".to_owned(),
+            prefix,
+            Some(paths[0]),
+            Some(paths[1]),
+            vec![paths[2]],
+            &options,
+        )
+        .await
+        .unwrap();
+        notify.assert();
+
+        let tmp_dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let timings_path = tmp_dir.join("timings.json");
+        options.timings.write(&timings_path, "upload").unwrap();
+        let timings: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&timings_path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+
+        assert_eq!(timings["command"], "upload");
+        let total_seconds = timings["total_seconds"].as_f64().unwrap();
+        let phases = timings["phases"].as_array().unwrap();
+        let has_phase = |phase: &str, file: Option<&str>| {
+            phases.iter().any(|timing| {
+                timing["phase"] == phase
+                    && timing.get("file").and_then(|f| f.as_str()) == file
+                    && timing["seconds"].as_f64().unwrap() <= total_seconds
+            })
+        };
+        assert!(has_phase("create_dataset", None));
+        for path in &paths {
+            for phase in &["transfer", "register"] {
+                assert!(
+                    has_phase(phase, Some(path)),
+                    "No {} timing for {}",
+                    phase,
+                    path
+                );
+            }
+        }
+        assert!(has_phase("notify", None));
+        // One entry per file phase, plus creating and notifying
+        assert_eq!(phases.len(), 2 * paths.len() + 2);
+    }
+
+    #[test]
+    fn test_validate_stdin_name() {
+        validate_stdin_name("capture.bag").unwrap();
+        validate_stdin_name("raw/2021-05-03/capture.bag").unwrap();
+        for name in &[
+            "",
+            "/capture.bag",
+            "raw/",
+            "raw//capture.bag",
+            "./capture.bag",
+            "../capture.bag",
+        ] {
+            let e = validate_stdin_name(name).unwrap_err().to_string();
+            assert!(e.contains("must be a relative path"), "{}: {}", name, e);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checksum_files() {
+        let files = vec![
+            ("fixtures/example.plex".to_owned(), ChecksumAlgorithm::Md5),
+            (
+                "fixtures/checkerboard_detector.toml".to_owned(),
+                ChecksumAlgorithm::Sha256,
+            ),
+            ("fixtures/empty.bag".to_owned(), ChecksumAlgorithm::Md5),
+        ];
+        let mut expected = Vec::new();
+        for (path, algorithm) in &files {
+            expected.push(storage::checksum_file(path, *algorithm).await.unwrap());
+        }
+        assert_eq!(checksum_files(files.clone(), 2).await.unwrap(), expected);
+
+        let files = vec![(
+            "fixtures/i-do-not-exist.bag".to_owned(),
+            ChecksumAlgorithm::Md5,
+        )];
+        checksum_files(files, 2).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_checksum_files_runs_jobs_concurrently() {
+        let in_flight = std::sync::atomic::AtomicUsize::new(0);
+        let most_in_flight = std::sync::atomic::AtomicUsize::new(0);
+        let files: Vec<_> = (0..7)
+            .map(|i| (format!("file{}", i), ChecksumAlgorithm::Md5))
+            .collect();
+        let checksums = checksum_files_with(files, 3, |path, _| {
+            let in_flight = &in_flight;
+            let most_in_flight = &most_in_flight;
+            async move {
+                let running = in_flight.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                most_in_flight.fetch_max(running, std::sync::atomic::Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                in_flight.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                Ok(format!("checksum of {}", path))
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(most_in_flight.into_inner(), 3);
+        // Checksums come back in the order the files were given
+        let expected: Vec<_> = (0..7).map(|i| format!("checksum of file{}", i)).collect();
+        assert_eq!(checksums, expected);
+    }
+
+    #[tokio::test]
+    async fn test_resumed_upload_skips_uploaded_files() {
+        // The mock server is both the database and S3-compatible storage
+        let server = MockServer::start();
+        let prefix = "807cffe5-df6d-434a-86a4-d007cd746bd3";
+        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
+        let plex_file_id = "0ae0d5a1-4ed6-4f39-8c85-c2a3e4fb1f8b";
+        let plex_path = "fixtures/example.plex";
+        let toml_path = "fixtures/checkerboard_detector.toml";
+        let data_path = "fixtures/empty.bag";
+        let file_url = |path: &str| {
+            format!(
+                "{}/datasets/{}/{}/{}",
+                server.base_url(),
+                prefix,
+                dataset_id,
+                path
+            )
+        };
+        let plex_checksum = storage::checksum_file(plex_path, ChecksumAlgorithm::Md5)
+            .await
+            .unwrap();
+        let create = server.mock(|when, then| {
+            when.method(POST).path("/datasets");
+            then.status(201);
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/files")
+                .query_param("dataset_id", &format!("eq.{}", dataset_id));
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([
+                    {
+                        "file_id": plex_file_id,
+                        "dataset_id": dataset_id,
+                        "created_date": "2021-02-03T21:21:57.713584+00:00",
+                        "url": file_url(plex_path),
+                        "filesize": std::fs::metadata(plex_path).unwrap().len(),
+                        "version": "v1",
+                        "metadata": {"checksum_algorithm": "md5", "md5": plex_checksum},
+                    },
+                    {
+                        // Interrupted upload of a different version of the toml
+                        "file_id": "4f5e0d3c-4a7c-4f4e-9f5e-2b2d1c7a8e90",
+                        "dataset_id": dataset_id,
+                        "created_date": "2021-02-03T21:21:57.713584+00:00",
+                        "url": file_url(toml_path),
+                        "filesize": 1,
+                        "version": "v1",
+                        "metadata": {},
+                    },
+                ]));
+        });
+        let plex_put =
+            mock_versioned_put(&server, &format!("{}/{}/{}", prefix, dataset_id, plex_path));
+        let other_puts = mock_versioned_puts_under(&server, prefix);
+        mock_register_files(&server, dataset_id);
+        let notify = server.mock(|when, then| {
+            when.method(POST)
+                .path("/rpc/dataset_upload_complete")
+                .body_contains(plex_file_id);
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{"status": "ok"}]));
+        });
+
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let db_config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TOKEN".to_owned())
+                .unwrap();
+        let mut options = UploadOptions {
+            quiet: true,
+            resume: Some(dataset_id),
+            ..Default::default()
+        };
+
+        let resumed_id = create_and_upload_dataset(
+            storage_config.clone(),
+            &db_config,
+            "robot-01".to_owned(),
+            prefix,
+            Some(plex_path),
+            Some(toml_path),
+            vec![data_path],
+            &options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resumed_id, Some(dataset_id));
+        create.assert_hits(0);
+        plex_put.assert_hits(0);
+        // Only the data file is uploaded, since the toml differs from the
+        // dataset's toml
+        other_puts.assert_hits(1);
+        notify.assert();
+
+        // With force, the differing toml is uploaded too
+        options.force = true;
+        create_and_upload_dataset(
+            storage_config,
+            &db_config,
+            "robot-01".to_owned(),
+            prefix,
+            Some(plex_path),
+            Some(toml_path),
+            vec![data_path],
+            &options,
+        )
+        .await
+        .unwrap();
+        plex_put.assert_hits(0);
+        other_puts.assert_hits(3);
+        notify.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_versioning_warning() {
+        let server = MockServer::start();
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let versioning = |status: &'static str| {
+            server.mock(move |when, then| {
+                when.method(GET).path("/datasets").query_param_exists("versioning");
+                then.status(200).body(format!(
+                    "<VersioningConfiguration xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">{}</VersioningConfiguration>",
+                    status
+                ));
+            })
+        };
+
+        // Versioning was never enabled
+        let mut mock = versioning("");
+        let warning = versioning_warning(&storage_config)
+            .await
+            .expect("Unversioned bucket should produce a warning");
+        assert!(
+            warning.starts_with("Warning: object versioning isn't enabled on bucket datasets."),
+            "{}",
+            warning
+        );
+        mock.assert();
+        mock.delete();
+
+        let mut mock = versioning("<Status>Suspended</Status>");
+        assert!(versioning_warning(&storage_config).await.is_some());
+        mock.delete();
+
+        let mut mock = versioning("<Status>Enabled</Status>");
+        assert_eq!(versioning_warning(&storage_config).await, None);
+        mock.delete();
+
+        // Not being able to check doesn't block uploads
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/datasets")
+                .query_param_exists("versioning");
+            then.status(403);
+        });
+        assert_eq!(versioning_warning(&storage_config).await, None);
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_overlong_key_is_rejected() {
+        let prefix = Uuid::new_v4().to_string();
+        let dataset_id = Uuid::new_v4();
+        let fits = "d/".repeat(400) + "file.bag";
+        let key = object_key(&prefix, dataset_id, &fits);
+        assert!(key.len() <= storage::MAX_KEY_LENGTH);
+        check_key_length(&key, &fits).unwrap();
+
+        let too_long = "d/".repeat(480) + "file.bag";
+        let key = object_key(&prefix, dataset_id, &too_long);
+        let error = check_key_length(&key, &too_long)
+            .expect_err("Key over the limit should be rejected")
+            .to_string();
+        assert!(
+            error.starts_with(&format!("Unable to upload {}: its cloud storage key would be 1042 bytes long, over the 1024-byte limit", too_long)),
+            "{}",
+            error
+        );
+
+        // Dry runs report the same problem, before touching the file
+        let error = plan_upload(
+            &prefix,
+            &[&too_long],
+            None,
+            MULTIPART_FILESIZE_THRESHOLD,
+            PartSizeStrategy::Fixed,
+        )
+        .await
+        .expect_err("Planning a key over the limit should fail")
+        .to_string();
+        assert!(error.contains("1042 bytes long"), "{}", error);
+    }
+
+    #[test]
+    fn test_uses_multipart_upload() {
+        let threshold = UploadOptions::default().multipart_threshold();
+        assert_eq!(threshold, MULTIPART_FILESIZE_THRESHOLD);
+        assert!(!uses_multipart_upload(0, threshold));
+        assert!(!uses_multipart_upload(threshold - 1, threshold));
+        assert!(uses_multipart_upload(threshold, threshold));
+        // A lower configured threshold uploads smaller files in chunks
+        let threshold = UploadOptions {
+            multipart_threshold: Some(storage::MIN_PART_SIZE),
+            ..Default::default()
+        }
+        .multipart_threshold();
+        assert!(uses_multipart_upload(storage::MIN_PART_SIZE, threshold));
+    }
+
+    #[tokio::test]
+    async fn test_plan_upload_large_object_space_file_is_multipart() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Sparse file, so the test doesn't need to write lots of data
+        let object_space_path = dir.join("object_space.toml");
+        let object_space_size = MULTIPART_FILESIZE_THRESHOLD + 1;
+        std::fs::File::create(&object_space_path)
+            .unwrap()
+            .set_len(object_space_size as u64)
+            .unwrap();
+        let object_space_path = object_space_path.to_str().unwrap();
+
+        // Same order as the plex, object-space, and data files are uploaded
+        let plan = plan_upload(
+            "user",
+            &[
+                "fixtures/example.plex",
+                object_space_path,
+                "fixtures/empty.bag",
+            ],
+            None,
+            MULTIPART_FILESIZE_THRESHOLD,
+            PartSizeStrategy::Fixed,
+        )
+        .await
+        .unwrap();
+        // A requested part count only changes how multipart files are split
+        let plan_with_num_parts = plan_upload(
+            "user",
+            &["fixtures/example.plex", object_space_path],
+            Some(8),
+            MULTIPART_FILESIZE_THRESHOLD,
+            PartSizeStrategy::Fixed,
+        )
+        .await
+        .unwrap();
+        let error = plan_upload(
+            "user",
+            &[object_space_path],
+            Some(20),
+            MULTIPART_FILESIZE_THRESHOLD,
+            PartSizeStrategy::Fixed,
+        )
+        .await
+        .expect_err("Parts under the minimum part size should be rejected");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(plan[0].chunk_size, None);
+        assert_eq!(
+            plan[1],
+            PlannedUpload {
+                path: object_space_path.to_owned(),
+                key: format!("user/<DATASET_UUID>/{}", object_space_path),
+                filesize: object_space_size,
+                chunk_size: Some(storage::DEFAULT_CHUNK_SIZE),
+                part_size_strategy: PartSizeStrategy::Fixed,
+                num_parts: 5,
+                compression: None,
+            }
+        );
+        assert_eq!(plan[2].chunk_size, None);
+
+        assert_eq!(plan_with_num_parts[0].chunk_size, None);
+        assert_eq!(
+            plan_with_num_parts[1].chunk_size,
+            Some(object_space_size / 8 + 1)
+        );
+        assert_eq!(plan_with_num_parts[1].num_parts, 8);
+        assert!(
+            format!("{:#}", error).contains("parts must be between"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_overall_progress_counts_completed_files() {
+        let bar = ProgressBar::with_draw_target(30, ProgressDrawTarget::hidden());
+        let mut overall = OverallProgress::with_reporter(Arc::new(bar.clone()), 3);
+        assert_eq!(overall.files_message(), "0/3 files");
+        overall.file_done(10);
+        overall.file_done(5);
+        assert_eq!(bar.position(), 15);
+        assert_eq!(overall.files_message(), "2/3 files");
+        assert!(!bar.is_finished());
+        overall.file_done(15);
+        assert_eq!(bar.position(), 30);
+        assert!(bar.is_finished());
+
+        // Dropping before every file finished leaves the bar where it was
+        let bar = ProgressBar::with_draw_target(20, ProgressDrawTarget::hidden());
+        let mut overall = OverallProgress::with_reporter(Arc::new(bar.clone()), 2);
+        overall.file_done(10);
+        drop(overall);
+        assert!(bar.is_finished());
+        assert_eq!(bar.position(), 10);
+    }
+
+    #[test]
+    fn test_upload_options_compression_for() {
+        let options = UploadOptions::default();
+        assert_eq!(options.compression_for(Path::new("logs/run.csv")), None);
+
+        let options = UploadOptions {
+            compression: Some(CompressionFormat::Gzip),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.compression_for(Path::new("logs/run.csv")),
+            Some(CompressionFormat::Gzip)
+        );
+        // Rosbags are usually compressed already
+        assert_eq!(options.compression_for(Path::new("data/a.bag")), None);
+
+        let options = UploadOptions {
+            compress_all: true,
+            ..options
+        };
+        assert_eq!(
+            options.compression_for(Path::new("data/a.bag")),
+            Some(CompressionFormat::Gzip)
+        );
+    }
+
+    #[test]
+    fn test_download_destination_with_output_dir() {
+        let url_str = "https://tangram-vision-datasets.s3.us-west-1.amazonaws.com/d11cc371-f33b-4dad-ac2e-3c4cca30a256/fixtures/test.dat";
+        let uploaded_file = UploadedFile {
+            file_id: Uuid::parse_str("c11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            dataset_id: Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            created_date: Utc::now(),
+            url: Url::parse(url_str).unwrap(),
+            filesize: 12,
+            version: "blah".to_owned(),
+            metadata: json!({}),
+        };
+
+        let options = DownloadOptions::default();
+        assert_eq!(
+            options.destination(&uploaded_file).unwrap(),
+            PathBuf::from("fixtures/test.dat")
+        );
+
+        let options = DownloadOptions {
+            output_dir: Some(PathBuf::from("out")),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.destination(&uploaded_file).unwrap(),
+            PathBuf::from("out/fixtures/test.dat")
+        );
+
+        // A key with no file name has no destination
+        let uploaded_file = UploadedFile {
+            url: Url::parse(
+                "https://tangram-vision-datasets.s3.us-west-1.amazonaws.com/d11cc371-f33b-4dad-ac2e-3c4cca30a256/",
+            )
+            .unwrap(),
+            ..uploaded_file
+        };
+        assert!(options.destination(&uploaded_file).is_err());
+    }
+
+    #[test]
+    fn test_render_output_template() {
+        let uploaded_file = |path: &str| UploadedFile {
+            file_id: Uuid::parse_str("c11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            dataset_id: Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            created_date: DateTime::parse_from_rfc3339("2021-05-03T21:21:57Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            url: Url::parse(&format!(
+                "https://bucket.example.com/user/d11cc371-f33b-4dad-ac2e-3c4cca30a256/{}",
+                path
+            ))
+            .unwrap(),
+            filesize: 12,
+            version: "blah".to_owned(),
+            metadata: json!({}),
+        };
+        let file = uploaded_file("fixtures/sub/test.dat");
+
+        let render = |template| render_output_template(template, &file).unwrap();
+        assert_eq!(
+            render("{dataset_id}/{filename}"),
+            PathBuf::from("d11cc371-f33b-4dad-ac2e-3c4cca30a256/test.dat")
+        );
+        assert_eq!(
+            render("{created_date}/{file_id}-{filename}"),
+            PathBuf::from("2021-05-03/c11cc371-f33b-4dad-ac2e-3c4cca30a256-test.dat")
+        );
+        assert_eq!(
+            render("by-date/{created_date}/{path}"),
+            PathBuf::from("by-date/2021-05-03/fixtures/sub/test.dat")
+        );
+        // Leading, doubled, and "." separators don't escape the output dir
+        assert_eq!(
+            render("/./flat//{filename}"),
+            PathBuf::from("flat/test.dat")
+        );
+        assert_eq!(render("a\\{filename}"), PathBuf::from("a/test.dat"));
+
+        let error = |template| {
+            render_output_template(template, &file)
+                .unwrap_err()
+                .to_string()
+        };
+        let e = error("../{filename}");
+        assert!(
+            e.contains("outside the output directory: ../test.dat"),
+            "{}",
+            e
+        );
+        let e = error("{name}");
+        assert!(e.contains("unknown placeholder {name}"), "{}", e);
+        let e = error("{filename");
+        assert!(e.contains("unclosed {"), "{}", e);
+        let e = error("/");
+        assert!(e.contains("empty path for fixtures/sub/test.dat"), "{}", e);
+
+        let options = DownloadOptions {
+            output_dir: Some(PathBuf::from("out")),
+            output_template: Some("{filename}".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.destination(&file).unwrap(),
+            PathBuf::from("out/test.dat")
+        );
+        // Flattening files with the same name would overwrite one with the
+        // other
+        let files = vec![file, uploaded_file("other/test.dat")];
+        let e = check_unique_destinations(&files, &options)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            e,
+            "Files fixtures/sub/test.dat and other/test.dat would both be downloaded to out/test.dat"
+        );
+        let options = DownloadOptions {
+            output_template: Some("{path}".to_owned()),
+            ..Default::default()
+        };
+        check_unique_destinations(&files, &options).unwrap();
+    }
+
+    #[test]
+    fn test_plan_download_flags_overwrites() {
+        let uploaded_file = |name: &str, filesize| {
+            UploadedFile {
+            file_id: Uuid::parse_str("c11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            dataset_id: Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            created_date: Utc::now(),
+            url: Url::parse(&format!(
+                "https://tangram-vision-datasets.s3.us-west-1.amazonaws.com/d11cc371-f33b-4dad-ac2e-3c4cca30a256/{}",
+                name
+            ))
+            .unwrap(),
+            filesize,
+            version: "blah".to_owned(),
+            metadata: json!({}),
+        }
+        };
+        let tmp_dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(tmp_dir.join("fixtures")).unwrap();
+        std::fs::write(tmp_dir.join("fixtures/old.dat"), b"old").unwrap();
+        let options = DownloadOptions {
+            output_dir: Some(tmp_dir.clone()),
+            ..Default::default()
+        };
+
+        let plan = plan_download(
+            &[
+                uploaded_file("fixtures/old.dat", 5),
+                uploaded_file("fixtures/new.dat", 7),
+            ],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            plan,
+            vec![
+                PlannedDownload {
+                    path: tmp_dir.join("fixtures/old.dat"),
+                    filesize: 5,
+                    overwrites: true,
+                },
+                PlannedDownload {
+                    path: tmp_dir.join("fixtures/new.dat"),
+                    filesize: 7,
+                    overwrites: false,
+                },
+            ]
+        );
+        // A directory that doesn't exist yet reports its parent's free space
+        assert_eq!(
+            available_download_space(&tmp_dir.join("not/created")).unwrap(),
+            fs2::available_space(&tmp_dir).unwrap()
+        );
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_download_files_error_report() {
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(
+                include_str!("../../fixtures/test_full_config.toml"),
+                config::FileFormat::Toml,
+            ))
+            .unwrap();
+        let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
+
+        // Downloading into a "directory" that's actually a file fails
+        let tmp_dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+        let not_a_dir = tmp_dir.join("not-a-dir");
+        std::fs::write(&not_a_dir, "bolster test").unwrap();
+        let report_path = tmp_dir.join("report.json");
+        let options = DownloadOptions {
+            output_dir: Some(not_a_dir),
+            quiet: true,
+            keep_going: true,
+            error_report: Some(report_path.clone()),
+            ..Default::default()
+        };
+        let mut uploaded_files = share_test_files();
+        uploaded_files.truncate(1);
+
+        let error = download_files(storage_config, uploaded_files, &options, future::pending())
+            .await
+            .expect_err("Downloading into a file should fail");
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        std::fs::remove_dir_all(&tmp_dir).unwrap();
+
+        assert_eq!(error.to_string(), "1 of 1 file(s) failed to download");
+        assert_eq!(report["operation"], "download");
+        assert_eq!(report["total_files"], 1);
+        let failures = report["failures"].as_array().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0]["path"], "data/b.bag");
+        assert_eq!(failures[0]["category"], "io");
+        assert!(
+            failures[0]["error"]
+                .as_str()
+                .unwrap()
+                .contains("Not a directory"),
+            "{}",
+            failures[0]["error"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partial_download_removed_unless_finished() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let partial_path = dir.join("partial.bag");
+        let complete_path = dir.join("complete.bag");
+        // An existing file is untouched by an incomplete download
+        std::fs::write(&partial_path, "existing").unwrap();
+
+        let partial = PartialDownload::new(&partial_path);
+        assert_eq!(partial.path(), dir.join("partial.bag.part"));
+        std::fs::write(partial.path(), "bolster").unwrap();
+        drop(partial);
+        let complete = PartialDownload::new(&complete_path);
+        std::fs::write(complete.path(), "bolster test").unwrap();
+        complete.finish().await.unwrap();
+
+        let mut remaining = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        let partial_contents = std::fs::read_to_string(&partial_path).unwrap();
+        let complete_contents = std::fs::read_to_string(&complete_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(remaining, vec!["complete.bag", "partial.bag"]);
+        assert_eq!(partial_contents, "existing");
+        assert_eq!(complete_contents, "bolster test");
+    }
+
+    #[tokio::test]
+    async fn test_download_files_cancelled() {
+        // Mock S3-compatible storage where one file downloads immediately and
+        // the other takes too long
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        let fast = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/datasets/{}/fast.bag", dataset_id));
+            then.status(200).body("bolster test");
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/datasets/{}/slow.bag", dataset_id));
+            then.status(200)
+                .body("bolster test")
+                .delay(Duration::from_secs(30));
+        });
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let uploaded_files: Vec<UploadedFile> = ["fast.bag", "slow.bag"]
+            .iter()
+            .map(|name| UploadedFile {
+                file_id: Uuid::new_v4(),
+                dataset_id,
+                created_date: Utc::now(),
+                url: Url::parse(&format!(
+                    "{}/datasets/{}/{}",
+                    server.base_url(),
+                    dataset_id,
+                    name
+                ))
+                .unwrap(),
+                filesize: 12,
+                version: "v1".to_owned(),
+                metadata: json!({}),
+            })
+            .collect();
+
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = DownloadOptions {
+            output_dir: Some(dir.clone()),
+            quiet: true,
+            ..Default::default()
+        };
+        // Interrupt once the fast file has finished downloading
+        let fast_path = dir.join("fast.bag");
+        let interrupt = async {
+            while std::fs::metadata(&fast_path).map_or(0, |m| m.len()) < 12 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        };
+
+        let error = tokio::time::timeout(
+            Duration::from_secs(10),
+            download_files(storage_config, uploaded_files, &options, interrupt),
+        )
+        .await
+        .expect("Interrupted download should stop promptly")
+        .expect_err("Interrupted download should error");
+        let fast_contents = std::fs::read_to_string(&fast_path).unwrap();
+        let slow_exists = dir.join("slow.bag").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(
+            error
+                .to_string()
+                .starts_with("Download interrupted: 1 of 2 file(s) finished downloading"),
+            "{}",
+            error
+        );
+        assert_eq!(fast_contents, "bolster test");
+        assert!(!slow_exists);
+        fast.assert();
+    }
+
+    #[tokio::test]
+    async fn test_download_files_decompresses() {
+        let contents = "timestamp,x,y\n".repeat(1000);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, contents.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/datasets/{}/logs/run.csv.gz", dataset_id));
+            then.status(200).body(&compressed);
+        });
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let uploaded_file = UploadedFile {
+            file_id: Uuid::new_v4(),
+            dataset_id,
+            created_date: Utc::now(),
+            url: Url::parse(&format!(
+                "{}/datasets/{}/logs/run.csv.gz",
+                server.base_url(),
+                dataset_id
+            ))
+            .unwrap(),
+            filesize: compressed.len() as u64,
+            version: "v1".to_owned(),
+            metadata: json!({
+                "path": "logs/run.csv",
+                "checksum_algorithm": "md5",
+                "md5": base64::encode(*md5::compute(&compressed)),
+                "compression": "gzip",
+                "uncompressed_size": contents.len(),
+                "uncompressed_md5": base64::encode(*md5::compute(&contents)),
+            }),
+        };
+
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        let options = DownloadOptions {
+            output_dir: Some(dir.clone()),
+            quiet: true,
+            ..Default::default()
+        };
+        let decompressed = download_files(
+            storage_config.clone(),
+            vec![uploaded_file.clone()],
+            &options,
+            future::pending(),
+        )
+        .await
+        .map(|()| std::fs::read_to_string(dir.join("logs/run.csv")));
+        // With --no-decompress, the file is kept as stored
+        let options = DownloadOptions {
+            no_decompress: true,
+            ..options
+        };
+        let kept = download_files(
+            storage_config,
+            vec![uploaded_file],
+            &options,
+            future::pending(),
+        )
+        .await
+        .map(|()| std::fs::read(dir.join("logs/run.csv.gz")));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(decompressed.unwrap().unwrap(), contents);
+        assert_eq!(kept.unwrap().unwrap(), compressed);
+        mock.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_download_files_checksum_mismatch_keeps_existing_file() {
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/datasets/{}/data/a.bag", dataset_id));
+            then.status(200).body("corrupted");
+        });
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let uploaded_file = UploadedFile {
+            file_id: Uuid::new_v4(),
+            dataset_id,
+            created_date: Utc::now(),
+            url: Url::parse(&format!(
+                "{}/datasets/{}/data/a.bag",
+                server.base_url(),
+                dataset_id
+            ))
+            .unwrap(),
+            filesize: 9,
+            version: "v1".to_owned(),
+            metadata: json!({
+                "path": "data/a.bag",
+                "checksum_algorithm": "md5",
+                "md5": base64::encode(*md5::compute("original")),
+            }),
+        };
+
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("data")).unwrap();
+        std::fs::write(dir.join("data/a.bag"), "original").unwrap();
+        let options = DownloadOptions {
+            output_dir: Some(dir.clone()),
+            quiet: true,
+            ..Default::default()
+        };
+        let result = download_files(
+            storage_config,
+            vec![uploaded_file],
+            &options,
+            future::pending(),
+        )
+        .await;
+        let contents = std::fs::read_to_string(dir.join("data/a.bag")).unwrap();
+        let part_exists = dir.join("data/a.bag.part").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let error = result.unwrap_err();
+        assert!(
+            error.downcast_ref::<ChecksumMismatch>().is_some(),
+            "{:?}",
+            error
+        );
+        assert_eq!(contents, "original");
+        assert!(!part_exists);
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_export_dataset_tar() {
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        let names = ["rig.plex", "board.csv", "camera-1/a.bag"];
+        for name in names.iter() {
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path(format!("/datasets/{}/{}", dataset_id, name));
+                then.status(200).body(format!("contents of {}", name));
+            });
+        }
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let created_date = DateTime::parse_from_rfc3339("2021-08-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let uploaded_files: Vec<UploadedFile> = names
+            .iter()
+            .map(|name| UploadedFile {
+                file_id: Uuid::new_v4(),
+                dataset_id,
+                created_date,
+                url: Url::parse(&format!(
+                    "{}/datasets/{}/{}",
+                    server.base_url(),
+                    dataset_id,
+                    name
+                ))
+                .unwrap(),
+                filesize: format!("contents of {}", name).len() as u64,
+                version: "v1".to_owned(),
+                metadata: json!({"path": name}),
+            })
+            .collect();
+        let dataset = Dataset {
+            dataset_id,
+            system_id: "robot-1".to_owned(),
+            created_date,
+            metadata: json!({"operator": "bolster"}),
+            files: Vec::new(),
+        };
+
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("export.tar");
+        let options = DownloadOptions {
+            quiet: true,
+            ..Default::default()
+        };
+        export_dataset(
+            storage_config,
+            &dataset,
+            uploaded_files.clone(),
+            &output,
+            ExportFormat::Tar,
+            &options,
+            future::pending(),
+        )
+        .await
+        .unwrap();
+        let leftovers: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        let extracted = dir.join("extracted");
+        std::fs::create_dir_all(&extracted).unwrap();
+        tar::Archive::new(std::fs::File::open(&output).unwrap())
+            .unpack(&extracted)
+            .unwrap();
+        let contents: Vec<String> = names
+            .iter()
+            .map(|name| std::fs::read_to_string(extracted.join(name)).unwrap())
+            .collect();
+        let manifest: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(extracted.join(EXPORT_MANIFEST_NAME)).unwrap(),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The staging directory is cleaned up
+        assert_eq!(leftovers, vec![std::ffi::OsString::from("export.tar")]);
+        for (name, contents) in names.iter().zip(contents) {
+            assert_eq!(contents, format!("contents of {}", name));
+        }
+        assert_eq!(manifest["dataset_id"], dataset_id.to_string());
+        assert_eq!(manifest["system_id"], "robot-1");
+        assert_eq!(manifest["created_date"], "2021-08-01T12:00:00Z");
+        assert_eq!(manifest["metadata"], json!({"operator": "bolster"}));
+        let files = manifest["files"].as_array().unwrap();
+        let paths: Vec<&str> = files.iter().map(|f| f["path"].as_str().unwrap()).collect();
+        assert_eq!(paths, vec!["board.csv", "camera-1/a.bag", "rig.plex"]);
+        let bag = uploaded_files
+            .iter()
+            .find(|f| f.metadata["path"] == "camera-1/a.bag")
+            .unwrap();
+        assert_eq!(files[1]["file_id"], bag.file_id.to_string());
+        assert_eq!(files[1]["filesize"], bag.filesize);
+        assert_eq!(files[1]["version"], "v1");
+        assert_eq!(files[1]["metadata"], json!({"path": "camera-1/a.bag"}));
+    }
+
+    #[tokio::test]
+    async fn test_export_dataset_lists_decompressed_files() {
+        let contents = "timestamp,x,y\n".repeat(1000);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, contents.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/datasets/{}/logs/run.csv.gz", dataset_id));
+            then.status(200).body(&compressed);
+        });
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let uploaded_file = UploadedFile {
+            file_id: Uuid::new_v4(),
+            dataset_id,
+            created_date: Utc::now(),
+            url: Url::parse(&format!(
+                "{}/datasets/{}/logs/run.csv.gz",
+                server.base_url(),
+                dataset_id
+            ))
+            .unwrap(),
+            filesize: compressed.len() as u64,
+            version: "v1".to_owned(),
+            metadata: json!({
+                "compression": "gzip",
+                "uncompressed_size": contents.len(),
+            }),
+        };
+        let dataset = Dataset {
+            dataset_id,
+            system_id: "robot-1".to_owned(),
+            created_date: Utc::now(),
+            metadata: json!({}),
+            files: Vec::new(),
+        };
+
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("export.tar");
+        let options = DownloadOptions {
+            quiet: true,
+            ..Default::default()
+        };
+        export_dataset(
+            storage_config,
+            &dataset,
+            vec![uploaded_file],
+            &output,
+            ExportFormat::Tar,
+            &options,
+            future::pending(),
+        )
+        .await
+        .unwrap();
+        let extracted = dir.join("extracted");
+        std::fs::create_dir_all(&extracted).unwrap();
+        tar::Archive::new(std::fs::File::open(&output).unwrap())
+            .unpack(&extracted)
+            .unwrap();
+        let run = std::fs::read_to_string(extracted.join("logs/run.csv")).unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(extracted.join(EXPORT_MANIFEST_NAME)).unwrap(),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(run, contents);
+        // The manifest matches the decompressed file in the archive
+        assert_eq!(manifest["files"][0]["path"], "logs/run.csv");
+        assert_eq!(manifest["files"][0]["filesize"], contents.len());
+    }
 
     #[tokio::test]
-    async fn test_upload_missing_file() {
+    async fn test_export_dataset_refuses_to_overwrite() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("export.zip");
+        std::fs::write(&output, "bolster test").unwrap();
         let mut config = config::Config::default();
         config
             .merge(config::File::from_str(
@@ -426,26 +5574,1044 @@ mod tests {
                 config::FileFormat::Toml,
             ))
             .unwrap();
-
-        let db = config
-            .clone()
-            .try_into::<DatabaseConfig>()
-            .unwrap()
-            .database;
-        let db_config = DatabaseApiConfig::new(db.url.clone(), db.jwt).unwrap();
         let storage_config = StorageConfig::new(config, StorageProviderChoices::Aws).unwrap();
-        let dataset_id = Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap();
-        let path = "nonexistent-file".to_owned();
-        let prefix = "";
-        let mp = MultiProgress::new();
-        let error = upload_file(storage_config, &db_config, dataset_id, path, prefix, &mp)
+        let dataset = Dataset {
+            dataset_id: Uuid::new_v4(),
+            system_id: "robot-1".to_owned(),
+            created_date: Utc::now(),
+            metadata: json!({}),
+            files: Vec::new(),
+        };
+
+        let error = export_dataset(
+            storage_config,
+            &dataset,
+            share_test_files(),
+            &output,
+            ExportFormat::Zip,
+            &DownloadOptions::default(),
+            future::pending(),
+        )
+        .await
+        .expect_err("Existing output should not be overwritten");
+        let contents = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(error
+            .to_string()
+            .ends_with("already exists, refusing to overwrite it"));
+        assert_eq!(contents, "bolster test");
+    }
+
+    #[tokio::test]
+    async fn test_export_dataset_rejects_manifest_name_collision() {
+        let server = MockServer::start();
+        let dataset_id = Uuid::new_v4();
+        let download = server.mock(|when, then| {
+            when.method(GET);
+            then.status(200).body("{}");
+        });
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let uploaded_files: Vec<UploadedFile> = ["manifest.json", "camera-1/a.bag"]
+            .iter()
+            .map(|name| UploadedFile {
+                file_id: Uuid::new_v4(),
+                dataset_id,
+                created_date: Utc::now(),
+                url: Url::parse(&format!(
+                    "{}/datasets/{}/{}",
+                    server.base_url(),
+                    dataset_id,
+                    name
+                ))
+                .unwrap(),
+                filesize: 2,
+                version: "v1".to_owned(),
+                metadata: json!({}),
+            })
+            .collect();
+        let dataset = Dataset {
+            dataset_id,
+            system_id: "robot-1".to_owned(),
+            created_date: Utc::now(),
+            metadata: json!({}),
+            files: Vec::new(),
+        };
+
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("export.tar");
+        let options = DownloadOptions {
+            quiet: true,
+            ..Default::default()
+        };
+        let error = export_dataset(
+            storage_config,
+            &dataset,
+            uploaded_files,
+            &output,
+            ExportFormat::Tar,
+            &options,
+            future::pending(),
+        )
+        .await
+        .expect_err("A dataset file named manifest.json should be rejected");
+        let leftovers = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(error
+            .to_string()
+            .contains("would overwrite the export's manifest.json"));
+        // Nothing is downloaded or written
+        assert_eq!(download.hits(), 0);
+        assert_eq!(leftovers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_archive_directory_zip() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        let staging = dir.join("staging");
+        std::fs::create_dir_all(staging.join("camera-1")).unwrap();
+        std::fs::write(staging.join("rig.plex"), "plex").unwrap();
+        std::fs::write(staging.join("camera-1").join("a.bag"), "bag").unwrap();
+        let output = dir.join("export.zip");
+        archive_directory(&staging, &output, ExportFormat::Zip)
             .await
-            .expect_err("Loading nonexistent file should fail");
+            .unwrap();
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&output).unwrap()).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(str::to_owned).collect();
+        names.sort();
+        let mut bag = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("camera-1/a.bag").unwrap(), &mut bag)
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(names, vec!["camera-1/a.bag", "rig.plex"]);
+        assert_eq!(bag, "bag");
+    }
+
+    #[test]
+    fn test_transfer_summary() {
+        let stats = storage::TransferStats::default();
+        stats.add_file_bytes(1_500_000);
+        stats.add_wire_bytes(1_500_000);
+        stats.add_wire_bytes(120_000);
+        assert_eq!(
+            transfer_summary("Uploaded", "sent", &stats),
+            "Uploaded 1.50 MB of file data (1.62 MB sent over the network)"
+        );
+    }
+
+    #[test]
+    fn test_read_batch_upload_spec() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let toml_path = dir.join("batch.toml");
+        std::fs::write(
+            &toml_path,
+            "[[dataset]]\nsystem_id = \"robot-01\"\nplex = \"a.plex\"\nobject_space = \"a.toml\"\n\
+             paths = [\"a/\"]\nmeta = { operator = \"alice\" }\n\n\
+             [[dataset]]\nsystem_id = \"robot-02\"\nplex = \"b.plex\"\nobject_space = \"b.toml\"\n\
+             paths = [\"b.bag\"]\n",
+        )
+        .unwrap();
+        let json_path = dir.join("batch.json");
+        std::fs::write(
+            &json_path,
+            r#"{"datasets": [{"system_id": "robot-03", "plex": "c.plex", "object_space": "c.toml", "paths": []}]}"#,
+        )
+        .unwrap();
+        let empty_path = dir.join("empty.toml");
+        std::fs::write(&empty_path, "dataset = []\n").unwrap();
+
+        let spec = read_batch_upload_spec(&toml_path).unwrap();
+        let json_spec = read_batch_upload_spec(&json_path).unwrap();
+        let empty_error = read_batch_upload_spec(&empty_path).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(spec.datasets.len(), 2);
+        assert_eq!(spec.datasets[0].system_id, "robot-01");
+        assert_eq!(spec.datasets[0].paths, vec![PathBuf::from("a/")]);
+        assert_eq!(
+            serde_json::Value::from(spec.datasets[0].meta.clone()),
+            json!({"operator": "alice"})
+        );
+        assert!(spec.datasets[1].meta.is_empty());
+        assert_eq!(json_spec.datasets[0].plex, PathBuf::from("c.plex"));
         assert!(
-            error.to_string().contains("No such file or directory"),
+            empty_error
+                .to_string()
+                .ends_with("doesn't list any datasets"),
+            "{}",
+            empty_error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_datasets_creates_each_dataset() {
+        // The mock server is both the database and S3-compatible storage
+        let server = MockServer::start();
+        let prefix = "807cffe5-df6d-434a-86a4-d007cd746bd3";
+        let datasets = [
+            (
+                "robot-01",
+                Uuid::parse_str("619e0899-ec94-4d87-812c-71736c09c4d6").unwrap(),
+                [
+                    "fixtures/example.plex",
+                    "fixtures/checkerboard_detector.toml",
+                    "fixtures/empty.bag",
+                ],
+            ),
+            (
+                "robot-02",
+                Uuid::parse_str("26fb2ac2-642a-4d7e-8233-b1835623b46b").unwrap(),
+                [
+                    "fixtures/empty.plex",
+                    "fixtures/charuco_detector.toml",
+                    "fixtures/aprilgrid_detector.toml",
+                ],
+            ),
+        ];
+        let mut mocks = Vec::new();
+        for (system_id, dataset_id, paths) in &datasets {
+            mocks.push(server.mock(|when, then| {
+                when.method(POST)
+                    .path("/datasets")
+                    .body_contains(format!("\"system_id\":\"{}\"", system_id));
+                then.status(201)
+                    .header("Content-Type", "application/json")
+                    .json_body(json!([{
+                        "dataset_id": dataset_id,
+                        "created_date": "2021-02-03T21:21:57.713584+00:00",
+                        "metadata": {},
+                    }]));
+            }));
+            for path in paths {
+                mocks.push(mock_versioned_put(
+                    &server,
+                    &format!("{}/{}/{}", prefix, dataset_id, path),
+                ));
+            }
+        }
+        let files = mock_register_files(&server, datasets[0].1);
+        let notify = server.mock(|when, then| {
+            when.method(POST).path("/rpc/dataset_upload_complete");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{"status": "ok"}]));
+        });
+
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let db_config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TOKEN".to_owned())
+                .unwrap();
+        let items = datasets
+            .iter()
+            .map(|(system_id, _, paths)| BatchUploadItem {
+                system_id: (*system_id).to_owned(),
+                plex_path: paths[0].to_owned(),
+                object_space_path: paths[1].to_owned(),
+                file_paths: vec![paths[2].to_owned()],
+                metadata: serde_json::Map::new(),
+            })
+            .collect();
+        let options = UploadOptions {
+            quiet: true,
+            created_message: Some(String::new()),
+            ..Default::default()
+        };
+
+        let outcomes = tokio::time::timeout(
+            Duration::from_secs(30),
+            upload_datasets(storage_config, &db_config, prefix, items, &options, 2),
+        )
+        .await
+        .expect("Batch upload should finish");
+
+        let uploaded: Vec<Uuid> = outcomes
+            .into_iter()
+            .map(|outcome| match outcome {
+                BatchUploadOutcome::Uploaded(dataset_id) => dataset_id,
+                other => panic!("Dataset should have uploaded: {:?}", other),
+            })
+            .collect();
+        assert_eq!(uploaded, vec![datasets[0].1, datasets[1].1]);
+        for mock in &mocks {
+            mock.assert();
+        }
+        files.assert_hits(6);
+        notify.assert_hits(2);
+    }
+
+    #[tokio::test]
+    async fn test_diff_dataset() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("subfolder")).unwrap();
+        std::fs::write(dir.join("same.bag"), "bolster test").unwrap();
+        std::fs::write(dir.join("subfolder/same.bag"), "bolster test").unwrap();
+        std::fs::write(dir.join("changed.bag"), "bolster TEST").unwrap();
+        std::fs::write(dir.join("resized.bag"), "bolster").unwrap();
+        std::fs::write(dir.join("unchecked.bag"), "bolster TEST").unwrap();
+        std::fs::write(dir.join("local.bag"), "bolster test").unwrap();
+        let dataset_id = Uuid::new_v4();
+        let remote_file = |path: &str, metadata: serde_json::Value| UploadedFile {
+            file_id: Uuid::new_v4(),
+            dataset_id,
+            created_date: Utc::now(),
+            url: Url::parse(&format!(
+                "https://bucket.s3.us-west-1.amazonaws.com/{}/{}",
+                dataset_id, path
+            ))
+            .unwrap(),
+            filesize: 12,
+            version: "v1".to_owned(),
+            metadata,
+        };
+        let md5 = json!({"md5": base64::encode(*md5::compute("bolster test"))});
+        let uploaded_files = vec![
+            remote_file("same.bag", md5.clone()),
+            remote_file("subfolder/same.bag", md5.clone()),
+            remote_file("changed.bag", md5.clone()),
+            remote_file("resized.bag", md5.clone()),
+            // No recorded checksum, so only the size is compared
+            remote_file("unchecked.bag", json!({})),
+            remote_file("remote.bag", md5),
+        ];
+
+        let diff = diff_dataset(&uploaded_files, &dir).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            diff.unwrap(),
+            DatasetDiff {
+                only_local: vec!["local.bag".to_owned()],
+                only_remote: vec!["remote.bag".to_owned()],
+                differing: vec!["changed.bag".to_owned(), "resized.bag".to_owned()],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diff_dataset_requires_directory() {
+        let error = diff_dataset(&share_test_files(), Path::new("Cargo.toml"))
+            .await
+            .expect_err("Diffing against a file should fail");
+        assert_eq!(error.to_string(), "\"Cargo.toml\" is not a directory");
+    }
+
+    #[test]
+    fn test_verify_stored_object() {
+        // md5 of "bolster test"
+        let md5_hex = format!("{:x}", md5::compute("bolster test"));
+        let mut uploaded_file = share_test_files().remove(0);
+        uploaded_file.metadata = json!({
+            "md5": base64::encode(*md5::compute("bolster test")),
+        });
+        let stored = |size: i64, e_tag: &str| {
+            Some(storage::StoredObject {
+                content_length: Some(size),
+                e_tag: Some(e_tag.to_owned()),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            verify_stored_object(&uploaded_file, stored(12, &format!("\"{}\"", md5_hex))),
+            VerifyStatus::Ok
+        );
+        assert_eq!(
+            verify_stored_object(&uploaded_file, None),
+            VerifyStatus::Missing
+        );
+        assert_eq!(
+            verify_stored_object(&uploaded_file, stored(11, &md5_hex)),
+            VerifyStatus::SizeMismatch {
+                expected: 12,
+                actual: 11
+            }
+        );
+        assert_eq!(
+            verify_stored_object(
+                &uploaded_file,
+                stored(12, "\"0123456789abcdef0123456789abcdef\"")
+            ),
+            VerifyStatus::ChecksumMismatch {
+                expected: md5_hex,
+                actual: "0123456789abcdef0123456789abcdef".to_owned(),
+            }
+        );
+        // Multipart ETags aren't md5s of the whole file
+        assert_eq!(
+            verify_stored_object(
+                &uploaded_file,
+                stored(12, "\"0123456789abcdef0123456789abcdef-2\"")
+            ),
+            VerifyStatus::Ok
+        );
+        // sha256 checksums can't be compared with ETags
+        uploaded_file.metadata = json!({"checksum_algorithm": "sha256", "sha256": "abc"});
+        assert_eq!(
+            verify_stored_object(
+                &uploaded_file,
+                stored(12, "\"0123456789abcdef0123456789abcdef\"")
+            ),
+            VerifyStatus::Ok
+        );
+    }
+
+    #[test]
+    fn test_prepare_output_dir() {
+        let error = prepare_output_dir(Path::new("fixtures/empty.bag"))
+            .expect_err("Output path that is a file should error");
+        assert!(
+            error.to_string().contains("exists but is not a directory"),
+            "{}",
+            error.to_string()
+        );
+
+        let dir = std::env::temp_dir()
+            .join(format!("bolster-test-{}", Uuid::new_v4()))
+            .join("nested");
+        prepare_output_dir(&dir).unwrap();
+        assert!(dir.is_dir());
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sequential_write_gate_serializes_commits() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let num_files = 4;
+        let gate = Arc::new(SequentialWriteGate::new(
+            SEQUENTIAL_WRITES_MAX_BUFFERED_BYTES,
+        ));
+        // Every "fetch" waits at the barrier, so this test only finishes if all
+        // fetches are in flight at the same time.
+        let fetch_barrier = Arc::new(tokio::sync::Barrier::new(num_files));
+        let active_commits = Arc::new(AtomicUsize::new(0));
+        let max_active_commits = Arc::new(AtomicUsize::new(0));
+        let commit_order = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let mut handles = Vec::new();
+        for idx in 0..num_files {
+            let gate = gate.clone();
+            let fetch_barrier = fetch_barrier.clone();
+            let active_commits = active_commits.clone();
+            let max_active_commits = max_active_commits.clone();
+            let commit_order = commit_order.clone();
+            handles.push(tokio::spawn(async move {
+                let _reservation = gate.reserve(1024).await.unwrap();
+                fetch_barrier.wait().await;
+                gate.commit(|| async {
+                    let active = active_commits.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_active_commits.fetch_max(active, Ordering::SeqCst);
+                    // Give other tasks a chance to (incorrectly) start committing
+                    for _ in 0..10 {
+                        let _ = tokio::task::yield_now().await;
+                    }
+                    commit_order.lock().unwrap().push(idx);
+                    active_commits.fetch_sub(1, Ordering::SeqCst);
+                })
+                .await;
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(max_active_commits.load(Ordering::SeqCst), 1);
+        assert_eq!(commit_order.lock().unwrap().len(), num_files);
+    }
+
+    #[tokio::test]
+    async fn test_sequential_write_gate_bounds_buffered_bytes() {
+        let gate = SequentialWriteGate::new(2 * (MEBIBYTE as usize));
+        let first = gate.reserve(MEBIBYTE as u64).await.unwrap();
+        let second = gate.reserve(MEBIBYTE as u64).await.unwrap();
+        assert_eq!(gate.buffer_budget.available_permits(), 0);
+        drop(first);
+        assert_eq!(gate.buffer_budget.available_permits(), 1);
+        // A file larger than the whole budget waits for the whole budget
+        drop(second);
+        let _huge = gate.reserve(10 * MEBIBYTE as u64).await.unwrap();
+        assert_eq!(gate.buffer_budget.available_permits(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_download_file_streams_files_larger_than_write_budget() {
+        let contents = "larger than the budget";
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/datasets/{}/data/a.bag", dataset_id));
+            then.status(200).body(contents);
+        });
+        let storage_config = s3_compatible_test_config(&server.base_url(), "");
+        let uploaded_file = UploadedFile {
+            file_id: Uuid::new_v4(),
+            dataset_id,
+            created_date: Utc::now(),
+            url: Url::parse(&format!(
+                "{}/datasets/{}/data/a.bag",
+                server.base_url(),
+                dataset_id
+            ))
+            .unwrap(),
+            filesize: contents.len() as u64,
+            version: "v1".to_owned(),
+            metadata: json!({
+                "path": "data/a.bag",
+                "checksum_algorithm": "md5",
+                "md5": base64::encode(*md5::compute(contents)),
+            }),
+        };
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", Uuid::new_v4()));
+        let options = DownloadOptions {
+            output_dir: Some(dir.clone()),
+            quiet: true,
+            ..Default::default()
+        };
+        let gate = SequentialWriteGate::new(4);
+        assert!(!gate.fits(contents.len() as u64));
+
+        // Nothing is fetched while another file is being written, since the
+        // file is streamed to disk rather than buffered
+        let lock = gate.commit_lock.lock().await;
+        let waiting = tokio::time::timeout(
+            Duration::from_millis(200),
+            download_file(
+                storage_config.clone(),
+                &uploaded_file,
+                &Progress::None,
+                &options,
+                Some(&gate),
+            ),
+        )
+        .await;
+        let hits_while_locked = mock.hits();
+        drop(lock);
+        download_file(
+            storage_config,
+            &uploaded_file,
+            &Progress::None,
+            &options,
+            Some(&gate),
+        )
+        .await
+        .unwrap();
+        let downloaded = std::fs::read_to_string(dir.join("data/a.bag")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(waiting.is_err());
+        assert_eq!(hits_while_locked, 0);
+        assert_eq!(downloaded, contents);
+        // No room in the buffer budget was reserved
+        assert_eq!(gate.buffer_budget.available_permits(), gate.budget_permits);
+        mock.assert();
+    }
+
+    #[tokio::test]
+    async fn test_write_and_verify_stream_matching_md5() {
+        let chunks: Vec<std::io::Result<Bytes>> =
+            vec![Ok(Bytes::from("bolster ")), Ok(Bytes::from("test"))];
+        let expected = base64::encode(*md5::compute("bolster test"));
+        let mut written: Vec<u8> = Vec::new();
+        write_and_verify_stream(
+            stream::iter(chunks),
+            &mut written,
+            Some((ChecksumAlgorithm::Md5, &expected)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(written, b"bolster test");
+    }
+
+    #[tokio::test]
+    async fn test_write_and_verify_stream_sha256() {
+        let chunks: Vec<std::io::Result<Bytes>> =
+            vec![Ok(Bytes::from("bolster ")), Ok(Bytes::from("test"))];
+        let expected = "eucE6bh7XGbHVkjr+9MBHS9tI1XmcS1rrTSTpCskoLI=";
+        let mut written: Vec<u8> = Vec::new();
+        write_and_verify_stream(
+            stream::iter(chunks),
+            &mut written,
+            Some((ChecksumAlgorithm::Sha256, expected)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(written, b"bolster test");
+
+        let chunks: Vec<std::io::Result<Bytes>> = vec![Ok(Bytes::from("bolster "))];
+        let error = write_and_verify_stream(
+            stream::iter(chunks),
+            &mut written,
+            Some((ChecksumAlgorithm::Sha256, expected)),
+        )
+        .await
+        .expect_err("Mismatched sha256 should error");
+        assert!(
+            error.to_string().contains("expected sha256"),
+            "{}",
+            error.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_and_verify_stream_mismatched_md5() {
+        // Simulate a truncated download
+        let chunks: Vec<std::io::Result<Bytes>> = vec![Ok(Bytes::from("bolster "))];
+        let expected = base64::encode(*md5::compute("bolster test"));
+        let mut written: Vec<u8> = Vec::new();
+        let error = write_and_verify_stream(
+            stream::iter(chunks),
+            &mut written,
+            Some((ChecksumAlgorithm::Md5, &expected)),
+        )
+        .await
+        .expect_err("Mismatched md5 should error");
+        assert!(
+            error.to_string().contains("Checksum mismatch"),
             "{}",
             error.to_string()
         );
+        assert_eq!(FailureCategory::of(&error), FailureCategory::Checksum);
+
+        // Skipping verification succeeds
+        let chunks: Vec<std::io::Result<Bytes>> = vec![Ok(Bytes::from("bolster "))];
+        write_and_verify_stream(stream::iter(chunks), &mut written, None)
+            .await
+            .unwrap();
+    }
+
+    fn mock_tagged_dataset(server: &MockServer, tags: serde_json::Value) -> MockRef<'_> {
+        server.mock(|when, then| {
+            when.method(GET)
+                .query_param("dataset_id", "eq.afd56ecf-9d87-4053-8c80-0d924f06da52")
+                .path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "system_id": "robot-1",
+                    "metadata": {
+                        "description": "Test",
+                        "tags": tags,
+                    },
+                    "files": [],
+                }]));
+        })
+    }
+
+    fn mock_patched_dataset(server: &MockServer, tags: serde_json::Value) -> MockRef<'_> {
+        server.mock(|when, then| {
+            when.method(PATCH)
+                .query_param("dataset_id", "eq.afd56ecf-9d87-4053-8c80-0d924f06da52")
+                .json_body(json!({"metadata": {"description": "Test", "tags": tags}}))
+                .path("/datasets");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(json!([{
+                    "dataset_id": "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                    "created_date": "2021-02-03T21:21:57.713584+00:00",
+                    "system_id": "robot-1",
+                    "metadata": {
+                        "description": "Test",
+                        "tags": tags,
+                    },
+                }]));
+        })
+    }
+
+    /// Builds a page of datasets with ids derived from the given numbers.
+    fn dataset_page(ids: impl Iterator<Item = usize>) -> serde_json::Value {
+        ids.map(|i| {
+            json!({
+                "dataset_id": format!("00000000-0000-0000-0000-{:012}", i),
+                "created_date": "2021-02-03T21:21:57.713584+00:00",
+                "system_id": "robot-1",
+                "metadata": {},
+                "files": [],
+            })
+        })
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_all_datasets_paginates() {
+        let server = MockServer::start();
+        let first_page = server.mock(|when, then| {
+            when.method(GET)
+                .path("/datasets")
+                .query_param("system_id", "eq.robot-1")
+                .query_param("order", "created_date.asc")
+                .query_param("limit", "100")
+                .query_param("offset", "0");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(dataset_page(0..100));
+        });
+        // Includes a dataset from the first page, as if results shifted
+        let second_page = server.mock(|when, then| {
+            when.method(GET)
+                .path("/datasets")
+                .query_param("order", "created_date.asc")
+                .query_param("limit", "100")
+                .query_param("offset", "100");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(dataset_page(99..102));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &Default::default(),
+        )
+        .unwrap();
+        let params = DatasetGetRequest {
+            system_id: Some("robot-1".to_owned()),
+            order: Some(DatasetOrdering::CreatedDateDesc),
+            limit: Some(20),
+            offset: Some(40),
+            ..Default::default()
+        };
+        let datasets = list_all_datasets(&config, params).await.unwrap();
+
+        first_page.assert();
+        second_page.assert();
+        assert_eq!(datasets.len(), 102);
+        assert_eq!(
+            datasets[101].dataset_id,
+            Uuid::parse_str("00000000-0000-0000-0000-000000000101").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_all_datasets_yields_pages_as_fetched() {
+        let server = MockServer::start();
+        let first_page = server.mock(|when, then| {
+            when.method(GET)
+                .path("/datasets")
+                .query_param("limit", "100")
+                .query_param("offset", "0");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(dataset_page(0..100));
+        });
+        let second_page = server.mock(|when, then| {
+            when.method(GET)
+                .path("/datasets")
+                .query_param("limit", "100")
+                .query_param("offset", "100");
+            then.status(200)
+                .header("Content-Type", "application/json")
+                .json_body(dataset_page(100..101));
+        });
+
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &Default::default(),
+        )
+        .unwrap();
+        let mut datasets = Box::pin(stream_all_datasets(&config, Default::default()));
+
+        // The second page isn't requested until the first page is consumed
+        for _ in 0..100 {
+            datasets.try_next().await.unwrap().unwrap();
+        }
+        first_page.assert();
+        second_page.assert_hits(0);
+
+        let last = datasets.try_next().await.unwrap().unwrap();
+        assert_eq!(
+            last.dataset_id,
+            Uuid::parse_str("00000000-0000-0000-0000-000000000100").unwrap()
+        );
+        assert!(datasets.try_next().await.unwrap().is_none());
+        second_page.assert();
+    }
+
+    #[tokio::test]
+    async fn test_notify_upload_complete_finds_calibration_files() {
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let file = |file_id: &str, path: &str| {
+            json!({
+                "file_id": file_id,
+                "dataset_id": dataset_id,
+                "created_date": "2021-02-03T21:21:57.713584+00:00",
+                "url": format!("https://bucket.s3.us-west-1.amazonaws.com/{}/{}", dataset_id, path),
+                "filesize": 12,
+                "version": "v1",
+                "metadata": {},
+            })
+        };
+        let dataset = |metadata: serde_json::Value, files: Vec<serde_json::Value>| {
+            json!([{
+                "dataset_id": dataset_id,
+                "system_id": "robot-1",
+                "created_date": "2021-02-03T21:21:57.713584+00:00",
+                "metadata": metadata,
+                "files": files,
+            }])
+        };
+        let plex = file("00000000-0000-0000-0000-000000000001", "cal/system.plex");
+        let object_space = file("00000000-0000-0000-0000-000000000002", "cal/targets.toml");
+        let data = file("00000000-0000-0000-0000-000000000003", "data/1.bag");
+        let data_config = file("00000000-0000-0000-0000-000000000004", "data/config.toml");
+
+        let mut get = server.mock(|when, then| {
+            when.method(GET).path("/datasets");
+            then.status(200).json_body(dataset(
+                json!({}),
+                vec![plex.clone(), object_space.clone(), data.clone()],
+            ));
+        });
+        let mut notify = server.mock(|when, then| {
+            when.method(POST)
+                .path("/rpc/dataset_upload_complete")
+                .json_body(json!({
+                    "dataset_id": dataset_id,
+                    "plex_file_id": "00000000-0000-0000-0000-000000000001",
+                    "object_space_file_id": "00000000-0000-0000-0000-000000000002",
+                }));
+            then.status(200).json_body(json!([{"status": "ok"}]));
+        });
+        let config = DatabaseApiConfig::new_with_params(
+            Url::parse(&server.base_url()).unwrap(),
+            "TEST-TOKEN".to_owned(),
+            10,
+            &Default::default(),
+        )
+        .unwrap();
+
+        let outcome = notify_upload_complete(&config, dataset_id, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(outcome, datasets::NotifyOutcome::Notified);
+        notify.assert();
+        get.delete();
+
+        // Another .toml file makes the object-space file ambiguous
+        get = server.mock(|when, then| {
+            when.method(GET).path("/datasets");
+            then.status(200).json_body(dataset(
+                json!({}),
+                vec![plex.clone(), object_space.clone(), data_config.clone()],
+            ));
+        });
+        let error = notify_upload_complete(&config, dataset_id, None, None, None)
+            .await
+            .expect_err("Object-space file is ambiguous");
+        assert!(
+            error
+                .to_string()
+                .contains("has several .toml files (cal/targets.toml, data/config.toml)"),
+            "{}",
+            error
+        );
+        notify_upload_complete(&config, dataset_id, None, Some("cal/targets.toml"), None)
+            .await
+            .unwrap();
+        notify.assert_hits(2);
+        let error = notify_upload_complete(&config, dataset_id, Some("cal/other.plex"), None, None)
+            .await
+            .expect_err("Plex path isn't in the dataset");
+        assert!(
+            error.to_string().contains("No file cal/other.plex found"),
+            "{}",
+            error
+        );
+        get.delete();
+        notify.delete();
+
+        // Uncalibrated datasets don't have calibration files inferred
+        server.mock(|when, then| {
+            when.method(GET).path("/datasets");
+            then.status(200).json_body(dataset(
+                json!({ UNCALIBRATED_METADATA_KEY: true }),
+                vec![data, data_config],
+            ));
+        });
+        let notify = server.mock(|when, then| {
+            when.method(POST)
+                .path("/rpc/dataset_upload_complete")
+                .json_body(json!({ "dataset_id": dataset_id }));
+            then.status(409)
+                .json_body(json!({"status": "already_notified"}));
+        });
+        let outcome = notify_upload_complete(&config, dataset_id, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(outcome, datasets::NotifyOutcome::AlreadyNotified);
+        notify.assert();
+    }
+
+    fn file_page(dataset_id: Uuid, ids: impl Iterator<Item = usize>) -> serde_json::Value {
+        ids.map(|i| {
+            json!({
+                "file_id": format!("00000000-0000-0000-0000-{:012}", i),
+                "dataset_id": dataset_id,
+                "created_date": "2021-02-03T21:21:57.713584+00:00",
+                "url": format!("https://bucket.s3.us-west-1.amazonaws.com/{}/{}.bag", dataset_id, i),
+                "filesize": 12,
+                "version": "v1",
+                "metadata": {},
+            })
+        })
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stream_files_paginates() {
+        let server = MockServer::start();
+        let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let page = |limit: usize, offset: usize, ids: std::ops::Range<usize>| {
+            server.mock(|when, then| {
+                when.method(GET)
+                    .path("/files")
+                    .query_param("dataset_id", &format!("eq.{}", dataset_id))
+                    .query_param("order", "created_date.asc,file_id.asc")
+                    .query_param("limit", &limit.to_string())
+                    .query_param("offset", &offset.to_string());
+                then.status(200)
+                    .header("Content-Type", "application/json")
+                    .json_body(file_page(dataset_id, ids));
+            })
+        };
+        let config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TEST".to_owned())
+                .unwrap();
+
+        // Fetches pages until a partial page is returned
+        let first_page = page(100, 0, 0..100);
+        let last_page = page(100, 100, 100..103);
+        let files: Vec<UploadedFile> = stream_files(&config, dataset_id, None, None, 0)
+            .try_collect()
+            .await
+            .unwrap();
+        first_page.assert();
+        last_page.assert();
+        assert_eq!(files.len(), 103);
+        assert_eq!(
+            files[102].file_id,
+            Uuid::parse_str("00000000-0000-0000-0000-000000000102").unwrap()
+        );
+
+        // Stops once the limit is reached, without fetching more pages
+        let offset_page = page(100, 10, 10..110);
+        let limited_page = page(20, 110, 110..130);
+        let files: Vec<UploadedFile> = stream_files(&config, dataset_id, None, Some(120), 10)
+            .try_collect()
+            .await
+            .unwrap();
+        offset_page.assert();
+        limited_page.assert();
+        assert_eq!(files.len(), 120);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_dataset_name() {
+        let server = MockServer::start();
+        let named = |name: &str, ids: &[&str]| {
+            let datasets: Vec<serde_json::Value> = ids
+                .iter()
+                .map(|id| {
+                    json!({
+                        "dataset_id": id,
+                        "created_date": "2021-02-03T21:21:57.713584+00:00",
+                        "system_id": "robot-1",
+                        "metadata": {"name": name},
+                        "files": [{"file_count": 1, "total_filesize": 1024}],
+                    })
+                })
+                .collect();
+            server.mock(|when, then| {
+                when.method(GET)
+                    .query_param("metadata->>name", &format!("eq.{}", name))
+                    .path("/datasets");
+                then.status(200)
+                    .header("Content-Type", "application/json")
+                    .json_body(json!(datasets));
+            })
+        };
+        let unique = named("parking-lot", &["afd56ecf-9d87-4053-8c80-0d924f06da52"]);
+        named("missing", &[]);
+        named(
+            "loop",
+            &[
+                "afd56ecf-9d87-4053-8c80-0d924f06da52",
+                "d11cc371-f33b-4dad-ac2e-3c4cca30a256",
+            ],
+        );
+        let config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TEST".to_owned())
+                .unwrap();
+
+        assert_eq!(
+            resolve_dataset_name(&config, "parking-lot").await.unwrap(),
+            Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap()
+        );
+        unique.assert();
+        assert_eq!(
+            resolve_dataset_name(&config, "missing")
+                .await
+                .unwrap_err()
+                .to_string(),
+            "No dataset named \"missing\""
+        );
+        assert_eq!(
+            resolve_dataset_name(&config, "loop")
+                .await
+                .unwrap_err()
+                .to_string(),
+            "Dataset name \"loop\" is ambiguous, it matches datasets \
+             afd56ecf-9d87-4053-8c80-0d924f06da52, d11cc371-f33b-4dad-ac2e-3c4cca30a256 \
+             (pass a UUID instead)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_tag() {
+        let server = MockServer::start();
+        let get_mock = mock_tagged_dataset(&server, json!(["indoor"]));
+        let patch_mock = mock_patched_dataset(&server, json!(["indoor", "outdoor"]));
+
+        let config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TEST".to_owned())
+                .unwrap();
+        let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let tags = add_tag(&config, dataset_id, "outdoor".to_owned())
+            .await
+            .unwrap();
+
+        get_mock.assert();
+        patch_mock.assert();
+        assert_eq!(tags, vec!["indoor".to_owned(), "outdoor".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_tag() {
+        let server = MockServer::start();
+        let get_mock = mock_tagged_dataset(&server, json!(["indoor", "outdoor"]));
+        let patch_mock = mock_patched_dataset(&server, json!(["outdoor"]));
+
+        let config =
+            DatabaseApiConfig::new(Url::parse(&server.base_url()).unwrap(), "TEST".to_owned())
+                .unwrap();
+        let dataset_id = Uuid::parse_str("afd56ecf-9d87-4053-8c80-0d924f06da52").unwrap();
+        let tags = remove_tag(&config, dataset_id, "indoor".to_owned())
+            .await
+            .unwrap();
+
+        get_mock.assert();
+        patch_mock.assert();
+        assert_eq!(tags, vec!["outdoor".to_owned()]);
     }
 
     #[test]
@@ -458,7 +6624,7 @@ mod tests {
             ))
             .unwrap();
 
-        let error = print_config(config).expect_err("Unexpected config format should error");
+        let error = print_config(config, false).expect_err("Unexpected config format should error");
         assert!(
             error.to_string().contains("missing field"),
             "{}",
@@ -478,7 +6644,7 @@ mod tests {
 
         let url_str =
             "https://tangram-vision-datasets.s3.us-west-1.amazonaws.com/fixtures/test.dat";
-        let uploaded_files = vec![UploadedFile {
+        let uploaded_files = [UploadedFile {
             file_id: Uuid::parse_str("c11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
             dataset_id: Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
             created_date: Utc::now(),
@@ -489,7 +6655,7 @@ mod tests {
         }];
 
         // Based on url from database, find which StorageProvider's config to use
-        let provider = StorageProviderChoices::from_url(&uploaded_files[0].url).unwrap();
+        let provider = StorageProviderChoices::from_url(&uploaded_files[0].url, &config).unwrap();
         let error =
             StorageConfig::new(config, provider).expect_err("Missing storage config should error");
         assert!(
@@ -0,0 +1,351 @@
+//! Optional client-side compression of uploaded files, and decompression of
+//! them when downloading.
+//!
+//! For overall architecture, see [ARCHITECTURE.md](https://gitlab.com/tangram-vision/oss/bolster/-/blob/main/ARCHITECTURE.md)
+
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use flate2::{
+    write::{GzEncoder, MultiGzDecoder},
+    Compression,
+};
+use futures::stream::{self, Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, EnumString, EnumVariantNames};
+use tokio::{io::AsyncRead, sync::mpsc, task::JoinHandle};
+use tokio_util::io::StreamReader;
+
+use super::api::storage::ChecksumContext;
+use crate::app_config::ChecksumAlgorithm;
+
+/// Formats files can be compressed with before uploading.
+#[derive(
+    AsRefStr, EnumString, EnumVariantNames, Debug, Clone, Copy, PartialEq, Deserialize, Serialize,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionFormat {
+    /// gzip (RFC 1952), readable with `gunzip`
+    Gzip,
+}
+
+impl CompressionFormat {
+    /// Extension appended to the cloud storage key of compressed files.
+    pub fn extension(self) -> &'static str {
+        match self {
+            CompressionFormat::Gzip => "gz",
+        }
+    }
+}
+
+/// Extensions of files that aren't compressed by default, since they're
+/// already compressed (or, for rosbags, usually compressed internally).
+pub const DEFAULT_SKIP_COMPRESSION_EXTENSIONS: [&str; 14] = [
+    "bag", "mcap", "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "jpg", "jpeg", "png", "mp4", "mkv",
+];
+
+/// Whether a file at `path` should be compressed, i.e. its extension isn't
+/// one of `skip_extensions` (compared case-insensitively, without the leading
+/// `.`).
+pub fn should_compress<S: AsRef<str>>(path: &Path, skip_extensions: &[S]) -> bool {
+    let extension = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extension,
+        None => return true,
+    };
+    !skip_extensions.iter().any(|skip| {
+        skip.as_ref()
+            .trim_start_matches('.')
+            .eq_ignore_ascii_case(extension)
+    })
+}
+
+/// Path of a downloaded compressed file once decompressed, i.e. without the
+/// compression's extension (e.g. `logs/run.csv.gz` becomes `logs/run.csv`).
+///
+/// Paths without the extension are returned unchanged.
+pub fn decompressed_path(path: &Path, format: CompressionFormat) -> PathBuf {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension == format.extension() => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Size of the buffers that compressed and decompressed data is written to.
+const BUFFER_SIZE: usize = 256 * 1024;
+
+/// Size and checksum of a file before it was compressed (see
+/// [compress_file]).
+#[derive(Debug, PartialEq)]
+pub struct Uncompressed {
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Checksum of the file, base64-encoded
+    pub checksum: String,
+}
+
+/// Number of compressed buffers queued for upload before compressing pauses.
+const COMPRESSED_BUFFERS_QUEUED: usize = 4;
+
+/// Compresses the file at `path` with `format` on a blocking thread, returning
+/// a reader of the compressed data and a handle that resolves to the size and
+/// `checksum_algorithm` checksum of the uncompressed file once it's all read.
+///
+/// The file is compressed as the reader is read, so only a few buffers of
+/// compressed data are held in memory at once.
+pub fn compress_file(
+    path: &Path,
+    format: CompressionFormat,
+    checksum_algorithm: ChecksumAlgorithm,
+) -> (
+    impl AsyncRead + Unpin + Send,
+    JoinHandle<io::Result<Uncompressed>>,
+) {
+    let (sender, mut receiver) = mpsc::channel::<io::Result<Bytes>>(COMPRESSED_BUFFERS_QUEUED);
+    let path = path.to_path_buf();
+    let handle = tokio::task::spawn_blocking(move || {
+        let result = compress_file_blocking(&path, format, checksum_algorithm, &sender);
+        if let Err(error) = &result {
+            // The reader fails too, rather than ending as if the file were
+            // complete
+            let _ = sender.blocking_send(Err(io::Error::new(error.kind(), error.to_string())));
+        }
+        result
+    });
+    let chunks = stream::poll_fn(move |cx| receiver.poll_recv(cx));
+    (StreamReader::new(Box::pin(chunks)), handle)
+}
+
+/// Compresses the file at `path`, sending the compressed data to `sender`
+/// (see [compress_file]).
+fn compress_file_blocking(
+    path: &Path,
+    format: CompressionFormat,
+    checksum_algorithm: ChecksumAlgorithm,
+    sender: &mpsc::Sender<io::Result<Bytes>>,
+) -> io::Result<Uncompressed> {
+    let CompressionFormat::Gzip = format;
+    let mut file = std::fs::File::open(path)?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let mut checksum = ChecksumContext::new(checksum_algorithm);
+    let mut size = 0;
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let send = |data: Vec<u8>| {
+        sender
+            .blocking_send(Ok(Bytes::from(data)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Upload stopped reading"))
+    };
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        size += read as u64;
+        checksum.update(&buffer[..read]);
+        encoder.write_all(&buffer[..read])?;
+        if encoder.get_ref().len() >= BUFFER_SIZE {
+            send(std::mem::take(encoder.get_mut()))?;
+        }
+    }
+    send(encoder.finish()?)?;
+    Ok(Uncompressed {
+        size,
+        checksum: checksum.finish(),
+    })
+}
+
+/// Decompresses a stream of `format`-compressed data as it's read.
+///
+/// # Errors
+///
+/// The stream yields an error if the data isn't validly compressed, e.g. if
+/// it's corrupt or ends early.
+pub fn decompress_stream<S>(
+    compressed: S,
+    format: CompressionFormat,
+) -> impl Stream<Item = io::Result<Bytes>>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin,
+{
+    let CompressionFormat::Gzip = format;
+    stream::try_unfold(
+        (compressed, Some(MultiGzDecoder::new(Vec::new()))),
+        |(mut compressed, decoder)| async move {
+            let mut decoder = match decoder {
+                Some(decoder) => decoder,
+                None => return Ok(None),
+            };
+            // Compressed chunks may not decompress to anything yet
+            while decoder.get_ref().is_empty() {
+                match compressed.try_next().await? {
+                    Some(chunk) => {
+                        decoder.write_all(&chunk).map_err(invalid_gzip)?;
+                        decoder.flush().map_err(invalid_gzip)?;
+                    }
+                    None => {
+                        decoder.try_finish().map_err(invalid_gzip)?;
+                        let output = std::mem::take(decoder.get_mut());
+                        return Ok(Some((Bytes::from(output), (compressed, None))));
+                    }
+                }
+            }
+            let output = std::mem::take(decoder.get_mut());
+            Ok(Some((Bytes::from(output), (compressed, Some(decoder)))))
+        },
+    )
+}
+
+/// Error for data that isn't valid gzip.
+fn invalid_gzip(error: io::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Unable to decompress gzip data -- {}", error),
+    )
+}
+
+/// Name of a compression format as recorded in file metadata.
+///
+/// # Errors
+///
+/// Returns an error if `name` isn't a supported format, e.g. if the file was
+/// uploaded by a newer version of bolster.
+pub fn parse_compression(name: &str) -> Result<CompressionFormat> {
+    match name.parse() {
+        Ok(format) => Ok(format),
+        Err(_) => bail!(
+            "Unsupported compression {} -- download with --no-decompress to keep the file as stored",
+            name
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Data that compresses well, but not trivially.
+    fn sample_data(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| b"timestamp,x,y,z\n0123456789"[(i * 7 + i / 13) % 26])
+            .collect()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn gunzip(compressed: &[u8], piece_size: usize) -> io::Result<Vec<u8>> {
+        let chunks = compressed
+            .chunks(piece_size)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let decompressed: Vec<Bytes> =
+            decompress_stream(stream::iter(chunks), CompressionFormat::Gzip)
+                .try_collect()
+                .await?;
+        Ok(decompressed.concat())
+    }
+
+    #[tokio::test]
+    async fn test_gzip_round_trip() {
+        for len in [0, 1, 1000, 3 * BUFFER_SIZE + 17] {
+            let data = sample_data(len);
+            let compressed = gzip(&data);
+            // Decompressing works no matter how the data is split up
+            for piece_size in [1, 4096, compressed.len()] {
+                assert_eq!(gunzip(&compressed, piece_size).await.unwrap(), data);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gzip_multiple_members() {
+        let mut compressed = gzip(b"first,");
+        compressed.extend(gzip(b"second"));
+        assert_eq!(gunzip(&compressed, 5).await.unwrap(), b"first,second");
+    }
+
+    #[tokio::test]
+    async fn test_gunzip_rejects_bad_data() {
+        gunzip(b"not gzip", 4).await.unwrap_err();
+        let compressed = gzip(&sample_data(10_000));
+        let error = gunzip(&compressed[..compressed.len() - 4], 64)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_should_compress() {
+        let skip = DEFAULT_SKIP_COMPRESSION_EXTENSIONS;
+        assert!(should_compress(Path::new("logs/run.csv"), &skip));
+        assert!(should_compress(Path::new("README"), &skip));
+        assert!(!should_compress(Path::new("data/a.bag"), &skip));
+        assert!(!should_compress(Path::new("images/A.JPG"), &skip));
+        assert!(should_compress::<&str>(Path::new("data/a.bag"), &[]));
+        assert!(!should_compress(Path::new("logs/run.csv"), &[".csv"]));
+    }
+
+    #[test]
+    fn test_decompressed_path() {
+        assert_eq!(
+            decompressed_path(Path::new("logs/run.csv.gz"), CompressionFormat::Gzip),
+            Path::new("logs/run.csv")
+        );
+        assert_eq!(
+            decompressed_path(Path::new("logs/run.csv"), CompressionFormat::Gzip),
+            Path::new("logs/run.csv")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compress_file_and_decompress_stream() {
+        let path = std::env::temp_dir().join(format!("bolster-test-{}.csv", Uuid::new_v4()));
+        let data = sample_data(2 * BUFFER_SIZE + 5);
+        std::fs::write(&path, &data).unwrap();
+
+        let (mut reader, handle) =
+            compress_file(&path, CompressionFormat::Gzip, ChecksumAlgorithm::Md5);
+        let mut compressed = Vec::new();
+        reader.read_to_end(&mut compressed).await.unwrap();
+        let uncompressed = handle.await.unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            uncompressed,
+            Uncompressed {
+                size: data.len() as u64,
+                checksum: base64::encode(*md5::compute(&data)),
+            }
+        );
+
+        let chunks = compressed
+            .chunks(1000)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let decompressed: Vec<Bytes> =
+            decompress_stream(stream::iter(chunks), CompressionFormat::Gzip)
+                .try_collect()
+                .await
+                .unwrap();
+        assert_eq!(decompressed.concat(), data);
+    }
+
+    #[tokio::test]
+    async fn test_compress_file_fails_reader_if_file_is_missing() {
+        let path = std::env::temp_dir().join(format!("bolster-test-{}.csv", Uuid::new_v4()));
+        let (mut reader, handle) =
+            compress_file(&path, CompressionFormat::Gzip, ChecksumAlgorithm::Md5);
+        let mut compressed = Vec::new();
+        reader.read_to_end(&mut compressed).await.unwrap_err();
+        handle.await.unwrap().unwrap_err();
+    }
+}
@@ -4,10 +4,14 @@ use std::{path::PathBuf, vec::Vec};
 
 use anyhow::{anyhow, bail, Result};
 use chrono::{DateTime, Utc};
+use percent_encoding::percent_decode_str;
 use reqwest::Url;
 use serde::Deserialize;
 use uuid::Uuid;
 
+use super::compression::{self, CompressionFormat};
+use crate::app_config::ChecksumAlgorithm;
+
 /// A dataset with embedded files.
 #[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct Dataset {
@@ -47,6 +51,82 @@ pub struct DatasetNoFiles {
     pub metadata: serde_json::Value,
 }
 
+/// A dataset with the number and total size of its files, rather than the
+/// files themselves.
+///
+/// The file aggregates are computed by the datasets API, so listing summaries
+/// is much cheaper than listing [Dataset]s when datasets contain many files.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DatasetSummary {
+    /// Dataset identifier, used for filtering by dataset and downloading files
+    /// from the dataset.
+    pub dataset_id: Uuid,
+    /// System/device/robot/installation identifier, used for filtering.
+    pub system_id: String,
+    /// Creation date of the dataset.
+    #[serde(with = "notz_rfc_3339")]
+    pub created_date: DateTime<Utc>,
+    /// Unimplemented -- may be used for holding sensor/platform/contextual data
+    /// in the future.
+    pub metadata: serde_json::Value,
+    /// Number and total size of the dataset's files.
+    #[serde(deserialize_with = "deserialize_file_aggregates")]
+    pub files: FileAggregates,
+}
+
+impl From<Dataset> for DatasetSummary {
+    fn from(dataset: Dataset) -> Self {
+        let files = FileAggregates {
+            file_count: dataset.files.len() as u64,
+            total_filesize: dataset.files.iter().map(|f| f.filesize).sum(),
+        };
+        DatasetSummary {
+            dataset_id: dataset.dataset_id,
+            system_id: dataset.system_id,
+            created_date: dataset.created_date,
+            metadata: dataset.metadata,
+            files,
+        }
+    }
+}
+
+/// Number and total size of a dataset's files.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FileAggregates {
+    /// Number of files in the dataset.
+    pub file_count: u64,
+    /// Total size of the dataset's files in bytes.
+    pub total_filesize: u64,
+}
+
+/// Deserializes the aggregates embedded by the datasets API, which look like
+/// `[{"file_count": 2, "total_filesize": 1024}]`.
+///
+/// The sum of an empty dataset's filesizes is null, and a missing row means
+/// the dataset has no files.
+fn deserialize_file_aggregates<'de, D>(deserializer: D) -> Result<FileAggregates, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    /// One row of aggregates, as returned by the datasets API.
+    #[derive(Deserialize)]
+    struct RawFileAggregates {
+        /// Number of files
+        file_count: Option<u64>,
+        /// Sum of filesizes
+        total_filesize: Option<u64>,
+    }
+
+    let rows: Vec<RawFileAggregates> = Deserialize::deserialize(deserializer)?;
+    Ok(rows
+        .into_iter()
+        .next()
+        .map_or_else(FileAggregates::default, |row| FileAggregates {
+            file_count: row.file_count.unwrap_or(0),
+            total_filesize: row.total_filesize.unwrap_or(0),
+        }))
+}
+
 /// A file in a dataset.
 #[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct UploadedFile {
@@ -70,23 +150,111 @@ pub struct UploadedFile {
     /// overwrite, this just creates a new version with a different version
     /// identifier.
     pub version: String,
-    /// Unimplemented -- may be used for holding sensor/platform/contextual data
-    /// in the future.
+    /// Info recorded at upload time, such as the file's original path,
+    /// modification time, and md5 hash.
     pub metadata: serde_json::Value,
 }
 
 impl UploadedFile {
-    /// Extracts the filepath portion of the url.
+    /// Checksum algorithm and base64-encoded checksum of the file recorded at
+    /// upload time, if any.
+    ///
+    /// Files uploaded before the algorithm was recorded always used md5. Files
+    /// uploaded by older versions of bolster don't have a recorded checksum.
+    pub fn expected_checksum(&self) -> Option<(ChecksumAlgorithm, &str)> {
+        let algorithm = match self.metadata.get("checksum_algorithm") {
+            Some(algorithm) => serde_json::from_value(algorithm.clone()).ok()?,
+            None => ChecksumAlgorithm::Md5,
+        };
+        self.metadata
+            .get(algorithm.as_str())
+            .and_then(|v| v.as_str())
+            .map(|checksum| (algorithm, checksum))
+    }
+
+    /// Modification time of the file when it was uploaded, if recorded.
+    ///
+    /// Files uploaded by older versions of bolster don't have a recorded
+    /// modification time.
+    pub fn modified_time(&self) -> Option<DateTime<Utc>> {
+        self.metadata
+            .get("mtime")
+            .and_then(|v| v.as_str())
+            .and_then(|mtime| DateTime::parse_from_rfc3339(mtime).ok())
+            .map(|mtime| mtime.with_timezone(&Utc))
+    }
+
+    /// Compression the file was stored with (see [UploadedFile::compression]),
+    /// as recorded at upload time.
+    pub fn compression_name(&self) -> Option<&str> {
+        self.metadata.get("compression").and_then(|v| v.as_str())
+    }
+
+    /// Compression the file was stored with, if it was compressed before
+    /// uploading (see `bolster upload --compress`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the recorded compression isn't supported (see
+    /// [compression::parse_compression]).
+    pub fn compression(&self) -> Result<Option<CompressionFormat>> {
+        self.compression_name()
+            .map(compression::parse_compression)
+            .transpose()
+    }
+
+    /// Size of the file before it was compressed, or its stored size if it
+    /// wasn't compressed.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.metadata
+            .get("uncompressed_size")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(self.filesize)
+    }
+
+    /// Checksum algorithm and base64-encoded checksum of the file before it
+    /// was compressed, or of the stored file if it wasn't compressed (see
+    /// [UploadedFile::expected_checksum]).
+    pub fn uncompressed_checksum(&self) -> Option<(ChecksumAlgorithm, &str)> {
+        let (algorithm, checksum) = self.expected_checksum()?;
+        if self.compression_name().is_none() {
+            return Some((algorithm, checksum));
+        }
+        self.metadata
+            .get(format!("uncompressed_{}", algorithm.as_str()))
+            .and_then(|v| v.as_str())
+            .map(|checksum| (algorithm, checksum))
+    }
+
+    /// Path of the file as it was uploaded, i.e. [UploadedFile::filepath_from_url]
+    /// without the extension added by compression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the url is malformed (see
+    /// [UploadedFile::filepath_from_url]).
+    pub fn local_filepath(&self) -> Result<PathBuf> {
+        let filepath = self.filepath_from_url()?;
+        Ok(match self.compression() {
+            Ok(Some(format)) => compression::decompressed_path(&filepath, format),
+            _ => filepath,
+        })
+    }
+
+    /// Extracts the filepath portion of the url, percent-decoding each path
+    /// segment. Any query string (e.g. a version id) is ignored.
     ///
     /// # Errors
     ///
     /// Returns an error if the url is somehow malformed (missing a path or the
-    /// required dataset id prefix).
+    /// required dataset id prefix), if a path segment doesn't decode to a
+    /// plain UTF-8 file or directory name, or if the path has no file name
+    /// (e.g. it ends with `/`).
     pub fn filepath_from_url(&self) -> Result<PathBuf> {
         let mut segments = self
             .url
             .path_segments()
-            .ok_or_else(|| anyhow!("File URL is malformed!"))?;
+            .ok_or_else(|| anyhow!("File url ({}) has no path.", self.url))?;
 
         loop {
             if let Some(segment) = segments.next() {
@@ -98,7 +266,31 @@ impl UploadedFile {
                 bail!("File url ({}) doesn't contain dataset-id.", self.url);
             }
         }
-        Ok(segments.collect::<PathBuf>())
+        let path_segments: Vec<String> = segments
+            .map(|segment| {
+                let decoded = percent_decode_str(segment).decode_utf8().map_err(|_| {
+                    anyhow!("File url ({}) has a path that isn't valid UTF-8.", self.url)
+                })?;
+                // Don't let a decoded segment escape the dataset's directory
+                if decoded == "." || decoded == ".." || decoded.contains(&['/', '\\'][..]) {
+                    bail!(
+                        "File url ({}) has an unsafe path segment: {}",
+                        self.url,
+                        decoded
+                    );
+                }
+                Ok(decoded.into_owned())
+            })
+            .collect::<Result<_>>()?;
+        // A url ending in the dataset id or a "/" would be downloaded to a file
+        // with no name
+        if path_segments
+            .last()
+            .is_none_or(|segment| segment.is_empty())
+        {
+            bail!("File url ({}) has no file name in its path.", self.url);
+        }
+        Ok(path_segments.into_iter().collect())
     }
 }
 
@@ -112,7 +304,7 @@ pub mod notz_rfc_3339 {
     /// Strptime format for datetimes when deserializing from json.
     ///
     /// Example: 2021-05-06T23:54:45.626411+00:00
-    const FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f%:z";
+    pub const FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f%:z";
 
     // The signature of a serialize_with function must follow the pattern:
     //
@@ -156,6 +348,35 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_dataset_summary_file_aggregates() {
+        let summary = |files| {
+            serde_json::from_value::<DatasetSummary>(json!({
+                "dataset_id": "d11cc371-f33b-4dad-ac2e-3c4cca30a256",
+                "created_date": "2021-02-03T21:21:57.713584+00:00",
+                "system_id": "robot-1",
+                "metadata": {},
+                "files": files,
+            }))
+            .unwrap()
+            .files
+        };
+
+        assert_eq!(
+            summary(json!([{"file_count": 2, "total_filesize": 1024}])),
+            FileAggregates {
+                file_count: 2,
+                total_filesize: 1024
+            }
+        );
+        // Summing no filesizes gives null
+        assert_eq!(
+            summary(json!([{"file_count": 0, "total_filesize": null}])),
+            FileAggregates::default()
+        );
+        assert_eq!(summary(json!([])), FileAggregates::default());
+    }
+
     #[test]
     fn test_uploadedfile_filepath_from_url_success() {
         let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
@@ -204,4 +425,133 @@ mod tests {
                 .eval(&e)
         );
     }
+
+    fn uploaded_file_at(path_and_query: &str) -> UploadedFile {
+        let dataset_id = Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        let url_str = format!(
+            "https://bucket.example.com/{}/{}",
+            dataset_id, path_and_query
+        );
+        UploadedFile {
+            dataset_id,
+            file_id: Uuid::parse_str("c11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            created_date: Utc::now(),
+            url: Url::parse(&url_str).unwrap(),
+            filesize: 12,
+            version: "blah".to_owned(),
+            metadata: json!({}),
+        }
+    }
+
+    #[test]
+    fn test_uploadedfile_filepath_from_url_decodes_segments() {
+        let uf = uploaded_file_at("my%20data/test%20run.dat");
+        assert_eq!(
+            PathBuf::from("my data/test run.dat"),
+            uf.filepath_from_url().unwrap()
+        );
+        let uf = uploaded_file_at("caf%C3%A9/%E6%97%A5%E6%9C%AC.bag");
+        assert_eq!(
+            PathBuf::from("café/日本.bag"),
+            uf.filepath_from_url().unwrap()
+        );
+        // Unicode that was never encoded is encoded by the url parser and
+        // decoded again here
+        let uf = uploaded_file_at("café/日本.bag");
+        assert_eq!(
+            PathBuf::from("café/日本.bag"),
+            uf.filepath_from_url().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_uploadedfile_filepath_from_url_ignores_query() {
+        let uf = uploaded_file_at("fixtures/test%20file.dat?versionId=3%2FL4kqtJl40");
+        assert_eq!(
+            PathBuf::from("fixtures/test file.dat"),
+            uf.filepath_from_url().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_uploadedfile_filepath_from_url_bad_segments() {
+        let e = uploaded_file_at("fixtures/a%2F..%2Fb.dat")
+            .filepath_from_url()
+            .expect_err("Decoded segment contains a separator")
+            .to_string();
+        assert!(e.contains("unsafe path segment: a/../b.dat"), "{}", e);
+        let e = uploaded_file_at("fixtures/%FF.dat")
+            .filepath_from_url()
+            .expect_err("Segment isn't valid UTF-8")
+            .to_string();
+        assert!(e.contains("isn't valid UTF-8"), "{}", e);
+    }
+
+    #[test]
+    fn test_uploadedfile_filepath_from_url_without_name() {
+        for path in &["", "fixtures/"] {
+            let e = uploaded_file_at(path)
+                .filepath_from_url()
+                .expect_err("Path has no file name")
+                .to_string();
+            assert!(e.contains("has no file name in its path"), "{}", e);
+        }
+        let mut uf = uploaded_file_at("");
+        uf.url =
+            Url::parse("https://bucket.example.com/d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap();
+        assert!(uf.filepath_from_url().is_err());
+        uf.url = Url::parse("mailto:datasets@example.com").unwrap();
+        let e = uf.filepath_from_url().unwrap_err().to_string();
+        assert!(e.contains("has no path"), "{}", e);
+    }
+
+    #[test]
+    fn test_uploadedfile_modified_time() {
+        let mut uf = UploadedFile {
+            dataset_id: Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            file_id: Uuid::parse_str("c11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            created_date: Utc::now(),
+            url: Url::parse("https://bucket.example.com/test.dat").unwrap(),
+            filesize: 12,
+            version: "blah".to_owned(),
+            metadata: json!({"mtime": "2021-05-03T00:00:00Z"}),
+        };
+        assert_eq!(
+            uf.modified_time().unwrap().to_rfc3339(),
+            "2021-05-03T00:00:00+00:00"
+        );
+
+        uf.metadata = json!({});
+        assert_eq!(uf.modified_time(), None);
+    }
+
+    #[test]
+    fn test_uploadedfile_expected_checksum() {
+        let mut uf = UploadedFile {
+            dataset_id: Uuid::parse_str("d11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            file_id: Uuid::parse_str("c11cc371-f33b-4dad-ac2e-3c4cca30a256").unwrap(),
+            created_date: Utc::now(),
+            url: Url::parse("https://bucket.example.com/test.dat").unwrap(),
+            filesize: 12,
+            version: "blah".to_owned(),
+            metadata: json!({"checksum_algorithm": "sha256", "sha256": "abc", "md5": "def"}),
+        };
+        assert_eq!(
+            uf.expected_checksum(),
+            Some((ChecksumAlgorithm::Sha256, "abc"))
+        );
+
+        // Files uploaded before the algorithm was recorded used md5
+        uf.metadata = json!({"md5": "def"});
+        assert_eq!(
+            uf.expected_checksum(),
+            Some((ChecksumAlgorithm::Md5, "def"))
+        );
+
+        uf.metadata = json!({"checksum_algorithm": "crc32", "crc32": "abc"});
+        assert_eq!(uf.expected_checksum(), None);
+
+        uf.metadata = json!({});
+        assert_eq!(uf.expected_checksum(), None);
+    }
 }
@@ -0,0 +1,320 @@
+//! Reporting progress of uploads and downloads, either as progress bars, as
+//! periodic lines of text (e.g. for CI logs), or not at all.
+//!
+//! For overall architecture, see [ARCHITECTURE.md](https://gitlab.com/tangram-vision/oss/bolster/-/blob/main/ARCHITECTURE.md)
+
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use byte_unit::Byte;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use strum_macros::{AsRefStr, EnumString, EnumVariantNames};
+
+/// How transfer progress is shown.
+#[derive(AsRefStr, EnumString, EnumVariantNames, Debug, Default, Clone, Copy, PartialEq)]
+#[strum(serialize_all = "lowercase")]
+pub enum ProgressMode {
+    /// A progress bar per file, redrawn in place
+    #[default]
+    Bar,
+    /// Periodic lines of text, which read well in CI logs
+    Plain,
+    /// Nothing
+    None,
+}
+
+/// How often [ProgressMode::Plain] prints a line for a file that's still
+/// transferring.
+pub const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Receives progress updates for one file (or stream) being transferred, or
+/// for a whole transfer (see [crate::core::commands::OverallProgress]).
+///
+/// Implemented by indicatif's [ProgressBar] and by [PlainProgress].
+pub trait ProgressReporter: Send + Sync {
+    /// Sets the number of bytes transferred so far.
+    fn set_position(&self, bytes: u64);
+    /// Adds to the number of bytes transferred so far.
+    fn inc(&self, bytes: u64);
+    /// Sets a message shown after the byte counts (e.g. "3/10 files").
+    fn set_message(&self, message: String);
+    /// Marks the transfer as complete.
+    fn finish(&self);
+    /// Marks the transfer as stopped before completing (e.g. after a
+    /// failure), leaving its progress as it is.
+    fn abandon(&self);
+}
+
+impl ProgressReporter for ProgressBar {
+    fn set_position(&self, bytes: u64) {
+        ProgressBar::set_position(self, bytes);
+    }
+
+    fn inc(&self, bytes: u64) {
+        ProgressBar::inc(self, bytes);
+    }
+
+    fn set_message(&self, message: String) {
+        ProgressBar::set_message(self, message);
+    }
+
+    fn finish(&self) {
+        ProgressBar::finish(self);
+    }
+
+    fn abandon(&self) {
+        ProgressBar::abandon(self);
+    }
+}
+
+/// Creates a [ProgressReporter] for each file of a transfer, according to a
+/// [ProgressMode].
+///
+/// Cloning is cheap, and clones add reporters to the same multi-progress bar.
+#[derive(Clone)]
+pub enum Progress {
+    /// Progress bars are added to this multi-progress bar (see
+    /// [crate::core::commands::MultiProgressGuard]).
+    Bar(Arc<MultiProgress>),
+    /// Progress is printed as lines of text (see [PlainProgress]).
+    Plain,
+    /// Progress isn't shown.
+    None,
+}
+
+impl Progress {
+    /// Reporter for a file (or whole transfer) of `total_bytes` bytes, shown
+    /// with the given bar style and labelled `name`.
+    pub fn bar(
+        &self,
+        name: &str,
+        total_bytes: u64,
+        style: ProgressStyle,
+    ) -> Arc<dyn ProgressReporter> {
+        match self {
+            Progress::Bar(multi_progress) => {
+                let bar = multi_progress.add(ProgressBar::new(total_bytes));
+                bar.set_style(style);
+                bar.set_prefix(name.to_owned());
+                bar.set_position(0);
+                Arc::new(bar)
+            }
+            Progress::Plain => PlainProgress::start(name, Some(total_bytes)),
+            Progress::None => Arc::new(ProgressBar::hidden()),
+        }
+    }
+
+    /// Reporter for a stream whose size isn't known up front (e.g. stdin),
+    /// labelled `name`.
+    pub fn spinner(&self, name: &str) -> Arc<dyn ProgressReporter> {
+        match self {
+            Progress::Bar(multi_progress) => {
+                let spinner = multi_progress.add(ProgressBar::new_spinner());
+                spinner.set_style(
+                    ProgressStyle::default_spinner().template(
+                        "{prefix} [{elapsed_precise}] {spinner} {bytes} ({bytes_per_sec})",
+                    ),
+                );
+                spinner.set_prefix(name.to_owned());
+                Arc::new(spinner)
+            }
+            Progress::Plain => PlainProgress::start(name, None),
+            Progress::None => Arc::new(ProgressBar::hidden()),
+        }
+    }
+}
+
+/// Reports progress as lines of text on stderr: one every
+/// [PLAIN_PROGRESS_INTERVAL] while bytes are being transferred, and one when
+/// the transfer finishes or is abandoned.
+///
+/// Lines are printed by a background task, so CI logs get incremental output
+/// even while no progress updates arrive.
+pub struct PlainProgress {
+    /// Label printed at the start of each line (e.g. the file's path).
+    name: String,
+    /// Total number of bytes, if known.
+    total: Option<u64>,
+    /// Progress so far.
+    state: Mutex<PlainProgressState>,
+}
+
+/// Mutable part of a [PlainProgress].
+#[derive(Default)]
+struct PlainProgressState {
+    /// Number of bytes transferred so far.
+    position: u64,
+    /// Message printed after the byte counts.
+    message: String,
+    /// Position when the last line was printed, if one was.
+    printed_position: Option<u64>,
+    /// Whether the transfer finished or was abandoned.
+    done: bool,
+}
+
+impl PlainProgress {
+    /// Starts reporting progress of `name`, printing a line every
+    /// [PLAIN_PROGRESS_INTERVAL] until the reporter is finished or dropped.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn start(name: &str, total: Option<u64>) -> Arc<dyn ProgressReporter> {
+        let progress = PlainProgress::new(name, total);
+        let weak = Arc::downgrade(&progress);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PLAIN_PROGRESS_INTERVAL);
+            // The first tick completes immediately, before anything was
+            // transferred
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if !PlainProgress::print_if_changed(&weak) {
+                    break;
+                }
+            }
+        });
+        progress
+    }
+
+    /// Creates a reporter without starting to print lines.
+    fn new(name: &str, total: Option<u64>) -> Arc<PlainProgress> {
+        Arc::new(PlainProgress {
+            name: name.to_owned(),
+            total,
+            state: Mutex::new(PlainProgressState::default()),
+        })
+    }
+
+    /// Prints a line if progress changed since the last line, returning
+    /// whether the reporter is still active.
+    fn print_if_changed(weak: &Weak<PlainProgress>) -> bool {
+        let progress = match weak.upgrade() {
+            Some(progress) => progress,
+            None => return false,
+        };
+        let mut state = progress.state.lock().unwrap();
+        if state.done {
+            return false;
+        }
+        if state.printed_position != Some(state.position) {
+            state.printed_position = Some(state.position);
+            eprintln!(
+                "{}",
+                plain_progress_line(
+                    &progress.name,
+                    state.position,
+                    progress.total,
+                    &state.message
+                )
+            );
+        }
+        true
+    }
+
+    /// Prints a final line with `status` (unless one was already printed),
+    /// and stops printing lines.
+    fn done(&self, status: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.done {
+            return;
+        }
+        state.done = true;
+        let line = plain_progress_line(&self.name, state.position, self.total, &state.message);
+        eprintln!("{} {}", line, status);
+    }
+}
+
+impl ProgressReporter for PlainProgress {
+    fn set_position(&self, bytes: u64) {
+        self.state.lock().unwrap().position = bytes;
+    }
+
+    fn inc(&self, bytes: u64) {
+        self.state.lock().unwrap().position += bytes;
+    }
+
+    fn set_message(&self, message: String) {
+        self.state.lock().unwrap().message = message;
+    }
+
+    fn finish(&self) {
+        self.done("done");
+    }
+
+    fn abandon(&self) {
+        self.done("stopped");
+    }
+}
+
+impl Drop for PlainProgress {
+    fn drop(&mut self) {
+        // Transfers that fail are dropped without finishing, so end their
+        // output too
+        self.abandon();
+    }
+}
+
+/// Formats a line of [PlainProgress], e.g. "data/a.bag: 12.00 MB/48.00 MB
+/// (25%)".
+pub fn plain_progress_line(name: &str, position: u64, total: Option<u64>, message: &str) -> String {
+    let bytes = |bytes: u64| Byte::from_bytes(bytes as u128).get_appropriate_unit(false);
+    let mut line = match total {
+        Some(total) => format!(
+            "{}: {}/{} ({}%)",
+            name,
+            bytes(position),
+            bytes(total),
+            (position * 100).checked_div(total).unwrap_or(100)
+        ),
+        None => format!("{}: {}", name, bytes(position)),
+    };
+    if !message.is_empty() {
+        line.push_str(&format!(", {}", message));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_progress_line() {
+        assert_eq!(
+            plain_progress_line("data/a.bag", 12_000_000, Some(48_000_000), ""),
+            "data/a.bag: 12.00 MB/48.00 MB (25%)"
+        );
+        assert_eq!(
+            plain_progress_line("Total", 0, Some(0), "0/0 files"),
+            "Total: 0 B/0 B (100%), 0/0 files"
+        );
+        // The size of streams isn't known up front
+        assert_eq!(
+            plain_progress_line("capture.bag", 1_500, None, ""),
+            "capture.bag: 1.50 KB"
+        );
+    }
+
+    #[test]
+    fn test_plain_progress_prints_until_done() {
+        let progress = PlainProgress::new("data/a.bag", Some(100));
+        let weak = Arc::downgrade(&progress);
+
+        progress.inc(40);
+        assert!(PlainProgress::print_if_changed(&weak));
+        assert_eq!(progress.state.lock().unwrap().printed_position, Some(40));
+
+        // Nothing is printed after finishing, so the background task stops
+        progress.finish();
+        progress.inc(10);
+        assert!(!PlainProgress::print_if_changed(&weak));
+        assert_eq!(progress.state.lock().unwrap().printed_position, Some(40));
+
+        // The background task also stops once the reporter is dropped
+        let progress = PlainProgress::new("data/b.bag", Some(100));
+        let weak = Arc::downgrade(&progress);
+        drop(progress);
+        assert!(!PlainProgress::print_if_changed(&weak));
+    }
+}
@@ -1,10 +1,12 @@
 //! A module to parse an object space TOML file for use in the Tangram Vision calibration system.
 
-use std::{fs::read_to_string, path::Path};
+use std::{cmp::Ordering, fs::read_to_string, path::Path};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::app_config::strip_bom;
+
 /// A type representing the possible object-space configurations.
 ///
 /// Configurations comprise a detector-descriptor pairing for each component type within the
@@ -72,7 +74,7 @@ pub enum Detector {
         edge_length: f64,
         /// Size of one edge of the ArUco markers in the board.
         ///
-        /// Should be smaller than `edge_length`.
+        /// Must be smaller than `edge_length`.
         marker_length: f64,
         /// The variances (X/Y/Z) of object-space points, in metres^2.
         variances: [f64; 3],
@@ -100,6 +102,8 @@ pub enum Detector {
         /// - tagStandard52h13
         /// - tagCustom48h12
         ///
+        /// or a custom family explicitly allowed when reading the config (see
+        /// [read_object_space_config]).
         family: String,
     },
 }
@@ -116,6 +120,80 @@ const SUPPORTED_APRILTAG_FAMILIES: [&str; 8] = [
     "tagCustom48h12",
 ];
 
+/// Smallest number of squares a checkerboard or ChArUco board can have along
+/// each side (fewer squares have no interior corners to detect).
+const MIN_BOARD_SQUARES: usize = 2;
+
+/// Ensures a board dimension (in squares) is at least [MIN_BOARD_SQUARES].
+fn check_board_squares(detector: &str, field: &str, value: usize) -> Result<()> {
+    if value < MIN_BOARD_SQUARES {
+        bail!(
+            "The {} detector's '{}' must be at least {} squares. Provided {}: {}",
+            detector,
+            field,
+            MIN_BOARD_SQUARES,
+            field,
+            value
+        );
+    }
+    Ok(())
+}
+
+/// Ensures a length (in metres) is a positive number.
+fn check_positive_length(detector: &str, field: &str, value: f64) -> Result<()> {
+    // Written so NaN is rejected too
+    if value.partial_cmp(&0.0) != Some(Ordering::Greater) {
+        bail!(
+            "The {} detector's '{}' must be greater than 0 metres. Provided {}: {}",
+            detector,
+            field,
+            field,
+            value
+        );
+    }
+    Ok(())
+}
+
+/// Sanity-checks the board dimensions of a detector, naming the offending
+/// field in the error.
+fn validate_detector_dimensions(detector: &Detector) -> Result<()> {
+    match *detector {
+        Detector::Checkerboard {
+            width,
+            height,
+            edge_length,
+            ..
+        } => {
+            check_board_squares("checkerboard", "width", width)?;
+            check_board_squares("checkerboard", "height", height)?;
+            check_positive_length("checkerboard", "edge_length", edge_length)
+        }
+        Detector::Charuco {
+            width,
+            height,
+            edge_length,
+            marker_length,
+            ..
+        } => {
+            check_board_squares("charuco", "width", width)?;
+            check_board_squares("charuco", "height", height)?;
+            check_positive_length("charuco", "edge_length", edge_length)?;
+            check_positive_length("charuco", "marker_length", marker_length)?;
+            if marker_length >= edge_length {
+                bail!(
+                    "The charuco detector's 'marker_length' ({}) must be smaller than its 'edge_length' ({}).",
+                    marker_length,
+                    edge_length
+                );
+            }
+            Ok(())
+        }
+        Detector::AprilGrid { length, .. } => {
+            check_positive_length("april_grid", "length", f64::from(length))
+        }
+    }
+}
+
 /// A target describing a point in 3D space.
 ///
 /// To be used within certain descriptors.
@@ -151,11 +229,23 @@ pub enum Descriptor {
 }
 
 /// A function to read in the object space config from a TOML file at the given path.
-pub fn read_object_space_config<P>(toml_path: P) -> Result<ObjectSpaceConfig>
+///
+/// A leading UTF-8 byte order mark (as written by some Windows editors) is
+/// ignored.
+///
+/// AprilGrid families must be one of the built-in families (so typos
+/// are caught) or one of `custom_families`, for users with custom-generated
+/// families.
+pub fn read_object_space_config<P>(
+    toml_path: P,
+    custom_families: &[String],
+) -> Result<ObjectSpaceConfig>
 where
     P: AsRef<Path>,
 {
-    let config = toml::from_str::<ObjectSpaceConfig>(&read_to_string(toml_path)?)?;
+    let contents = read_to_string(toml_path)?;
+    let config = toml::from_str::<ObjectSpaceConfig>(strip_bom(&contents))?;
+    validate_detector_dimensions(&config.camera.detector)?;
 
     match &config.camera.detector {
         Detector::Checkerboard { .. } => match &config.camera.descriptor {
@@ -171,12 +261,17 @@ where
             )),
         },
         Detector::AprilGrid { family, .. } => {
-            SUPPORTED_APRILTAG_FAMILIES.iter().find(|f| f == &family).ok_or_else(||
-                anyhow::anyhow!(
-                    "The april_grid 'family' is not one of the supported family types. Provided family: {}",
+            if !SUPPORTED_APRILTAG_FAMILIES.contains(&family.as_str())
+                && !custom_families.contains(family)
+            {
+                bail!(
+                    "The april_grid 'family' is not one of the supported family types ({}). Provided family: {}\n\
+                     To use a custom family, allow it with `--apriltag-family {}` or apriltag_families in the [upload] config.",
+                    SUPPORTED_APRILTAG_FAMILIES.join(", "),
+                    &family,
                     &family
-                )
-            )?;
+                );
+            }
 
             match &config.camera.descriptor {
                 Descriptor::TargetList { .. } => Ok(()),
@@ -196,26 +291,132 @@ mod tests {
 
     #[test]
     fn valid_checkerboard_is_ok() {
-        read_object_space_config("fixtures/checkerboard_detector.toml").unwrap();
+        read_object_space_config("fixtures/checkerboard_detector.toml", &[]).unwrap();
     }
 
     #[test]
     fn valid_charuco_is_ok() {
-        read_object_space_config("fixtures/charuco_detector.toml").unwrap();
+        read_object_space_config("fixtures/charuco_detector.toml", &[]).unwrap();
     }
 
     #[test]
     fn valid_aprilgrid_is_ok() {
-        read_object_space_config("fixtures/aprilgrid_detector.toml").unwrap();
+        read_object_space_config("fixtures/aprilgrid_detector.toml", &[]).unwrap();
+    }
+
+    #[test]
+    fn valid_checkerboard_with_bom_is_ok() {
+        read_object_space_config("fixtures/checkerboard_detector_bom.toml", &[]).unwrap();
+    }
+
+    /// Parses a detector from a `[camera.detector]` TOML table body.
+    fn detector_from_toml(table: &str) -> Detector {
+        toml::from_str::<ObjectSpaceConfig>(&format!(
+            "[camera.detector]\n{}\n[camera.descriptor]\ntype = \"detector_defined\"\n",
+            table
+        ))
+        .unwrap()
+        .camera
+        .detector
+    }
+
+    #[test]
+    fn board_dimensions_must_be_at_least_two_squares() {
+        let detector = detector_from_toml(
+            "type = \"checkerboard\"\nwidth = 1\nheight = 6\nedge_length = 0.06\nvariances = [0.01, 0.01, 0.01]",
+        );
+        assert_eq!(
+            validate_detector_dimensions(&detector)
+                .unwrap_err()
+                .to_string(),
+            "The checkerboard detector's 'width' must be at least 2 squares. Provided width: 1"
+        );
+
+        let detector = detector_from_toml(
+            "type = \"charuco\"\nwidth = 9\nheight = 0\nedge_length = 0.06\nmarker_length = 0.05\nvariances = [0.01, 0.01, 0.01]",
+        );
+        assert_eq!(
+            validate_detector_dimensions(&detector)
+                .unwrap_err()
+                .to_string(),
+            "The charuco detector's 'height' must be at least 2 squares. Provided height: 0"
+        );
+    }
+
+    #[test]
+    fn lengths_must_be_positive() {
+        let detector = detector_from_toml(
+            "type = \"checkerboard\"\nwidth = 9\nheight = 6\nedge_length = 0.0\nvariances = [0.01, 0.01, 0.01]",
+        );
+        assert_eq!(
+            validate_detector_dimensions(&detector).unwrap_err().to_string(),
+            "The checkerboard detector's 'edge_length' must be greater than 0 metres. Provided edge_length: 0"
+        );
+
+        let detector = detector_from_toml(
+            "type = \"charuco\"\nwidth = 9\nheight = 6\nedge_length = 0.06\nmarker_length = -0.05\nvariances = [0.01, 0.01, 0.01]",
+        );
+        assert_eq!(
+            validate_detector_dimensions(&detector).unwrap_err().to_string(),
+            "The charuco detector's 'marker_length' must be greater than 0 metres. Provided marker_length: -0.05"
+        );
+
+        let detector = Detector::AprilGrid {
+            length: f32::NAN,
+            family: "tag36h11".to_owned(),
+        };
+        assert_eq!(
+            validate_detector_dimensions(&detector).unwrap_err().to_string(),
+            "The april_grid detector's 'length' must be greater than 0 metres. Provided length: NaN"
+        );
+    }
+
+    #[test]
+    fn charuco_marker_must_fit_in_square() {
+        let detector = detector_from_toml(
+            "type = \"charuco\"\nwidth = 9\nheight = 6\nedge_length = 0.05\nmarker_length = 0.06\nvariances = [0.01, 0.01, 0.01]",
+        );
+        assert_eq!(
+            validate_detector_dimensions(&detector).unwrap_err().to_string(),
+            "The charuco detector's 'marker_length' (0.06) must be smaller than its 'edge_length' (0.05)."
+        );
+    }
+
+    #[test]
+    fn unknown_aprilgrid_family_is_err() {
+        let dir = std::env::temp_dir().join(format!("bolster-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("custom_family.toml");
+        let contents = read_to_string("fixtures/aprilgrid_detector.toml").unwrap();
+        std::fs::write(
+            &path,
+            contents.replace("family = \"tag16h5\"", "family = \"tagLab30h9\""),
+        )
+        .unwrap();
+
+        let msg = read_object_space_config(&path, &[])
+            .unwrap_err()
+            .to_string();
+        assert!(msg.contains("Provided family: tagLab30h9"), "{}", msg);
+        assert!(msg.contains("--apriltag-family tagLab30h9"), "{}", msg);
+        // Other custom families don't let an unknown one through
+        read_object_space_config(&path, &["tagOther".to_owned()]).unwrap_err();
+
+        let config = read_object_space_config(&path, &["tagLab30h9".to_owned()]).unwrap();
+        assert!(matches!(
+            config.camera.detector,
+            Detector::AprilGrid { ref family, .. } if family == "tagLab30h9"
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
     fn invalid_toml_does_not_parse() {
-        read_object_space_config("Cargo.toml").unwrap_err();
+        read_object_space_config("Cargo.toml", &[]).unwrap_err();
     }
 
     #[test]
     fn file_that_does_not_exist_is_err() {
-        read_object_space_config("fixtures/i-do-not-exist.png").unwrap_err();
+        read_object_space_config("fixtures/i-do-not-exist.png", &[]).unwrap_err();
     }
 }